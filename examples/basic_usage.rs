@@ -1,13 +1,21 @@
 extern crate reducto;
 
 use reducto::huffman::Huffman;
+use std::env;
 use std::fs::File;
 use std::io::{BufReader, Read};
-use std::path::Path;
+use std::path::PathBuf;
+use std::process;
 
 fn main() {
-    let path = Path::new("resources/lorem.txt");
-    let mut file = match File::open(path) {
+    let path = match env::args().nth(1) {
+        Some(path) => PathBuf::from(path),
+        None => {
+            eprintln!("usage: cargo run --example basic_usage -- <file>");
+            process::exit(2);
+        }
+    };
+    let file = match File::open(&path) {
         Ok(f) => f,
         Err(e) => panic!("{}", e),
     };
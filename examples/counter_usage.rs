@@ -1,14 +1,22 @@
 extern crate reducto;
 
 use reducto::counter::Counter;
+use std::env;
 use std::fs::File;
 use std::io::{BufReader, Read};
 use std::iter::FromIterator;
-use std::path::Path;
+use std::path::PathBuf;
+use std::process;
 
 fn main() {
-    let path = Path::new("resources/lorem.txt");
-    let mut file = match File::open(path) {
+    let path = match env::args().nth(1) {
+        Some(path) => PathBuf::from(path),
+        None => {
+            eprintln!("usage: cargo run --example counter_usage -- <file>");
+            process::exit(2);
+        }
+    };
+    let file = match File::open(&path) {
         Ok(f) => f,
         Err(e) => panic!("{}", e),
     };
@@ -39,4 +47,18 @@ fn main() {
     // update with value
     counter.update_from_value('c');
     println!("Count for c: {}", counter['c']);
+
+    // most common elements
+    println!("Most common: {:?}", counter.most_common(3));
+
+    // merge with the counts of another text
+    let other: Counter<char> = Counter::from_iter("lorem ipsum".chars());
+    let merged = &counter + &other;
+    println!(
+        "Total: {} + {} = {}",
+        counter.total(),
+        other.total(),
+        merged.total()
+    );
+    assert_eq!(merged.total(), counter.total() + other.total());
 }
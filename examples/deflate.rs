@@ -1,7 +1,7 @@
 extern crate reducto;
 
 use reducto::deflate::Deflate;
-use reducto::lz77::LZ77;
+use std::env;
 use std::fs::File;
 use std::io::{BufReader, Read};
 use std::path::Path;
@@ -47,20 +47,22 @@ fn main() {
     let decoded = deflate.decompress(&compressed);
     assert_eq!(test_sample, decoded);
 
-    let path = Path::new("resources/lorem.txt");
-    let file = match File::open(path) {
-        Ok(f) => f,
-        Err(e) => panic!("{}", e),
-    };
-    let mut test_sample = String::new();
-    let mut reader = BufReader::new(file);
-    if let Err(e) = reader.read_to_string(&mut test_sample) {
-        panic!("{}", e)
-    }
+    // usage: cargo run --example deflate -- [file]
+    if let Some(path) = env::args().nth(1) {
+        let file = match File::open(&path) {
+            Ok(f) => f,
+            Err(e) => panic!("{}", e),
+        };
+        let mut test_sample = String::new();
+        let mut reader = BufReader::new(file);
+        if let Err(e) = reader.read_to_string(&mut test_sample) {
+            panic!("{}", e)
+        }
 
-    let mut deflate = Deflate::new(16, 32);
-    let compressed = deflate.compress(&test_sample);
+        let mut deflate = Deflate::new(16, 32);
+        let compressed = deflate.compress(&test_sample);
 
-    let decoded = deflate.decompress(&compressed);
-    assert_eq!(test_sample, decoded);
+        let decoded = deflate.decompress(&compressed);
+        assert_eq!(test_sample, decoded);
+    }
 }
@@ -1,6 +1,7 @@
 extern crate reducto;
 
 use reducto::lz77::LZ77;
+use std::env;
 use std::fs::File;
 use std::io::{BufReader, Read};
 use std::path::Path;
@@ -54,22 +55,24 @@ fn main() {
     println!("Decoded String: {}", decoded);
     assert_eq!(test_sample, decoded);
 
-    let path = Path::new("resources/lorem.txt");
-    let file = match File::open(path) {
-        Ok(f) => f,
-        Err(e) => panic!("{}", e),
-    };
-    let mut test_sample = String::new();
-    let mut reader = BufReader::new(file);
-    if let Err(e) = reader.read_to_string(&mut test_sample) {
-        panic!("{}", e)
-    }
+    // usage: cargo run --example lz77 -- [file]
+    if let Some(path) = env::args().nth(1) {
+        let file = match File::open(&path) {
+            Ok(f) => f,
+            Err(e) => panic!("{}", e),
+        };
+        let mut test_sample = String::new();
+        let mut reader = BufReader::new(file);
+        if let Err(e) = reader.read_to_string(&mut test_sample) {
+            panic!("{}", e)
+        }
 
-    let mut lz77 = LZ77::new(16, 32);
-    lz77.encode(&test_sample);
+        let mut lz77 = LZ77::new(16, 32);
+        lz77.encode(&test_sample);
 
-    let decoded = lz77.decode();
-    println!("Test String   : {}", test_sample);
-    println!("Decoded String: {}", decoded);
-    assert_eq!(test_sample, decoded);
+        let decoded = lz77.decode();
+        println!("Test String   : {}", test_sample);
+        println!("Decoded String: {}", decoded);
+        assert_eq!(test_sample, decoded);
+    }
 }
@@ -0,0 +1,108 @@
+//! Compressibility report of some bytes
+//!
+//! The statistics only look at the frequency of each byte, so they bound what
+//! an order-0 coder like Huffman can reach. Matches found by LZ77 and its
+//! descendants can compress repetitive data much further.
+use std::fmt;
+
+use crate::counter::DenseCounter;
+
+/// Number of most common bytes kept in the report.
+const MOST_COMMON: usize = 8;
+
+/// Statistics of the bytes of some data, as returned by `analyze`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analysis {
+    /// Number of bytes.
+    pub size: usize,
+    /// Number of distinct bytes.
+    pub distinct: usize,
+    /// Shannon entropy, in bits per byte.
+    pub entropy: f64,
+    /// Smallest size, in bytes, of the data coded byte per byte.
+    pub minimum_size: u64,
+    /// Size, in bytes, of the data coded with a Huffman code of its bytes, without the code itself.
+    pub huffman_size: u64,
+    /// Chi-square statistic against uniform bytes, close to 255 for random data.
+    pub chi_square: f64,
+    /// The most common bytes and their counts, from the most common.
+    pub most_common: Vec<(u8, u64)>,
+}
+
+/// Compute the statistics of the bytes of `data`.
+///
+/// # Arguments
+/// * data - The bytes to analyze
+///
+/// # Examples
+/// ```
+/// # use reducto::analysis::analyze;
+/// let lorem = std::fs::read("resources/lorem.txt").unwrap();
+/// let analysis = analyze(&lorem);
+/// assert_eq!(analysis.size, lorem.len());
+/// assert_eq!(analysis.most_common[0].0, b' ');
+/// assert!(analysis.entropy > 3.0 && analysis.entropy < 5.0);
+/// assert!(analysis.minimum_size <= analysis.huffman_size);
+/// assert!(analysis.huffman_size < lorem.len() as u64 * 5 / 8);
+/// println!("{}", analysis);
+/// ```
+pub fn analyze(data: &[u8]) -> Analysis {
+    let counter: DenseCounter<u8> = data.iter().collect();
+    Analysis {
+        size: data.len(),
+        distinct: counter.len(),
+        entropy: counter.entropy(),
+        minimum_size: (counter.minimum_bits() / 8.0).ceil() as u64,
+        huffman_size: counter.huffman_bits().div_ceil(8),
+        chi_square: counter.chi_square(256),
+        most_common: counter
+            .most_common(MOST_COMMON)
+            .into_iter()
+            .map(|(byte, count)| (*byte, count))
+            .collect(),
+    }
+}
+
+impl Analysis {
+    fn percent(&self, size: u64) -> f64 {
+        match self.size {
+            0 => 0.0,
+            total => 100.0 * size as f64 / total as f64,
+        }
+    }
+}
+
+/// Multiline report, sizes being compared to the size of the data.
+impl fmt::Display for Analysis {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(formatter, "size            {} bytes", self.size)?;
+        writeln!(formatter, "distinct bytes  {}", self.distinct)?;
+        writeln!(formatter, "entropy         {:.4} bits/byte", self.entropy)?;
+        writeln!(
+            formatter,
+            "order-0 bound   {} bytes ({:.1}%)",
+            self.minimum_size,
+            self.percent(self.minimum_size)
+        )?;
+        writeln!(
+            formatter,
+            "huffman         {} bytes ({:.1}%)",
+            self.huffman_size,
+            self.percent(self.huffman_size)
+        )?;
+        writeln!(
+            formatter,
+            "chi-square      {:.1} (about 255 when random)",
+            self.chi_square
+        )?;
+        write!(formatter, "most common    ")?;
+        for (byte, count) in &self.most_common {
+            let shown = match *byte {
+                0x21..=0x7e => format!("'{}'", *byte as char),
+                _ => format!("0x{:02x}", byte),
+            };
+            write!(formatter, " {} {:.1}%", shown, self.percent(*count))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,488 @@
+//! `reducto` command line tool
+//!
+//! Compresses and decompresses files with the codecs of the crate, in the
+//! manner of gzip: `reducto compress file` replaces `file` by `file.gz`, and
+//! `reducto decompress file.gz` restores it, whatever the format of the data.
+extern crate reducto;
+
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use reducto::bzip2::Bzip2;
+use reducto::gzip::Gzip;
+use reducto::lz4::LZ4;
+use reducto::lzma::Lzma;
+use reducto::options::CompressionOptions;
+use reducto::tar::TarArchive;
+use reducto::zip::ZipArchive;
+use reducto::zlib::Zlib;
+use reducto::{analysis, snappy, Format};
+
+const USAGE: &str = "\
+usage: reducto <command> [options] [files...]
+
+commands:
+  compress, c     compress files, or the standard input to the standard output
+  decompress, d   decompress files, detecting their format
+  test, t         check that files decompress
+  list, l         show the format and sizes of files, and the entries of archives
+  analyze, a      show the entropy and byte statistics of files, as they are
+
+options:
+  -a, --algorithm <name>  gzip (default), zlib, bzip2, xz, lzma, lz4 or snappy
+  -0 ... -9, --level <n>  compression level, from fastest to best
+  -T, --threads <n>       threads compressing gzip and zlib blocks, 1 by default
+  -c, --stdout            write to the standard output, keeping the files
+  -k, --keep              keep the input files
+  -f, --force             overwrite existing output files
+  -r, --recursive         process the files of directories
+  -h, --help              show this help
+
+zstd (.zst), brotli (.br) and zip files can be decompressed, tested or listed only.";
+
+/// Extensions of the files of `format` and the ones of their decompressed files.
+fn suffixes(format: Format) -> &'static [(&'static str, &'static str)] {
+    match format {
+        Format::Gzip => &[(".gz", ""), (".tgz", ".tar")],
+        Format::Zlib => &[(".zz", "")],
+        Format::Bzip2 => &[(".bz2", ""), (".tbz2", ".tar"), (".tbz", ".tar")],
+        Format::Xz => &[(".xz", ""), (".txz", ".tar")],
+        Format::Lzma => &[(".lzma", ""), (".tlz", ".tar")],
+        Format::LZ4 => &[(".lz4", "")],
+        Format::Snappy => &[(".sz", "")],
+        Format::Zstd => &[(".zst", ""), (".tzst", ".tar")],
+        Format::Brotli => &[(".br", "")],
+        Format::Zip => &[(".zip", "")],
+    }
+}
+
+fn format_of_path(path: &Path) -> Option<Format> {
+    let name = path.to_string_lossy();
+    Format::ALL.iter().copied().find(|format| {
+        suffixes(*format)
+            .iter()
+            .any(|(suffix, _)| name.ends_with(suffix))
+    })
+}
+
+/// Format of `data` from its magic number, or from the extension of `path` for the formats without one.
+fn detect(data: &[u8], path: Option<&Path>) -> Option<Format> {
+    // raw streams could pass for a zlib header, so their extension goes first
+    match path.and_then(format_of_path) {
+        Some(format @ Format::Lzma) | Some(format @ Format::Brotli) => Some(format),
+        format => reducto::detect(data).or(format),
+    }
+}
+
+/// Output path of `path` decompressed, if it has one of the extensions of `format`.
+fn strip_suffix(format: Format, path: &Path) -> Option<PathBuf> {
+    let name = path.to_string_lossy();
+    suffixes(format)
+        .iter()
+        .find_map(|(suffix, replacement)| match name.strip_suffix(suffix) {
+            Some(stem) if !stem.is_empty() && !stem.ends_with('/') => {
+                Some(PathBuf::from(format!("{}{}", stem, replacement)))
+            }
+            _ => None,
+        })
+}
+
+fn compress_data(
+    format: Format,
+    data: &[u8],
+    level: Option<u8>,
+    threads: usize,
+) -> Result<Vec<u8>, String> {
+    Ok(match format {
+        Format::Gzip => Gzip::new(level.unwrap_or(6))
+            .threads(threads)
+            .compress(data),
+        Format::Zlib => Zlib::new(level.unwrap_or(6))
+            .threads(threads)
+            .compress(data),
+        Format::Bzip2 => Bzip2::new(level.unwrap_or(9).max(1)).compress(data),
+        Format::Xz => Lzma::new(level.unwrap_or(6)).compress_xz(data),
+        Format::Lzma => Lzma::new(level.unwrap_or(6)).compress(data),
+        Format::LZ4 => match level {
+            Some(level) => LZ4::with_options(CompressionOptions::new(level)),
+            None => LZ4::new(),
+        }
+        .compress(data),
+        Format::Snappy => snappy::compress_framed(data),
+        Format::Zstd | Format::Brotli | Format::Zip => {
+            return Err(format!("{} compression is not supported", format.name()))
+        }
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Compress,
+    Decompress,
+    Test,
+    List,
+    Analyze,
+}
+
+#[derive(Debug)]
+struct Options {
+    command: Command,
+    format: Format,
+    level: Option<u8>,
+    threads: usize,
+    stdout: bool,
+    keep: bool,
+    force: bool,
+    recursive: bool,
+    files: Vec<PathBuf>,
+}
+
+/// Parse the arguments, returning `None` when the help is asked.
+fn parse_arguments(arguments: &[String]) -> Result<Option<Options>, String> {
+    let mut command: Option<Command> = None;
+    let mut options = Options {
+        command: Command::Compress,
+        format: Format::Gzip,
+        level: None,
+        threads: 1,
+        stdout: false,
+        keep: false,
+        force: false,
+        recursive: false,
+        files: Vec::new(),
+    };
+    let mut arguments = arguments.iter();
+    let mut only_files = false;
+    while let Some(argument) = arguments.next() {
+        if only_files || argument == "-" || !argument.starts_with('-') {
+            if command.is_none() {
+                command = Some(match argument.as_str() {
+                    "compress" | "c" => Command::Compress,
+                    "decompress" | "d" => Command::Decompress,
+                    "test" | "t" => Command::Test,
+                    "list" | "l" => Command::List,
+                    "analyze" | "a" => Command::Analyze,
+                    _ => return Err(format!("unknown command '{}'", argument)),
+                });
+            } else {
+                options.files.push(PathBuf::from(argument));
+            }
+            continue;
+        }
+        if argument == "--" {
+            only_files = true;
+            continue;
+        }
+
+        let (flags, value): (Vec<String>, Option<&str>) = match argument.strip_prefix("--") {
+            Some(long) => match long.split_once('=') {
+                Some((name, value)) => (vec![name.to_string()], Some(value)),
+                None => (vec![long.to_string()], None),
+            },
+            None => (argument[1..].chars().map(String::from).collect(), None),
+        };
+        let mut value = value.map(String::from);
+        for (index, flag) in flags.iter().enumerate() {
+            let last = index + 1 == flags.len();
+            let mut required_value = || -> Result<String, String> {
+                match (value.take(), last) {
+                    (Some(value), _) => Ok(value),
+                    (None, true) => arguments
+                        .next()
+                        .cloned()
+                        .ok_or_else(|| format!("missing value of '{}'", flag)),
+                    (None, false) => Err(format!("missing value of '{}'", flag)),
+                }
+            };
+            match flag.as_str() {
+                "a" | "algorithm" => {
+                    let name = required_value()?;
+                    options.format = Format::from_name(&name)
+                        .ok_or_else(|| format!("unknown algorithm '{}'", name))?;
+                }
+                "level" => {
+                    let level = required_value()?;
+                    options.level = match level.parse() {
+                        Ok(level) if level <= 9 => Some(level),
+                        _ => return Err(format!("invalid level '{}'", level)),
+                    };
+                }
+                "T" | "threads" => {
+                    let threads = required_value()?;
+                    options.threads = match threads.parse() {
+                        Ok(threads) if threads > 0 => threads,
+                        _ => return Err(format!("invalid thread count '{}'", threads)),
+                    };
+                }
+                digit if digit.len() == 1 && digit.as_bytes()[0].is_ascii_digit() => {
+                    options.level = Some(digit.as_bytes()[0] - b'0');
+                }
+                "c" | "stdout" => options.stdout = true,
+                "k" | "keep" => options.keep = true,
+                "f" | "force" => options.force = true,
+                "r" | "recursive" => options.recursive = true,
+                "h" | "help" => return Ok(None),
+                _ => return Err(format!("unknown option '{}'", argument)),
+            }
+        }
+    }
+    match command {
+        Some(command) => options.command = command,
+        None => return Err("missing command".to_string()),
+    }
+    Ok(Some(options))
+}
+
+/// Files to process, walking the directories when recursive.
+fn collect_files(options: &Options, errors: &mut usize) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    let mut pending: Vec<PathBuf> = options.files.iter().rev().cloned().collect();
+    while let Some(path) = pending.pop() {
+        if path.as_os_str() == "-" || !path.is_dir() {
+            files.push(path);
+            continue;
+        }
+        if !options.recursive {
+            report(&path, "is a directory, use -r to process its files");
+            *errors += 1;
+            continue;
+        }
+        match fs::read_dir(&path).and_then(|entries| entries.collect::<io::Result<Vec<_>>>()) {
+            Ok(entries) => {
+                let mut children: Vec<PathBuf> = entries.iter().map(|entry| entry.path()).collect();
+                children.sort();
+                let skipped = |child: &PathBuf| match options.command {
+                    Command::Compress => {
+                        child.is_file() && strip_suffix(options.format, child).is_some()
+                    }
+                    _ => child.is_file() && format_of_path(child).is_none(),
+                };
+                children.retain(|child| !skipped(child));
+                pending.extend(children.into_iter().rev());
+            }
+            Err(error) => {
+                report(&path, &error.to_string());
+                *errors += 1;
+            }
+        }
+    }
+    files
+}
+
+fn report(path: &Path, message: &str) {
+    eprintln!("reducto: {}: {}", display_name(path), message);
+}
+
+fn display_name(path: &Path) -> String {
+    match path.as_os_str() == "-" {
+        true => "(stdin)".to_string(),
+        false => path.display().to_string(),
+    }
+}
+
+fn read_input(path: &Path) -> io::Result<Vec<u8>> {
+    if path.as_os_str() == "-" {
+        let mut data: Vec<u8> = Vec::new();
+        io::stdin().lock().read_to_end(&mut data)?;
+        return Ok(data);
+    }
+    fs::read(path)
+}
+
+/// Write `data` to the standard output or to `output`, then remove `input` unless kept.
+fn write_output(options: &Options, input: &Path, output: &Path, data: &[u8]) -> Result<(), String> {
+    if options.stdout || input.as_os_str() == "-" {
+        return io::stdout()
+            .lock()
+            .write_all(data)
+            .map_err(|error| error.to_string());
+    }
+    if output.exists() && !options.force {
+        return Err(format!(
+            "{} already exists, use -f to overwrite it",
+            output.display()
+        ));
+    }
+    fs::write(output, data).map_err(|error| format!("{}: {}", output.display(), error))?;
+    if let Ok(metadata) = fs::metadata(input) {
+        // keep the permissions of the original, best effort
+        let _ = fs::set_permissions(output, metadata.permissions());
+    }
+    if !options.keep {
+        fs::remove_file(input).map_err(|error| error.to_string())?;
+    }
+    Ok(())
+}
+
+fn compress(options: &Options, path: &Path) -> Result<(), String> {
+    let format = options.format;
+    if path.as_os_str() != "-" && strip_suffix(format, path).is_some() {
+        return Err(format!("already has the .{} suffix", format.extension()));
+    }
+    let data = read_input(path).map_err(|error| error.to_string())?;
+    let compressed = compress_data(format, &data, options.level, options.threads)?;
+    let mut output = path.as_os_str().to_owned();
+    output.push(".");
+    output.push(format.extension());
+    write_output(options, path, Path::new(&output), &compressed)
+}
+
+/// Decompress `data`, read from `path`, returning its format, unknown for the standard input, and content.
+fn decompress_data(data: &[u8], path: &Path) -> Result<(Option<Format>, Vec<u8>), String> {
+    if path.as_os_str() == "-" {
+        let content = reducto::decompress_any(data).map_err(|error| error.to_string())?;
+        return Ok((None, content));
+    }
+    let format = detect(data, Some(path)).ok_or("unknown format")?;
+    let content = format
+        .decompress(data)
+        .map_err(|error| format!("{}: {}", format.name(), error))?;
+    Ok((Some(format), content))
+}
+
+fn decompress(options: &Options, path: &Path) -> Result<(), String> {
+    let data = read_input(path).map_err(|error| error.to_string())?;
+    let (format, content) = decompress_data(&data, path)?;
+    let output = match format.and_then(|format| strip_suffix(format, path)) {
+        Some(output) => output,
+        None if options.stdout || path.as_os_str() == "-" => PathBuf::new(),
+        None => {
+            return Err(format!(
+                "unknown suffix for a {} file, use -c",
+                format.map_or("compressed", Format::name)
+            ))
+        }
+    };
+    write_output(options, path, &output, &content)
+}
+
+fn test(path: &Path) -> Result<(), String> {
+    let data = read_input(path).map_err(|error| error.to_string())?;
+    if detect(&data, Some(path)) == Some(Format::Zip) {
+        let archive = ZipArchive::new(&data).map_err(|error| error.to_string())?;
+        for entry in archive.entries() {
+            archive
+                .extract(entry)
+                .map_err(|error| format!("{}: {}", entry.name(), error))?;
+        }
+        return Ok(());
+    }
+    decompress_data(&data, path).map(|_| ())
+}
+
+fn ratio(compressed: u64, size: u64) -> String {
+    match size {
+        0 => "-".to_string(),
+        size => format!("{:.1}%", 100.0 * (1.0 - compressed as f64 / size as f64)),
+    }
+}
+
+fn list(path: &Path) -> Result<(), String> {
+    let data = read_input(path).map_err(|error| error.to_string())?;
+    let name = display_name(path);
+    if detect(&data, Some(path)) == Some(Format::Zip) {
+        let archive = ZipArchive::new(&data).map_err(|error| error.to_string())?;
+        println!(
+            "{:>8} {:>12} {:>12} {:>7}  {}",
+            "zip",
+            data.len(),
+            "",
+            "",
+            name
+        );
+        for entry in archive.entries() {
+            println!(
+                "{:>8} {:>12} {:>12} {:>7}    {}",
+                "",
+                entry.compressed_size(),
+                entry.size(),
+                ratio(entry.compressed_size(), entry.size()),
+                entry.name()
+            );
+        }
+        return Ok(());
+    }
+
+    let format = detect(&data, Some(path)).ok_or("unknown format")?;
+    let content = format
+        .decompress(&data)
+        .map_err(|error| format!("{}: {}", format.name(), error))?;
+    let (compressed, size) = (data.len() as u64, content.len() as u64);
+    println!(
+        "{:>8} {:>12} {:>12} {:>7}  {}",
+        format.name(),
+        compressed,
+        size,
+        ratio(compressed, size),
+        name
+    );
+    let is_tar = content.len() >= 512 && &content[257..262] == b"ustar";
+    if let Some(archive) = Some(&content)
+        .filter(|_| is_tar)
+        .and_then(|content| TarArchive::new(content).ok())
+    {
+        for entry in archive.entries() {
+            println!(
+                "{:>8} {:>12} {:>12} {:>7}    {}",
+                "",
+                "",
+                entry.size(),
+                "",
+                entry.path()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn analyze(path: &Path) -> Result<(), String> {
+    let data = read_input(path).map_err(|error| error.to_string())?;
+    println!("{}:\n{}\n", display_name(path), analysis::analyze(&data));
+    Ok(())
+}
+
+fn main() {
+    let arguments: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_arguments(&arguments) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Err(error) => {
+            eprintln!("reducto: {}\n\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let mut errors = 0;
+    let mut files = collect_files(&options, &mut errors);
+    if options.files.is_empty() {
+        files.push(PathBuf::from("-"));
+    }
+    if options.command == Command::List {
+        println!(
+            "{:>8} {:>12} {:>12} {:>7}  name",
+            "format", "compressed", "size", "ratio"
+        );
+    }
+    for path in &files {
+        let result = match options.command {
+            Command::Compress => compress(&options, path),
+            Command::Decompress => decompress(&options, path),
+            Command::Test => test(path),
+            Command::List => list(path),
+            Command::Analyze => analyze(path),
+        };
+        if let Err(error) = result {
+            report(path, &error);
+            errors += 1;
+        }
+    }
+    if errors > 0 {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,423 @@
+//! Bit and byte level helpers shared by the codecs
+use std::io::{Error, ErrorKind, Result};
+
+/// Source of single bits, used by the Huffman decoding tables.
+pub trait BitRead {
+    /// Read one bit, returned as `0` or `1`.
+    fn read_bit(&mut self) -> Result<u32>;
+
+    /// Read `count` bits (at most 32) as an unsigned value.
+    fn read_bits(&mut self, count: u8) -> Result<u32>;
+}
+
+/// Sink of bits, implemented by both bit orders.
+pub trait BitWrite {
+    /// Write the `count` low bits of `value` (at most 32).
+    fn write_bits(&mut self, value: u32, count: u8);
+}
+
+/// Writer packing bits from the most significant bit of each byte.
+///
+/// # Examples
+/// ```
+/// # use reducto::bits::MsbBitWriter;
+/// let mut writer = MsbBitWriter::new();
+/// writer.write_bits(0b101, 3);
+/// writer.write_bits(0b1, 1);
+/// assert_eq!(writer.finish(), vec![0b1011_0000]);
+/// ```
+#[derive(Debug, Default)]
+pub struct MsbBitWriter {
+    buffer: Vec<u8>,
+    accumulator: u64,
+    count: u8,
+}
+
+impl MsbBitWriter {
+    /// Create a new empty `MsbBitWriter`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Write the `count` low bits of `value` (at most 32), most significant first.
+    pub fn write_bits(&mut self, value: u32, count: u8) {
+        assert!(count <= 32);
+        if count == 0 {
+            return;
+        }
+        let mask = (1u64 << count) - 1;
+        self.accumulator = (self.accumulator << count) | (u64::from(value) & mask);
+        self.count += count;
+        while self.count >= 8 {
+            self.count -= 8;
+            self.buffer.push((self.accumulator >> self.count) as u8);
+        }
+    }
+
+    /// Write a single bit.
+    pub fn write_bit(&mut self, bit: bool) {
+        self.write_bits(bit as u32, 1);
+    }
+
+    /// Number of bits written so far.
+    pub fn bit_len(&self) -> usize {
+        self.buffer.len() * 8 + self.count as usize
+    }
+
+    /// Flush the pending bits, padding the last byte with zeros.
+    pub fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.buffer
+                .push((self.accumulator << (8 - self.count)) as u8);
+        }
+        self.buffer
+    }
+}
+
+impl BitWrite for MsbBitWriter {
+    fn write_bits(&mut self, value: u32, count: u8) {
+        MsbBitWriter::write_bits(self, value, count)
+    }
+}
+
+/// Reader of bits packed from the most significant bit of each byte.
+///
+/// # Examples
+/// ```
+/// # use reducto::bits::{BitRead, MsbBitReader};
+/// let data = [0b1011_0000];
+/// let mut reader = MsbBitReader::new(&data);
+/// assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+/// assert_eq!(reader.read_bit().unwrap(), 1);
+/// ```
+#[derive(Debug)]
+pub struct MsbBitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> MsbBitReader<'a> {
+    /// Create a reader over `data`, starting at its first bit.
+    pub fn new(data: &'a [u8]) -> Self {
+        MsbBitReader { data, position: 0 }
+    }
+
+    /// Number of bits consumed so far.
+    pub fn bit_position(&self) -> usize {
+        self.position
+    }
+
+    /// Skip to the start of the next byte.
+    pub fn align(&mut self) {
+        self.position = (self.position + 7) & !7;
+    }
+}
+
+impl<'a> BitRead for MsbBitReader<'a> {
+    fn read_bit(&mut self) -> Result<u32> {
+        let byte = match self.data.get(self.position >> 3) {
+            Some(byte) => *byte,
+            None => return Err(unexpected_end()),
+        };
+        let bit = (byte >> (7 - (self.position & 7))) & 1;
+        self.position += 1;
+        Ok(u32::from(bit))
+    }
+
+    fn read_bits(&mut self, count: u8) -> Result<u32> {
+        assert!(count <= 32);
+        let mut value: u32 = 0;
+        for _ in 0..count {
+            value = (value << 1) | self.read_bit()?;
+        }
+        Ok(value)
+    }
+}
+
+/// Writer packing bits from the least significant bit of each byte.
+///
+/// # Examples
+/// ```
+/// # use reducto::bits::LsbBitWriter;
+/// let mut writer = LsbBitWriter::new();
+/// writer.write_bits(0b101, 3);
+/// writer.write_bits(0b1, 1);
+/// assert_eq!(writer.finish(), vec![0b0000_1101]);
+/// ```
+#[derive(Debug, Default)]
+pub struct LsbBitWriter {
+    buffer: Vec<u8>,
+    accumulator: u64,
+    count: u8,
+}
+
+impl LsbBitWriter {
+    /// Create a new empty `LsbBitWriter`.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Write the `count` low bits of `value` (at most 32), least significant first.
+    pub fn write_bits(&mut self, value: u32, count: u8) {
+        assert!(count <= 32);
+        if count == 0 {
+            return;
+        }
+        let mask = (1u64 << count) - 1;
+        self.accumulator |= (u64::from(value) & mask) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.buffer.push(self.accumulator as u8);
+            self.accumulator >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Write a single bit.
+    pub fn write_bit(&mut self, bit: bool) {
+        self.write_bits(bit as u32, 1);
+    }
+
+    /// Number of bits written so far.
+    pub fn bit_len(&self) -> usize {
+        self.buffer.len() * 8 + self.count as usize
+    }
+
+    /// Pad the current byte with zeros.
+    pub fn align(&mut self) {
+        if self.count > 0 {
+            self.write_bits(0, 8 - self.count);
+        }
+    }
+
+    /// Flush the pending bits, padding the last byte with zeros.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.align();
+        self.buffer
+    }
+}
+
+impl BitWrite for LsbBitWriter {
+    fn write_bits(&mut self, value: u32, count: u8) {
+        LsbBitWriter::write_bits(self, value, count)
+    }
+}
+
+/// Reader of bits packed from the least significant bit of each byte.
+///
+/// # Examples
+/// ```
+/// # use reducto::bits::{BitRead, LsbBitReader};
+/// let data = [0b0000_1101];
+/// let mut reader = LsbBitReader::new(&data);
+/// assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+/// assert_eq!(reader.read_bit().unwrap(), 1);
+/// ```
+#[derive(Debug)]
+pub struct LsbBitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> LsbBitReader<'a> {
+    /// Create a reader over `data`, starting at its first bit.
+    pub fn new(data: &'a [u8]) -> Self {
+        LsbBitReader { data, position: 0 }
+    }
+
+    /// Number of bits consumed so far.
+    pub fn bit_position(&self) -> usize {
+        self.position
+    }
+
+    /// Skip to the start of the next byte.
+    pub fn align(&mut self) {
+        self.position = (self.position + 7) & !7;
+    }
+
+    /// Read `count` whole bytes, the reader being aligned on a byte.
+    pub fn read_bytes(&mut self, count: usize) -> Result<&'a [u8]> {
+        assert!(self.position & 7 == 0);
+        let start = self.position >> 3;
+        match self.data.get(start..start + count) {
+            Some(bytes) => {
+                self.position += count << 3;
+                Ok(bytes)
+            }
+            None => Err(unexpected_end()),
+        }
+    }
+}
+
+impl<'a> BitRead for LsbBitReader<'a> {
+    fn read_bit(&mut self) -> Result<u32> {
+        let byte = match self.data.get(self.position >> 3) {
+            Some(byte) => *byte,
+            None => return Err(unexpected_end()),
+        };
+        let bit = (byte >> (self.position & 7)) & 1;
+        self.position += 1;
+        Ok(u32::from(bit))
+    }
+
+    fn read_bits(&mut self, count: u8) -> Result<u32> {
+        assert!(count <= 32);
+        let mut value: u32 = 0;
+        for shift in 0..count {
+            value |= self.read_bit()? << shift;
+        }
+        Ok(value)
+    }
+}
+
+/// Reader of a bitstream written least significant bit first and read back from its end.
+///
+/// The highest set bit of the last byte marks where the stream starts. Bits
+/// are then returned from the most significant down, reading past the
+/// beginning yields zeros.
+///
+/// # Examples
+/// ```
+/// # use reducto::bits::{BackwardBitReader, BitRead};
+/// let data = [0b0000_1101];
+/// let mut reader = BackwardBitReader::new(&data).unwrap();
+/// assert_eq!(reader.read_bits(2).unwrap(), 0b10);
+/// assert_eq!(reader.read_bit().unwrap(), 1);
+/// assert!(reader.is_finished());
+/// ```
+#[derive(Debug)]
+pub struct BackwardBitReader<'a> {
+    data: &'a [u8],
+    position: isize,
+}
+
+impl<'a> BackwardBitReader<'a> {
+    /// Create a reader over `data`, starting below the padding marker of its last byte.
+    pub fn new(data: &'a [u8]) -> Result<Self> {
+        match data.last() {
+            Some(last) if *last != 0 => {
+                let marker = (data.len() * 8 - 1) as isize - last.leading_zeros() as isize;
+                Ok(BackwardBitReader {
+                    data,
+                    position: marker,
+                })
+            }
+            _ => Err(invalid_data("bitstream without padding marker")),
+        }
+    }
+
+    /// Look at the next `count` bits (at most 32) without consuming them.
+    pub fn peek_bits(&self, count: u8) -> u32 {
+        assert!(count <= 32);
+        if count == 0 || self.position <= 0 {
+            return 0;
+        }
+        let start = (self.position - count as isize).max(0) as usize;
+        let available = self.position as usize - start;
+        let window = self.data[start >> 3..]
+            .iter()
+            .take(5)
+            .enumerate()
+            .fold(0u64, |window, (index, byte)| {
+                window | u64::from(*byte) << (8 * index)
+            });
+        let value = (window >> (start & 7)) & ((1u64 << available) - 1);
+        (value << (count as usize - available)) as u32
+    }
+
+    /// Consume `count` bits.
+    pub fn consume(&mut self, count: u8) {
+        self.position -= count as isize;
+    }
+
+    /// Whether every bit has been read, and no more.
+    pub fn is_finished(&self) -> bool {
+        self.position == 0
+    }
+
+    /// Whether more bits than available have been read.
+    pub fn is_overflowed(&self) -> bool {
+        self.position < 0
+    }
+}
+
+impl<'a> BitRead for BackwardBitReader<'a> {
+    fn read_bit(&mut self) -> Result<u32> {
+        self.read_bits(1)
+    }
+
+    fn read_bits(&mut self, count: u8) -> Result<u32> {
+        let value = self.peek_bits(count);
+        self.consume(count);
+        Ok(value)
+    }
+}
+
+/// Error returned when a stream stops in the middle of a value.
+pub(crate) fn unexpected_end() -> Error {
+    Error::new(ErrorKind::UnexpectedEof, "unexpected end of data")
+}
+
+/// Error returned when a stream does not follow its format.
+pub(crate) fn invalid_data(reason: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, reason.to_string())
+}
+
+/// Split the first `count` bytes off `data`.
+pub(crate) fn take<'a>(data: &mut &'a [u8], count: usize) -> Result<&'a [u8]> {
+    if data.len() < count {
+        return Err(unexpected_end());
+    }
+    let (head, tail) = data.split_at(count);
+    *data = tail;
+    Ok(head)
+}
+
+/// Value of the little endian `bytes`, at most 8 of them.
+pub(crate) fn read_le(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |value, byte| value << 8 | u64::from(*byte))
+}
+
+/// Little endian `u16` at the start of `bytes`.
+pub(crate) fn read_u16(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+/// Little endian `u32` at the start of `bytes`.
+pub(crate) fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Little endian `u64` at the start of `bytes`.
+pub(crate) fn read_u64(bytes: &[u8]) -> u64 {
+    u64::from(read_u32(bytes)) | u64::from(read_u32(&bytes[4..])) << 32
+}
+
+/// Append `value` as an unsigned LEB128 varint.
+pub(crate) fn write_varint(output: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        output.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+/// Read an unsigned LEB128 varint, advancing `data` past it.
+pub(crate) fn read_varint(data: &mut &[u8]) -> Result<u64> {
+    let mut value: u64 = 0;
+    for shift in (0..64).step_by(7) {
+        let (byte, rest) = match data.split_first() {
+            Some((byte, rest)) => (*byte, rest),
+            None => return Err(unexpected_end()),
+        };
+        *data = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid_data("varint is too long"))
+}
@@ -0,0 +1,854 @@
+//! Brotli decoder
+//!
+//! Decodes streams described by RFC 7932. After the window size, a stream is
+//! a sequence of meta-blocks, either stored, metadata to skip or compressed.
+//! A compressed meta-block interleaves insert-and-copy commands, literals and
+//! distances, each coded with prefix codes picked by block types and, for
+//! literals and distances, by a context map.
+//!
+//! Copies reaching further back than the decoded data refer to words of the
+//! built-in static dictionary, altered by one of 121 transforms.
+use std::cmp::min;
+use std::io::Result;
+
+use crate::bits::{invalid_data, BitRead, LsbBitReader};
+use crate::huffman::HuffmanTable;
+
+/// Words of the static dictionary, grouped by length from 4 to 24 bytes.
+const DICTIONARY: &[u8] = include_bytes!("../resources/brotli/dictionary.bin");
+const MIN_WORD_LENGTH: usize = 4;
+const MAX_WORD_LENGTH: usize = 24;
+/// Offset of the words of every length in the dictionary.
+const DICTIONARY_OFFSETS: [u32; 25] = [
+    0, 0, 0, 0, 0, 4096, 9216, 21504, 35840, 44032, 53248, 63488, 74752, 87040, 93696, 100864,
+    104704, 106752, 108928, 113536, 115968, 118528, 119872, 121280, 122016,
+];
+/// Base 2 logarithm of the number of words of every length.
+const DICTIONARY_SIZE_BITS: [u8; 25] = [
+    0, 0, 0, 0, 10, 10, 11, 11, 10, 10, 10, 10, 10, 9, 9, 8, 7, 7, 8, 7, 7, 6, 6, 5, 5,
+];
+
+const LITERAL_ALPHABET_SIZE: usize = 256;
+const INSERT_AND_COPY_ALPHABET_SIZE: usize = 704;
+const BLOCK_COUNT_ALPHABET_SIZE: usize = 26;
+const MAX_CODE_LENGTH_CODES: usize = 18;
+/// Order in which the code length code lengths are stored.
+const CODE_LENGTH_ORDER: [usize; MAX_CODE_LENGTH_CODES] =
+    [1, 2, 3, 4, 0, 5, 17, 6, 16, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+const REPEAT_PREVIOUS_LENGTH: u8 = 16;
+const DEFAULT_CODE_LENGTH: u8 = 8;
+
+const BLOCK_COUNT_BASES: [u32; 26] = [
+    1, 5, 9, 13, 17, 25, 33, 41, 49, 65, 81, 97, 113, 145, 177, 209, 241, 305, 369, 497, 753, 1265,
+    2289, 4337, 8433, 16625,
+];
+const BLOCK_COUNT_BITS: [u8; 26] = [
+    2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 6, 6, 7, 8, 9, 10, 11, 12, 13, 24,
+];
+const INSERT_LENGTH_BASES: [u32; 24] = [
+    0, 1, 2, 3, 4, 5, 6, 8, 10, 14, 18, 26, 34, 50, 66, 98, 130, 194, 322, 578, 1090, 2114, 6210,
+    22594,
+];
+const INSERT_LENGTH_BITS: [u8; 24] = [
+    0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 7, 8, 9, 10, 12, 14, 24,
+];
+const COPY_LENGTH_BASES: [u32; 24] = [
+    2, 3, 4, 5, 6, 7, 8, 9, 10, 12, 14, 18, 22, 30, 38, 54, 70, 102, 134, 198, 326, 582, 1094, 2118,
+];
+const COPY_LENGTH_BITS: [u8; 24] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 7, 8, 9, 10, 24,
+];
+/// Insert and copy length ranges of every cell of 64 insert-and-copy symbols.
+const INSERT_AND_COPY_CELLS: [(usize, usize); 11] = [
+    (0, 0),
+    (0, 1),
+    (0, 0),
+    (0, 1),
+    (1, 0),
+    (1, 1),
+    (0, 2),
+    (2, 0),
+    (1, 2),
+    (2, 1),
+    (2, 2),
+];
+/// Symbols below this one reuse the last distance without reading a distance code.
+const IMPLICIT_DISTANCE_SYMBOLS: usize = 128;
+/// Distance codes of the ring buffer: index of the last distance used and delta.
+const DISTANCE_SHORT_CODES: [(usize, isize); 16] = [
+    (0, 0),
+    (1, 0),
+    (2, 0),
+    (3, 0),
+    (0, -1),
+    (0, 1),
+    (0, -2),
+    (0, 2),
+    (0, -3),
+    (0, 3),
+    (1, -1),
+    (1, 1),
+    (1, -2),
+    (1, 2),
+    (1, -3),
+    (1, 3),
+];
+const INITIAL_DISTANCES: [usize; 4] = [4, 11, 15, 16];
+
+const CONTEXT_LSB6: u8 = 0;
+const CONTEXT_MSB6: u8 = 1;
+const CONTEXT_UTF8: u8 = 2;
+const LITERAL_CONTEXTS: usize = 64;
+const DISTANCE_CONTEXTS: usize = 4;
+
+/// Context of the last byte in the UTF-8 mode.
+const UTF8_LOOKUP_1: [u8; 256] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 4, 4, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    8, 12, 16, 12, 12, 20, 12, 16, 24, 28, 12, 12, 32, 12, 36, 12, 44, 44, 44, 44, 44, 44, 44, 44,
+    44, 44, 32, 32, 24, 40, 28, 12, 12, 48, 52, 52, 52, 48, 52, 52, 52, 48, 52, 52, 52, 52, 52, 48,
+    52, 52, 52, 52, 52, 48, 52, 52, 52, 52, 52, 24, 12, 28, 12, 12, 12, 56, 60, 60, 60, 56, 60, 60,
+    60, 56, 60, 60, 60, 60, 60, 56, 60, 60, 60, 60, 60, 56, 60, 60, 60, 60, 60, 24, 12, 28, 12, 0,
+    0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1,
+    0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1,
+    2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3,
+    2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3, 2, 3,
+];
+/// Context of the byte before the last one in the UTF-8 mode.
+const UTF8_LOOKUP_2: [u8; 256] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1,
+    1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1,
+    1, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 1, 1, 1, 1, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2,
+];
+/// Class of a byte in the signed mode.
+const SIGNED_LOOKUP: [u8; 256] = [
+    0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2,
+    2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2,
+    3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+    3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3,
+    4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
+    4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
+    5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+    5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 6, 7,
+];
+
+/// Alteration of a dictionary word, between its prefix and suffix.
+#[derive(Debug, Clone, Copy)]
+enum Transform {
+    Identity,
+    OmitLast(usize),
+    UppercaseFirst,
+    UppercaseAll,
+    OmitFirst(usize),
+}
+
+/// Prefix, transformation and suffix of every dictionary word transform.
+const TRANSFORMS: [(&[u8], Transform, &[u8]); 121] = [
+    (b"", Transform::Identity, b""),
+    (b"", Transform::Identity, b" "),
+    (b" ", Transform::Identity, b" "),
+    (b"", Transform::OmitFirst(1), b""),
+    (b"", Transform::UppercaseFirst, b" "),
+    (b"", Transform::Identity, b" the "),
+    (b" ", Transform::Identity, b""),
+    (b"s ", Transform::Identity, b" "),
+    (b"", Transform::Identity, b" of "),
+    (b"", Transform::UppercaseFirst, b""),
+    (b"", Transform::Identity, b" and "),
+    (b"", Transform::OmitFirst(2), b""),
+    (b"", Transform::OmitLast(1), b""),
+    (b", ", Transform::Identity, b" "),
+    (b"", Transform::Identity, b", "),
+    (b" ", Transform::UppercaseFirst, b" "),
+    (b"", Transform::Identity, b" in "),
+    (b"", Transform::Identity, b" to "),
+    (b"e ", Transform::Identity, b" "),
+    (b"", Transform::Identity, b"\""),
+    (b"", Transform::Identity, b"."),
+    (b"", Transform::Identity, b"\">"),
+    (b"", Transform::Identity, b"\n"),
+    (b"", Transform::OmitLast(3), b""),
+    (b"", Transform::Identity, b"]"),
+    (b"", Transform::Identity, b" for "),
+    (b"", Transform::OmitFirst(3), b""),
+    (b"", Transform::OmitLast(2), b""),
+    (b"", Transform::Identity, b" a "),
+    (b"", Transform::Identity, b" that "),
+    (b" ", Transform::UppercaseFirst, b""),
+    (b"", Transform::Identity, b". "),
+    (b".", Transform::Identity, b""),
+    (b" ", Transform::Identity, b", "),
+    (b"", Transform::OmitFirst(4), b""),
+    (b"", Transform::Identity, b" with "),
+    (b"", Transform::Identity, b"'"),
+    (b"", Transform::Identity, b" from "),
+    (b"", Transform::Identity, b" by "),
+    (b"", Transform::OmitFirst(5), b""),
+    (b"", Transform::OmitFirst(6), b""),
+    (b" the ", Transform::Identity, b""),
+    (b"", Transform::OmitLast(4), b""),
+    (b"", Transform::Identity, b". The "),
+    (b"", Transform::UppercaseAll, b""),
+    (b"", Transform::Identity, b" on "),
+    (b"", Transform::Identity, b" as "),
+    (b"", Transform::Identity, b" is "),
+    (b"", Transform::OmitLast(7), b""),
+    (b"", Transform::OmitLast(1), b"ing "),
+    (b"", Transform::Identity, b"\n\t"),
+    (b"", Transform::Identity, b":"),
+    (b" ", Transform::Identity, b". "),
+    (b"", Transform::Identity, b"ed "),
+    (b"", Transform::OmitFirst(9), b""),
+    (b"", Transform::OmitFirst(7), b""),
+    (b"", Transform::OmitLast(6), b""),
+    (b"", Transform::Identity, b"("),
+    (b"", Transform::UppercaseFirst, b", "),
+    (b"", Transform::OmitLast(8), b""),
+    (b"", Transform::Identity, b" at "),
+    (b"", Transform::Identity, b"ly "),
+    (b" the ", Transform::Identity, b" of "),
+    (b"", Transform::OmitLast(5), b""),
+    (b"", Transform::OmitLast(9), b""),
+    (b" ", Transform::UppercaseFirst, b", "),
+    (b"", Transform::UppercaseFirst, b"\""),
+    (b".", Transform::Identity, b"("),
+    (b"", Transform::UppercaseAll, b" "),
+    (b"", Transform::UppercaseFirst, b"\">"),
+    (b"", Transform::Identity, b"=\""),
+    (b" ", Transform::Identity, b"."),
+    (b".com/", Transform::Identity, b""),
+    (b" the ", Transform::Identity, b" of the "),
+    (b"", Transform::UppercaseFirst, b"'"),
+    (b"", Transform::Identity, b". This "),
+    (b"", Transform::Identity, b","),
+    (b".", Transform::Identity, b" "),
+    (b"", Transform::UppercaseFirst, b"("),
+    (b"", Transform::UppercaseFirst, b"."),
+    (b"", Transform::Identity, b" not "),
+    (b" ", Transform::Identity, b"=\""),
+    (b"", Transform::Identity, b"er "),
+    (b" ", Transform::UppercaseAll, b" "),
+    (b"", Transform::Identity, b"al "),
+    (b" ", Transform::UppercaseAll, b""),
+    (b"", Transform::Identity, b"='"),
+    (b"", Transform::UppercaseAll, b"\""),
+    (b"", Transform::UppercaseFirst, b". "),
+    (b" ", Transform::Identity, b"("),
+    (b"", Transform::Identity, b"ful "),
+    (b" ", Transform::UppercaseFirst, b". "),
+    (b"", Transform::Identity, b"ive "),
+    (b"", Transform::Identity, b"less "),
+    (b"", Transform::UppercaseAll, b"'"),
+    (b"", Transform::Identity, b"est "),
+    (b" ", Transform::UppercaseFirst, b"."),
+    (b"", Transform::UppercaseAll, b"\">"),
+    (b" ", Transform::Identity, b"='"),
+    (b"", Transform::UppercaseFirst, b","),
+    (b"", Transform::Identity, b"ize "),
+    (b"", Transform::UppercaseAll, b"."),
+    (b"\xc2\xa0", Transform::Identity, b""),
+    (b" ", Transform::Identity, b","),
+    (b"", Transform::UppercaseFirst, b"=\""),
+    (b"", Transform::UppercaseAll, b"=\""),
+    (b"", Transform::Identity, b"ous "),
+    (b"", Transform::UppercaseAll, b", "),
+    (b"", Transform::UppercaseFirst, b"='"),
+    (b" ", Transform::UppercaseFirst, b","),
+    (b" ", Transform::UppercaseAll, b"=\""),
+    (b" ", Transform::UppercaseAll, b", "),
+    (b"", Transform::UppercaseAll, b","),
+    (b"", Transform::UppercaseAll, b"("),
+    (b"", Transform::UppercaseAll, b". "),
+    (b" ", Transform::UppercaseAll, b"."),
+    (b"", Transform::UppercaseAll, b"='"),
+    (b" ", Transform::UppercaseAll, b". "),
+    (b" ", Transform::UppercaseFirst, b"=\""),
+    (b" ", Transform::UppercaseAll, b"='"),
+    (b" ", Transform::UppercaseFirst, b"='"),
+];
+
+/// Flip the case of the character starting at `word[index]` the way Brotli does, and
+/// return its length.
+fn uppercase(word: &mut [u8], index: usize) -> usize {
+    if word[index] < 0xc0 {
+        if word[index].is_ascii_lowercase() {
+            word[index] ^= 0x20;
+        }
+        1
+    } else if word[index] < 0xe0 {
+        if index + 1 < word.len() {
+            word[index + 1] ^= 0x20;
+        }
+        2
+    } else {
+        if index + 2 < word.len() {
+            word[index + 2] ^= 0x05;
+        }
+        3
+    }
+}
+
+/// Append the dictionary `word` altered by the transform `transform_id` to `output`.
+fn transform_word(word: &[u8], transform_id: usize, output: &mut Vec<u8>) {
+    let (prefix, transform, suffix) = TRANSFORMS[transform_id];
+    output.extend_from_slice(prefix);
+    let start = output.len();
+    match transform {
+        Transform::OmitLast(count) => {
+            output.extend_from_slice(&word[..word.len().saturating_sub(count)]);
+        }
+        Transform::OmitFirst(count) => output.extend_from_slice(&word[min(count, word.len())..]),
+        _ => output.extend_from_slice(word),
+    }
+    let transformed = &mut output[start..];
+    match transform {
+        Transform::UppercaseFirst if !transformed.is_empty() => {
+            uppercase(transformed, 0);
+        }
+        Transform::UppercaseAll => {
+            let mut index = 0;
+            while index < transformed.len() {
+                index += uppercase(transformed, index);
+            }
+        }
+        _ => {}
+    }
+    output.extend_from_slice(suffix);
+}
+
+/// Prefix code of an alphabet, whose only symbol takes no bit when it is alone.
+#[derive(Debug)]
+enum PrefixCode {
+    Single(u16),
+    Table(HuffmanTable),
+}
+
+impl PrefixCode {
+    fn decode(&self, reader: &mut LsbBitReader) -> Result<u16> {
+        match self {
+            PrefixCode::Single(symbol) => Ok(*symbol),
+            PrefixCode::Table(table) => table.decode(reader),
+        }
+    }
+
+    /// Read a prefix code in its simple or complex form.
+    ///
+    /// # Arguments
+    /// * reader - Reader of the stream
+    /// * alphabet_size - Number of symbols of the alphabet
+    fn read(reader: &mut LsbBitReader, alphabet_size: usize) -> Result<PrefixCode> {
+        let skipped = reader.read_bits(2)? as usize;
+        let mut lengths: Vec<u8> = vec![0; alphabet_size];
+        if skipped == 1 {
+            let symbol_bits = (usize::BITS - (alphabet_size - 1).leading_zeros()) as u8;
+            let count = reader.read_bits(2)? as usize + 1;
+            let mut symbols: Vec<usize> = Vec::with_capacity(count);
+            for _ in 0..count {
+                let symbol = reader.read_bits(symbol_bits)? as usize;
+                if symbol >= alphabet_size || symbols.contains(&symbol) {
+                    return Err(invalid_data("invalid Brotli simple prefix code"));
+                }
+                symbols.push(symbol);
+            }
+            let symbol_lengths: &[u8] = match count {
+                1 => return Ok(PrefixCode::Single(symbols[0] as u16)),
+                2 => &[1, 1],
+                3 => &[1, 2, 2],
+                _ if reader.read_bit()? == 0 => &[2, 2, 2, 2],
+                _ => &[1, 2, 3, 3],
+            };
+            for (symbol, length) in symbols.iter().zip(symbol_lengths) {
+                lengths[*symbol] = *length;
+            }
+            return Ok(PrefixCode::Table(HuffmanTable::new(&lengths)?));
+        }
+
+        let mut code_length_lengths = [0u8; MAX_CODE_LENGTH_CODES];
+        let mut space: i32 = 32;
+        let mut used = 0;
+        for symbol in &CODE_LENGTH_ORDER[skipped..] {
+            let length = read_code_length_length(reader)?;
+            code_length_lengths[*symbol] = length;
+            if length > 0 {
+                space -= 32 >> length;
+                used += 1;
+                if space <= 0 {
+                    break;
+                }
+            }
+        }
+        if used != 1 && space != 0 {
+            return Err(invalid_data("invalid Brotli code length code"));
+        }
+        let code_length_code = match used {
+            1 => {
+                let symbol = code_length_lengths.iter().position(|l| *l > 0).unwrap_or(0);
+                PrefixCode::Single(symbol as u16)
+            }
+            _ => PrefixCode::Table(HuffmanTable::new(&code_length_lengths)?),
+        };
+
+        let mut space: i32 = 1 << 15;
+        let mut previous_length = DEFAULT_CODE_LENGTH;
+        let mut repeat: usize = 0;
+        let mut repeat_length: u8 = 0;
+        let mut symbol: usize = 0;
+        while symbol < alphabet_size && space > 0 {
+            let length = code_length_code.decode(reader)? as u8;
+            if length < REPEAT_PREVIOUS_LENGTH {
+                repeat = 0;
+                lengths[symbol] = length;
+                symbol += 1;
+                if length > 0 {
+                    previous_length = length;
+                    space -= (1 << 15) >> length;
+                }
+                continue;
+            }
+
+            let (extra_bits, new_length) = match length {
+                REPEAT_PREVIOUS_LENGTH => (2, previous_length),
+                _ => (3, 0),
+            };
+            if repeat_length != new_length {
+                repeat = 0;
+                repeat_length = new_length;
+            }
+            // consecutive repeat codes extend the previous run
+            let previous_repeat = repeat;
+            if repeat > 0 {
+                repeat = (repeat - 2) << extra_bits;
+            }
+            repeat += reader.read_bits(extra_bits)? as usize + 3;
+            let delta = repeat - previous_repeat;
+            if symbol + delta > alphabet_size {
+                return Err(invalid_data("Brotli code lengths exceed the alphabet"));
+            }
+            lengths[symbol..symbol + delta].fill(repeat_length);
+            symbol += delta;
+            if repeat_length > 0 {
+                space -= (delta as i32) << (15 - repeat_length);
+            }
+        }
+        if space != 0 {
+            return Err(invalid_data("incomplete Brotli prefix code"));
+        }
+        Ok(PrefixCode::Table(HuffmanTable::new(&lengths)?))
+    }
+}
+
+/// Read the length of a code length code, stored with a fixed code of 2 to 4 bits.
+fn read_code_length_length(reader: &mut LsbBitReader) -> Result<u8> {
+    Ok(match reader.read_bits(2)? {
+        0 => 0,
+        1 => 4,
+        2 => 3,
+        _ if reader.read_bit()? == 0 => 2,
+        _ if reader.read_bit()? == 0 => 1,
+        _ => 5,
+    })
+}
+
+/// Read a number from 0 to 255 stored on 1 to 11 bits.
+fn read_small_number(reader: &mut LsbBitReader) -> Result<usize> {
+    if reader.read_bit()? == 0 {
+        return Ok(0);
+    }
+    let bits = reader.read_bits(3)? as u8;
+    Ok((1 << bits) + reader.read_bits(bits)? as usize)
+}
+
+/// Read a number of prefix codes and the context map assigning them to contexts.
+///
+/// # Arguments
+/// * reader - Reader of the stream
+/// * size - Number of contexts of all the block types
+fn read_context_map(reader: &mut LsbBitReader, size: usize) -> Result<(usize, Vec<u8>)> {
+    let trees = read_small_number(reader)? + 1;
+    let mut map: Vec<u8> = vec![0; size];
+    if trees < 2 {
+        return Ok((trees, map));
+    }
+
+    let max_run_prefix = match reader.read_bit()? {
+        0 => 0,
+        _ => reader.read_bits(4)? as usize + 1,
+    };
+    let code = PrefixCode::read(reader, trees + max_run_prefix)?;
+    let mut index: usize = 0;
+    while index < size {
+        let symbol = code.decode(reader)? as usize;
+        if symbol == 0 || symbol > max_run_prefix {
+            map[index] = symbol.saturating_sub(max_run_prefix) as u8;
+            index += 1;
+            continue;
+        }
+        let run = (1 << symbol) + reader.read_bits(symbol as u8)? as usize;
+        if index + run > size {
+            return Err(invalid_data("Brotli context map run is too long"));
+        }
+        // the map is already filled with zeros
+        index += run;
+    }
+
+    // inverse move-to-front transform
+    if reader.read_bit()? == 1 {
+        let mut order: Vec<u8> = (0..=255).collect();
+        for value in map.iter_mut() {
+            let position = *value as usize;
+            *value = order[position];
+            order[..=position].rotate_right(1);
+        }
+    }
+    Ok((trees, map))
+}
+
+/// Read a block count coded with `code` and its extra bits.
+fn read_block_count(reader: &mut LsbBitReader, code: &PrefixCode) -> Result<usize> {
+    let symbol = code.decode(reader)? as usize;
+    let extra = reader.read_bits(BLOCK_COUNT_BITS[symbol])?;
+    Ok((BLOCK_COUNT_BASES[symbol] + extra) as usize)
+}
+
+/// Block types of one category: literals, insert-and-copy commands or distances.
+#[derive(Debug)]
+struct BlockTypes {
+    count: usize,
+    codes: Option<(PrefixCode, PrefixCode)>,
+    current: usize,
+    previous: usize,
+    remaining: usize,
+}
+
+impl BlockTypes {
+    fn read(reader: &mut LsbBitReader) -> Result<BlockTypes> {
+        let count = read_small_number(reader)? + 1;
+        let mut types = BlockTypes {
+            count,
+            codes: None,
+            current: 0,
+            previous: 1,
+            remaining: usize::MAX,
+        };
+        if count >= 2 {
+            let type_code = PrefixCode::read(reader, count + 2)?;
+            let count_code = PrefixCode::read(reader, BLOCK_COUNT_ALPHABET_SIZE)?;
+            types.remaining = read_block_count(reader, &count_code)?;
+            types.codes = Some((type_code, count_code));
+        }
+        Ok(types)
+    }
+
+    /// Type of the next symbol, switching to the next block when the current one is over.
+    fn next(&mut self, reader: &mut LsbBitReader) -> Result<usize> {
+        if self.remaining == 0 {
+            let (type_code, count_code) = match &self.codes {
+                Some(codes) => codes,
+                None => return Err(invalid_data("Brotli block switch without block types")),
+            };
+            let next = match type_code.decode(reader)? as usize {
+                0 => self.previous,
+                1 => (self.current + 1) % self.count,
+                symbol => symbol - 2,
+            };
+            self.previous = self.current;
+            self.current = next;
+            self.remaining = read_block_count(reader, count_code)?;
+        }
+        self.remaining -= 1;
+        Ok(self.current)
+    }
+}
+
+/// Context of a literal from the two bytes before it.
+fn literal_context(mode: u8, last: u8, before_last: u8) -> usize {
+    let context = match mode {
+        CONTEXT_LSB6 => last & 0x3f,
+        CONTEXT_MSB6 => last >> 2,
+        CONTEXT_UTF8 => UTF8_LOOKUP_1[last as usize] | UTF8_LOOKUP_2[before_last as usize],
+        _ => SIGNED_LOOKUP[last as usize] << 3 | SIGNED_LOOKUP[before_last as usize],
+    };
+    context as usize
+}
+
+/// Decoder state kept from one meta-block to the next.
+#[derive(Debug)]
+struct Decoder<'a> {
+    reader: LsbBitReader<'a>,
+    window_size: usize,
+    output: Vec<u8>,
+    /// Last distances, the most recent first.
+    distances: [usize; 4],
+}
+
+impl<'a> Decoder<'a> {
+    /// Read the window size from the stream header.
+    fn new(data: &'a [u8]) -> Result<Decoder<'a>> {
+        let mut reader = LsbBitReader::new(data);
+        let window_bits = if reader.read_bit()? == 0 {
+            16
+        } else {
+            match reader.read_bits(3)? {
+                0 => match reader.read_bits(3)? {
+                    0 => 17,
+                    1 => return Err(invalid_data("invalid Brotli window size")),
+                    bits => 8 + bits,
+                },
+                bits => 17 + bits,
+            }
+        };
+        Ok(Decoder {
+            reader,
+            window_size: (1 << window_bits) - 16,
+            output: Vec::new(),
+            distances: INITIAL_DISTANCES,
+        })
+    }
+
+    /// Read `count` nibbles or bytes of a little endian value.
+    ///
+    /// The last part cannot be zero when there are more than `minimum` parts.
+    fn read_length(&mut self, count: usize, bits: u8, minimum: usize) -> Result<usize> {
+        let mut value: usize = 0;
+        for index in 0..count {
+            let part = self.reader.read_bits(bits)? as usize;
+            if index + 1 == count && count > minimum && part == 0 {
+                return Err(invalid_data("Brotli length has a useless last part"));
+            }
+            value |= part << (index * bits as usize);
+        }
+        Ok(value)
+    }
+
+    /// Skip to the next byte, whose padding bits must be zero.
+    fn align(&mut self) -> Result<()> {
+        while !self.reader.bit_position().is_multiple_of(8) {
+            if self.reader.read_bit()? != 0 {
+                return Err(invalid_data("invalid Brotli padding"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Decode one meta-block and return whether it was the last one.
+    fn read_meta_block(&mut self) -> Result<bool> {
+        let last = self.reader.read_bit()? == 1;
+        if last && self.reader.read_bit()? == 1 {
+            return Ok(true);
+        }
+        let nibbles = match self.reader.read_bits(2)? {
+            3 => 0,
+            nibbles => 4 + nibbles as usize,
+        };
+        if nibbles == 0 {
+            if self.reader.read_bit()? != 0 {
+                return Err(invalid_data("invalid Brotli reserved bit"));
+            }
+            let bytes = self.reader.read_bits(2)? as usize;
+            let skipped = match bytes {
+                0 => 0,
+                _ => self.read_length(bytes, 8, 1)? + 1,
+            };
+            self.align()?;
+            self.reader.read_bytes(skipped)?;
+            return Ok(last);
+        }
+
+        let length = self.read_length(nibbles, 4, 4)? + 1;
+        if !last && self.reader.read_bit()? == 1 {
+            self.align()?;
+            let stored = self.reader.read_bytes(length)?;
+            self.output.extend_from_slice(stored);
+            return Ok(false);
+        }
+        self.read_compressed(length)?;
+        Ok(last)
+    }
+
+    /// Decode a compressed meta-block of `length` bytes.
+    fn read_compressed(&mut self, length: usize) -> Result<()> {
+        let reader = &mut self.reader;
+        let mut literal_types = BlockTypes::read(reader)?;
+        let mut command_types = BlockTypes::read(reader)?;
+        let mut distance_types = BlockTypes::read(reader)?;
+
+        let postfix_bits = reader.read_bits(2)? as usize;
+        let direct_codes = (reader.read_bits(4)? as usize) << postfix_bits;
+        let postfix_mask = (1 << postfix_bits) - 1;
+        let mut context_modes: Vec<u8> = Vec::with_capacity(literal_types.count);
+        for _ in 0..literal_types.count {
+            context_modes.push(reader.read_bits(2)? as u8);
+        }
+        let (literal_trees, literal_map) =
+            read_context_map(reader, LITERAL_CONTEXTS * literal_types.count)?;
+        let (distance_trees, distance_map) =
+            read_context_map(reader, DISTANCE_CONTEXTS * distance_types.count)?;
+
+        let literal_codes = (0..literal_trees)
+            .map(|_| PrefixCode::read(reader, LITERAL_ALPHABET_SIZE))
+            .collect::<Result<Vec<PrefixCode>>>()?;
+        let command_codes = (0..command_types.count)
+            .map(|_| PrefixCode::read(reader, INSERT_AND_COPY_ALPHABET_SIZE))
+            .collect::<Result<Vec<PrefixCode>>>()?;
+        let distance_alphabet_size = 16 + direct_codes + (48 << postfix_bits);
+        let distance_codes = (0..distance_trees)
+            .map(|_| PrefixCode::read(reader, distance_alphabet_size))
+            .collect::<Result<Vec<PrefixCode>>>()?;
+
+        let end = self.output.len() + length;
+        while self.output.len() < end {
+            let command_type = command_types.next(reader)?;
+            let symbol = command_codes[command_type].decode(reader)? as usize;
+            let (insert_range, copy_range) = INSERT_AND_COPY_CELLS[symbol >> 6];
+            let insert_code = insert_range * 8 + ((symbol >> 3) & 7);
+            let copy_code = copy_range * 8 + (symbol & 7);
+            let insert_length = (INSERT_LENGTH_BASES[insert_code]
+                + reader.read_bits(INSERT_LENGTH_BITS[insert_code])?)
+                as usize;
+            let copy_length = (COPY_LENGTH_BASES[copy_code]
+                + reader.read_bits(COPY_LENGTH_BITS[copy_code])?)
+                as usize;
+
+            if self.output.len() + insert_length > end {
+                return Err(invalid_data("Brotli meta-block is longer than announced"));
+            }
+            for _ in 0..insert_length {
+                let literal_type = literal_types.next(reader)?;
+                let last = self.output.last().copied().unwrap_or(0);
+                let before_last = match self.output.len() {
+                    0 | 1 => 0,
+                    size => self.output[size - 2],
+                };
+                let context = literal_context(context_modes[literal_type], last, before_last);
+                let tree = literal_map[literal_type * LITERAL_CONTEXTS + context] as usize;
+                self.output.push(literal_codes[tree].decode(reader)? as u8);
+            }
+            if self.output.len() == end {
+                break;
+            }
+
+            let distance_code = if symbol < IMPLICIT_DISTANCE_SYMBOLS {
+                0
+            } else {
+                let distance_type = distance_types.next(reader)?;
+                let context = min(copy_length - 2, DISTANCE_CONTEXTS - 1);
+                let tree = distance_map[distance_type * DISTANCE_CONTEXTS + context] as usize;
+                distance_codes[tree].decode(reader)? as usize
+            };
+            let distance = if distance_code < 16 {
+                let (index, delta) = DISTANCE_SHORT_CODES[distance_code];
+                let distance = self.distances[index] as isize + delta;
+                if distance <= 0 {
+                    return Err(invalid_data("invalid Brotli distance"));
+                }
+                distance as usize
+            } else if distance_code < 16 + direct_codes {
+                distance_code - 15
+            } else {
+                let code = distance_code - 16 - direct_codes;
+                let extra_bits = 1 + (code >> (postfix_bits + 1));
+                let extra = reader.read_bits(extra_bits as u8)? as usize;
+                let offset = ((2 + ((code >> postfix_bits) & 1)) << extra_bits) - 4;
+                ((offset + extra) << postfix_bits) + (code & postfix_mask) + direct_codes + 1
+            };
+
+            let max_distance = min(self.window_size, self.output.len());
+            if distance > max_distance {
+                // beyond the decoded data, the distance picks a word of the static dictionary
+                if !(MIN_WORD_LENGTH..=MAX_WORD_LENGTH).contains(&copy_length) {
+                    return Err(invalid_data("invalid Brotli dictionary word length"));
+                }
+                let word_id = distance - max_distance - 1;
+                let bits = DICTIONARY_SIZE_BITS[copy_length];
+                let index = word_id & ((1 << bits) - 1);
+                let transform_id = word_id >> bits;
+                if transform_id >= TRANSFORMS.len() {
+                    return Err(invalid_data("invalid Brotli dictionary transform"));
+                }
+                let offset = DICTIONARY_OFFSETS[copy_length] as usize + index * copy_length;
+                let word = &DICTIONARY[offset..offset + copy_length];
+                transform_word(word, transform_id, &mut self.output);
+                if self.output.len() > end {
+                    return Err(invalid_data("Brotli meta-block is longer than announced"));
+                }
+                continue;
+            }
+
+            if self.output.len() + copy_length > end {
+                return Err(invalid_data("Brotli meta-block is longer than announced"));
+            }
+            if distance_code != 0 {
+                self.distances = [
+                    distance,
+                    self.distances[0],
+                    self.distances[1],
+                    self.distances[2],
+                ];
+            }
+            let start = self.output.len() - distance;
+            if distance >= copy_length {
+                self.output.extend_from_within(start..start + copy_length);
+            } else {
+                for index in start..start + copy_length {
+                    self.output.push(self.output[index]);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Decompress a Brotli stream.
+///
+/// # Examples
+/// ```
+/// # use reducto::brotli;
+/// // "hello hello hello hello" compressed by the reference encoder
+/// let compressed = [
+///     0x1b, 0x16, 0x00, 0xf8, 0x8d, 0x94, 0x6e, 0xde, 0x44, 0x55, 0x86, 0x96, 0x6c, 0x20, 0x6f,
+///     0x21, 0x4f, 0x1c, 0xe0, 0x38,
+/// ];
+/// assert_eq!(brotli::decompress(&compressed).unwrap(), b"hello hello hello hello");
+///
+/// let expected = std::fs::read("resources/lorem.txt").unwrap();
+/// let vector = std::fs::read("resources/brotli/lorem.txt.br").unwrap();
+/// assert_eq!(brotli::decompress(&vector).unwrap(), expected);
+///
+/// // metadata and stored meta-blocks
+/// let expected = std::fs::read("resources/brotli/stored.txt").unwrap();
+/// let vector = std::fs::read("resources/brotli/stored.br").unwrap();
+/// assert_eq!(brotli::decompress(&vector).unwrap(), expected);
+///
+/// // cases of the tests/testdata directory of the reference implementation
+/// let directory = std::path::Path::new("resources/brotli/testdata");
+/// let mut cases = 0;
+/// for entry in std::fs::read_dir(directory).unwrap() {
+///     let path = entry.unwrap().path();
+///     let name = path.file_name().unwrap().to_str().unwrap().to_string();
+///     let original = match name.find(".compressed") {
+///         Some(end) => name[..end].to_string(),
+///         None => continue,
+///     };
+///     let expected = match original.as_str() {
+///         "alice29.txt" => std::fs::read("resources/alice29.txt").unwrap(),
+///         _ => std::fs::read(directory.join(&original)).unwrap(),
+///     };
+///     let vector = std::fs::read(&path).unwrap();
+///     assert_eq!(brotli::decompress(&vector).unwrap(), expected, "{}", name);
+///     cases += 1;
+/// }
+/// assert_eq!(cases, 25);
+/// ```
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut decoder = Decoder::new(data)?;
+    while !decoder.read_meta_block()? {}
+    decoder.align()?;
+    if decoder.reader.bit_position() / 8 != data.len() {
+        return Err(invalid_data("unexpected data after the Brotli stream"));
+    }
+    Ok(decoder.output)
+}
@@ -0,0 +1,330 @@
+//! Burrows–Wheeler transform
+//!
+//! The rotations of each block are sorted through a suffix array built in
+//! linear time with SA-IS (Nong, Zhang & Chan), instead of sorting them directly.
+use std::io::Result;
+
+use crate::bits::{invalid_data, read_varint, unexpected_end, write_varint};
+use crate::pipeline::{Stage, StageKind};
+
+/// Block size of `bzip2 -9`.
+pub const DEFAULT_BLOCK_SIZE: usize = 900_000;
+
+const EMPTY: usize = usize::MAX;
+
+/// Build the suffix array of `data`.
+///
+/// A suffix that is a prefix of another one is sorted first.
+///
+/// # Examples
+/// ```
+/// # use reducto::bwt::suffix_array;
+/// assert_eq!(suffix_array(b"banana"), vec![5, 3, 1, 0, 4, 2]);
+/// ```
+pub fn suffix_array(data: &[u8]) -> Vec<usize> {
+    // shift the alphabet to append a unique smallest sentinel
+    let mut text: Vec<usize> = data.iter().map(|byte| *byte as usize + 1).collect();
+    text.push(0);
+    let mut suffixes = sais(&text, 257);
+    suffixes.remove(0);
+    suffixes
+}
+
+/// SA-IS over `text`, whose last symbol must be a unique `0`.
+fn sais(text: &[usize], alphabet_size: usize) -> Vec<usize> {
+    let length = text.len();
+    if length == 1 {
+        return vec![0];
+    }
+
+    // `true` for S-type suffixes, `false` for L-type ones
+    let mut types: Vec<bool> = vec![false; length];
+    types[length - 1] = true;
+    for i in (0..length - 1).rev() {
+        types[i] = text[i] < text[i + 1] || (text[i] == text[i + 1] && types[i + 1]);
+    }
+    let is_lms = |i: usize| i > 0 && types[i] && !types[i - 1];
+
+    let mut buckets: Vec<usize> = vec![0; alphabet_size];
+    for symbol in text {
+        buckets[*symbol] += 1;
+    }
+
+    // 1. sort the LMS substrings
+    let lms_positions: Vec<usize> = (1..length).filter(|i| is_lms(*i)).collect();
+    let mut suffixes: Vec<usize> = vec![EMPTY; length];
+    let mut tails = bucket_tails(&buckets);
+    for position in lms_positions.iter().rev() {
+        tails[text[*position]] -= 1;
+        suffixes[tails[text[*position]]] = *position;
+    }
+    induce(text, &types, &buckets, &mut suffixes);
+
+    // 2. name them, equal substrings share a name
+    let mut names: Vec<usize> = vec![EMPTY; length];
+    let mut name: usize = 0;
+    let mut previous: usize = EMPTY;
+    for position in suffixes.iter().copied().filter(|p| is_lms(*p)) {
+        if previous != EMPTY && !lms_substrings_equal(text, &types, previous, position) {
+            name += 1;
+        }
+        names[position] = name;
+        previous = position;
+    }
+    let reduced: Vec<usize> = lms_positions.iter().map(|p| names[*p]).collect();
+
+    // 3. sort the LMS suffixes, recursing while names are not unique
+    let reduced_suffixes: Vec<usize> = if name + 1 < reduced.len() {
+        sais(&reduced, name + 1)
+    } else {
+        let mut reduced_suffixes: Vec<usize> = vec![0; reduced.len()];
+        for (index, name) in reduced.iter().enumerate() {
+            reduced_suffixes[*name] = index;
+        }
+        reduced_suffixes
+    };
+
+    // 4. induce the whole suffix array from the sorted LMS suffixes
+    suffixes.iter_mut().for_each(|suffix| *suffix = EMPTY);
+    let mut tails = bucket_tails(&buckets);
+    for index in reduced_suffixes.iter().rev() {
+        let position = lms_positions[*index];
+        tails[text[position]] -= 1;
+        suffixes[tails[text[position]]] = position;
+    }
+    induce(text, &types, &buckets, &mut suffixes);
+    suffixes
+}
+
+fn bucket_heads(buckets: &[usize]) -> Vec<usize> {
+    let mut sum: usize = 0;
+    buckets
+        .iter()
+        .map(|size| {
+            sum += size;
+            sum - size
+        })
+        .collect()
+}
+
+fn bucket_tails(buckets: &[usize]) -> Vec<usize> {
+    let mut sum: usize = 0;
+    buckets
+        .iter()
+        .map(|size| {
+            sum += size;
+            sum
+        })
+        .collect()
+}
+
+fn induce(text: &[usize], types: &[bool], buckets: &[usize], suffixes: &mut [usize]) {
+    let mut heads = bucket_heads(buckets);
+    for i in 0..suffixes.len() {
+        let position = suffixes[i];
+        if position != EMPTY && position > 0 && !types[position - 1] {
+            let symbol = text[position - 1];
+            suffixes[heads[symbol]] = position - 1;
+            heads[symbol] += 1;
+        }
+    }
+
+    let mut tails = bucket_tails(buckets);
+    for i in (0..suffixes.len()).rev() {
+        let position = suffixes[i];
+        if position != EMPTY && position > 0 && types[position - 1] {
+            let symbol = text[position - 1];
+            tails[symbol] -= 1;
+            suffixes[tails[symbol]] = position - 1;
+        }
+    }
+}
+
+fn lms_substrings_equal(text: &[usize], types: &[bool], first: usize, second: usize) -> bool {
+    let last = text.len() - 1;
+    if first == last || second == last {
+        // the sentinel is unique
+        return false;
+    }
+    let is_lms = |i: usize| i > 0 && types[i] && !types[i - 1];
+    let mut i: usize = 0;
+    loop {
+        let first_end = is_lms(first + i);
+        let second_end = is_lms(second + i);
+        if i > 0 && first_end && second_end {
+            return true;
+        }
+        if first_end != second_end
+            || text[first + i] != text[second + i]
+            || types[first + i] != types[second + i]
+        {
+            return false;
+        }
+        i += 1;
+    }
+}
+
+/// Transform one block, returning the last column of its sorted rotations and the primary index.
+///
+/// The primary index is the row of the block itself among its sorted rotations.
+///
+/// # Arguments
+/// * block - The bytes to transform
+///
+/// # Examples
+/// ```
+/// # use reducto::bwt;
+/// let (transformed, primary_index) = bwt::forward(b"banana");
+/// assert_eq!(transformed, b"nnbaaa");
+/// assert_eq!(primary_index, 3);
+/// assert_eq!(bwt::inverse(&transformed, primary_index).unwrap(), b"banana");
+/// ```
+pub fn forward(block: &[u8]) -> (Vec<u8>, usize) {
+    let length = block.len();
+    if length == 0 {
+        return (Vec::new(), 0);
+    }
+
+    // the first `length` bytes of a suffix of the doubled block are a rotation
+    let mut doubled: Vec<u8> = Vec::with_capacity(2 * length);
+    doubled.extend_from_slice(block);
+    doubled.extend_from_slice(block);
+
+    let mut transformed: Vec<u8> = Vec::with_capacity(length);
+    let mut primary_index: usize = 0;
+    for rotation in suffix_array(&doubled)
+        .into_iter()
+        .filter(|suffix| *suffix < length)
+    {
+        if rotation == 0 {
+            primary_index = transformed.len();
+        }
+        transformed.push(block[(rotation + length - 1) % length]);
+    }
+    (transformed, primary_index)
+}
+
+/// Undo `forward` from the last column and the primary index.
+///
+/// # Arguments
+/// * transformed - Last column of the sorted rotations
+/// * primary_index - Row of the original block
+pub fn inverse(transformed: &[u8], primary_index: usize) -> Result<Vec<u8>> {
+    let length = transformed.len();
+    if length == 0 {
+        return Ok(Vec::new());
+    }
+    if primary_index >= length {
+        return Err(invalid_data("BWT primary index out of bounds"));
+    }
+
+    let mut starts: Vec<usize> = vec![0; 256];
+    for byte in transformed {
+        starts[*byte as usize] += 1;
+    }
+    let mut sum: usize = 0;
+    for start in starts.iter_mut() {
+        sum += *start;
+        *start = sum - *start;
+    }
+
+    // last-to-first mapping: row of the rotation starting one byte earlier
+    let mut last_to_first: Vec<usize> = vec![0; length];
+    for (row, byte) in transformed.iter().enumerate() {
+        last_to_first[row] = starts[*byte as usize];
+        starts[*byte as usize] += 1;
+    }
+
+    let mut block: Vec<u8> = vec![0; length];
+    let mut row = primary_index;
+    for byte in block.iter_mut().rev() {
+        *byte = transformed[row];
+        row = last_to_first[row];
+    }
+    Ok(block)
+}
+
+/// Block-wise Burrows–Wheeler transform.
+///
+/// Each block is written as its primary index (varint) followed by its last column.
+///
+/// # Examples
+/// ```
+/// # use reducto::bwt::Bwt;
+/// # use reducto::huffman::Huffman;
+/// # use reducto::pipeline::{Pipeline, Stage};
+/// let bwt = Bwt::new(4096);
+/// let transformed = bwt.forward(b"abracadabra abracadabra");
+/// assert_eq!(bwt.inverse(&transformed).unwrap(), b"abracadabra abracadabra");
+///
+/// let pipeline = Pipeline::new().stage(Bwt::new(4096)).stage(Huffman::new());
+/// let compressed = pipeline.compress(b"abracadabra abracadabra");
+/// assert_eq!(Pipeline::decompress(&compressed).unwrap(), b"abracadabra abracadabra");
+/// ```
+#[derive(Debug)]
+pub struct Bwt {
+    block_size: usize,
+}
+
+impl Bwt {
+    /// Create a new `Bwt` splitting its input in blocks of `block_size` bytes.
+    pub fn new(block_size: usize) -> Bwt {
+        assert_ne!(block_size, 0);
+        Bwt { block_size }
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+}
+
+impl Default for Bwt {
+    fn default() -> Self {
+        Bwt::new(DEFAULT_BLOCK_SIZE)
+    }
+}
+
+impl Stage for Bwt {
+    fn kind(&self) -> StageKind {
+        StageKind::Bwt
+    }
+
+    fn parameters(&self) -> Vec<u8> {
+        let mut parameters: Vec<u8> = Vec::new();
+        write_varint(&mut parameters, self.block_size as u64);
+        parameters
+    }
+
+    fn from_parameters(parameters: &[u8]) -> Result<Self> {
+        let mut parameters = parameters;
+        match read_varint(&mut parameters)? as usize {
+            0 => Err(invalid_data("invalid BWT block size")),
+            block_size => Ok(Bwt::new(block_size)),
+        }
+    }
+
+    fn forward(&self, data: &[u8]) -> Vec<u8> {
+        let mut output: Vec<u8> = Vec::with_capacity(data.len() + 8);
+        for block in data.chunks(self.block_size) {
+            let (transformed, primary_index) = forward(block);
+            write_varint(&mut output, primary_index as u64);
+            output.extend(transformed);
+        }
+        output
+    }
+
+    fn inverse(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut output: Vec<u8> = Vec::with_capacity(data.len());
+        let mut rest = data;
+        while !rest.is_empty() {
+            let primary_index = read_varint(&mut rest)? as usize;
+            if rest.is_empty() {
+                return Err(unexpected_end());
+            }
+            let length = rest.len().min(self.block_size);
+            output.extend(inverse(&rest[..length], primary_index)?);
+            rest = &rest[length..];
+        }
+        Ok(output)
+    }
+}
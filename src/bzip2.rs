@@ -0,0 +1,577 @@
+//! bzip2 compressor and decompressor
+//!
+//! Reads and writes `.bz2` streams: each block goes through an initial run
+//! length encoding, the Burrows–Wheeler transform, move-to-front, zero-run
+//! length encoding and finally two to six Huffman tables chosen every 50
+//! symbols by a selector.
+use std::io::Result;
+
+use crate::bits::{invalid_data, unexpected_end, BitRead, MsbBitReader, MsbBitWriter};
+use crate::huffman::{canonical_codes, code_lengths, HuffmanTable};
+use crate::options::CompressionOptions;
+use crate::rle::Symbol;
+use crate::{bwt, mtf, rle};
+
+const STREAM_MAGIC: &[u8; 3] = b"BZh";
+const BLOCK_MAGIC: u64 = 0x3141_5926_5359;
+const END_OF_STREAM_MAGIC: u64 = 0x1772_4538_5090;
+
+/// Number of symbols coded with the same table.
+const GROUP_SIZE: usize = 50;
+const MAX_GROUPS: usize = 6;
+const MAX_SELECTORS: usize = 18002;
+/// Longest code written by the compressor, decoders accept up to 20 bits.
+const MAX_CODE_LENGTH: u8 = 17;
+const MAX_DECODE_CODE_LENGTH: u32 = 20;
+const REFINEMENT_PASSES: usize = 4;
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// CRC-32 of bzip2: the IEEE polynomial, fed from the most significant bit.
+fn block_crc(data: &[u8]) -> u32 {
+    !data.iter().fold(u32::MAX, |crc, byte| {
+        (crc << 8) ^ CRC_TABLE[((crc >> 24) ^ u32::from(*byte)) as usize]
+    })
+}
+
+fn combine_crc(combined: u32, block: u32) -> u32 {
+    combined.rotate_left(1) ^ block
+}
+
+/// bzip2 codec.
+///
+/// # Examples
+/// ```
+/// # use reducto::bzip2::Bzip2;
+/// let bzip2 = Bzip2::new(9);
+/// let compressed = bzip2.compress(b"hello hello hello hello");
+/// assert_eq!(&compressed[..4], b"BZh9");
+/// assert_eq!(bzip2.decompress(&compressed).unwrap(), b"hello hello hello hello");
+/// ```
+#[derive(Debug)]
+pub struct Bzip2 {
+    level: u8,
+}
+
+impl Bzip2 {
+    /// Create a new `Bzip2` using blocks of `level` × 100 kB.
+    ///
+    /// # Arguments
+    /// * level - Block size level, from 1 to 9
+    pub fn new(level: u8) -> Bzip2 {
+        assert!((1..=9).contains(&level));
+        Bzip2 { level }
+    }
+
+    /// Create a new `Bzip2` tuned by `options`.
+    ///
+    /// The level is the block size level, 0 being taken as 1, unless the
+    /// block size is set, which is rounded up to a multiple of 100 kB and
+    /// reduced to 900 kB when larger. The other knobs are ignored.
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::bzip2::Bzip2;
+    /// # use reducto::options::CompressionOptions;
+    /// let bzip2 = Bzip2::with_options(CompressionOptions::fast());
+    /// assert_eq!(&bzip2.compress(b"hello")[..4], b"BZh1");
+    /// let bzip2 = Bzip2::with_options(CompressionOptions::fast().block_size(250_000));
+    /// assert_eq!(&bzip2.compress(b"hello")[..4], b"BZh3");
+    /// ```
+    pub fn with_options(options: CompressionOptions) -> Bzip2 {
+        let level = match options.block_size {
+            Some(block_size) => block_size.div_ceil(100_000).min(9) as u8,
+            None => options.level.max(1),
+        };
+        Bzip2::new(level)
+    }
+
+    /// Largest block after the initial run length encoding.
+    fn max_block_size(&self) -> usize {
+        self.level as usize * 100_000 - 19
+    }
+
+    /// Compress `data` into a single `.bz2` stream.
+    pub fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut writer = MsbBitWriter::new();
+        for byte in STREAM_MAGIC {
+            writer.write_bits(u32::from(*byte), 8);
+        }
+        writer.write_bits(u32::from(b'0' + self.level), 8);
+
+        let mut combined_crc: u32 = 0;
+        let mut rest = data;
+        while !rest.is_empty() {
+            let (block, consumed) = initial_run_length_encode(rest, self.max_block_size());
+            let crc = block_crc(&rest[..consumed]);
+            combined_crc = combine_crc(combined_crc, crc);
+            write_block(&mut writer, &block, crc);
+            rest = &rest[consumed..];
+        }
+
+        write_u48(&mut writer, END_OF_STREAM_MAGIC);
+        writer.write_bits(combined_crc, 32);
+        writer.finish()
+    }
+
+    /// Decompress every concatenated `.bz2` stream of `data`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::bzip2::Bzip2;
+    /// // the 32 bits following every 48 bits `magic` of `stream`, which are not byte aligned
+    /// fn crcs(stream: &[u8], magic: u64) -> Vec<u32> {
+    ///     let bit = |index: usize| stream[index / 8] >> (7 - index % 8) & 1;
+    ///     let mut window: u64 = 0;
+    ///     let mut crcs = Vec::new();
+    ///     for index in 0..stream.len() * 8 - 32 {
+    ///         window = (window << 1 | u64::from(bit(index))) & 0xffff_ffff_ffff;
+    ///         if window == magic {
+    ///             let crc = (index + 1..index + 33)
+    ///                 .fold(0, |crc, next| crc << 1 | u32::from(bit(next)));
+    ///             crcs.push(crc);
+    ///         }
+    ///     }
+    ///     crcs
+    /// }
+    /// let (block_magic, end_magic) = (0x3141_5926_5359, 0x1772_4538_5090);
+    ///
+    /// // `bzip2 -9`, a single block, and `bzip2 -1`, two blocks of 100 kB
+    /// for (name, level, blocks) in [("lorem.txt", 9, 1), ("alice29.txt", 1, 2)] {
+    ///     let data = std::fs::read(format!("resources/{}", name)).unwrap();
+    ///     let compressed = std::fs::read(format!("resources/bzip2/{}.bz2", name)).unwrap();
+    ///     let bzip2 = Bzip2::new(level);
+    ///     assert_eq!(bzip2.decompress(&compressed).unwrap(), data);
+    ///
+    ///     // same block and stream CRCs as bzip2
+    ///     let encoded = bzip2.compress(&data);
+    ///     assert_eq!(crcs(&encoded, block_magic), crcs(&compressed, block_magic));
+    ///     assert_eq!(crcs(&encoded, block_magic).len(), blocks);
+    ///     assert_eq!(crcs(&encoded, end_magic), crcs(&compressed, end_magic));
+    /// }
+    /// ```
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut output: Vec<u8> = Vec::new();
+        let mut reader = MsbBitReader::new(data);
+        loop {
+            read_stream(&mut reader, &mut output)?;
+            reader.align();
+            if reader.bit_position() / 8 >= data.len() {
+                return Ok(output);
+            }
+        }
+    }
+}
+
+impl Default for Bzip2 {
+    fn default() -> Self {
+        Bzip2::new(9)
+    }
+}
+
+fn write_u48(writer: &mut MsbBitWriter, value: u64) {
+    writer.write_bits((value >> 24) as u32, 24);
+    writer.write_bits((value & 0xff_ffff) as u32, 24);
+}
+
+fn read_u48(reader: &mut MsbBitReader) -> Result<u64> {
+    let high = u64::from(reader.read_bits(24)?);
+    let low = u64::from(reader.read_bits(24)?);
+    Ok(high << 24 | low)
+}
+
+/// Replace runs of 4 to 255 bytes by 4 bytes and a repeat count, until `max_size` bytes are written.
+///
+/// Returns the encoded block and the number of bytes of `data` it holds.
+fn initial_run_length_encode(data: &[u8], max_size: usize) -> (Vec<u8>, usize) {
+    let mut block: Vec<u8> = Vec::with_capacity(max_size.min(data.len()));
+    let mut position: usize = 0;
+    while position < data.len() {
+        let byte = data[position];
+        let run = data[position..]
+            .iter()
+            .take(255)
+            .take_while(|value| **value == byte)
+            .count();
+        if run >= 4 {
+            if block.len() + 5 > max_size {
+                break;
+            }
+            block.extend(&[byte, byte, byte, byte, (run - 4) as u8]);
+        } else {
+            if block.len() + run > max_size {
+                break;
+            }
+            block.resize(block.len() + run, byte);
+        }
+        position += run;
+    }
+    (block, position)
+}
+
+fn initial_run_length_decode(block: &[u8], output: &mut Vec<u8>) {
+    let mut run: usize = 0;
+    let mut previous: Option<u8> = None;
+    let mut bytes = block.iter();
+    while let Some(byte) = bytes.next() {
+        output.push(*byte);
+        if previous == Some(*byte) {
+            run += 1;
+        } else {
+            run = 1;
+            previous = Some(*byte);
+        }
+        if run == 4 {
+            if let Some(count) = bytes.next() {
+                output.resize(output.len() + *count as usize, *byte);
+            }
+            run = 0;
+            previous = None;
+        }
+    }
+}
+
+fn write_block(writer: &mut MsbBitWriter, block: &[u8], crc: u32) {
+    let (transformed, primary_index) = bwt::forward(block);
+
+    // map the bytes in use to a compact alphabet before the move-to-front
+    let mut in_use = [false; 256];
+    for byte in &transformed {
+        in_use[*byte as usize] = true;
+    }
+    let mut compact = [0u8; 256];
+    let mut in_use_count: usize = 0;
+    for byte in 0..256 {
+        if in_use[byte] {
+            compact[byte] = in_use_count as u8;
+            in_use_count += 1;
+        }
+    }
+    let ranks = mtf::encode(
+        &transformed
+            .iter()
+            .map(|byte| compact[*byte as usize])
+            .collect::<Vec<u8>>(),
+    );
+    let alphabet_size = in_use_count + 2;
+    let end_of_block = (in_use_count + 1) as u16;
+    let mut symbols: Vec<u16> = rle::encode(&ranks)
+        .into_iter()
+        .map(Symbol::to_u16)
+        .collect();
+    symbols.push(end_of_block);
+
+    write_u48(writer, BLOCK_MAGIC);
+    writer.write_bits(crc, 32);
+    writer.write_bit(false); // not randomised
+    writer.write_bits(primary_index as u32, 24);
+
+    for range in 0..16 {
+        writer.write_bit(in_use[range * 16..range * 16 + 16].iter().any(|u| *u));
+    }
+    for range in 0..16 {
+        if in_use[range * 16..range * 16 + 16].iter().any(|u| *u) {
+            for used in &in_use[range * 16..range * 16 + 16] {
+                writer.write_bit(*used);
+            }
+        }
+    }
+
+    let (lengths, selectors) = build_tables(&symbols, alphabet_size);
+    writer.write_bits(lengths.len() as u32, 3);
+    writer.write_bits(selectors.len() as u32, 15);
+    let mut order: Vec<u8> = (0..lengths.len() as u8).collect();
+    for selector in &selectors {
+        let rank = match order.iter().position(|table| table == selector) {
+            Some(rank) => rank,
+            None => unreachable!(),
+        };
+        for _ in 0..rank {
+            writer.write_bit(true);
+        }
+        writer.write_bit(false);
+        order[..=rank].rotate_right(1);
+    }
+
+    for table in &lengths {
+        let mut current = table[0];
+        writer.write_bits(u32::from(current), 5);
+        for length in table {
+            while current < *length {
+                writer.write_bits(0b10, 2);
+                current += 1;
+            }
+            while current > *length {
+                writer.write_bits(0b11, 2);
+                current -= 1;
+            }
+            writer.write_bit(false);
+        }
+    }
+
+    let codes: Vec<Vec<u32>> = lengths.iter().map(|table| canonical_codes(table)).collect();
+    for (group, selector) in symbols.chunks(GROUP_SIZE).zip(&selectors) {
+        let table = *selector as usize;
+        for symbol in group {
+            writer.write_bits(
+                codes[table][*symbol as usize],
+                lengths[table][*symbol as usize],
+            );
+        }
+    }
+}
+
+/// Choose the code lengths of each table and the table used by each group of symbols.
+fn build_tables(symbols: &[u16], alphabet_size: usize) -> (Vec<Vec<u8>>, Vec<u8>) {
+    let table_count = match symbols.len() {
+        0..=199 => 2,
+        200..=599 => 3,
+        600..=1199 => 4,
+        1200..=2399 => 5,
+        _ => MAX_GROUPS,
+    };
+
+    let mut frequencies: Vec<u64> = vec![0; alphabet_size];
+    for symbol in symbols {
+        frequencies[*symbol as usize] += 1;
+    }
+
+    // start with tables covering consecutive ranges of symbols of similar total frequency
+    let mut costs: Vec<Vec<u8>> = vec![vec![0; alphabet_size]; table_count];
+    let mut remaining: u64 = symbols.len() as u64;
+    let mut start: usize = 0;
+    for part in (1..=table_count).rev() {
+        let target = remaining / part as u64;
+        let mut end = start;
+        let mut accumulated: u64 = 0;
+        while accumulated < target && end < alphabet_size {
+            accumulated += frequencies[end];
+            end += 1;
+        }
+        if end > start + 1 && part != table_count && part != 1 && (table_count - part) % 2 == 1 {
+            end -= 1;
+            accumulated -= frequencies[end];
+        }
+        for (symbol, cost) in costs[part - 1].iter_mut().enumerate() {
+            *cost = if symbol >= start && symbol < end {
+                0
+            } else {
+                15
+            };
+        }
+        start = end;
+        remaining -= accumulated;
+    }
+
+    let mut selectors: Vec<u8> = Vec::new();
+    for _ in 0..REFINEMENT_PASSES {
+        let mut table_frequencies: Vec<Vec<u64>> = vec![vec![0; alphabet_size]; table_count];
+        selectors.clear();
+        for group in symbols.chunks(GROUP_SIZE) {
+            let mut best_table: usize = 0;
+            let mut best_cost: u32 = u32::MAX;
+            for (table, lengths) in costs.iter().enumerate() {
+                let cost: u32 = group
+                    .iter()
+                    .map(|symbol| u32::from(lengths[*symbol as usize]))
+                    .sum();
+                if cost < best_cost {
+                    best_cost = cost;
+                    best_table = table;
+                }
+            }
+            selectors.push(best_table as u8);
+            for symbol in group {
+                table_frequencies[best_table][*symbol as usize] += 1;
+            }
+        }
+        // every symbol of the alphabet needs a code
+        costs = table_frequencies
+            .iter()
+            .map(|table| {
+                let table: Vec<u64> = table.iter().map(|f| (*f).max(1)).collect();
+                code_lengths(&table, MAX_CODE_LENGTH)
+            })
+            .collect();
+    }
+    (costs, selectors)
+}
+
+fn read_stream(reader: &mut MsbBitReader, output: &mut Vec<u8>) -> Result<()> {
+    for byte in STREAM_MAGIC {
+        if reader.read_bits(8)? != u32::from(*byte) {
+            return Err(invalid_data("not a bzip2 stream"));
+        }
+    }
+    let level = reader.read_bits(8)?;
+    if !(u32::from(b'1')..=u32::from(b'9')).contains(&level) {
+        return Err(invalid_data("invalid bzip2 block size"));
+    }
+    let max_block_size = (level - u32::from(b'0')) as usize * 100_000;
+
+    let mut combined_crc: u32 = 0;
+    loop {
+        match read_u48(reader)? {
+            BLOCK_MAGIC => {
+                let expected_crc = reader.read_bits(32)?;
+                let start = output.len();
+                read_block(reader, max_block_size, output)?;
+                let crc = block_crc(&output[start..]);
+                if crc != expected_crc {
+                    return Err(invalid_data("bzip2 block CRC mismatch"));
+                }
+                combined_crc = combine_crc(combined_crc, crc);
+            }
+            END_OF_STREAM_MAGIC => {
+                if reader.read_bits(32)? != combined_crc {
+                    return Err(invalid_data("bzip2 stream CRC mismatch"));
+                }
+                return Ok(());
+            }
+            _ => return Err(invalid_data("invalid bzip2 block magic")),
+        }
+    }
+}
+
+fn read_block(
+    reader: &mut MsbBitReader,
+    max_block_size: usize,
+    output: &mut Vec<u8>,
+) -> Result<()> {
+    if reader.read_bit()? == 1 {
+        return Err(invalid_data("randomised bzip2 blocks are not supported"));
+    }
+    let primary_index = reader.read_bits(24)? as usize;
+
+    let ranges = reader.read_bits(16)?;
+    let mut used: Vec<u8> = Vec::new();
+    for range in 0..16 {
+        if ranges & (0x8000 >> range) != 0 {
+            for byte in range * 16..range * 16 + 16 {
+                if reader.read_bit()? == 1 {
+                    used.push(byte as u8);
+                }
+            }
+        }
+    }
+    if used.is_empty() {
+        return Err(invalid_data("bzip2 block uses no byte"));
+    }
+    let alphabet_size = used.len() + 2;
+    let end_of_block = (used.len() + 1) as u16;
+
+    let table_count = reader.read_bits(3)? as usize;
+    if !(2..=MAX_GROUPS).contains(&table_count) {
+        return Err(invalid_data("invalid number of bzip2 Huffman tables"));
+    }
+    let selector_count = reader.read_bits(15)? as usize;
+    if selector_count == 0 {
+        return Err(invalid_data("invalid number of bzip2 selectors"));
+    }
+    let mut order: Vec<u8> = (0..table_count as u8).collect();
+    let mut selectors: Vec<u8> = Vec::with_capacity(selector_count.min(MAX_SELECTORS));
+    for _ in 0..selector_count {
+        let mut rank: usize = 0;
+        while reader.read_bit()? == 1 {
+            rank += 1;
+            if rank >= table_count {
+                return Err(invalid_data("invalid bzip2 selector"));
+            }
+        }
+        let table = order[rank];
+        order[..=rank].rotate_right(1);
+        // selectors beyond the limit are read and ignored, as bzip2 1.0.8 does
+        if selectors.len() < MAX_SELECTORS {
+            selectors.push(table);
+        }
+    }
+
+    let mut tables: Vec<HuffmanTable> = Vec::with_capacity(table_count);
+    for _ in 0..table_count {
+        let mut lengths: Vec<u8> = vec![0; alphabet_size];
+        let mut current = reader.read_bits(5)?;
+        for length in lengths.iter_mut() {
+            loop {
+                if !(1..=MAX_DECODE_CODE_LENGTH).contains(&current) {
+                    return Err(invalid_data("invalid bzip2 code length"));
+                }
+                if reader.read_bit()? == 0 {
+                    break;
+                }
+                match reader.read_bit()? {
+                    0 => current += 1,
+                    _ => current -= 1,
+                }
+            }
+            *length = current as u8;
+        }
+        tables.push(HuffmanTable::new(&lengths)?);
+    }
+
+    // decode the symbols up to the end of block, keeping track of the block size
+    let mut symbols: Vec<Symbol> = Vec::new();
+    let mut block_size: usize = 0;
+    let mut run_weight: usize = 1;
+    for index in 0.. {
+        let selector = match selectors.get(index / GROUP_SIZE) {
+            Some(selector) => *selector as usize,
+            None => return Err(invalid_data("bzip2 block runs out of selectors")),
+        };
+        let value = tables[selector].decode(reader)?;
+        if value == end_of_block {
+            break;
+        }
+        let symbol = match Symbol::from_u16(value) {
+            Some(symbol) => symbol,
+            None => return Err(invalid_data("invalid bzip2 symbol")),
+        };
+        match symbol {
+            Symbol::RunA | Symbol::RunB => {
+                let digit = if symbol == Symbol::RunA { 1 } else { 2 };
+                block_size += run_weight * digit;
+                run_weight *= 2;
+            }
+            Symbol::Literal(_) => {
+                block_size += 1;
+                run_weight = 1;
+            }
+        }
+        if block_size > max_block_size {
+            return Err(invalid_data("bzip2 block is too large"));
+        }
+        symbols.push(symbol);
+    }
+
+    let ranks = rle::decode(&symbols, max_block_size)?;
+    let transformed: Vec<u8> = mtf::decode(&ranks)
+        .into_iter()
+        .map(|index| used.get(index as usize).copied())
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| invalid_data("invalid bzip2 move-to-front rank"))?;
+    if transformed.is_empty() {
+        return Err(unexpected_end());
+    }
+    let block = bwt::inverse(&transformed, primary_index)?;
+    initial_run_length_decode(&block, output);
+    Ok(())
+}
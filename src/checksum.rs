@@ -0,0 +1,789 @@
+//! Checksums of the container formats
+//!
+//! Every checksum implements `Checksum`, fed incrementally like a
+//! `std::hash::Hasher`:
+//! * CRC-32 (zip, gzip, xz) and CRC-32C (Snappy framing), computed with
+//!   slicing-by-8 tables
+//! * Adler-32 (zlib), reducing its sums modulo 65521 once every 5552 bytes
+//! * CRC-64 (xz), the ECMA-182 polynomial
+//! * xxHash32 (LZ4 frames) and xxHash64 (Zstandard frames)
+//! * SHA-256 (xz), FIPS 180-4
+//!
+//! CRC-32s of consecutive chunks can be merged with `crc32_combine`.
+
+use crate::bits::{read_u32, read_u64};
+
+/// Incremental checksum.
+///
+/// # Examples
+/// ```
+/// # use reducto::checksum::{crc32, Checksum, Crc32};
+/// let mut crc = Crc32::new();
+/// crc.write(b"1234");
+/// crc.write(b"56789");
+/// assert_eq!(crc.finish(), 0xcbf4_3926);
+/// assert_eq!(crc.finish(), crc32(b"123456789"));
+/// ```
+pub trait Checksum {
+    /// Type of the checksum value.
+    type Output;
+
+    /// Feed `data` to the checksum.
+    fn write(&mut self, data: &[u8]);
+
+    /// Checksum of all the data written so far.
+    fn finish(&self) -> Self::Output;
+}
+
+const CRC32_POLYNOMIAL: u32 = 0xedb8_8320;
+const CRC32C_POLYNOMIAL: u32 = 0x82f6_3b78;
+const CRC64_POLYNOMIAL: u64 = 0xc96c_5795_d787_0f42;
+
+const CRC32_TABLES: [[u32; 256]; 8] = crc32_tables(CRC32_POLYNOMIAL);
+const CRC32C_TABLES: [[u32; 256]; 8] = crc32_tables(CRC32C_POLYNOMIAL);
+const CRC64_TABLE: [u64; 256] = crc64_table();
+
+/// Tables of a reflected CRC-32, `tables[k][byte]` being the CRC of `byte` followed by `k` zero bytes.
+const fn crc32_tables(polynomial: u32) -> [[u32; 256]; 8] {
+    let mut tables = [[0u32; 256]; 8];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ polynomial
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        tables[0][i] = crc;
+        i += 1;
+    }
+    let mut k = 1;
+    while k < 8 {
+        let mut i = 0;
+        while i < 256 {
+            let previous = tables[k - 1][i];
+            tables[k][i] = (previous >> 8) ^ tables[0][(previous & 0xff) as usize];
+            i += 1;
+        }
+        k += 1;
+    }
+    tables
+}
+
+const fn crc64_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC64_POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Continue the CRC register `crc` over `data`, eight bytes at a time.
+fn slice_by_8(tables: &[[u32; 256]; 8], mut crc: u32, data: &[u8]) -> u32 {
+    let mut words = data.chunks_exact(8);
+    for word in &mut words {
+        let low = crc ^ read_u32(word);
+        crc = tables[7][(low & 0xff) as usize]
+            ^ tables[6][(low >> 8 & 0xff) as usize]
+            ^ tables[5][(low >> 16 & 0xff) as usize]
+            ^ tables[4][(low >> 24) as usize]
+            ^ tables[3][word[4] as usize]
+            ^ tables[2][word[5] as usize]
+            ^ tables[1][word[6] as usize]
+            ^ tables[0][word[7] as usize];
+    }
+    for byte in words.remainder() {
+        crc = (crc >> 8) ^ tables[0][((crc ^ u32::from(*byte)) & 0xff) as usize];
+    }
+    crc
+}
+
+/// CRC-32 of zip, gzip and xz.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crc32 {
+    crc: u32,
+}
+
+impl Crc32 {
+    pub fn new() -> Crc32 {
+        Crc32 { crc: u32::MAX }
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Crc32::new()
+    }
+}
+
+impl Checksum for Crc32 {
+    type Output = u32;
+
+    fn write(&mut self, data: &[u8]) {
+        self.crc = slice_by_8(&CRC32_TABLES, self.crc, data);
+    }
+
+    fn finish(&self) -> u32 {
+        !self.crc
+    }
+}
+
+/// CRC-32 of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.write(data);
+    crc.finish()
+}
+
+/// CRC-32C (Castagnoli) of iSCSI and the Snappy framing format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crc32c {
+    crc: u32,
+}
+
+impl Crc32c {
+    pub fn new() -> Crc32c {
+        Crc32c { crc: u32::MAX }
+    }
+}
+
+impl Default for Crc32c {
+    fn default() -> Self {
+        Crc32c::new()
+    }
+}
+
+impl Checksum for Crc32c {
+    type Output = u32;
+
+    fn write(&mut self, data: &[u8]) {
+        self.crc = slice_by_8(&CRC32C_TABLES, self.crc, data);
+    }
+
+    fn finish(&self) -> u32 {
+        !self.crc
+    }
+}
+
+/// CRC-32C of `data`.
+///
+/// # Examples
+/// ```
+/// # use reducto::checksum::crc32c;
+/// assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+/// ```
+pub fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = Crc32c::new();
+    crc.write(data);
+    crc.finish()
+}
+
+/// Product of two polynomials modulo the CRC-32 polynomial, bit reflected.
+fn multiply_modulo(a: u32, mut b: u32) -> u32 {
+    let mut product = 0;
+    let mut mask = 1 << 31;
+    while mask != 0 {
+        if a & mask != 0 {
+            product ^= b;
+        }
+        b = if b & 1 != 0 {
+            (b >> 1) ^ CRC32_POLYNOMIAL
+        } else {
+            b >> 1
+        };
+        mask >>= 1;
+    }
+    product
+}
+
+/// `x^(8 * count)` modulo the CRC-32 polynomial, bit reflected.
+fn x_power_bytes(mut count: u64) -> u32 {
+    // x^1, squared at every bit of `count * 8`
+    let mut square = 1 << 30;
+    for _ in 0..3 {
+        square = multiply_modulo(square, square);
+    }
+    let mut power = 1 << 31;
+    while count != 0 {
+        if count & 1 != 0 {
+            power = multiply_modulo(square, power);
+        }
+        square = multiply_modulo(square, square);
+        count >>= 1;
+    }
+    power
+}
+
+/// CRC-32 of two consecutive chunks, from their CRC-32s and the size of the second one.
+///
+/// # Examples
+/// ```
+/// # use reducto::checksum::{crc32, crc32_combine};
+/// let data = b"hello hello hello hello";
+/// let (first, second) = data.split_at(10);
+/// let combined = crc32_combine(crc32(first), crc32(second), second.len() as u64);
+/// assert_eq!(combined, crc32(data));
+/// ```
+pub fn crc32_combine(first: u32, second: u32, second_size: u64) -> u32 {
+    multiply_modulo(x_power_bytes(second_size), first) ^ second
+}
+
+/// Adler-32 of zlib.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Adler32 {
+    a: u32,
+    b: u32,
+}
+
+const ADLER_MODULO: u32 = 65_521;
+/// Most bytes summed before `b` may overflow 32 bits.
+const ADLER_MAX_RUN: usize = 5552;
+
+impl Adler32 {
+    pub fn new() -> Adler32 {
+        Adler32 { a: 1, b: 0 }
+    }
+}
+
+impl Default for Adler32 {
+    fn default() -> Self {
+        Adler32::new()
+    }
+}
+
+impl Checksum for Adler32 {
+    type Output = u32;
+
+    fn write(&mut self, data: &[u8]) {
+        for run in data.chunks(ADLER_MAX_RUN) {
+            for byte in run {
+                self.a += u32::from(*byte);
+                self.b += self.a;
+            }
+            self.a %= ADLER_MODULO;
+            self.b %= ADLER_MODULO;
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        self.b << 16 | self.a
+    }
+}
+
+/// Adler-32 of `data`.
+///
+/// # Examples
+/// ```
+/// # use reducto::checksum::adler32;
+/// assert_eq!(adler32(b"123456789"), 0x091e_01de);
+/// assert_eq!(adler32(&[0xff; 100_000]), 0x149a_302c);
+/// ```
+pub fn adler32(data: &[u8]) -> u32 {
+    let mut adler = Adler32::new();
+    adler.write(data);
+    adler.finish()
+}
+
+/// CRC-64 (ECMA-182) of xz.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crc64 {
+    crc: u64,
+}
+
+impl Crc64 {
+    pub fn new() -> Crc64 {
+        Crc64 { crc: u64::MAX }
+    }
+}
+
+impl Default for Crc64 {
+    fn default() -> Self {
+        Crc64::new()
+    }
+}
+
+impl Checksum for Crc64 {
+    type Output = u64;
+
+    fn write(&mut self, data: &[u8]) {
+        self.crc = data.iter().fold(self.crc, |crc, byte| {
+            (crc >> 8) ^ CRC64_TABLE[((crc ^ u64::from(*byte)) & 0xff) as usize]
+        });
+    }
+
+    fn finish(&self) -> u64 {
+        !self.crc
+    }
+}
+
+/// CRC-64 of `data`.
+///
+/// # Examples
+/// ```
+/// # use reducto::checksum::crc64;
+/// assert_eq!(crc64(b"123456789"), 0x995d_c9bb_df19_39fa);
+/// ```
+pub fn crc64(data: &[u8]) -> u64 {
+    let mut crc = Crc64::new();
+    crc.write(data);
+    crc.finish()
+}
+
+const PRIME32_1: u32 = 2_654_435_761;
+const PRIME32_2: u32 = 2_246_822_519;
+const PRIME32_3: u32 = 3_266_489_917;
+const PRIME32_4: u32 = 668_265_263;
+const PRIME32_5: u32 = 374_761_393;
+
+const PRIME64_1: u64 = 0x9e37_79b1_85eb_ca87;
+const PRIME64_2: u64 = 0xc2b2_ae3d_27d4_eb4f;
+const PRIME64_3: u64 = 0x1656_67b1_9e37_79f9;
+const PRIME64_4: u64 = 0x85eb_ca77_c2b2_ae63;
+const PRIME64_5: u64 = 0x27d4_eb2f_1656_67c5;
+
+fn xxhash32_round(accumulator: u32, lane: u32) -> u32 {
+    accumulator
+        .wrapping_add(lane.wrapping_mul(PRIME32_2))
+        .rotate_left(13)
+        .wrapping_mul(PRIME32_1)
+}
+
+fn xxhash64_round(accumulator: u64, lane: u64) -> u64 {
+    accumulator
+        .wrapping_add(lane.wrapping_mul(PRIME64_2))
+        .rotate_left(31)
+        .wrapping_mul(PRIME64_1)
+}
+
+fn xxhash64_merge(hash: u64, accumulator: u64) -> u64 {
+    (hash ^ xxhash64_round(0, accumulator))
+        .wrapping_mul(PRIME64_1)
+        .wrapping_add(PRIME64_4)
+}
+
+/// xxHash32 of LZ4 frames.
+///
+/// Data is consumed by stripes of 16 bytes, the last partial stripe being
+/// kept until more data comes or the hash is finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XxHash32 {
+    seed: u32,
+    accumulators: [u32; 4],
+    buffer: [u8; 16],
+    buffered: usize,
+    length: u64,
+}
+
+impl XxHash32 {
+    pub fn new(seed: u32) -> XxHash32 {
+        XxHash32 {
+            seed,
+            accumulators: [
+                seed.wrapping_add(PRIME32_1).wrapping_add(PRIME32_2),
+                seed.wrapping_add(PRIME32_2),
+                seed,
+                seed.wrapping_sub(PRIME32_1),
+            ],
+            buffer: [0; 16],
+            buffered: 0,
+            length: 0,
+        }
+    }
+
+    fn consume(accumulators: &mut [u32; 4], stripe: &[u8]) {
+        for (lane, accumulator) in accumulators.iter_mut().enumerate() {
+            *accumulator = xxhash32_round(*accumulator, read_u32(&stripe[lane * 4..]));
+        }
+    }
+}
+
+impl Default for XxHash32 {
+    fn default() -> Self {
+        XxHash32::new(0)
+    }
+}
+
+impl Checksum for XxHash32 {
+    type Output = u32;
+
+    fn write(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+        if self.buffered > 0 {
+            let count = data.len().min(16 - self.buffered);
+            self.buffer[self.buffered..self.buffered + count].copy_from_slice(&data[..count]);
+            self.buffered += count;
+            data = &data[count..];
+            if self.buffered < 16 {
+                return;
+            }
+            let buffer = self.buffer;
+            XxHash32::consume(&mut self.accumulators, &buffer);
+            self.buffered = 0;
+        }
+        let mut stripes = data.chunks_exact(16);
+        for stripe in &mut stripes {
+            XxHash32::consume(&mut self.accumulators, stripe);
+        }
+        let rest = stripes.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+
+    fn finish(&self) -> u32 {
+        let accumulators = &self.accumulators;
+        let mut hash = if self.length >= 16 {
+            accumulators[0]
+                .rotate_left(1)
+                .wrapping_add(accumulators[1].rotate_left(7))
+                .wrapping_add(accumulators[2].rotate_left(12))
+                .wrapping_add(accumulators[3].rotate_left(18))
+        } else {
+            self.seed.wrapping_add(PRIME32_5)
+        };
+        hash = hash.wrapping_add(self.length as u32);
+
+        let mut words = self.buffer[..self.buffered].chunks_exact(4);
+        for word in &mut words {
+            hash = hash
+                .wrapping_add(read_u32(word).wrapping_mul(PRIME32_3))
+                .rotate_left(17)
+                .wrapping_mul(PRIME32_4);
+        }
+        for byte in words.remainder() {
+            hash = hash
+                .wrapping_add(u32::from(*byte).wrapping_mul(PRIME32_5))
+                .rotate_left(11)
+                .wrapping_mul(PRIME32_1);
+        }
+
+        hash ^= hash >> 15;
+        hash = hash.wrapping_mul(PRIME32_2);
+        hash ^= hash >> 13;
+        hash = hash.wrapping_mul(PRIME32_3);
+        hash ^ (hash >> 16)
+    }
+}
+
+/// xxHash32 of `data`.
+///
+/// # Examples
+/// ```
+/// # use reducto::checksum::{xxhash32, Checksum, XxHash32};
+/// assert_eq!(xxhash32(b"", 0), 0x02cc_5d05);
+///
+/// let data = std::fs::read("resources/lorem.txt").unwrap();
+/// let mut hash = XxHash32::new(7);
+/// for chunk in data.chunks(1000) {
+///     hash.write(chunk);
+/// }
+/// assert_eq!(hash.finish(), xxhash32(&data, 7));
+/// ```
+pub fn xxhash32(data: &[u8], seed: u32) -> u32 {
+    let mut hash = XxHash32::new(seed);
+    hash.write(data);
+    hash.finish()
+}
+
+/// xxHash64 of Zstandard frames.
+///
+/// Data is consumed by stripes of 32 bytes, the last partial stripe being
+/// kept until more data comes or the hash is finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XxHash64 {
+    seed: u64,
+    accumulators: [u64; 4],
+    buffer: [u8; 32],
+    buffered: usize,
+    length: u64,
+}
+
+impl XxHash64 {
+    pub fn new(seed: u64) -> XxHash64 {
+        XxHash64 {
+            seed,
+            accumulators: [
+                seed.wrapping_add(PRIME64_1).wrapping_add(PRIME64_2),
+                seed.wrapping_add(PRIME64_2),
+                seed,
+                seed.wrapping_sub(PRIME64_1),
+            ],
+            buffer: [0; 32],
+            buffered: 0,
+            length: 0,
+        }
+    }
+
+    fn consume(accumulators: &mut [u64; 4], stripe: &[u8]) {
+        for (lane, accumulator) in accumulators.iter_mut().enumerate() {
+            *accumulator = xxhash64_round(*accumulator, read_u64(&stripe[lane * 8..]));
+        }
+    }
+}
+
+impl Default for XxHash64 {
+    fn default() -> Self {
+        XxHash64::new(0)
+    }
+}
+
+impl Checksum for XxHash64 {
+    type Output = u64;
+
+    fn write(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+        if self.buffered > 0 {
+            let count = data.len().min(32 - self.buffered);
+            self.buffer[self.buffered..self.buffered + count].copy_from_slice(&data[..count]);
+            self.buffered += count;
+            data = &data[count..];
+            if self.buffered < 32 {
+                return;
+            }
+            let buffer = self.buffer;
+            XxHash64::consume(&mut self.accumulators, &buffer);
+            self.buffered = 0;
+        }
+        let mut stripes = data.chunks_exact(32);
+        for stripe in &mut stripes {
+            XxHash64::consume(&mut self.accumulators, stripe);
+        }
+        let rest = stripes.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+
+    fn finish(&self) -> u64 {
+        let accumulators = &self.accumulators;
+        let mut hash = if self.length >= 32 {
+            let hash = accumulators[0]
+                .rotate_left(1)
+                .wrapping_add(accumulators[1].rotate_left(7))
+                .wrapping_add(accumulators[2].rotate_left(12))
+                .wrapping_add(accumulators[3].rotate_left(18));
+            accumulators
+                .iter()
+                .fold(hash, |hash, accumulator| xxhash64_merge(hash, *accumulator))
+        } else {
+            self.seed.wrapping_add(PRIME64_5)
+        };
+        hash = hash.wrapping_add(self.length);
+
+        let mut words = self.buffer[..self.buffered].chunks_exact(8);
+        for word in &mut words {
+            hash = (hash ^ xxhash64_round(0, read_u64(word)))
+                .rotate_left(27)
+                .wrapping_mul(PRIME64_1)
+                .wrapping_add(PRIME64_4);
+        }
+        let mut rest = words.remainder();
+        if rest.len() >= 4 {
+            hash = (hash ^ u64::from(read_u32(rest)).wrapping_mul(PRIME64_1))
+                .rotate_left(23)
+                .wrapping_mul(PRIME64_2)
+                .wrapping_add(PRIME64_3);
+            rest = &rest[4..];
+        }
+        for byte in rest {
+            hash = (hash ^ u64::from(*byte).wrapping_mul(PRIME64_5))
+                .rotate_left(11)
+                .wrapping_mul(PRIME64_1);
+        }
+
+        hash ^= hash >> 33;
+        hash = hash.wrapping_mul(PRIME64_2);
+        hash ^= hash >> 29;
+        hash = hash.wrapping_mul(PRIME64_3);
+        hash ^ (hash >> 32)
+    }
+}
+
+/// xxHash64 of `data`.
+///
+/// # Examples
+/// ```
+/// # use reducto::checksum::{xxhash64, Checksum, XxHash64};
+/// assert_eq!(xxhash64(b"", 0), 0xef46_db37_51d8_e999);
+///
+/// let data = std::fs::read("resources/lorem.txt").unwrap();
+/// let mut hash = XxHash64::new(7);
+/// for chunk in data.chunks(1000) {
+///     hash.write(chunk);
+/// }
+/// assert_eq!(hash.finish(), xxhash64(&data, 7));
+/// ```
+pub fn xxhash64(data: &[u8], seed: u64) -> u64 {
+    let mut hash = XxHash64::new(seed);
+    hash.write(data);
+    hash.finish()
+}
+
+/// Round constants of SHA-256, the fractional parts of the cube roots of the first 64 primes.
+const SHA256_ROUNDS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+/// Initial state of SHA-256, the fractional parts of the square roots of the first 8 primes.
+const SHA256_INIT: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// SHA-256 of xz.
+///
+/// Data is consumed by blocks of 64 bytes, the last partial block being kept
+/// until more data comes or the hash is finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sha256 {
+    state: [u32; 8],
+    buffer: [u8; 64],
+    buffered: usize,
+    length: u64,
+}
+
+impl Sha256 {
+    pub fn new() -> Sha256 {
+        Sha256 {
+            state: SHA256_INIT,
+            buffer: [0; 64],
+            buffered: 0,
+            length: 0,
+        }
+    }
+
+    fn consume(state: &mut [u32; 8], block: &[u8]) {
+        let mut schedule = [0u32; 64];
+        for (word, bytes) in schedule.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for index in 16..64 {
+            let previous = schedule[index - 2];
+            let early = schedule[index - 15];
+            let sigma0 = early.rotate_right(7) ^ early.rotate_right(18) ^ (early >> 3);
+            let sigma1 = previous.rotate_right(17) ^ previous.rotate_right(19) ^ (previous >> 10);
+            schedule[index] = schedule[index - 16]
+                .wrapping_add(sigma0)
+                .wrapping_add(schedule[index - 7])
+                .wrapping_add(sigma1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+        for (round, word) in SHA256_ROUNDS.iter().zip(&schedule) {
+            let sum1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let temporary1 = h
+                .wrapping_add(sum1)
+                .wrapping_add(choice)
+                .wrapping_add(*round)
+                .wrapping_add(*word);
+            let sum0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let temporary2 = sum0.wrapping_add(majority);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(temporary1);
+            d = c;
+            c = b;
+            b = a;
+            a = temporary1.wrapping_add(temporary2);
+        }
+        for (value, added) in state.iter_mut().zip(&[a, b, c, d, e, f, g, h]) {
+            *value = value.wrapping_add(*added);
+        }
+    }
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Sha256::new()
+    }
+}
+
+impl Checksum for Sha256 {
+    type Output = [u8; 32];
+
+    fn write(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+        if self.buffered > 0 {
+            let count = data.len().min(64 - self.buffered);
+            self.buffer[self.buffered..self.buffered + count].copy_from_slice(&data[..count]);
+            self.buffered += count;
+            data = &data[count..];
+            if self.buffered < 64 {
+                return;
+            }
+            let buffer = self.buffer;
+            Sha256::consume(&mut self.state, &buffer);
+            self.buffered = 0;
+        }
+        let mut blocks = data.chunks_exact(64);
+        for block in &mut blocks {
+            Sha256::consume(&mut self.state, block);
+        }
+        let rest = blocks.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+
+    fn finish(&self) -> [u8; 32] {
+        // a one bit, zeros and the length in bits end the last block, or spill into another one
+        let mut state = self.state;
+        let mut padding = [0u8; 128];
+        padding[..self.buffered].copy_from_slice(&self.buffer[..self.buffered]);
+        padding[self.buffered] = 0x80;
+        let padded_length = if self.buffered < 56 { 64 } else { 128 };
+        padding[padded_length - 8..padded_length].copy_from_slice(&(self.length * 8).to_be_bytes());
+        for block in padding[..padded_length].chunks_exact(64) {
+            Sha256::consume(&mut state, block);
+        }
+
+        let mut hash = [0u8; 32];
+        for (bytes, value) in hash.chunks_exact_mut(4).zip(&state) {
+            bytes.copy_from_slice(&value.to_be_bytes());
+        }
+        hash
+    }
+}
+
+/// SHA-256 of `data`.
+///
+/// # Examples
+/// ```
+/// # use reducto::checksum::{sha256, Checksum, Sha256};
+/// let hash = sha256(b"abc");
+/// assert_eq!(&hash[..8], &[0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea]);
+/// assert_eq!(&hash[24..], &[0xb4, 0x10, 0xff, 0x61, 0xf2, 0x00, 0x15, 0xad]);
+///
+/// let data = std::fs::read("resources/lorem.txt").unwrap();
+/// let mut hash = Sha256::new();
+/// for chunk in data.chunks(1000) {
+///     hash.write(chunk);
+/// }
+/// assert_eq!(hash.finish(), sha256(&data));
+/// ```
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut hash = Sha256::new();
+    hash.write(data);
+    hash.finish()
+}
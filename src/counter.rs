@@ -1,18 +1,407 @@
 //! This module implements python-like `Counter`
+//!
+//! As in Python, `+`, `-`, `&` and `|` combine counters by summing, subtracting,
+//! taking the minimum and the maximum of the counts, keeping only the positive
+//! results.
+//!
+//! A `Counter` is generic over the integer of its counts and over the map
+//! storing them: a `BTreeMap` by default, a `HashMap` for `Hash` elements
+//! without an order, or a `Dense` array indexed by small integers like bytes.
+//!
+//! Large inputs can be counted on several threads, either by merging the
+//! counters of chunks or by sharing an `AtomicCounter`.
+//!
+//! The statistics of the counts, like their entropy, estimate how well the
+//! elements can be compressed by an order-0 model such as a Huffman code.
 use std::cmp::Eq;
-use std::cmp::Ord;
-use std::collections::btree_map::{BTreeMap, IntoIter, Iter};
-use std::fmt::Debug;
+use std::cmp::{max, min};
+use std::collections::{btree_map, hash_map, BTreeMap, HashMap};
+use std::fmt::{self, Debug};
+use std::hash::Hash;
 use std::iter::FromIterator;
-use std::ops::Index;
+use std::iter::Sum;
+use std::marker::PhantomData;
+use std::ops::{Add, AddAssign, BitAnd, BitAndAssign, BitOr, BitOrAssign, Index, Sub, SubAssign};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+
+#[cfg(feature = "serde")]
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+#[cfg(feature = "serde")]
+use serde::ser::{Serialize, Serializer};
+
+use crate::huffman::code_lengths;
+
+/// Unsigned integer counting the occurences of an element.
+pub trait Count: Copy + Ord + Debug + Add<Output = Self> + AddAssign + 'static {
+    const ZERO: Self;
+    const ONE: Self;
+
+    /// Reference to zero, the count of the missing elements.
+    fn zero_ref() -> &'static Self;
+
+    fn saturating_sub(self, other: Self) -> Self;
+
+    fn as_usize(self) -> usize;
+
+    fn as_u64(self) -> u64;
+
+    fn as_f64(self) -> f64;
+}
+
+macro_rules! impl_count {
+    ($($count:ty),*) => {
+        $(
+            impl Count for $count {
+                const ZERO: $count = 0;
+                const ONE: $count = 1;
+
+                fn zero_ref() -> &'static $count {
+                    &0
+                }
+
+                fn saturating_sub(self, other: $count) -> $count {
+                    <$count>::saturating_sub(self, other)
+                }
+
+                fn as_usize(self) -> usize {
+                    self as usize
+                }
+
+                fn as_u64(self) -> u64 {
+                    self as u64
+                }
+
+                fn as_f64(self) -> f64 {
+                    self as f64
+                }
+            }
+        )*
+    };
+}
+
+impl_count!(u8, u16, u32, u64, u128, usize);
+
+/// Map from the elements to their counts.
+pub trait Storage<T, C>: Default {
+    type Iter<'a>: Iterator<Item = (&'a T, &'a C)>
+    where
+        Self: 'a,
+        T: 'a,
+        C: 'a;
+    type IntoIter: Iterator<Item = (T, C)>;
+
+    fn get(&self, elem: &T) -> Option<&C>;
+
+    fn get_mut(&mut self, elem: &T) -> Option<&mut C>;
+
+    /// Count of `elem`, inserted as zero when missing.
+    fn entry(&mut self, elem: T) -> &mut C;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn retain<F: FnMut(&T, &mut C) -> bool>(&mut self, f: F);
+
+    fn iter(&self) -> Self::Iter<'_>;
+
+    fn into_entries(self) -> Self::IntoIter;
+}
+
+/// Ordered storage, iterating over the elements by value.
+impl<T: Ord, C: Count> Storage<T, C> for BTreeMap<T, C> {
+    type Iter<'a>
+        = btree_map::Iter<'a, T, C>
+    where
+        T: 'a,
+        C: 'a;
+    type IntoIter = btree_map::IntoIter<T, C>;
+
+    fn get(&self, elem: &T) -> Option<&C> {
+        BTreeMap::get(self, elem)
+    }
+
+    fn get_mut(&mut self, elem: &T) -> Option<&mut C> {
+        BTreeMap::get_mut(self, elem)
+    }
+
+    fn entry(&mut self, elem: T) -> &mut C {
+        BTreeMap::entry(self, elem).or_insert(C::ZERO)
+    }
+
+    fn len(&self) -> usize {
+        BTreeMap::len(self)
+    }
+
+    fn retain<F: FnMut(&T, &mut C) -> bool>(&mut self, f: F) {
+        BTreeMap::retain(self, f)
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        BTreeMap::iter(self)
+    }
+
+    fn into_entries(self) -> Self::IntoIter {
+        self.into_iter()
+    }
+}
+
+/// Hashed storage, iterating over the elements in any order.
+impl<T: Hash + Eq, C: Count> Storage<T, C> for HashMap<T, C> {
+    type Iter<'a>
+        = hash_map::Iter<'a, T, C>
+    where
+        T: 'a,
+        C: 'a;
+    type IntoIter = hash_map::IntoIter<T, C>;
+
+    fn get(&self, elem: &T) -> Option<&C> {
+        HashMap::get(self, elem)
+    }
+
+    fn get_mut(&mut self, elem: &T) -> Option<&mut C> {
+        HashMap::get_mut(self, elem)
+    }
+
+    fn entry(&mut self, elem: T) -> &mut C {
+        HashMap::entry(self, elem).or_insert(C::ZERO)
+    }
+
+    fn len(&self) -> usize {
+        HashMap::len(self)
+    }
+
+    fn retain<F: FnMut(&T, &mut C) -> bool>(&mut self, f: F) {
+        HashMap::retain(self, f)
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        HashMap::iter(self)
+    }
+
+    fn into_entries(self) -> Self::IntoIter {
+        self.into_iter()
+    }
+}
+
+static U8_KEYS: [u8; 1 << 8] = {
+    let mut keys = [0; 1 << 8];
+    let mut index = 0;
+    while index < keys.len() {
+        keys[index] = index as u8;
+        index += 1;
+    }
+    keys
+};
+
+static U16_KEYS: [u16; 1 << 16] = {
+    let mut keys = [0; 1 << 16];
+    let mut index = 0;
+    while index < keys.len() {
+        keys[index] = index as u16;
+        index += 1;
+    }
+    keys
+};
+
+/// Element of a small alphabet, usable as an index in a `Dense` storage.
+pub trait DenseKey: Copy + 'static {
+    /// Every element, each one at its index.
+    fn keys() -> &'static [Self];
+
+    fn index(self) -> usize;
+}
+
+impl DenseKey for u8 {
+    fn keys() -> &'static [u8] {
+        &U8_KEYS
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl DenseKey for u16 {
+    fn keys() -> &'static [u16] {
+        &U16_KEYS
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Array storage with a slot for every element of the alphabet, iterating over them by index.
+#[derive(Debug, Clone)]
+pub struct Dense<T, C> {
+    counts: Vec<C>,
+    /// Whether each element has a count, which can be zero.
+    present: Vec<bool>,
+    len: usize,
+    marker: PhantomData<T>,
+}
+
+impl<T: DenseKey, C: Count> Default for Dense<T, C> {
+    fn default() -> Self {
+        Dense {
+            counts: vec![C::ZERO; T::keys().len()],
+            present: vec![false; T::keys().len()],
+            len: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Iterator over the counts of a `Dense` storage.
+#[derive(Debug, Clone)]
+pub struct DenseIter<'a, T: 'static, C> {
+    keys: &'static [T],
+    counts: &'a [C],
+    present: &'a [bool],
+    index: usize,
+}
+
+impl<'a, T: 'static, C> Iterator for DenseIter<'a, T, C> {
+    type Item = (&'a T, &'a C);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.index < self.counts.len() {
+            let index = self.index;
+            self.index += 1;
+            if self.present[index] {
+                return Some((&self.keys[index], &self.counts[index]));
+            }
+        }
+        None
+    }
+}
+
+/// Owning iterator over the counts of a `Dense` storage.
+#[derive(Debug, Clone)]
+pub struct DenseIntoIter<T: 'static, C> {
+    keys: &'static [T],
+    counts: Vec<C>,
+    present: Vec<bool>,
+    index: usize,
+}
+
+impl<T: Copy + 'static, C: Copy> Iterator for DenseIntoIter<T, C> {
+    type Item = (T, C);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.index < self.counts.len() {
+            let index = self.index;
+            self.index += 1;
+            if self.present[index] {
+                return Some((self.keys[index], self.counts[index]));
+            }
+        }
+        None
+    }
+}
+
+impl<T: DenseKey, C: Count> Storage<T, C> for Dense<T, C> {
+    type Iter<'a>
+        = DenseIter<'a, T, C>
+    where
+        C: 'a;
+    type IntoIter = DenseIntoIter<T, C>;
+
+    fn get(&self, elem: &T) -> Option<&C> {
+        let index = elem.index();
+        match self.present[index] {
+            true => Some(&self.counts[index]),
+            false => None,
+        }
+    }
+
+    fn get_mut(&mut self, elem: &T) -> Option<&mut C> {
+        let index = elem.index();
+        match self.present[index] {
+            true => Some(&mut self.counts[index]),
+            false => None,
+        }
+    }
+
+    fn entry(&mut self, elem: T) -> &mut C {
+        let index = elem.index();
+        if !self.present[index] {
+            self.present[index] = true;
+            self.len += 1;
+        }
+        &mut self.counts[index]
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn retain<F: FnMut(&T, &mut C) -> bool>(&mut self, mut f: F) {
+        for (index, key) in T::keys().iter().enumerate() {
+            if self.present[index] && !f(key, &mut self.counts[index]) {
+                self.present[index] = false;
+                self.counts[index] = C::ZERO;
+                self.len -= 1;
+            }
+        }
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        DenseIter {
+            keys: T::keys(),
+            counts: &self.counts,
+            present: &self.present,
+            index: 0,
+        }
+    }
+
+    fn into_entries(self) -> Self::IntoIter {
+        DenseIntoIter {
+            keys: T::keys(),
+            counts: self.counts,
+            present: self.present,
+            index: 0,
+        }
+    }
+}
 
 /// Structure that count occurences of `T` elements
-#[derive(Debug)]
-pub struct Counter<T> {
-    state: BTreeMap<T, u128>,
+pub struct Counter<T, C = u128, S = BTreeMap<T, C>> {
+    state: S,
+    marker: PhantomData<(T, C)>,
 }
 
-impl<T: Ord + Debug> Counter<T> {
+/// `Counter` of `Hash` elements.
+///
+/// # Examples
+/// ```
+/// # use reducto::counter::HashCounter;
+/// let words = "the cat and the hat";
+/// let counter: HashCounter<&str, u32> = words.split(' ').collect();
+/// assert_eq!(counter["the"], 2);
+/// assert_eq!(counter.most_common(1), vec![(&"the", 2)]);
+/// ```
+pub type HashCounter<T, C = u128> = Counter<T, C, HashMap<T, C>>;
+
+/// `Counter` of bytes or other small integers, stored in an array.
+///
+/// # Examples
+/// ```
+/// # use reducto::counter::DenseCounter;
+/// let counter: DenseCounter<u8> = b"abracadabra".iter().collect();
+/// assert_eq!(counter[b'a'], 5);
+/// assert_eq!(counter[b'z'], 0);
+/// assert_eq!(counter.len(), 5);
+/// let bytes: Vec<u8> = counter.iter().map(|(byte, _)| *byte).collect();
+/// assert_eq!(bytes, b"abcdr");
+/// ```
+pub type DenseCounter<T, C = u64> = Counter<T, C, Dense<T, C>>;
+
+impl<T, C: Count, S: Storage<T, C>> Counter<T, C, S> {
     /// Create a new empty `Counter`.
     ///
     /// # Examples
@@ -22,7 +411,8 @@ impl<T: Ord + Debug> Counter<T> {
     /// ```
     pub fn new() -> Self {
         Counter {
-            state: BTreeMap::new(),
+            state: S::default(),
+            marker: PhantomData,
         }
     }
 
@@ -52,8 +442,7 @@ impl<T: Ord + Debug> Counter<T> {
         I: IntoIterator<Item = T>,
     {
         for elem in iter {
-            let count = self.state.entry(elem).or_insert(0);
-            *count += 1;
+            *self.state.entry(elem) += C::ONE;
         }
     }
 
@@ -73,54 +462,403 @@ impl<T: Ord + Debug> Counter<T> {
     /// assert_eq!(counter['a'], 1);
     /// ```
     pub fn update_from_value(&mut self, elem: T) {
-        let count = self.state.entry(elem).or_insert(0);
-        *count += 1;
+        *self.state.entry(elem) += C::ONE;
+    }
+
+    /// Lower the counts with the elements of an iterator, stopping at zero.
+    ///
+    /// Unlike `-`, elements whose count reaches zero are kept.
+    ///
+    /// # Arguments
+    /// * iter - An iterator of the elements to remove
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::counter::Counter;
+    /// let mut counter: Counter<char> = "abracadabra".chars().collect();
+    /// counter.subtract_from_iter("aaaaaaabc".chars());
+    /// assert_eq!(counter['a'], 0);
+    /// assert_eq!(counter['b'], 1);
+    /// assert_eq!(counter['c'], 0);
+    /// assert_eq!(counter.len(), 5);
+    /// ```
+    pub fn subtract_from_iter<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        for elem in iter {
+            if let Some(count) = self.state.get_mut(&elem) {
+                *count = count.saturating_sub(C::ONE);
+            }
+        }
+    }
+
+    /// Lower the counts by the ones of `other`, stopping at zero.
+    ///
+    /// Unlike `-`, elements whose count reaches zero are kept.
+    ///
+    /// # Arguments
+    /// * other - The counts to remove
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::counter::Counter;
+    /// let mut counter: Counter<char> = "aaab".chars().collect();
+    /// counter.subtract(&"abbc".chars().collect());
+    /// assert_eq!(counter['a'], 2);
+    /// assert_eq!(counter['b'], 0);
+    /// assert_eq!(counter.len(), 2);
+    /// ```
+    pub fn subtract(&mut self, other: &Counter<T, C, S>) {
+        for (elem, other_count) in other.state.iter() {
+            if let Some(count) = self.state.get_mut(elem) {
+                *count = count.saturating_sub(*other_count);
+            }
+        }
+    }
+
+    /// Return the `n` most common elements and their counts, from the most common.
+    ///
+    /// Elements with the same count are in the order of the storage, so
+    /// ordered by value except with a `HashMap`.
+    ///
+    /// # Arguments
+    /// * n - The number of elements to return, all of them if there are fewer
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::counter::Counter;
+    /// let counter: Counter<char> = "abracadabra".chars().collect();
+    /// assert_eq!(counter.most_common(3), vec![(&'a', 5), (&'b', 2), (&'r', 2)]);
+    /// assert_eq!(counter.most_common(10).len(), 5);
+    /// ```
+    pub fn most_common(&self, n: usize) -> Vec<(&T, C)> {
+        let mut elements: Vec<(&T, C)> = self
+            .state
+            .iter()
+            .map(|(elem, count)| (elem, *count))
+            .collect();
+        elements.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        elements.truncate(n);
+        elements
+    }
+
+    /// Sum of the counts.
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::counter::Counter;
+    /// let counter: Counter<char> = "abracadabra".chars().collect();
+    /// assert_eq!(counter.total(), 11);
+    /// ```
+    pub fn total(&self) -> C {
+        self.state
+            .iter()
+            .fold(C::ZERO, |total, (_, count)| total + *count)
+    }
+
+    /// Iterate over the elements, each one repeated as many times as its count.
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::counter::Counter;
+    /// let counter: Counter<char> = "abracadabra".chars().collect();
+    /// let elements: String = counter.elements().collect();
+    /// assert_eq!(elements, "aaaaabbcdrr");
+    /// ```
+    pub fn elements(&self) -> impl Iterator<Item = &T> {
+        self.state
+            .iter()
+            .flat_map(|(elem, count)| std::iter::repeat_n(elem, count.as_usize()))
+    }
+
+    /// Keep only the elements for which `f` returns `true`.
+    ///
+    /// # Arguments
+    /// * f - Called with each element and its count
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::counter::Counter;
+    /// let mut counter: Counter<char> = "aab".chars().collect();
+    /// counter.subtract_from_iter("b".chars());
+    /// counter.retain(|_, count| count > 0);
+    /// assert_eq!(counter.len(), 1);
+    /// ```
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T, C) -> bool,
+    {
+        self.state.retain(|elem, count| f(elem, *count));
+    }
+
+    /// Iterate over the elements and their counts.
+    pub fn iter(&self) -> S::Iter<'_> {
+        self.state.iter()
     }
 
     pub fn len(&self) -> usize {
         self.state.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.state.is_empty()
+    }
+
+    /// Shannon entropy of the counts, in bits per element.
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::counter::Counter;
+    /// let counter: Counter<char> = "aabbccdd".chars().collect();
+    /// assert_eq!(counter.entropy(), 2.0);
+    /// let counter: Counter<char> = "aaaa".chars().collect();
+    /// assert_eq!(counter.entropy(), 0.0);
+    /// ```
+    pub fn entropy(&self) -> f64 {
+        let total = self.total().as_f64();
+        if total == 0.0 {
+            return 0.0;
+        }
+        self.state
+            .iter()
+            .filter(|(_, count)| **count > C::ZERO)
+            .map(|(_, count)| count.as_f64() / total * (total / count.as_f64()).log2())
+            .sum()
+    }
+
+    /// Smallest size, in bits, of the elements coded by an order-0 model of these counts.
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::counter::Counter;
+    /// let counter: Counter<char> = "aabbccdd".chars().collect();
+    /// assert_eq!(counter.minimum_bits(), 16.0);
+    /// ```
+    pub fn minimum_bits(&self) -> f64 {
+        self.entropy() * self.total().as_f64()
+    }
+
+    /// Size, in bits, of the elements coded by a Huffman code built from these counts.
+    ///
+    /// It is at most one bit per element more than `minimum_bits`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::counter::Counter;
+    /// let counter: Counter<char> = "aaaaaabc".chars().collect();
+    /// // 'a' gets a code of 1 bit, 'b' and 'c' codes of 2 bits
+    /// assert_eq!(counter.huffman_bits(), 10);
+    /// assert!(counter.minimum_bits() < 10.0);
+    /// ```
+    pub fn huffman_bits(&self) -> u64 {
+        let frequencies: Vec<u64> = self.state.iter().map(|(_, count)| count.as_u64()).collect();
+        if frequencies.iter().all(|frequency| *frequency == 0) {
+            return 0;
+        }
+        code_lengths(&frequencies, 63)
+            .iter()
+            .zip(&frequencies)
+            .map(|(length, frequency)| u64::from(*length) * frequency)
+            .sum()
+    }
+
+    /// Pearson's chi-square statistic of the counts against a uniform distribution.
+    ///
+    /// Random data has a statistic close to `alphabet_size - 1`, much larger
+    /// values telling the elements are biased.
+    ///
+    /// # Arguments
+    /// * alphabet_size - Number of possible elements, including the ones never counted
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::counter::Counter;
+    /// let counter: Counter<u8> = [0, 1, 2, 3].iter().collect();
+    /// assert_eq!(counter.chi_square(4), 0.0);
+    /// let counter: Counter<u8> = [0, 0, 0, 0].iter().collect();
+    /// assert_eq!(counter.chi_square(4), 12.0);
+    /// ```
+    pub fn chi_square(&self, alphabet_size: usize) -> f64 {
+        assert!(alphabet_size >= self.len());
+        let expected = self.total().as_f64() / alphabet_size as f64;
+        if expected == 0.0 {
+            return 0.0;
+        }
+        let missing = (alphabet_size - self.len()) as f64 * expected;
+        self.state
+            .iter()
+            .map(|(_, count)| (count.as_f64() - expected).powi(2) / expected)
+            .sum::<f64>()
+            + missing
+    }
+
+    /// Add the counts of `other`, keeping the elements whose count is zero unlike `+`.
+    ///
+    /// # Arguments
+    /// * other - The counter to merge, like the one of another shard of the data
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::counter::Counter;
+    /// let mut counter: Counter<char> = "aab".chars().collect();
+    /// counter.subtract_from_iter("b".chars());
+    /// counter.merge("ac".chars().collect());
+    /// assert_eq!(counter['a'], 3);
+    /// assert_eq!(counter['b'], 0);
+    /// assert_eq!(counter.len(), 3);
+    /// ```
+    pub fn merge(&mut self, other: Counter<T, C, S>) {
+        for (elem, count) in other.state.into_entries() {
+            *self.state.entry(elem) += count;
+        }
+    }
+
+    /// Drop the elements whose count is zero, like the operators do.
+    fn retain_positive(&mut self) {
+        self.state.retain(|_, count| *count > C::ZERO);
+    }
 }
 
-impl<T: Ord + Debug> FromIterator<T> for Counter<T> {
+impl<T, C, S: Debug> Debug for Counter<T, C, S> {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter
+            .debug_struct("Counter")
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl<T, C, S: Clone> Clone for Counter<T, C, S> {
+    fn clone(&self) -> Self {
+        Counter {
+            state: self.state.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, C, S> Counter<T, C, S>
+where
+    T: Copy + Send + Sync,
+    C: Count + Send,
+    S: Storage<T, C> + Send,
+{
+    /// Count `data` split in chunks counted on `threads` threads, then merged.
+    ///
+    /// # Arguments
+    /// * data - The elements to count
+    /// * threads - The number of threads, one chunk being counted by each one
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::counter::{Counter, DenseCounter};
+    /// let data = std::fs::read("resources/lorem.txt").unwrap();
+    /// let counter: DenseCounter<u8> = Counter::from_slice_parallel(&data, 4);
+    /// assert_eq!(counter, data.iter().collect());
+    /// assert_eq!(counter.total(), data.len() as u64);
+    /// ```
+    pub fn from_slice_parallel(data: &[T], threads: usize) -> Self {
+        assert!(threads > 0);
+        let chunk_size = data.len().div_ceil(threads).max(1);
+        thread::scope(|scope| {
+            let workers: Vec<_> = data
+                .chunks(chunk_size)
+                .map(|chunk| scope.spawn(move || chunk.iter().collect::<Counter<T, C, S>>()))
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().expect("counting thread panicked"))
+                .sum()
+        })
+    }
+}
+
+impl<T, C: Count, S: Storage<T, C>> Default for Counter<T, C, S> {
+    fn default() -> Self {
+        Counter::new()
+    }
+}
+
+impl<T, C: Count, S: Storage<T, C>> FromIterator<T> for Counter<T, C, S> {
     fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
-        let mut counter: Counter<T> = Counter::new();
-        counter.update_from_iter(iter.into_iter());
+        let mut counter: Counter<T, C, S> = Counter::new();
+        counter.update_from_iter(iter);
+        counter
+    }
+}
+
+impl<'a, T: 'a + Copy, C: Count, S: Storage<T, C>> FromIterator<&'a T> for Counter<T, C, S> {
+    fn from_iter<I: IntoIterator<Item = &'a T>>(iter: I) -> Self {
+        let mut counter: Counter<T, C, S> = Counter::new();
+        counter.update_from_iter(iter.into_iter().copied());
+        counter
+    }
+}
+
+/// Merge counters, like the ones of the shards of some data.
+///
+/// # Examples
+/// ```
+/// # use reducto::counter::HashCounter;
+/// let shards = ["to be", "or not", "to be"];
+/// let counter: HashCounter<char, u32> = shards.iter().map(|shard| shard.chars().collect()).sum();
+/// assert_eq!(counter['o'], 4);
+/// assert_eq!(counter.total(), 16);
+/// ```
+impl<T, C: Count, S: Storage<T, C>> Sum for Counter<T, C, S> {
+    fn sum<I: Iterator<Item = Counter<T, C, S>>>(iter: I) -> Self {
+        let mut counter: Counter<T, C, S> = Counter::new();
+        for other in iter {
+            counter.merge(other);
+        }
         counter
     }
 }
 
-impl<T: Ord + Debug> IntoIterator for Counter<T> {
-    type Item = (T, u128);
-    type IntoIter = IntoIter<T, u128>;
+impl<T, C: Count, S: Storage<T, C>> Extend<T> for Counter<T, C, S> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.update_from_iter(iter);
+    }
+}
+
+impl<'a, T: 'a + Copy, C: Count, S: Storage<T, C>> Extend<&'a T> for Counter<T, C, S> {
+    fn extend<I: IntoIterator<Item = &'a T>>(&mut self, iter: I) {
+        self.update_from_iter(iter.into_iter().copied());
+    }
+}
+
+impl<T, C: Count, S: Storage<T, C>> IntoIterator for Counter<T, C, S> {
+    type Item = (T, C);
+    type IntoIter = S::IntoIter;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.state.into_iter()
+        self.state.into_entries()
     }
 }
 
-impl<'a, T: Ord + Debug> IntoIterator for &'a Counter<T> {
-    type Item = (&'a T, &'a u128);
-    type IntoIter = Iter<'a, T, u128>;
+impl<'a, T: 'a, C: Count + 'a, S: Storage<T, C> + 'a> IntoIterator for &'a Counter<T, C, S> {
+    type Item = (&'a T, &'a C);
+    type IntoIter = S::Iter<'a>;
 
     fn into_iter(self) -> Self::IntoIter {
         self.state.iter()
     }
 }
 
-impl<T: Ord + Debug> Index<T> for Counter<T> {
-    type Output = u128;
+impl<T, C: Count, S: Storage<T, C>> Index<T> for Counter<T, C, S> {
+    type Output = C;
 
     fn index(&self, index: T) -> &Self::Output {
         match self.state.get(&index) {
             Some(value) => value,
-            None => &0,
+            None => C::zero_ref(),
         }
     }
 }
 
-impl<T: Ord + Debug> PartialEq for Counter<T> {
-    fn eq(&self, other: &Counter<T>) -> bool {
+impl<T, C: Count, S: Storage<T, C>> PartialEq for Counter<T, C, S> {
+    fn eq(&self, other: &Counter<T, C, S>) -> bool {
         if self.state.len() == other.state.len() {
             for (key, value) in self.state.iter() {
                 let other_value = match other.state.get(key) {
@@ -139,4 +877,288 @@ impl<T: Ord + Debug> PartialEq for Counter<T> {
     }
 }
 
-impl<T: Ord + Debug> Eq for Counter<T> {}
+impl<T, C: Count, S: Storage<T, C>> Eq for Counter<T, C, S> {}
+
+/// Serialized as a map of the elements to their counts, whatever the storage.
+///
+/// # Examples
+/// ```
+/// # #[cfg(feature = "serde")]
+/// # {
+/// # use reducto::counter::{Counter, DenseCounter};
+/// let counter: Counter<char> = "abracadabra".chars().collect();
+/// let json = serde_json::to_string(&counter).unwrap();
+/// assert_eq!(json, r#"{"a":5,"b":2,"c":1,"d":1,"r":2}"#);
+///
+/// let dense: DenseCounter<u8> = serde_json::from_str(r#"{"97":5,"98":2}"#).unwrap();
+/// assert_eq!(dense[b'a'], 5);
+/// assert_eq!(dense.total(), 7);
+/// # }
+/// ```
+#[cfg(feature = "serde")]
+impl<T, C, S> Serialize for Counter<T, C, S>
+where
+    T: Serialize,
+    C: Count + Serialize,
+    S: Storage<T, C>,
+{
+    fn serialize<Z: Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_map(self.state.iter())
+    }
+}
+
+/// Counts of repeated elements are summed.
+#[cfg(feature = "serde")]
+impl<'de, T, C, S> Deserialize<'de> for Counter<T, C, S>
+where
+    T: Deserialize<'de>,
+    C: Count + Deserialize<'de>,
+    S: Storage<T, C>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(CounterVisitor(PhantomData))
+    }
+}
+
+#[cfg(feature = "serde")]
+struct CounterVisitor<T, C, S>(PhantomData<(T, C, S)>);
+
+#[cfg(feature = "serde")]
+impl<'de, T, C, S> Visitor<'de> for CounterVisitor<T, C, S>
+where
+    T: Deserialize<'de>,
+    C: Count + Deserialize<'de>,
+    S: Storage<T, C>,
+{
+    type Value = Counter<T, C, S>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a map of elements to their counts")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut counter: Counter<T, C, S> = Counter::new();
+        while let Some((elem, count)) = map.next_entry::<T, C>()? {
+            *counter.state.entry(elem) += count;
+        }
+        Ok(counter)
+    }
+}
+
+/// Sum the counts.
+///
+/// # Examples
+/// ```
+/// # use reducto::counter::Counter;
+/// let mut shards: Vec<Counter<u8>> = vec![b"abc".iter().collect(), b"bcd".iter().collect()];
+/// let merged = &shards[0] + &shards[1];
+/// assert_eq!(merged[b'b'], 2);
+/// assert_eq!(merged[b'd'], 1);
+///
+/// let mut total: Counter<u8> = Counter::new();
+/// for shard in shards.drain(..) {
+///     total += shard;
+/// }
+/// assert_eq!(total, merged);
+/// ```
+impl<T, C: Count, S: Storage<T, C>> AddAssign for Counter<T, C, S> {
+    fn add_assign(&mut self, other: Counter<T, C, S>) {
+        for (elem, count) in other.state.into_entries() {
+            *self.state.entry(elem) += count;
+        }
+        self.retain_positive();
+    }
+}
+
+impl<'a, T: Clone, C: Count, S: Storage<T, C>> AddAssign<&'a Counter<T, C, S>>
+    for Counter<T, C, S>
+{
+    fn add_assign(&mut self, other: &'a Counter<T, C, S>) {
+        for (elem, count) in other.state.iter() {
+            *self.state.entry(elem.clone()) += *count;
+        }
+        self.retain_positive();
+    }
+}
+
+/// Subtract the counts, dropping the elements that are not positive anymore.
+///
+/// # Examples
+/// ```
+/// # use reducto::counter::Counter;
+/// let counter: Counter<char> = "aaabc".chars().collect();
+/// let difference = counter - "abbd".chars().collect();
+/// assert_eq!(difference, "aac".chars().collect());
+/// ```
+impl<T, C: Count, S: Storage<T, C>> SubAssign for Counter<T, C, S> {
+    fn sub_assign(&mut self, other: Counter<T, C, S>) {
+        *self -= &other;
+    }
+}
+
+impl<'a, T, C: Count, S: Storage<T, C>> SubAssign<&'a Counter<T, C, S>> for Counter<T, C, S> {
+    fn sub_assign(&mut self, other: &'a Counter<T, C, S>) {
+        self.subtract(other);
+        self.retain_positive();
+    }
+}
+
+/// Keep the minimum of the counts, so the elements of both counters.
+///
+/// # Examples
+/// ```
+/// # use reducto::counter::Counter;
+/// let counter: Counter<char> = "aaabc".chars().collect();
+/// let intersection = counter & "abbd".chars().collect();
+/// assert_eq!(intersection, "ab".chars().collect());
+/// ```
+impl<T, C: Count, S: Storage<T, C>> BitAndAssign for Counter<T, C, S> {
+    fn bitand_assign(&mut self, other: Counter<T, C, S>) {
+        *self &= &other;
+    }
+}
+
+impl<'a, T, C: Count, S: Storage<T, C>> BitAndAssign<&'a Counter<T, C, S>> for Counter<T, C, S> {
+    fn bitand_assign(&mut self, other: &'a Counter<T, C, S>) {
+        self.state.retain(|elem, count| {
+            *count = min(*count, other.state.get(elem).copied().unwrap_or(C::ZERO));
+            *count > C::ZERO
+        });
+    }
+}
+
+/// Keep the maximum of the counts, so the elements of either counter.
+///
+/// # Examples
+/// ```
+/// # use reducto::counter::Counter;
+/// let counter: Counter<char> = "aaabc".chars().collect();
+/// let union = counter | "abbd".chars().collect();
+/// assert_eq!(union, "aaabbcd".chars().collect());
+/// ```
+impl<T, C: Count, S: Storage<T, C>> BitOrAssign for Counter<T, C, S> {
+    fn bitor_assign(&mut self, other: Counter<T, C, S>) {
+        for (elem, count) in other.state.into_entries() {
+            let current = self.state.entry(elem);
+            *current = max(*current, count);
+        }
+        self.retain_positive();
+    }
+}
+
+impl<'a, T: Clone, C: Count, S: Storage<T, C>> BitOrAssign<&'a Counter<T, C, S>>
+    for Counter<T, C, S>
+{
+    fn bitor_assign(&mut self, other: &'a Counter<T, C, S>) {
+        for (elem, count) in other.state.iter() {
+            let current = self.state.entry(elem.clone());
+            *current = max(*current, *count);
+        }
+        self.retain_positive();
+    }
+}
+
+/// Implement the binary operator `$trait` on owned counters and references from `$assign_trait`.
+macro_rules! counter_operator {
+    ($trait:ident, $method:ident, $assign_trait:ident, $assign_method:ident) => {
+        impl<T, C: Count, S: Storage<T, C>> $trait for Counter<T, C, S> {
+            type Output = Counter<T, C, S>;
+
+            fn $method(mut self, other: Counter<T, C, S>) -> Counter<T, C, S> {
+                $assign_trait::$assign_method(&mut self, other);
+                self
+            }
+        }
+
+        impl<'a, 'b, T: Clone, C: Count, S: Storage<T, C> + Clone> $trait<&'b Counter<T, C, S>>
+            for &'a Counter<T, C, S>
+        {
+            type Output = Counter<T, C, S>;
+
+            fn $method(self, other: &'b Counter<T, C, S>) -> Counter<T, C, S> {
+                let mut result = self.clone();
+                $assign_trait::$assign_method(&mut result, other);
+                result
+            }
+        }
+    };
+}
+
+counter_operator!(Add, add, AddAssign, add_assign);
+counter_operator!(Sub, sub, SubAssign, sub_assign);
+counter_operator!(BitAnd, bitand, BitAndAssign, bitand_assign);
+counter_operator!(BitOr, bitor, BitOrAssign, bitor_assign);
+
+/// Counter of a small alphabet shared between threads, each one updating it through a reference.
+///
+/// The counts are atomic integers. `update_from_iter` first counts locally, so
+/// there is a single atomic addition per element of the alphabet.
+///
+/// # Examples
+/// ```
+/// # use reducto::counter::{AtomicCounter, DenseCounter};
+/// let data = std::fs::read("resources/lorem.txt").unwrap();
+/// let shared: AtomicCounter<u8> = AtomicCounter::new();
+/// std::thread::scope(|scope| {
+///     for chunk in data.chunks(10_000) {
+///         let shared = &shared;
+///         scope.spawn(move || shared.update_from_iter(chunk.iter().copied()));
+///     }
+/// });
+/// let counter: DenseCounter<u8> = shared.into_counter();
+/// assert_eq!(counter, data.iter().collect());
+/// ```
+#[derive(Debug)]
+pub struct AtomicCounter<T> {
+    counts: Vec<AtomicU64>,
+    marker: PhantomData<fn(T)>,
+}
+
+impl<T: DenseKey> AtomicCounter<T> {
+    /// Create a new `AtomicCounter` where every count is zero.
+    pub fn new() -> Self {
+        AtomicCounter {
+            counts: T::keys().iter().map(|_| AtomicU64::new(0)).collect(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Update the counter with a value.
+    pub fn update_from_value(&self, elem: T) {
+        self.counts[elem.index()].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Update the counter with an iterator.
+    pub fn update_from_iter<I>(&self, iter: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let local: DenseCounter<T> = iter.into_iter().collect();
+        for (elem, count) in &local {
+            self.counts[elem.index()].fetch_add(*count, Ordering::Relaxed);
+        }
+    }
+
+    /// Count of `elem` so far.
+    pub fn get(&self, elem: T) -> u64 {
+        self.counts[elem.index()].load(Ordering::Relaxed)
+    }
+
+    /// Convert into a `DenseCounter` holding the elements seen at least once.
+    pub fn into_counter(self) -> DenseCounter<T> {
+        let mut counter: DenseCounter<T> = Counter::new();
+        for (elem, count) in T::keys().iter().zip(self.counts) {
+            let count = count.into_inner();
+            if count > 0 {
+                *counter.state.entry(*elem) += count;
+            }
+        }
+        counter
+    }
+}
+
+impl<T: DenseKey> Default for AtomicCounter<T> {
+    fn default() -> Self {
+        AtomicCounter::new()
+    }
+}
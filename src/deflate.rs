@@ -1,35 +1,1010 @@
-use crate::huffman::Huffman;
-use crate::lz77::LZ77;
+//! DEFLATE compressor and decompressor
+//!
+//! `Deflate` chains the `LZ77` triples and the `Huffman` coder of this crate,
+//! its output being a string of `0` and `1` characters.
+//!
+//! `RawDeflate` implements the raw format of RFC 1951, shared by ZIP, gzip and
+//! zlib. The data is a sequence of blocks, each stored or made of literals and
+//! `(length, distance)` matches coded with the fixed Huffman codes or with
+//! codes described at the start of the block.
+//!
+//! Matches are found by the hash chains of the `lz77` module, with the lazy
+//! evaluation of zlib on the higher levels.
+//!
+//! Like pigz, the input can be split in blocks compressed on several threads,
+//! each one using the 32 KiB before it as dictionary. Every block but the last
+//! ends with an empty stored block so they stay aligned on bytes and their
+//! outputs can be concatenated.
+//!
+//! A preset dictionary primes the history with data resembling the input, so
+//! even the first bytes of short messages can be matches. The decoder needs
+//! the same dictionary.
+use std::cmp::min;
+use std::io::{Read, Result};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
+use crate::bits::{invalid_data, BitRead, LsbBitReader, LsbBitWriter};
+use crate::huffman::{canonical_codes, code_lengths, Huffman, HuffmanTable};
+use crate::lz77::{self, MatchFinder, HASH_LENGTH, LZ77};
+use crate::options::{CompressionOptions, Strategy};
+
+/// Largest distance of a match.
+const WINDOW_SIZE: usize = 1 << 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// Matches of 3 bytes further than this cost more than their literals, as in zlib.
+const TOO_FAR: usize = 4096;
+/// Size of the blocks compressed in parallel.
+const DEFAULT_BLOCK_SIZE: usize = 128 * 1024;
+/// Chain depth, nice length and lazy matching of every level.
+const LEVELS: [(usize, usize, bool); 10] = [
+    (0, 0, false),
+    (4, 8, false),
+    (8, 16, false),
+    (32, 32, false),
+    (16, 16, true),
+    (32, 32, true),
+    (128, 128, true),
+    (256, 128, true),
+    (1024, MAX_MATCH, true),
+    (4096, MAX_MATCH, true),
+];
+const MAX_STORED_SIZE: usize = 0xffff;
+/// Number of tokens of a compressed block.
+const BLOCK_TOKENS: usize = 1 << 14;
+/// Output decoded in advance by the `Inflater`.
+const OUTPUT_CHUNK: usize = 1 << 16;
+
+const BLOCK_STORED: u32 = 0;
+const BLOCK_FIXED: u32 = 1;
+const BLOCK_DYNAMIC: u32 = 2;
+
+const END_OF_BLOCK: usize = 256;
+const LITERAL_LENGTH_CODES: usize = 286;
+const DISTANCE_CODES: usize = 30;
+const CODE_LENGTH_CODES: usize = 19;
+const MAX_CODE_LENGTH: u8 = 15;
+const MAX_CODE_LENGTH_CODE_LENGTH: u8 = 7;
+/// Order in which the code length code lengths are stored.
+const CODE_LENGTH_ORDER: [usize; CODE_LENGTH_CODES] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+fn fixed_literal_lengths() -> [u8; 288] {
+    let mut lengths = [8; 288];
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths
+}
+
+fn fixed_distance_lengths() -> [u8; 32] {
+    [5; 32]
+}
+
+/// Symbol and extra bits of a match length.
+fn length_code(length: usize) -> (usize, u32) {
+    let code = LENGTH_BASES.partition_point(|base| *base as usize <= length) - 1;
+    (code, (length - LENGTH_BASES[code] as usize) as u32)
+}
+
+/// Symbol and extra bits of a match distance.
+fn distance_code(distance: usize) -> (usize, u32) {
+    let code = DISTANCE_BASES.partition_point(|base| *base as usize <= distance) - 1;
+    (code, (distance - DISTANCE_BASES[code] as usize) as u32)
+}
+
+/// Output token of the match finder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+/// Codes of one alphabet, bit reversed to be written least significant bit first.
+#[derive(Debug)]
+struct Codes {
+    lengths: Vec<u8>,
+    codes: Vec<u32>,
+}
+
+impl Codes {
+    fn new(lengths: &[u8]) -> Codes {
+        let codes = canonical_codes(lengths)
+            .iter()
+            .zip(lengths)
+            .map(|(code, length)| match *length {
+                0 => 0,
+                length => code.reverse_bits() >> (32 - length),
+            })
+            .collect();
+        Codes {
+            lengths: lengths.to_vec(),
+            codes,
+        }
+    }
+
+    fn write(&self, writer: &mut LsbBitWriter, symbol: usize) {
+        writer.write_bits(self.codes[symbol], self.lengths[symbol]);
+    }
+
+    /// Number of bits of the symbols counted in `frequencies`.
+    fn cost(&self, frequencies: &[u64]) -> u64 {
+        frequencies
+            .iter()
+            .zip(&self.lengths)
+            .map(|(frequency, length)| frequency * u64::from(*length))
+            .sum()
+    }
+}
+
+/// Code lengths limited to `max_length` bits, with at least two codes so that every decoder accepts them.
+fn complete_code_lengths(frequencies: &[u64], max_length: u8) -> Vec<u8> {
+    let mut frequencies = frequencies.to_vec();
+    for symbol in 0..2 {
+        if frequencies.iter().filter(|f| **f > 0).count() < 2 && frequencies[symbol] == 0 {
+            frequencies[symbol] = 1;
+        }
+    }
+    code_lengths(&frequencies, max_length)
+}
+
+/// Run-length encode code lengths as symbols of the code length alphabet and their extra bits.
+fn encode_lengths(lengths: &[u8]) -> Vec<(usize, u32)> {
+    let mut symbols: Vec<(usize, u32)> = Vec::new();
+    let mut index = 0;
+    while index < lengths.len() {
+        let length = lengths[index];
+        let run = lengths[index..]
+            .iter()
+            .take_while(|l| **l == length)
+            .count();
+        index += run;
+        let mut run = run;
+        if length == 0 {
+            while run >= 11 {
+                let count = min(run, 138);
+                symbols.push((18, (count - 11) as u32));
+                run -= count;
+            }
+            if run >= 3 {
+                symbols.push((17, (run - 3) as u32));
+                run = 0;
+            }
+        } else {
+            symbols.push((length as usize, 0));
+            run -= 1;
+            while run >= 3 {
+                let count = min(run, 6);
+                symbols.push((16, (count - 3) as u32));
+                run -= count;
+            }
+        }
+        symbols.extend(std::iter::repeat_n((length as usize, 0), run));
+    }
+    symbols
+}
+
+fn code_length_extra_bits(symbol: usize) -> u8 {
+    match symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0,
+    }
+}
+
+/// RFC 1951 DEFLATE codec.
+///
+/// # Examples
+/// ```
+/// # use reducto::deflate::RawDeflate;
+/// let deflate = RawDeflate::new(6);
+/// let data = b"hello hello hello hello hello hello";
+/// let compressed = deflate.compress(data);
+/// assert!(compressed.len() < data.len());
+/// assert_eq!(deflate.decompress(&compressed).unwrap(), &data[..]);
+///
+/// // "hello hello hello hello" compressed by zlib
+/// let compressed = [0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x27, 0x01];
+/// assert_eq!(deflate.decompress(&compressed).unwrap(), b"hello hello hello hello");
+///
+/// let lorem = std::fs::read("resources/lorem.txt").unwrap();
+/// let parallel = RawDeflate::new(6).threads(4).block_size(16 * 1024);
+/// let compressed = parallel.compress(&lorem);
+/// assert!(compressed.len() < deflate.compress(&lorem).len() * 11 / 10);
+/// assert_eq!(deflate.decompress(&compressed).unwrap(), lorem);
+///
+/// let event = br#"{"type":"click","page":"/home","user":42}"#;
+/// let primed = RawDeflate::new(9).dictionary(br#"{"type":"click","page":"/"#);
+/// let compressed = primed.compress(event);
+/// assert!(compressed.len() < deflate.compress(event).len());
+/// assert_eq!(primed.decompress(&compressed).unwrap(), &event[..]);
+/// assert!(deflate.decompress(&compressed).is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct RawDeflate {
+    options: CompressionOptions,
+    threads: usize,
+    dictionary: Vec<u8>,
+}
+
+impl RawDeflate {
+    /// Create a new `RawDeflate`.
+    ///
+    /// # Arguments
+    /// * level - From 0 (stored) and 1 (fastest) to 9 (best)
+    pub fn new(level: u8) -> RawDeflate {
+        RawDeflate::with_options(CompressionOptions::new(level))
+    }
+
+    /// Create a new `RawDeflate` tuned by `options`.
+    ///
+    /// The level sets the chain depth, the nice length and the lazy matching
+    /// like in zlib, unless they are set. The history is at most 32 KiB and the
+    /// matches from 3 to 258 bytes long, the options out of these bounds being
+    /// clamped, and the nice length lies between the shortest and the longest
+    /// match.
+    pub fn with_options(options: CompressionOptions) -> RawDeflate {
+        RawDeflate {
+            options,
+            threads: 1,
+            dictionary: Vec::new(),
+        }
+    }
+
+    /// Set the preset dictionary that matches can refer to, only its last 32 KiB are used.
+    pub fn dictionary(mut self, dictionary: &[u8]) -> RawDeflate {
+        let start = dictionary.len().saturating_sub(WINDOW_SIZE);
+        self.dictionary = dictionary[start..].to_vec();
+        self
+    }
+
+    /// Set the number of threads compressing the blocks of the input, 1 by default.
+    pub fn threads(mut self, threads: usize) -> RawDeflate {
+        assert!(threads > 0);
+        self.threads = threads;
+        self
+    }
+
+    /// Set the size of the blocks compressed in parallel, 128 KiB by default.
+    pub fn block_size(mut self, block_size: usize) -> RawDeflate {
+        self.options = self.options.block_size(block_size);
+        self
+    }
+
+    /// Compress `data` into raw DEFLATE blocks.
+    pub fn compress(&self, data: &[u8]) -> Vec<u8> {
+        if self.threads > 1 {
+            return self
+                .compress_parallel(data, |_| ())
+                .into_iter()
+                .flat_map(|(compressed, _)| compressed)
+                .collect();
+        }
+        let mut writer = LsbBitWriter::new();
+        self.compress_primed(&mut writer, data, true);
+        writer.finish()
+    }
+
+    /// Decompress raw DEFLATE blocks, ignoring the data after the last block.
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut output: Vec<u8> = Vec::new();
+        Inflater::with_dictionary(data, &self.dictionary).read_to_end(&mut output)?;
+        Ok(output)
+    }
+
+    /// Write the blocks compressing `data`, after the preset dictionary.
+    fn compress_primed(&self, writer: &mut LsbBitWriter, data: &[u8], last: bool) {
+        if self.dictionary.is_empty() {
+            self.compress_into(writer, data, 0, last);
+            return;
+        }
+        let primed = [&self.dictionary[..], data].concat();
+        self.compress_into(writer, &primed, self.dictionary.len(), last);
+    }
+
+    /// Write the blocks compressing `data[start..]`, matches may refer to `data[..start]`.
+    ///
+    /// # Arguments
+    /// * writer - Destination of the blocks
+    /// * data - History followed by the data to compress
+    /// * start - Position of the first byte to compress
+    /// * last - Whether the last block ends the stream
+    pub(crate) fn compress_into(
+        &self,
+        writer: &mut LsbBitWriter,
+        data: &[u8],
+        start: usize,
+        last: bool,
+    ) {
+        if self.options.level == 0 {
+            write_stored_blocks(writer, &data[start..], last);
+            return;
+        }
+        let tokens = self.tokenize(data, start);
+        if tokens.is_empty() {
+            write_block(writer, &[], &[], last, self.fixed_codes());
+            return;
+        }
+        let mut position = start;
+        let block_count = tokens.len().div_ceil(BLOCK_TOKENS);
+        for (index, block) in tokens.chunks(BLOCK_TOKENS).enumerate() {
+            let size: usize = block
+                .iter()
+                .map(|token| match token {
+                    Token::Literal(_) => 1,
+                    Token::Match { length, .. } => *length as usize,
+                })
+                .sum();
+            let final_block = last && index + 1 == block_count;
+            let content = &data[position..position + size];
+            write_block(writer, block, content, final_block, self.fixed_codes());
+            position += size;
+        }
+    }
+
+    /// Compress the blocks of `data` on the threads, returning each one with `summary` of its content.
+    ///
+    /// # Arguments
+    /// * data - Data to compress
+    /// * summary - Computed by the threads on the content of each block, like its CRC-32
+    pub(crate) fn compress_parallel<T, F>(&self, data: &[u8], summary: F) -> Vec<(Vec<u8>, T)>
+    where
+        T: Send,
+        F: Fn(&[u8]) -> T + Sync,
+    {
+        let block_size = self.options.block_size.unwrap_or(DEFAULT_BLOCK_SIZE);
+        let count = data.len().div_ceil(block_size).max(1);
+        let compress_block = |index: usize| {
+            let start = index * block_size;
+            let end = min(start + block_size, data.len());
+            let history = start.saturating_sub(WINDOW_SIZE);
+            let last = index + 1 == count;
+            let mut writer = LsbBitWriter::new();
+            match index {
+                0 => self.compress_primed(&mut writer, &data[..end], last),
+                _ => self.compress_into(&mut writer, &data[history..end], start - history, last),
+            }
+            if !last {
+                write_stored_block(&mut writer, &[], false);
+            }
+            (writer.finish(), summary(&data[start..end]))
+        };
+
+        let next = AtomicUsize::new(0);
+        let mut blocks: Vec<(usize, (Vec<u8>, T))> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..min(self.threads, count))
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut done: Vec<(usize, (Vec<u8>, T))> = Vec::new();
+                        loop {
+                            let index = next.fetch_add(1, Ordering::Relaxed);
+                            if index >= count {
+                                return done;
+                            }
+                            done.push((index, compress_block(index)));
+                        }
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("deflate thread panicked"))
+                .collect()
+        });
+        blocks.sort_by_key(|(index, _)| *index);
+        blocks.into_iter().map(|(_, block)| block).collect()
+    }
+
+    fn fixed_codes(&self) -> bool {
+        self.options.strategy == Strategy::Fixed
+    }
+
+    /// Shortest and longest lengths of the matches.
+    fn match_lengths(&self) -> (usize, usize) {
+        let max_length = match self.options.max_match_length {
+            Some(max_length) => max_length.clamp(MIN_MATCH, MAX_MATCH),
+            None => MAX_MATCH,
+        };
+        let min_length = self.options.min_match_length.unwrap_or(MIN_MATCH);
+        let min_length = match self.options.strategy {
+            Strategy::Filtered => min_length.max(6),
+            _ => min_length,
+        };
+        (min_length.clamp(MIN_MATCH, max_length), max_length)
+    }
+
+    /// Split `data[start..]` in literals and matches.
+    fn tokenize(&self, data: &[u8], start: usize) -> Vec<Token> {
+        match self.options.strategy {
+            Strategy::HuffmanOnly => data[start..]
+                .iter()
+                .map(|byte| Token::Literal(*byte))
+                .collect(),
+            Strategy::Rle => self.tokenize_runs(data, start),
+            _ => self.tokenize_matches(data, start),
+        }
+    }
+
+    /// Split `data[start..]` in literals and repetitions of the previous byte.
+    fn tokenize_runs(&self, data: &[u8], start: usize) -> Vec<Token> {
+        let (min_length, max_length) = self.match_lengths();
+        let mut tokens: Vec<Token> = Vec::with_capacity(data.len() - start);
+        let mut position = start;
+        while position < data.len() {
+            let length = match position {
+                0 => 0,
+                _ => data[position..min(position + max_length, data.len())]
+                    .iter()
+                    .take_while(|byte| **byte == data[position - 1])
+                    .count(),
+            };
+            if length >= min_length {
+                tokens.push(Token::Match {
+                    length: length as u16,
+                    distance: 1,
+                });
+                position += length;
+            } else {
+                tokens.push(Token::Literal(data[position]));
+                position += 1;
+            }
+        }
+        tokens
+    }
+
+    /// Split `data[start..]` in literals and matches found in the hash chains.
+    fn tokenize_matches(&self, data: &[u8], start: usize) -> Vec<Token> {
+        let (min_length, max_length) = self.match_lengths();
+        let (chain_depth, nice_length, lazy) = LEVELS[self.options.level as usize];
+        let chain_depth = self.options.chain_depth.unwrap_or(chain_depth);
+        let nice_length = self.options.nice_length.unwrap_or(nice_length);
+        let nice_length = nice_length.clamp(min_length, max_length);
+        let lazy = self.options.lazy_matching.unwrap_or(lazy);
+        let history_size = min(
+            self.options.history_size.unwrap_or(WINDOW_SIZE),
+            WINDOW_SIZE,
+        );
+        let mut finder = MatchFinder::new(data.len(), history_size, chain_depth)
+            .hash_length(min(min_length, HASH_LENGTH));
+        for position in start.saturating_sub(history_size)..start {
+            finder.insert(data, position);
+        }
+        let find = |finder: &MatchFinder, position: usize| {
+            let limit = min(max_length, data.len() - position);
+            finder
+                .find(data, position, min(nice_length, limit))
+                .map(|(distance, mut length)| {
+                    while length < limit
+                        && data[position + length] == data[position + length - distance]
+                    {
+                        length += 1;
+                    }
+                    (distance, length)
+                })
+                .filter(|(distance, length)| {
+                    *length >= min_length && (*length > MIN_MATCH || *distance <= TOO_FAR)
+                })
+        };
+
+        let mut tokens: Vec<Token> = Vec::with_capacity((data.len() - start) / 2);
+        let mut position = start;
+        // match found at `position` by the lazy evaluation of the previous position
+        let mut next: Option<Option<(usize, usize)>> = None;
+        while position < data.len() {
+            let current = match next.take() {
+                Some(found) => found,
+                None => {
+                    let found = find(&finder, position);
+                    finder.insert(data, position);
+                    found
+                }
+            };
+            let (distance, length) = match current {
+                Some(found) => found,
+                None => {
+                    tokens.push(Token::Literal(data[position]));
+                    position += 1;
+                    continue;
+                }
+            };
+            let mut inserted = position + 1;
+            if lazy && length < nice_length && position + 1 < data.len() {
+                let following = find(&finder, position + 1);
+                finder.insert(data, position + 1);
+                inserted += 1;
+                if following.is_some_and(|(_, next_length)| next_length > length) {
+                    tokens.push(Token::Literal(data[position]));
+                    position += 1;
+                    next = Some(following);
+                    continue;
+                }
+            }
+            for skipped in inserted..position + length {
+                finder.insert(data, skipped);
+            }
+            tokens.push(Token::Match {
+                length: length as u16,
+                distance: distance as u16,
+            });
+            position += length;
+        }
+        tokens
+    }
+}
+
+impl Default for RawDeflate {
+    fn default() -> Self {
+        RawDeflate::new(6)
+    }
+}
+
+/// LZ77 and Huffman codec working on strings.
+///
+/// The data is encoded by `LZ77`, its triples are packed in bytes then coded
+/// by `Huffman`. The output is not RFC 1951 DEFLATE, see `RawDeflate` for it.
+///
+/// # Examples
+/// ```
+/// # use reducto::deflate::Deflate;
+/// # use reducto::options::CompressionOptions;
+/// let mut deflate = Deflate::new(4, 6);
+/// let compressed = deflate.compress("aacaacabcabaaac");
+/// assert!(compressed.chars().all(|c| c == '0' || c == '1'));
+/// assert_eq!(deflate.decompress(&compressed), "aacaacabcabaaac");
+///
+/// let lorem = std::fs::read_to_string("resources/small_lorem.txt").unwrap();
+/// let mut fast = Deflate::with_options(CompressionOptions::fast());
+/// let mut best = Deflate::with_options(CompressionOptions::best());
+/// let compressed = best.compress(&lorem);
+/// assert!(compressed.len() < fast.compress(&lorem).len());
+/// assert_eq!(best.decompress(&compressed), lorem);
+/// ```
 #[derive(Debug)]
 pub struct Deflate {
     lz77: LZ77,
+    huffman: Huffman,
+    lookahead_size: usize,
+    history_size: usize,
 }
 
 impl Deflate {
-    pub fn new(window_size: usize, dictionary_size: usize) -> Deflate {
+    /// Create a new `Deflate`.
+    ///
+    /// # Arguments
+    /// * lookahead_size - Longest match of the `LZ77` encoder, at most 255
+    /// * history_size - Largest distance of a match of the `LZ77` encoder, at most 255
+    pub fn new(lookahead_size: usize, history_size: usize) -> Deflate {
         Deflate {
-            lz77: LZ77::new(window_size, dictionary_size),
+            lz77: LZ77::new(lookahead_size, history_size),
+            huffman: Huffman::new(),
+            lookahead_size,
+            history_size,
         }
     }
 
+    /// Create a new `Deflate` with the lookahead and history sizes of `options`, see `LZ77::with_options`.
+    pub fn with_options(options: CompressionOptions) -> Deflate {
+        let (lookahead_size, history_size) = lz77::sizes(&options);
+        Deflate::new(lookahead_size, history_size)
+    }
+
+    /// Compress `data` into a string of `0` and `1`, keeping the Huffman codes to decompress it.
     pub fn compress<S>(&mut self, data: S) -> String
     where
         S: Sized + ToString,
     {
         self.lz77.encode(data);
-        let mut huffman = Huffman::new();
-        let lz77_vec: Vec<u8> = self.lz77.iter().map(|n| n.to_vec_u8()).flatten().collect();
+        let lz77_vec: Vec<u8> = self.lz77.iter().flat_map(|n| n.to_vec_u8()).collect();
         let lz77_string: String = lz77_vec.iter().map(|v| *v as char).collect();
-        huffman.encode(&lz77_string)
+        self.huffman.encode(&lz77_string)
     }
 
+    /// Decompress `data` with the Huffman codes of the last compression.
     pub fn decompress<S>(&self, data: S) -> String
     where
         S: Sized + ToString,
     {
-        let mut huffman = Huffman::new();
-        let huffman_decoded = huffman.decode(&data);
-        self.lz77.decode()
+        let huffman_decoded = self.huffman.decode(&data);
+        let lz77_vec: Vec<u8> = huffman_decoded.chars().map(|c| c as u8).collect();
+        let mut lz77 = LZ77::new(self.lookahead_size, self.history_size);
+        if let Err(reason) = lz77.load(&lz77_vec) {
+            panic!("{}", reason)
+        }
+        lz77.decode()
+    }
+}
+
+fn write_stored_blocks(writer: &mut LsbBitWriter, data: &[u8], last: bool) {
+    let mut chunks = data.chunks(MAX_STORED_SIZE).peekable();
+    if chunks.peek().is_none() {
+        write_stored_block(writer, &[], last);
+    }
+    while let Some(chunk) = chunks.next() {
+        write_stored_block(writer, chunk, last && chunks.peek().is_none());
+    }
+}
+
+fn write_stored_block(writer: &mut LsbBitWriter, data: &[u8], last: bool) {
+    writer.write_bit(last);
+    writer.write_bits(BLOCK_STORED, 2);
+    writer.align();
+    writer.write_bits(data.len() as u32, 16);
+    writer.write_bits(!data.len() as u32 & 0xffff, 16);
+    for byte in data {
+        writer.write_bits(u32::from(*byte), 8);
+    }
+}
+
+/// Write `tokens`, covering `data`, as the smallest of a stored, fixed or dynamic block.
+///
+/// # Arguments
+/// * writer - Destination of the block
+/// * tokens - Literals and matches of the block
+/// * data - Content of the block
+/// * last - Whether the block ends the stream
+/// * fixed - Whether the dynamic codes are not considered
+fn write_block(writer: &mut LsbBitWriter, tokens: &[Token], data: &[u8], last: bool, fixed: bool) {
+    let mut literal_frequencies: Vec<u64> = vec![0; LITERAL_LENGTH_CODES];
+    let mut distance_frequencies: Vec<u64> = vec![0; DISTANCE_CODES];
+    let mut extra_bits: u64 = 0;
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => literal_frequencies[byte as usize] += 1,
+            Token::Match { length, distance } => {
+                let (length_symbol, _) = length_code(length as usize);
+                let (distance_symbol, _) = distance_code(distance as usize);
+                literal_frequencies[257 + length_symbol] += 1;
+                distance_frequencies[distance_symbol] += 1;
+                extra_bits +=
+                    u64::from(LENGTH_BITS[length_symbol] + DISTANCE_BITS[distance_symbol]);
+            }
+        }
+    }
+    literal_frequencies[END_OF_BLOCK] += 1;
+
+    let literal_codes = Codes::new(&complete_code_lengths(
+        &literal_frequencies,
+        MAX_CODE_LENGTH,
+    ));
+    let distance_codes = Codes::new(&complete_code_lengths(
+        &distance_frequencies,
+        MAX_CODE_LENGTH,
+    ));
+    let literal_count = 257
+        + literal_codes.lengths[257..]
+            .iter()
+            .rposition(|length| *length > 0)
+            .map_or(0, |index| index + 1);
+    let distance_count = 1 + distance_codes
+        .lengths
+        .iter()
+        .rposition(|length| *length > 0)
+        .unwrap_or(0);
+    let mut all_lengths = literal_codes.lengths[..literal_count].to_vec();
+    all_lengths.extend_from_slice(&distance_codes.lengths[..distance_count]);
+    let length_symbols = encode_lengths(&all_lengths);
+    let mut length_frequencies: Vec<u64> = vec![0; CODE_LENGTH_CODES];
+    for (symbol, _) in &length_symbols {
+        length_frequencies[*symbol] += 1;
+    }
+    let length_codes = Codes::new(&complete_code_lengths(
+        &length_frequencies,
+        MAX_CODE_LENGTH_CODE_LENGTH,
+    ));
+    let length_code_count = 4 + CODE_LENGTH_ORDER[4..]
+        .iter()
+        .rposition(|symbol| length_codes.lengths[*symbol] > 0)
+        .map_or(0, |index| index + 1);
+
+    let dynamic_header = 5
+        + 5
+        + 4
+        + 3 * length_code_count as u64
+        + length_codes.cost(&length_frequencies)
+        + length_symbols
+            .iter()
+            .map(|(symbol, _)| u64::from(code_length_extra_bits(*symbol)))
+            .sum::<u64>();
+    let dynamic_cost = dynamic_header
+        + literal_codes.cost(&literal_frequencies)
+        + distance_codes.cost(&distance_frequencies)
+        + extra_bits;
+    let fixed_literals = Codes::new(&fixed_literal_lengths());
+    let fixed_distances = Codes::new(&fixed_distance_lengths());
+    let fixed_cost = fixed_literals.cost(&literal_frequencies)
+        + fixed_distances.cost(&distance_frequencies)
+        + extra_bits;
+    let stored_cost =
+        8 * data.len() as u64 + 40 * data.len().div_ceil(MAX_STORED_SIZE).max(1) as u64;
+
+    let coded_cost = if fixed {
+        fixed_cost
+    } else {
+        min(dynamic_cost, fixed_cost)
+    };
+    if stored_cost < coded_cost {
+        write_stored_blocks(writer, data, last);
+        return;
+    }
+    writer.write_bit(last);
+    if fixed || fixed_cost <= dynamic_cost {
+        writer.write_bits(BLOCK_FIXED, 2);
+        write_tokens(writer, tokens, &fixed_literals, &fixed_distances);
+        return;
+    }
+    writer.write_bits(BLOCK_DYNAMIC, 2);
+    writer.write_bits((literal_count - 257) as u32, 5);
+    writer.write_bits((distance_count - 1) as u32, 5);
+    writer.write_bits((length_code_count - 4) as u32, 4);
+    for symbol in &CODE_LENGTH_ORDER[..length_code_count] {
+        writer.write_bits(u32::from(length_codes.lengths[*symbol]), 3);
+    }
+    for (symbol, extra) in &length_symbols {
+        length_codes.write(writer, *symbol);
+        writer.write_bits(*extra, code_length_extra_bits(*symbol));
+    }
+    write_tokens(writer, tokens, &literal_codes, &distance_codes);
+}
+
+fn write_tokens(writer: &mut LsbBitWriter, tokens: &[Token], literals: &Codes, distances: &Codes) {
+    for token in tokens {
+        match *token {
+            Token::Literal(byte) => literals.write(writer, byte as usize),
+            Token::Match { length, distance } => {
+                let (length_symbol, length_extra) = length_code(length as usize);
+                literals.write(writer, 257 + length_symbol);
+                writer.write_bits(length_extra, LENGTH_BITS[length_symbol]);
+                let (distance_symbol, distance_extra) = distance_code(distance as usize);
+                distances.write(writer, distance_symbol);
+                writer.write_bits(distance_extra, DISTANCE_BITS[distance_symbol]);
+            }
+        }
+    }
+    literals.write(writer, END_OF_BLOCK);
+}
+
+/// Decoding state between two blocks or inside one.
+#[derive(Debug)]
+enum Block {
+    Header,
+    Stored(usize),
+    Huffman(HuffmanTable, HuffmanTable),
+    End,
+}
+
+/// Streaming DEFLATE decoder.
+///
+/// Only the last 32 KiB of output are kept to resolve matches, the rest being
+/// handed over through `Read`.
+///
+/// # Examples
+/// ```
+/// # use reducto::deflate::{RawDeflate, Inflater};
+/// # use std::io::Read;
+/// let data = std::fs::read("resources/lorem.txt").unwrap();
+/// let compressed = RawDeflate::new(9).compress(&data);
+///
+/// let mut inflater = Inflater::new(&compressed);
+/// let mut start = [0; 11];
+/// inflater.read_exact(&mut start).unwrap();
+/// assert_eq!(&start, b"Lorem ipsum");
+/// ```
+#[derive(Debug)]
+pub struct Inflater<'a> {
+    reader: LsbBitReader<'a>,
+    window: Vec<u8>,
+    /// Position in `window` of the first byte not read yet.
+    position: usize,
+    block: Block,
+    last: bool,
+}
+
+impl<'a> Inflater<'a> {
+    /// Create an `Inflater` reading the blocks at the start of `data`.
+    pub fn new(data: &'a [u8]) -> Inflater<'a> {
+        Inflater::with_dictionary(data, &[])
+    }
+
+    /// Create an `Inflater` whose matches can refer to the preset `dictionary`.
+    ///
+    /// # Arguments
+    /// * data - The compressed blocks
+    /// * dictionary - The dictionary given to the compressor, only its last 32 KiB are used
+    pub fn with_dictionary(data: &'a [u8], dictionary: &[u8]) -> Inflater<'a> {
+        let window = dictionary[dictionary.len().saturating_sub(WINDOW_SIZE)..].to_vec();
+        Inflater {
+            reader: LsbBitReader::new(data),
+            position: window.len(),
+            window,
+            block: Block::Header,
+            last: false,
+        }
+    }
+
+    /// Whether the last block has been decoded.
+    pub fn is_finished(&self) -> bool {
+        matches!(self.block, Block::End)
+    }
+
+    /// Number of bytes of compressed data read so far, including the last partial byte.
+    pub fn compressed_len(&self) -> usize {
+        self.reader.bit_position().div_ceil(8)
+    }
+
+    /// Decode until `OUTPUT_CHUNK` bytes are waiting to be read or the last block ends.
+    fn fill(&mut self) -> Result<()> {
+        if self.position > 2 * WINDOW_SIZE {
+            let dropped = self.position - WINDOW_SIZE;
+            self.window.drain(..dropped);
+            self.position -= dropped;
+        }
+        let target = self.position + OUTPUT_CHUNK;
+        while self.window.len() < target {
+            match &mut self.block {
+                Block::Header if self.last => self.block = Block::End,
+                Block::Header => self.read_block_header()?,
+                Block::Stored(remaining) => {
+                    let count = min(*remaining, target - self.window.len());
+                    *remaining -= count;
+                    if *remaining == 0 {
+                        self.block = Block::Header;
+                    }
+                    let bytes = self.reader.read_bytes(count)?;
+                    self.window.extend_from_slice(bytes);
+                }
+                Block::Huffman(literals, distances) => {
+                    let ended = decode_symbols(
+                        &mut self.reader,
+                        &mut self.window,
+                        literals,
+                        distances,
+                        target,
+                    )?;
+                    if ended {
+                        self.block = Block::Header;
+                    }
+                }
+                Block::End => break,
+            }
+        }
+        Ok(())
+    }
+
+    fn read_block_header(&mut self) -> Result<()> {
+        let reader = &mut self.reader;
+        self.last = reader.read_bit()? == 1;
+        self.block = match reader.read_bits(2)? {
+            BLOCK_STORED => {
+                reader.align();
+                let length = reader.read_bits(16)?;
+                if reader.read_bits(16)? != !length & 0xffff {
+                    return Err(invalid_data("invalid DEFLATE stored block length"));
+                }
+                match length {
+                    0 => Block::Header,
+                    length => Block::Stored(length as usize),
+                }
+            }
+            BLOCK_FIXED => Block::Huffman(
+                HuffmanTable::new(&fixed_literal_lengths())?,
+                HuffmanTable::new(&fixed_distance_lengths())?,
+            ),
+            BLOCK_DYNAMIC => read_dynamic_tables(reader)?,
+            _ => return Err(invalid_data("invalid DEFLATE block type")),
+        };
+        Ok(())
+    }
+}
+
+impl<'a> Read for Inflater<'a> {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        if self.position == self.window.len() {
+            self.fill()?;
+        }
+        let count = min(buffer.len(), self.window.len() - self.position);
+        buffer[..count].copy_from_slice(&self.window[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+fn read_dynamic_tables(reader: &mut LsbBitReader) -> Result<Block> {
+    let literal_count = reader.read_bits(5)? as usize + 257;
+    let distance_count = reader.read_bits(5)? as usize + 1;
+    let length_code_count = reader.read_bits(4)? as usize + 4;
+    if literal_count > LITERAL_LENGTH_CODES || distance_count > DISTANCE_CODES {
+        return Err(invalid_data("too many DEFLATE length or distance codes"));
+    }
+    let mut length_code_lengths = [0u8; CODE_LENGTH_CODES];
+    for symbol in &CODE_LENGTH_ORDER[..length_code_count] {
+        length_code_lengths[*symbol] = reader.read_bits(3)? as u8;
+    }
+    let length_table = HuffmanTable::new(&length_code_lengths)?;
+
+    let mut lengths: Vec<u8> = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let symbol = length_table.decode(reader)? as usize;
+        let (length, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => match lengths.last() {
+                Some(previous) => (*previous, 3 + reader.read_bits(2)? as usize),
+                None => return Err(invalid_data("DEFLATE length repeat without a length")),
+            },
+            17 => (0, 3 + reader.read_bits(3)? as usize),
+            _ => (0, 11 + reader.read_bits(7)? as usize),
+        };
+        if lengths.len() + repeat > literal_count + distance_count {
+            return Err(invalid_data("DEFLATE code lengths exceed the alphabets"));
+        }
+        lengths.extend(std::iter::repeat_n(length, repeat));
+    }
+    if lengths[END_OF_BLOCK] == 0 {
+        return Err(invalid_data("DEFLATE block without an end of block code"));
+    }
+    Ok(Block::Huffman(
+        HuffmanTable::new(&lengths[..literal_count])?,
+        HuffmanTable::new(&lengths[literal_count..])?,
+    ))
+}
+
+/// Decode symbols until `window` reaches `target` bytes or the block ends, returning whether it ended.
+fn decode_symbols(
+    reader: &mut LsbBitReader,
+    window: &mut Vec<u8>,
+    literals: &HuffmanTable,
+    distances: &HuffmanTable,
+    target: usize,
+) -> Result<bool> {
+    while window.len() < target {
+        let symbol = literals.decode(reader)? as usize;
+        if symbol < END_OF_BLOCK {
+            window.push(symbol as u8);
+            continue;
+        }
+        if symbol == END_OF_BLOCK {
+            return Ok(true);
+        }
+        let length_symbol = symbol - 257;
+        if length_symbol >= LENGTH_BASES.len() {
+            return Err(invalid_data("invalid DEFLATE length code"));
+        }
+        let length = LENGTH_BASES[length_symbol] as usize
+            + reader.read_bits(LENGTH_BITS[length_symbol])? as usize;
+        let distance_symbol = distances.decode(reader)? as usize;
+        if distance_symbol >= DISTANCE_CODES {
+            return Err(invalid_data("invalid DEFLATE distance code"));
+        }
+        let distance = DISTANCE_BASES[distance_symbol] as usize
+            + reader.read_bits(DISTANCE_BITS[distance_symbol])? as usize;
+        if distance > window.len() {
+            return Err(invalid_data("DEFLATE distance too far back"));
+        }
+        let start = window.len() - distance;
+        if distance >= length {
+            window.extend_from_within(start..start + length);
+        } else {
+            for index in start..start + length {
+                window.push(window[index]);
+            }
+        }
     }
+    Ok(false)
 }
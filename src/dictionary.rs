@@ -0,0 +1,261 @@
+//! Training of preset dictionaries
+//!
+//! Implements the COVER algorithm of zstd. The samples are concatenated and
+//! every substring of `dmer_size` bytes (a dmer) is given the number of samples
+//! containing it. The concatenation is split in epochs, and in each one the
+//! segment whose distinct dmers have the highest total frequency is added to
+//! the dictionary, then the frequencies of its dmers are cleared so the next
+//! segments cover other content.
+//!
+//! The dictionary is raw content, to give to `LZ77::dictionary`,
+//! `RawDeflate::dictionary` or `Zlib::dictionary`. The best segments are put at
+//! its end, where matches are the closest and the cheapest.
+use std::cmp::min;
+
+use crate::bwt::suffix_array;
+use crate::counter::HashCounter;
+
+/// Marks the positions whose dmer crosses the end of a sample.
+const NO_DMER: u32 = u32::MAX;
+
+/// COVER dictionary trainer.
+///
+/// # Examples
+/// ```
+/// # use reducto::dictionary::Cover;
+/// # use reducto::deflate::RawDeflate;
+/// let samples: Vec<String> = (0..200)
+///     .map(|i| format!(r#"{{"type":"click","page":"/item/{}","user":{}}}"#, i * 13, i * 7))
+///     .collect();
+/// let samples: Vec<&[u8]> = samples.iter().map(|sample| sample.as_bytes()).collect();
+/// let dictionary = Cover::new().segment_size(64).train(&samples, 1024);
+/// assert!(!dictionary.is_empty() && dictionary.len() <= 1024);
+///
+/// let event = br#"{"type":"click","page":"/item/4242","user":99}"#;
+/// let primed = RawDeflate::new(9).dictionary(&dictionary);
+/// let compressed = primed.compress(event);
+/// assert!(compressed.len() < RawDeflate::new(9).compress(event).len() / 2);
+/// assert_eq!(primed.decompress(&compressed).unwrap(), &event[..]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Cover {
+    segment_size: usize,
+    dmer_size: usize,
+}
+
+impl Cover {
+    /// Create a new `Cover` selecting segments of 256 bytes scored by their dmers of 8 bytes.
+    pub fn new() -> Cover {
+        Cover {
+            segment_size: 256,
+            dmer_size: 8,
+        }
+    }
+
+    /// Set the size of the segments added to the dictionary.
+    pub fn segment_size(mut self, segment_size: usize) -> Cover {
+        assert!(segment_size >= self.dmer_size);
+        self.segment_size = segment_size;
+        self
+    }
+
+    /// Set the size of the substrings scoring the segments, from 4 to 16.
+    pub fn dmer_size(mut self, dmer_size: usize) -> Cover {
+        assert!((4..=16).contains(&dmer_size));
+        assert!(dmer_size <= self.segment_size);
+        self.dmer_size = dmer_size;
+        self
+    }
+
+    /// Build a dictionary of at most `size` bytes from the content shared by `samples`.
+    ///
+    /// # Arguments
+    /// * samples - Data resembling the one that will be compressed, like many small messages
+    /// * size - Largest size of the dictionary
+    pub fn train(&self, samples: &[&[u8]], size: usize) -> Vec<u8> {
+        let data: Vec<u8> = samples.concat();
+        let (dmers, dmer_count) = self.group_dmers(samples, &data);
+        let mut frequencies = self.sample_frequencies(samples, &dmers, dmer_count);
+        if size == 0 || frequencies.iter().all(|frequency| *frequency == 0) {
+            return Vec::new();
+        }
+
+        let epoch_count = (size / self.segment_size)
+            .min(data.len() / self.segment_size)
+            .max(1);
+        let epoch_size = data.len() / epoch_count;
+        let mut active: Vec<u32> = vec![0; dmer_count];
+        let mut segments: Vec<&[u8]> = Vec::new();
+        let mut total = 0;
+        let mut empty_epochs = 0;
+        let mut epoch = 0;
+        while total < size && empty_epochs < epoch_count {
+            let begin = epoch * epoch_size;
+            let end = if epoch + 1 == epoch_count {
+                data.len()
+            } else {
+                begin + epoch_size
+            };
+            epoch = (epoch + 1) % epoch_count;
+
+            match self.best_segment(&dmers, &frequencies, &mut active, begin, end) {
+                Some((first, last)) => {
+                    empty_epochs = 0;
+                    for id in &dmers[first..=last] {
+                        if *id != NO_DMER {
+                            frequencies[*id as usize] = 0;
+                        }
+                    }
+                    let segment = &data[first..min(last + self.dmer_size, size - total + first)];
+                    total += segment.len();
+                    segments.push(segment);
+                }
+                None => empty_epochs += 1,
+            }
+        }
+        segments
+            .iter()
+            .rev()
+            .flat_map(|segment| segment.iter().copied())
+            .collect()
+    }
+
+    /// Give an identifier to the dmer starting at each position of `data`, equal dmers sharing it.
+    ///
+    /// Returns the identifiers, `NO_DMER` where the dmer crosses the end of a
+    /// sample, and the number of distinct dmers.
+    fn group_dmers(&self, samples: &[&[u8]], data: &[u8]) -> (Vec<u32>, usize) {
+        let mut valid = vec![false; data.len()];
+        let mut start = 0;
+        for sample in samples {
+            let end = start + sample.len();
+            for flag in valid[start..end.saturating_sub(self.dmer_size - 1).max(start)].iter_mut() {
+                *flag = true;
+            }
+            start = end;
+        }
+
+        // equal dmers are next to each other in the suffixes sorted by their first bytes
+        let mut dmers = vec![NO_DMER; data.len()];
+        let mut count: u32 = 0;
+        let mut previous: Option<usize> = None;
+        for position in suffix_array(data) {
+            if !valid[position] {
+                continue;
+            }
+            let dmer = &data[position..position + self.dmer_size];
+            if previous.is_none_or(|previous| data[previous..previous + self.dmer_size] != *dmer) {
+                count += 1;
+            }
+            dmers[position] = count - 1;
+            previous = Some(position);
+        }
+        (dmers, count as usize)
+    }
+
+    /// Number of samples containing each dmer.
+    fn sample_frequencies(&self, samples: &[&[u8]], dmers: &[u32], dmer_count: usize) -> Vec<u64> {
+        let mut counter: HashCounter<u32, u64> = HashCounter::new();
+        let mut last_sample: Vec<usize> = vec![usize::MAX; dmer_count];
+        let mut start = 0;
+        for (index, sample) in samples.iter().enumerate() {
+            for id in &dmers[start..start + sample.len()] {
+                if *id != NO_DMER && last_sample[*id as usize] != index {
+                    last_sample[*id as usize] = index;
+                    counter.update_from_value(*id);
+                }
+            }
+            start += sample.len();
+        }
+        (0..dmer_count as u32).map(|id| counter[id]).collect()
+    }
+
+    /// Find the segment of `data[begin..end]` whose distinct dmers have the highest total frequency.
+    ///
+    /// Returns the positions of its first and last dmers with a frequency, or
+    /// `None` when no dmer of the epoch has one left. `active` counts the dmers
+    /// of the sliding segment and is left zeroed.
+    fn best_segment(
+        &self,
+        dmers: &[u32],
+        frequencies: &[u64],
+        active: &mut [u32],
+        begin: usize,
+        end: usize,
+    ) -> Option<(usize, usize)> {
+        let span = self.segment_size - self.dmer_size + 1;
+        let mut score: u64 = 0;
+        let mut best: (u64, usize) = (0, begin);
+        for position in begin..end {
+            let id = dmers[position];
+            if id != NO_DMER {
+                if active[id as usize] == 0 {
+                    score += frequencies[id as usize];
+                }
+                active[id as usize] += 1;
+            }
+            if position >= begin + span {
+                let id = dmers[position - span];
+                if id != NO_DMER {
+                    active[id as usize] -= 1;
+                    if active[id as usize] == 0 {
+                        score -= frequencies[id as usize];
+                    }
+                }
+            }
+            let start = (position + 1).saturating_sub(span).max(begin);
+            if score > best.0 {
+                best = (score, start);
+            }
+        }
+        for id in &dmers[end.saturating_sub(span).max(begin)..end] {
+            if *id != NO_DMER {
+                active[*id as usize] = 0;
+            }
+        }
+
+        let (score, start) = best;
+        if score == 0 {
+            return None;
+        }
+        let scored = |position: &usize| {
+            let id = dmers[*position];
+            id != NO_DMER && frequencies[id as usize] > 0
+        };
+        let stop = min(start + span, end);
+        let first = (start..stop).find(scored)?;
+        let last = (start..stop).rev().find(scored)?;
+        Some((first, last))
+    }
+}
+
+impl Default for Cover {
+    fn default() -> Self {
+        Cover::new()
+    }
+}
+
+/// Build a dictionary of at most `size` bytes from `samples` with the default `Cover`.
+///
+/// # Arguments
+/// * samples - Data resembling the one that will be compressed, like many small messages
+/// * size - Largest size of the dictionary
+///
+/// # Examples
+/// ```
+/// # use reducto::lz77::LZ77;
+/// let samples: [&[u8]; 3] = [
+///     b"GET /index.html HTTP/1.1\r\nHost: example.com\r\nAccept: */*\r\n\r\n",
+///     b"GET /about.html HTTP/1.1\r\nHost: example.com\r\nAccept: */*\r\n\r\n",
+///     b"GET /style.css HTTP/1.1\r\nHost: example.com\r\nAccept: text/css\r\n\r\n",
+/// ];
+/// let dictionary = reducto::dictionary::train(&samples, 128);
+/// assert!(dictionary.windows(17).any(|window| window == b"Host: example.com"));
+///
+/// let mut lz77 = LZ77::new(255, 255).dictionary(&dictionary);
+/// lz77.encode("GET /news.html HTTP/1.1\r\nHost: example.com\r\n");
+/// assert_eq!(lz77.decode(), "GET /news.html HTTP/1.1\r\nHost: example.com\r\n");
+/// ```
+pub fn train(samples: &[&[u8]], size: usize) -> Vec<u8> {
+    Cover::default().train(samples, size)
+}
@@ -0,0 +1,230 @@
+//! Detection of the compressed formats
+//!
+//! Every container of the crate starts with a magic number, except raw
+//! streams like `.lzma` and brotli. `detect` sniffs it so callers can
+//! decompress data without knowing its format, the raw streams being left to
+//! the caller, from the file extension for instance.
+use std::io::{Error, ErrorKind, Read, Result};
+
+use crate::bits::{invalid_data, read_u32};
+use crate::bzip2::Bzip2;
+use crate::gzip::Gzip;
+use crate::lz4::LZ4;
+use crate::lzma::Lzma;
+use crate::zip::ZipArchive;
+use crate::zlib::{self, Zlib};
+use crate::{brotli, snappy, zstd};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const BZIP2_MAGIC: &[u8] = b"BZh";
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const LZ4_MAGIC: &[u8] = &[0x04, 0x22, 0x4d, 0x18];
+const SNAPPY_MAGIC: &[u8] = b"\xff\x06\x00\x00sNaPpY";
+/// Local file header, or end of central directory of an empty archive.
+const ZIP_MAGICS: [&[u8]; 2] = [b"PK\x03\x04", b"PK\x05\x06"];
+/// Skippable frames of zstd and LZ4 use the magic numbers `0x184D2A50` to `0x184D2A5F`.
+const SKIPPABLE_MAGIC: u32 = 0x184d_2a50;
+
+/// Compressed format.
+///
+/// # Examples
+/// ```
+/// # use reducto::format::Format;
+/// assert_eq!(Format::from_name("xz"), Some(Format::Xz));
+/// assert_eq!(Format::from_name("bz2"), Some(Format::Bzip2));
+/// assert_eq!(Format::Snappy.extension(), "sz");
+///
+/// let lorem = std::fs::read("resources/lorem.txt").unwrap();
+/// let vector = std::fs::read("resources/brotli/lorem.txt.br").unwrap();
+/// assert_eq!(Format::Brotli.decompress(&vector).unwrap(), lorem);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Gzip,
+    Zlib,
+    Bzip2,
+    Xz,
+    /// Raw LZMA stream, never returned by `detect` as it has no magic number.
+    Lzma,
+    Zstd,
+    LZ4,
+    Zip,
+    Snappy,
+    /// Brotli stream, never returned by `detect` as it has no magic number.
+    Brotli,
+}
+
+impl Format {
+    /// Every format.
+    pub const ALL: [Format; 10] = [
+        Format::Gzip,
+        Format::Zlib,
+        Format::Bzip2,
+        Format::Xz,
+        Format::Lzma,
+        Format::Zstd,
+        Format::LZ4,
+        Format::Zip,
+        Format::Snappy,
+        Format::Brotli,
+    ];
+
+    /// Name of the format, in lowercase.
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Gzip => "gzip",
+            Format::Zlib => "zlib",
+            Format::Bzip2 => "bzip2",
+            Format::Xz => "xz",
+            Format::Lzma => "lzma",
+            Format::Zstd => "zstd",
+            Format::LZ4 => "lz4",
+            Format::Zip => "zip",
+            Format::Snappy => "snappy",
+            Format::Brotli => "brotli",
+        }
+    }
+
+    /// Usual extension of the files of the format, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Gzip => "gz",
+            Format::Zlib => "zz",
+            Format::Bzip2 => "bz2",
+            Format::Xz => "xz",
+            Format::Lzma => "lzma",
+            Format::Zstd => "zst",
+            Format::LZ4 => "lz4",
+            Format::Zip => "zip",
+            Format::Snappy => "sz",
+            Format::Brotli => "br",
+        }
+    }
+
+    /// Format named `name`, or whose extension is `name`.
+    pub fn from_name(name: &str) -> Option<Format> {
+        Format::ALL
+            .iter()
+            .copied()
+            .find(|format| name == format.name() || name == format.extension())
+    }
+
+    /// Decompress `data`, made of one or several concatenated members of the format.
+    ///
+    /// A zip archive is decompressed only when it holds a single file.
+    pub fn decompress(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Format::Gzip => Gzip::default().decompress(data),
+            Format::Zlib => Zlib::default().decompress(data),
+            Format::Bzip2 => Bzip2::default().decompress(data),
+            Format::Xz => Lzma::default().decompress_xz(data),
+            Format::Lzma => Lzma::default().decompress(data),
+            Format::Zstd => zstd::decompress(data),
+            Format::LZ4 => LZ4::new().decompress(data),
+            Format::Snappy => snappy::decompress_framed(data),
+            Format::Brotli => brotli::decompress(data),
+            Format::Zip => {
+                let archive = ZipArchive::new(data)?;
+                let mut files = archive.entries().iter().filter(|entry| !entry.is_dir());
+                match (files.next(), files.next()) {
+                    (Some(entry), None) => archive.extract(entry),
+                    _ => Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "zip archive does not hold a single file",
+                    )),
+                }
+            }
+        }
+    }
+}
+
+/// Skip the skippable frames in front of zstd and LZ4 frames.
+fn skip_skippable_frames(mut data: &[u8]) -> &[u8] {
+    while data.len() >= 8 {
+        let magic = read_u32(data);
+        if magic & !0x0f != SKIPPABLE_MAGIC {
+            break;
+        }
+        let size = read_u32(&data[4..]) as usize;
+        data = data.get(8 + size..).unwrap_or(&[]);
+    }
+    data
+}
+
+/// Guess the format of `data` from its magic number.
+///
+/// # Arguments
+/// * data - start of the compressed data, a few bytes are usually enough
+///
+/// # Examples
+/// ```
+/// # use reducto::format::Format;
+/// # use reducto::gzip::Gzip;
+/// # use reducto::zlib::Zlib;
+/// assert_eq!(reducto::detect(&Gzip::default().compress(b"abc")), Some(Format::Gzip));
+/// assert_eq!(reducto::detect(&Zlib::default().compress(b"abc")), Some(Format::Zlib));
+/// assert_eq!(reducto::detect(b"BZh91AY&SY"), Some(Format::Bzip2));
+///
+/// let zip = std::fs::read("resources/zip/lorem.zip").unwrap();
+/// assert_eq!(reducto::detect(&zip), Some(Format::Zip));
+/// assert_eq!(reducto::detect(b"plain text"), None);
+/// assert_eq!(reducto::detect(b"x = 1"), None);
+/// ```
+pub fn detect(data: &[u8]) -> Option<Format> {
+    let magics: [(&[u8], Format); 6] = [
+        (GZIP_MAGIC, Format::Gzip),
+        (BZIP2_MAGIC, Format::Bzip2),
+        (XZ_MAGIC, Format::Xz),
+        (SNAPPY_MAGIC, Format::Snappy),
+        (ZIP_MAGICS[0], Format::Zip),
+        (ZIP_MAGICS[1], Format::Zip),
+    ];
+    if let Some((_, format)) = magics.iter().find(|(magic, _)| data.starts_with(magic)) {
+        return Some(*format);
+    }
+    let frames = skip_skippable_frames(data);
+    if frames.starts_with(ZSTD_MAGIC) {
+        return Some(Format::Zstd);
+    }
+    if frames.starts_with(LZ4_MAGIC) {
+        return Some(Format::LZ4);
+    }
+    // only two bytes, with a check on 5 bits, so tested last
+    if zlib::is_header(data, false) {
+        return Some(Format::Zlib);
+    }
+    None
+}
+
+/// Read everything from `reader` and decompress it, whatever its format.
+///
+/// Concatenated members, like the ones of `cat a.gz b.gz`, are decompressed one after the other.
+///
+/// # Arguments
+/// * reader - source of the compressed data
+///
+/// # Examples
+/// ```
+/// # use reducto::gzip::Gzip;
+/// # use reducto::lz4::LZ4;
+/// let lorem = std::fs::read("resources/lorem.txt").unwrap();
+/// let file = std::fs::File::open("resources/gzip/lorem.txt.gz").unwrap();
+/// assert_eq!(reducto::decompress_any(file).unwrap(), lorem);
+///
+/// let mut members = Gzip::new(1).compress(b"hello ");
+/// members.extend(Gzip::new(9).compress(b"world"));
+/// assert_eq!(reducto::decompress_any(&members[..]).unwrap(), b"hello world");
+///
+/// let frame = LZ4::new().compress(b"hello world");
+/// assert_eq!(reducto::decompress_any(&frame[..]).unwrap(), b"hello world");
+/// assert!(reducto::decompress_any(&b"plain text"[..]).is_err());
+/// ```
+pub fn decompress_any<R: Read>(mut reader: R) -> Result<Vec<u8>> {
+    let mut data: Vec<u8> = Vec::new();
+    reader.read_to_end(&mut data)?;
+    match detect(&data) {
+        Some(format) => format.decompress(&data),
+        None => Err(invalid_data("unknown compressed format")),
+    }
+}
@@ -0,0 +1,193 @@
+//! gzip compressor and decompressor
+//!
+//! A `.gz` file is a list of members, each one a header with an optional file
+//! name and comment, raw DEFLATE blocks, then the CRC-32 and size modulo 2^32
+//! of the content.
+use std::io::{Read, Result};
+
+use crate::bits::{invalid_data, read_u16, read_u32, take, unexpected_end};
+use crate::checksum::{crc32, crc32_combine};
+use crate::deflate::{Inflater, RawDeflate};
+use crate::options::CompressionOptions;
+
+const MAGIC: [u8; 2] = [0x1f, 0x8b];
+const METHOD_DEFLATE: u8 = 8;
+
+const FLAG_HEADER_CRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+const FLAG_RESERVED: u8 = 0xe0;
+
+/// Extra flags telling that the slowest or fastest level was used.
+const EXTRA_FLAGS_BEST: u8 = 2;
+const EXTRA_FLAGS_FASTEST: u8 = 4;
+const OS_UNIX: u8 = 3;
+
+/// Skip a zero terminated string.
+fn skip_string(data: &mut &[u8]) -> Result<()> {
+    let end = data
+        .iter()
+        .position(|byte| *byte == 0)
+        .ok_or_else(unexpected_end)?;
+    take(data, end + 1)?;
+    Ok(())
+}
+
+/// gzip codec.
+///
+/// # Examples
+/// ```
+/// # use reducto::gzip::Gzip;
+/// let gzip = Gzip::new(6).name("hello.txt");
+/// let data = b"hello hello hello hello hello hello";
+/// let compressed = gzip.compress(data);
+/// assert_eq!(&compressed[..2], &[0x1f, 0x8b]);
+/// assert_eq!(gzip.decompress(&compressed).unwrap(), &data[..]);
+///
+/// let compressed = std::fs::read("resources/gzip/lorem.txt.gz").unwrap();
+/// let lorem = std::fs::read("resources/lorem.txt").unwrap();
+/// assert_eq!(gzip.decompress(&compressed).unwrap(), lorem);
+///
+/// let compressed = Gzip::new(9).threads(4).compress(&lorem);
+/// assert_eq!(gzip.decompress(&compressed).unwrap(), lorem);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Gzip {
+    options: CompressionOptions,
+    name: Option<String>,
+    mtime: u32,
+    threads: usize,
+}
+
+impl Gzip {
+    /// Create a new `Gzip`.
+    ///
+    /// # Arguments
+    /// * level - DEFLATE level, from 0 (stored) and 1 (fastest) to 9 (best)
+    pub fn new(level: u8) -> Gzip {
+        Gzip::with_options(CompressionOptions::new(level))
+    }
+
+    /// Create a new `Gzip` deflating with `options`, see `RawDeflate::with_options`.
+    pub fn with_options(options: CompressionOptions) -> Gzip {
+        Gzip {
+            options,
+            name: None,
+            mtime: 0,
+            threads: 1,
+        }
+    }
+
+    /// Set the original file name written in the header.
+    pub fn name(mut self, name: &str) -> Gzip {
+        assert!(!name.contains('\0'));
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Set the modification time written in the header, in seconds since the Unix epoch.
+    pub fn mtime(mut self, mtime: u32) -> Gzip {
+        self.mtime = mtime;
+        self
+    }
+
+    /// Set the number of threads compressing the blocks of the input, see `RawDeflate::threads`.
+    pub fn threads(mut self, threads: usize) -> Gzip {
+        assert!(threads > 0);
+        self.threads = threads;
+        self
+    }
+
+    /// Compress `data` into a single member.
+    pub fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut output: Vec<u8> = Vec::with_capacity(data.len() / 2 + 32);
+        output.extend_from_slice(&MAGIC);
+        output.push(METHOD_DEFLATE);
+        output.push(if self.name.is_some() { FLAG_NAME } else { 0 });
+        output.extend_from_slice(&self.mtime.to_le_bytes());
+        output.push(match self.options.level {
+            9 => EXTRA_FLAGS_BEST,
+            1 => EXTRA_FLAGS_FASTEST,
+            _ => 0,
+        });
+        output.push(OS_UNIX);
+        if let Some(name) = &self.name {
+            output.extend_from_slice(name.as_bytes());
+            output.push(0);
+        }
+        let deflate = RawDeflate::with_options(self.options.clone()).threads(self.threads);
+        let crc = if self.threads > 1 {
+            let blocks = deflate.compress_parallel(data, |block| (crc32(block), block.len()));
+            let mut crc = crc32(&[]);
+            for (compressed, (block_crc, size)) in blocks {
+                output.extend_from_slice(&compressed);
+                crc = crc32_combine(crc, block_crc, size as u64);
+            }
+            crc
+        } else {
+            output.extend_from_slice(&deflate.compress(data));
+            crc32(data)
+        };
+        output.extend_from_slice(&crc.to_le_bytes());
+        output.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        output
+    }
+
+    /// Decompress every member of `data`, each one checked against its CRC-32 and size.
+    pub fn decompress(&self, mut data: &[u8]) -> Result<Vec<u8>> {
+        let mut output: Vec<u8> = Vec::new();
+        loop {
+            read_member(&mut data, &mut output)?;
+            if data.is_empty() {
+                return Ok(output);
+            }
+        }
+    }
+}
+
+impl Default for Gzip {
+    fn default() -> Self {
+        Gzip::new(6)
+    }
+}
+
+/// Decompress the member at the start of `data` and skip it.
+fn read_member(data: &mut &[u8], output: &mut Vec<u8>) -> Result<()> {
+    let header = take(data, 10)?;
+    if header[..2] != MAGIC {
+        return Err(invalid_data("invalid gzip magic"));
+    }
+    if header[2] != METHOD_DEFLATE {
+        return Err(invalid_data("unsupported gzip compression method"));
+    }
+    let flags = header[3];
+    if flags & FLAG_RESERVED != 0 {
+        return Err(invalid_data("reserved gzip flags set"));
+    }
+    if flags & FLAG_EXTRA != 0 {
+        let size = take(data, 2)?;
+        take(data, usize::from(read_u16(size)))?;
+    }
+    if flags & FLAG_NAME != 0 {
+        skip_string(data)?;
+    }
+    if flags & FLAG_COMMENT != 0 {
+        skip_string(data)?;
+    }
+    if flags & FLAG_HEADER_CRC != 0 {
+        take(data, 2)?;
+    }
+
+    let start = output.len();
+    let mut inflater = Inflater::new(data);
+    inflater.read_to_end(output)?;
+    take(data, inflater.compressed_len())?;
+    if read_u32(take(data, 4)?) != crc32(&output[start..]) {
+        return Err(invalid_data("gzip CRC-32 mismatch"));
+    }
+    if read_u32(take(data, 4)?) != (output.len() - start) as u32 {
+        return Err(invalid_data("gzip size mismatch"));
+    }
+    Ok(())
+}
@@ -2,12 +2,20 @@
 
 use std::collections::binary_heap::BinaryHeap;
 use std::collections::btree_map::BTreeMap;
+use std::io::Result;
 use std::iter::FromIterator;
 
-use crate::counter::Counter;
-use std::cmp::Ordering;
+use crate::bits::{invalid_data, read_varint, write_varint, BitRead, MsbBitReader, MsbBitWriter};
+use crate::counter::{Counter, DenseCounter};
+use crate::pipeline::{Stage, StageKind};
+use std::cmp::{Ordering, Reverse};
 use std::rc::Rc;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+
 type HuffmanRcNode = Option<Rc<HuffmanNode>>;
 
 #[derive(Debug, Eq)]
@@ -52,7 +60,7 @@ impl HuffmanNode {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 struct HuffmanTree {
     tree: BinaryHeap<HuffmanNode>,
 }
@@ -94,31 +102,23 @@ impl HuffmanTree {
             // Make the first extracted node as its left child
             // and the other extracted node as its right child.
             // Add this node to the min heap.
-            let new_node: HuffmanNode;
-            if first_node.frequency == second_node.frequency {
-                if first_node.character < second_node.character {
-                    new_node = HuffmanNode::new(
-                        first_node.frequency + second_node.frequency,
-                        '$',
-                        Some(Rc::new(first_node)),
-                        Some(Rc::new(second_node)),
-                    );
-                } else {
-                    new_node = HuffmanNode::new(
-                        first_node.frequency + second_node.frequency,
-                        '$',
-                        Some(Rc::new(second_node)),
-                        Some(Rc::new(first_node)),
-                    );
-                }
+            let new_node = if first_node.frequency == second_node.frequency
+                && first_node.character >= second_node.character
+            {
+                HuffmanNode::new(
+                    first_node.frequency + second_node.frequency,
+                    '$',
+                    Some(Rc::new(second_node)),
+                    Some(Rc::new(first_node)),
+                )
             } else {
-                new_node = HuffmanNode::new(
+                HuffmanNode::new(
                     first_node.frequency + second_node.frequency,
                     '$',
                     Some(Rc::new(first_node)),
                     Some(Rc::new(second_node)),
-                );
-            }
+                )
+            };
             self.tree.push(new_node);
         }
     }
@@ -130,7 +130,7 @@ impl HuffmanTree {
         if let Some(rc_node) = &node.right {
             self.explore_tree(rc_node.as_ref(), format!("{}1", buffer), output);
         }
-        if node.character != '$' {
+        if node.left.is_none() && node.right.is_none() {
             output.push((node.character, buffer));
         }
     }
@@ -138,6 +138,11 @@ impl HuffmanTree {
     fn extract_encoding(&mut self) -> Vec<(char, String)> {
         if let Some(node) = self.tree.peek() {
             let mut output_vec: Vec<(char, String)> = Vec::new();
+            // a lone character still needs a bit to be counted
+            if node.left.is_none() && node.right.is_none() {
+                output_vec.push((node.character, String::from("0")));
+                return output_vec;
+            }
             self.explore_tree(node, String::new(), &mut output_vec);
             output_vec
         } else {
@@ -147,13 +152,79 @@ impl HuffmanTree {
 }
 
 /// Huffman `Encoder`
-#[derive(Debug)]
+///
+/// With the `serde` feature, it is serialized as its `encoding`, the code of
+/// each char as a string of `0` and `1`, from which decoding is rebuilt. The
+/// codes are checked to be non-empty and prefix-free when deserialized.
+///
+/// # Examples
+/// ```
+/// # #[cfg(feature = "serde")]
+/// # {
+/// # use reducto::huffman::Huffman;
+/// let mut huffman = Huffman::new();
+/// let encoded = huffman.encode(&"abracadabra");
+/// let json = serde_json::to_string(&huffman).unwrap();
+/// assert!(json.starts_with(r#"{"encoding":{"a":"#));
+///
+/// let reloaded: Huffman = serde_json::from_str(&json).unwrap();
+/// assert_eq!(reloaded.decode(&encoded), "abracadabra");
+///
+/// assert!(serde_json::from_str::<Huffman>(r#"{"encoding":{"a":"0","b":"1"}}"#).is_ok());
+/// assert!(serde_json::from_str::<Huffman>(r#"{"encoding":{"a":"0","b":"01"}}"#).is_err());
+/// assert!(serde_json::from_str::<Huffman>(r#"{"encoding":{"a":"1","b":"1"}}"#).is_err());
+/// assert!(serde_json::from_str::<Huffman>(r#"{"encoding":{"a":"0","b":"12"}}"#).is_err());
+/// assert!(serde_json::from_str::<Huffman>(r#"{"encoding":{"a":""}}"#).is_err());
+///
+/// let mut single = Huffman::new();
+/// let encoded = single.encode(&"aaaa");
+/// let reloaded: Huffman = serde_json::from_str(&serde_json::to_string(&single).unwrap()).unwrap();
+/// assert_eq!(reloaded.decode(&encoded), "aaaa");
+/// # }
+/// ```
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "HuffmanCodes"))]
 pub struct Huffman {
     encoding: BTreeMap<char, String>,
+    #[cfg_attr(feature = "serde", serde(skip))]
     decoding: BTreeMap<String, char>,
+    #[cfg_attr(feature = "serde", serde(skip))]
     huffman_tree: HuffmanTree,
 }
 
+/// Serialized form of a `Huffman`.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct HuffmanCodes {
+    encoding: BTreeMap<char, String>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<HuffmanCodes> for Huffman {
+    type Error = std::io::Error;
+
+    /// Rebuild the decoding, rejecting the codes that are empty, not made of `0` and `1` or not prefix-free.
+    fn try_from(codes: HuffmanCodes) -> Result<Self> {
+        let mut huffman = Huffman::new();
+        for (character, encoding) in codes.encoding {
+            if encoding.is_empty() || !encoding.chars().all(|c| c == '0' || c == '1') {
+                return Err(invalid_data("Huffman codes must be strings of 0 and 1"));
+            }
+            huffman.update(character, encoding);
+        }
+        // a code that is a prefix of others sorts right before one of them
+        let codes: Vec<&String> = huffman.decoding.keys().collect();
+        let prefix = codes
+            .windows(2)
+            .any(|pair| pair[1].starts_with(pair[0].as_str()));
+        if prefix || codes.len() != huffman.encoding.len() {
+            return Err(invalid_data("Huffman codes are not prefix-free"));
+        }
+        Ok(huffman)
+    }
+}
+
 impl Huffman {
     pub fn new() -> Self {
         Huffman {
@@ -197,7 +268,7 @@ impl Huffman {
             .collect::<String>()
     }
 
-    pub fn decode<S>(&mut self, stream: &S) -> String
+    pub fn decode<S>(&self, stream: &S) -> String
     where
         S: ToString + Sized,
     {
@@ -217,3 +288,308 @@ impl Huffman {
         decoded
     }
 }
+
+/// Longest code used by the `Huffman` pipeline stage.
+const STAGE_MAX_CODE_LENGTH: u8 = 15;
+
+/// Compute the length of the code of every symbol from its frequency.
+///
+/// Symbols with a null frequency get no code (length `0`). When the optimal
+/// tree is deeper than `max_length`, the frequencies are flattened until it fits.
+///
+/// # Arguments
+/// * frequencies - Frequency of each symbol, indexed by symbol
+/// * max_length - Longest code allowed
+///
+/// # Examples
+/// ```
+/// # use reducto::huffman::code_lengths;
+/// assert_eq!(code_lengths(&[10, 1, 1, 0, 2], 15), vec![1, 3, 3, 0, 2]);
+/// assert_eq!(code_lengths(&[0, 7], 15), vec![0, 1]);
+/// ```
+pub fn code_lengths(frequencies: &[u64], max_length: u8) -> Vec<u8> {
+    let used = frequencies.iter().filter(|f| **f > 0).count();
+    assert!(
+        used <= 1 || (max_length < 64 && used as u64 <= 1u64 << max_length),
+        "{} symbols do not fit in codes of {} bits",
+        used,
+        max_length
+    );
+
+    let mut weights: Vec<u64> = Vec::from(frequencies);
+    loop {
+        let lengths = unlimited_code_lengths(&weights);
+        if lengths.iter().all(|length| *length <= max_length as usize) {
+            return lengths.iter().map(|length| *length as u8).collect();
+        }
+        for weight in weights.iter_mut() {
+            *weight = weight.div_ceil(2);
+        }
+    }
+}
+
+fn unlimited_code_lengths(weights: &[u64]) -> Vec<usize> {
+    let mut lengths: Vec<usize> = vec![0; weights.len()];
+    let symbols: Vec<usize> = (0..weights.len()).filter(|s| weights[*s] > 0).collect();
+    if symbols.len() == 1 {
+        lengths[symbols[0]] = 1;
+        return lengths;
+    }
+
+    // leaves are the nodes `0..symbols.len()`, internal nodes are appended after them
+    let mut parents: Vec<usize> = vec![usize::MAX; symbols.len()];
+    let mut heap: BinaryHeap<Reverse<(u64, usize)>> = symbols
+        .iter()
+        .enumerate()
+        .map(|(node, symbol)| Reverse((weights[*symbol], node)))
+        .collect();
+    while heap.len() > 1 {
+        let (first_weight, first_node) = match heap.pop() {
+            Some(Reverse(node)) => node,
+            None => break,
+        };
+        let (second_weight, second_node) = match heap.pop() {
+            Some(Reverse(node)) => node,
+            None => break,
+        };
+        let node = parents.len();
+        parents.push(usize::MAX);
+        parents[first_node] = node;
+        parents[second_node] = node;
+        heap.push(Reverse((first_weight + second_weight, node)));
+    }
+
+    // parents are always created after their children, walk from the root down
+    let mut depths: Vec<usize> = vec![0; parents.len()];
+    for node in (0..parents.len().saturating_sub(1)).rev() {
+        depths[node] = depths[parents[node]] + 1;
+    }
+    for (node, symbol) in symbols.iter().enumerate() {
+        lengths[*symbol] = depths[node];
+    }
+    lengths
+}
+
+/// Assign canonical codes to symbols from their code lengths.
+///
+/// Shorter codes come first and codes of the same length follow the symbol order.
+///
+/// # Examples
+/// ```
+/// # use reducto::huffman::canonical_codes;
+/// assert_eq!(canonical_codes(&[1, 3, 3, 0, 2]), vec![0b0, 0b110, 0b111, 0, 0b10]);
+/// ```
+pub fn canonical_codes(lengths: &[u8]) -> Vec<u32> {
+    let max_length = lengths.iter().copied().max().unwrap_or(0) as usize;
+    let mut counts: Vec<u32> = vec![0; max_length + 1];
+    for length in lengths.iter().filter(|l| **l > 0) {
+        counts[*length as usize] += 1;
+    }
+
+    let mut next_codes: Vec<u32> = vec![0; max_length + 1];
+    let mut code: u32 = 0;
+    for length in 1..=max_length {
+        code = (code + counts[length - 1]) << 1;
+        next_codes[length] = code;
+    }
+
+    lengths
+        .iter()
+        .map(|length| match *length {
+            0 => 0,
+            length => {
+                let code = next_codes[length as usize];
+                next_codes[length as usize] += 1;
+                code
+            }
+        })
+        .collect()
+}
+
+/// Decoding table of a canonical Huffman code.
+///
+/// The code is read one bit at a time, most significant bit of the code first.
+///
+/// With the `serde` feature, it is serialized as the code length of every
+/// symbol, and checked again when deserialized.
+///
+/// # Examples
+/// ```
+/// # use reducto::bits::MsbBitReader;
+/// # use reducto::huffman::HuffmanTable;
+/// let table = HuffmanTable::new(&[1, 3, 3, 0, 2]).unwrap();
+/// let data = [0b0110_1000];
+/// let mut reader = MsbBitReader::new(&data);
+/// assert_eq!(table.decode(&mut reader).unwrap(), 0);
+/// assert_eq!(table.decode(&mut reader).unwrap(), 1);
+/// assert_eq!(table.decode(&mut reader).unwrap(), 4);
+/// assert_eq!(table.lengths(), &[1, 3, 3, 0, 2]);
+///
+/// # #[cfg(feature = "serde")]
+/// # {
+/// let json = serde_json::to_string(&table).unwrap();
+/// assert_eq!(json, r#"{"lengths":[1,3,3,0,2]}"#);
+/// let reloaded: HuffmanTable = serde_json::from_str(&json).unwrap();
+/// let mut reader = MsbBitReader::new(&data);
+/// assert_eq!(reloaded.decode(&mut reader).unwrap(), 0);
+/// assert!(serde_json::from_str::<HuffmanTable>(r#"{"lengths":[1,1,1]}"#).is_err());
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "HuffmanLengths"))]
+pub struct HuffmanTable {
+    lengths: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    counts: Vec<u16>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    symbols: Vec<u16>,
+}
+
+/// Serialized form of a `HuffmanTable`.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct HuffmanLengths {
+    lengths: Vec<u8>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<HuffmanLengths> for HuffmanTable {
+    type Error = std::io::Error;
+
+    fn try_from(lengths: HuffmanLengths) -> Result<Self> {
+        HuffmanTable::new(&lengths.lengths)
+    }
+}
+
+impl HuffmanTable {
+    /// Build the table from the code length of every symbol.
+    ///
+    /// Incomplete codes are accepted, over-subscribed ones are rejected.
+    pub fn new(lengths: &[u8]) -> Result<Self> {
+        let max_length = lengths.iter().copied().max().unwrap_or(0) as usize;
+        let mut counts: Vec<u16> = vec![0; max_length + 1];
+        for length in lengths.iter().filter(|l| **l > 0) {
+            counts[*length as usize] += 1;
+        }
+
+        let mut left: i64 = 1;
+        for count in counts.iter().skip(1) {
+            left = (left << 1) - i64::from(*count);
+            if left < 0 {
+                return Err(invalid_data("over-subscribed Huffman code"));
+            }
+        }
+
+        let mut offsets: Vec<usize> = vec![0; max_length + 1];
+        for length in 1..max_length {
+            offsets[length + 1] = offsets[length] + counts[length] as usize;
+        }
+        let mut symbols: Vec<u16> = vec![0; lengths.iter().filter(|l| **l > 0).count()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length > 0 {
+                symbols[offsets[*length as usize]] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+
+        Ok(HuffmanTable {
+            lengths: lengths.to_vec(),
+            counts,
+            symbols,
+        })
+    }
+
+    /// Code length of every symbol, `0` for the symbols without a code.
+    pub fn lengths(&self) -> &[u8] {
+        &self.lengths
+    }
+
+    /// Read the next symbol from `reader`.
+    pub fn decode<R: BitRead>(&self, reader: &mut R) -> Result<u16> {
+        let mut code: i64 = 0;
+        let mut first: i64 = 0;
+        let mut index: i64 = 0;
+        for count in self.counts.iter().skip(1) {
+            code |= i64::from(reader.read_bit()?);
+            let count = i64::from(*count);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid_data("invalid Huffman code"))
+    }
+}
+
+/// Byte oriented stage: the original length, the code lengths and the packed codes.
+impl Stage for Huffman {
+    fn kind(&self) -> StageKind {
+        StageKind::Huffman
+    }
+
+    fn from_parameters(parameters: &[u8]) -> Result<Self> {
+        match parameters.is_empty() {
+            true => Ok(Huffman::new()),
+            false => Err(invalid_data("unexpected Huffman parameters")),
+        }
+    }
+
+    fn forward(&self, data: &[u8]) -> Vec<u8> {
+        let mut output: Vec<u8> = Vec::new();
+        write_varint(&mut output, data.len() as u64);
+        if data.is_empty() {
+            return output;
+        }
+
+        let counter: DenseCounter<u8> = Counter::from_iter(data);
+        let mut frequencies: Vec<u64> = vec![0; 256];
+        for (byte, count) in &counter {
+            frequencies[*byte as usize] = *count;
+        }
+        let lengths = code_lengths(&frequencies, STAGE_MAX_CODE_LENGTH);
+        let codes = canonical_codes(&lengths);
+
+        let mut writer = MsbBitWriter::new();
+        for length in &lengths {
+            writer.write_bit(*length > 0);
+        }
+        for length in lengths.iter().filter(|l| **l > 0) {
+            writer.write_bits(u32::from(*length), 4);
+        }
+        for byte in data {
+            writer.write_bits(codes[*byte as usize], lengths[*byte as usize]);
+        }
+        output.extend(writer.finish());
+        output
+    }
+
+    fn inverse(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut data = data;
+        let length = read_varint(&mut data)? as usize;
+        if length == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut reader = MsbBitReader::new(data);
+        let mut lengths: Vec<u8> = vec![0; 256];
+        for length in lengths.iter_mut() {
+            *length = reader.read_bit()? as u8;
+        }
+        for length in lengths.iter_mut().filter(|l| **l > 0) {
+            *length = reader.read_bits(4)? as u8;
+            if *length == 0 {
+                return Err(invalid_data("invalid Huffman code length"));
+            }
+        }
+        let table = HuffmanTable::new(&lengths)?;
+
+        let mut output: Vec<u8> = Vec::new();
+        for _ in 0..length {
+            output.push(table.decode(&mut reader)? as u8);
+        }
+        Ok(output)
+    }
+}
@@ -26,7 +26,31 @@
 
 //! oua
 
+pub mod analysis;
+pub mod bits;
+pub mod brotli;
+pub mod bwt;
+pub mod bzip2;
+pub mod checksum;
 pub mod counter;
 pub mod deflate;
+pub mod dictionary;
+pub mod format;
+pub mod gzip;
 pub mod huffman;
+pub mod lz4;
 pub mod lz77;
+pub mod lz78;
+pub mod lzma;
+pub mod lzw;
+pub mod mtf;
+pub mod options;
+pub mod pipeline;
+pub mod rle;
+pub mod snappy;
+pub mod tar;
+pub mod zip;
+pub mod zlib;
+pub mod zstd;
+
+pub use format::{decompress_any, detect, Format};
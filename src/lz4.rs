@@ -0,0 +1,461 @@
+//! LZ4 block and frame formats
+//!
+//! A block is a list of sequences, each one being a token byte holding the
+//! literal and match lengths, the literals, a two bytes little endian offset
+//! and the length extensions. The last sequence only has literals.
+//!
+//! A frame starts with a magic number and a descriptor, followed by blocks
+//! prefixed by their size and by an optional xxHash32 of the whole content.
+use std::cmp::min;
+use std::io::Result;
+
+use crate::bits::{invalid_data, read_u16, read_u32, take};
+use crate::checksum::xxhash32;
+use crate::lz77::{MatchFinder, HASH_LENGTH};
+use crate::options::CompressionOptions;
+
+const FRAME_MAGIC: u32 = 0x184d_2204;
+/// Skippable frames use the magic numbers `0x184D2A50` to `0x184D2A5F`.
+const SKIPPABLE_MAGIC: u32 = 0x184d_2a50;
+
+const MIN_MATCH: usize = HASH_LENGTH;
+const MAX_DISTANCE: usize = 65_535;
+/// The last match starts at least 12 bytes before the end of the block.
+const MATCH_START_LIMIT: usize = 12;
+/// The last 5 bytes of a block are always literals.
+const LAST_LITERALS: usize = 5;
+/// Searches skip more positions after every 64 bytes without a match.
+const SKIP_STRENGTH: usize = 6;
+const UNCOMPRESSED_FLAG: u32 = 0x8000_0000;
+
+const FLAG_VERSION: u8 = 0x40;
+const FLAG_INDEPENDENT_BLOCKS: u8 = 0x20;
+const FLAG_BLOCK_CHECKSUM: u8 = 0x10;
+const FLAG_CONTENT_SIZE: u8 = 0x08;
+const FLAG_CONTENT_CHECKSUM: u8 = 0x04;
+const FLAG_DICTIONARY_ID: u8 = 0x01;
+
+/// Largest block of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockSize {
+    Max64KB = 4,
+    Max256KB = 5,
+    Max1MB = 6,
+    Max4MB = 7,
+}
+
+impl BlockSize {
+    fn from_u8(id: u8) -> Option<BlockSize> {
+        match id {
+            4 => Some(BlockSize::Max64KB),
+            5 => Some(BlockSize::Max256KB),
+            6 => Some(BlockSize::Max1MB),
+            7 => Some(BlockSize::Max4MB),
+            _ => None,
+        }
+    }
+
+    /// Size in bytes.
+    pub fn bytes(self) -> usize {
+        1 << (8 + 2 * self as usize)
+    }
+}
+
+/// LZ4 codec.
+///
+/// # Examples
+/// ```
+/// # use reducto::lz4::{decompress_block, LZ4};
+/// let lz4 = LZ4::new();
+/// let data = b"hello hello hello hello hello hello";
+///
+/// let block = lz4.compress_block(data);
+/// assert!(block.len() < data.len());
+/// assert_eq!(decompress_block(&block, data.len()).unwrap(), &data[..]);
+///
+/// let frame = lz4.compress(data);
+/// assert_eq!(&frame[..4], &[0x04, 0x22, 0x4d, 0x18]);
+/// assert_eq!(lz4.decompress(&frame).unwrap(), &data[..]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct LZ4 {
+    block_size: BlockSize,
+    block_checksum: bool,
+    content_checksum: bool,
+    content_size: bool,
+    max_chain: usize,
+    max_distance: usize,
+}
+
+impl LZ4 {
+    /// Create a new `LZ4` writing frames of 4 MB blocks ended by a content checksum.
+    pub fn new() -> LZ4 {
+        LZ4 {
+            block_size: BlockSize::Max4MB,
+            block_checksum: false,
+            content_checksum: true,
+            content_size: false,
+            max_chain: 4,
+            max_distance: MAX_DISTANCE,
+        }
+    }
+
+    /// Create a new `LZ4` tuned by `options`.
+    ///
+    /// The level compares `2^level` candidates per search, unless the chain
+    /// depth is set. The history is at most 64 KiB, larger ones being clamped,
+    /// and the blocks are the smallest of the frame block sizes holding
+    /// `block_size` bytes, up to 4 MB. The matches are at least 4 bytes long,
+    /// the other knobs are ignored.
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::lz4::LZ4;
+    /// # use reducto::options::CompressionOptions;
+    /// let lorem = std::fs::read("resources/lorem.txt").unwrap();
+    /// let fast = LZ4::with_options(CompressionOptions::fast()).compress(&lorem);
+    /// let best = LZ4::with_options(CompressionOptions::best()).compress(&lorem);
+    /// assert!(best.len() < fast.len());
+    ///
+    /// let near = LZ4::with_options(CompressionOptions::best().history_size(1024));
+    /// let compressed = near.compress(&lorem);
+    /// assert!(compressed.len() > best.len());
+    /// assert_eq!(near.decompress(&compressed).unwrap(), lorem);
+    /// ```
+    pub fn with_options(options: CompressionOptions) -> LZ4 {
+        let max_chain = options.chain_depth.unwrap_or(1 << options.level);
+        let history_size = options.history_size.unwrap_or(MAX_DISTANCE);
+        let block_size = options.block_size.map_or(BlockSize::Max4MB, |size| {
+            [BlockSize::Max64KB, BlockSize::Max256KB, BlockSize::Max1MB]
+                .iter()
+                .copied()
+                .find(|block_size| block_size.bytes() >= size)
+                .unwrap_or(BlockSize::Max4MB)
+        });
+        LZ4 {
+            max_distance: min(history_size, MAX_DISTANCE),
+            ..LZ4::new()
+                .block_size(block_size)
+                .max_chain(max_chain.max(1))
+        }
+    }
+
+    /// Set the largest block of the frames.
+    pub fn block_size(mut self, block_size: BlockSize) -> LZ4 {
+        self.block_size = block_size;
+        self
+    }
+
+    /// Write an xxHash32 after each block.
+    pub fn block_checksum(mut self, block_checksum: bool) -> LZ4 {
+        self.block_checksum = block_checksum;
+        self
+    }
+
+    /// Write an xxHash32 of the whole content at the end of the frame.
+    pub fn content_checksum(mut self, content_checksum: bool) -> LZ4 {
+        self.content_checksum = content_checksum;
+        self
+    }
+
+    /// Write the content size in the frame descriptor.
+    pub fn content_size(mut self, content_size: bool) -> LZ4 {
+        self.content_size = content_size;
+        self
+    }
+
+    /// Set the number of candidates compared when looking for a match.
+    ///
+    /// # Arguments
+    /// * max_chain - Candidates per search, 1 being the fastest
+    pub fn max_chain(mut self, max_chain: usize) -> LZ4 {
+        assert!(max_chain > 0);
+        self.max_chain = max_chain;
+        self
+    }
+
+    /// Compress `data` into a single block, without any framing.
+    pub fn compress_block(&self, data: &[u8]) -> Vec<u8> {
+        let mut output: Vec<u8> = Vec::with_capacity(data.len() / 2 + 16);
+        let mut finder = MatchFinder::new(data.len(), self.max_distance, self.max_chain);
+        let match_start_end = data.len().saturating_sub(MATCH_START_LIMIT);
+        let match_end = data.len().saturating_sub(LAST_LITERALS);
+        let mut anchor: usize = 0;
+        let mut position: usize = 0;
+
+        while position < match_start_end {
+            let found = finder.find(data, position, match_end - position);
+            finder.insert(data, position);
+            let (distance, length) = match found {
+                Some(found) => found,
+                None => {
+                    position += 1 + ((position - anchor) >> SKIP_STRENGTH);
+                    continue;
+                }
+            };
+            write_sequence(&mut output, &data[anchor..position], distance, length);
+            for inserted in position + 1..min(position + length, match_start_end) {
+                finder.insert(data, inserted);
+            }
+            position += length;
+            anchor = position;
+        }
+
+        write_lengths(&mut output, data.len() - anchor, 0);
+        output.extend_from_slice(&data[anchor..]);
+        output
+    }
+
+    /// Compress `data` into a frame of independent blocks.
+    pub fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut output: Vec<u8> = Vec::with_capacity(data.len() / 2 + 32);
+        output.extend_from_slice(&FRAME_MAGIC.to_le_bytes());
+
+        let descriptor_start = output.len();
+        let mut flags = FLAG_VERSION | FLAG_INDEPENDENT_BLOCKS;
+        if self.block_checksum {
+            flags |= FLAG_BLOCK_CHECKSUM;
+        }
+        if self.content_size {
+            flags |= FLAG_CONTENT_SIZE;
+        }
+        if self.content_checksum {
+            flags |= FLAG_CONTENT_CHECKSUM;
+        }
+        output.push(flags);
+        output.push((self.block_size as u8) << 4);
+        if self.content_size {
+            output.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        }
+        let header_checksum = (xxhash32(&output[descriptor_start..], 0) >> 8) as u8;
+        output.push(header_checksum);
+
+        for block in data.chunks(self.block_size.bytes()) {
+            let compressed = self.compress_block(block);
+            let stored_start = output.len() + 4;
+            if compressed.len() < block.len() {
+                output.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+                output.extend_from_slice(&compressed);
+            } else {
+                output.extend_from_slice(&(block.len() as u32 | UNCOMPRESSED_FLAG).to_le_bytes());
+                output.extend_from_slice(block);
+            }
+            if self.block_checksum {
+                let checksum = xxhash32(&output[stored_start..], 0);
+                output.extend_from_slice(&checksum.to_le_bytes());
+            }
+        }
+
+        output.extend_from_slice(&0u32.to_le_bytes());
+        if self.content_checksum {
+            output.extend_from_slice(&xxhash32(data, 0).to_le_bytes());
+        }
+        output
+    }
+
+    /// Decompress every concatenated frame of `data`, ignoring the skippable ones.
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::lz4::LZ4;
+    /// // `lz4 -9 -B4 -BD -BX --content-size`: linked 64 KB blocks, block checksums, content size
+    /// let lorem = std::fs::read("resources/lorem.txt").unwrap();
+    /// let mut compressed = std::fs::read("resources/lz4/lorem.txt.lz4").unwrap();
+    /// assert_eq!(compressed[4], 0x5c);
+    /// assert_eq!(LZ4::new().decompress(&compressed).unwrap(), lorem);
+    /// compressed[100] ^= 1;
+    /// assert!(LZ4::new().decompress(&compressed).is_err());
+    ///
+    /// // `lz4 -1 -B4 -BX`: independent 64 KB blocks, block checksums
+    /// let alice = std::fs::read("resources/alice29.txt").unwrap();
+    /// let compressed = std::fs::read("resources/lz4/alice29.txt.lz4").unwrap();
+    /// assert_eq!(compressed[4], 0x74);
+    /// assert_eq!(LZ4::new().decompress(&compressed).unwrap(), alice);
+    /// ```
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut output: Vec<u8> = Vec::new();
+        let mut rest = data;
+        while !rest.is_empty() {
+            let magic = read_u32(take(&mut rest, 4)?);
+            if magic & 0xffff_fff0 == SKIPPABLE_MAGIC {
+                let size = read_u32(take(&mut rest, 4)?) as usize;
+                take(&mut rest, size)?;
+            } else if magic == FRAME_MAGIC {
+                read_frame(&mut rest, &mut output)?;
+            } else {
+                return Err(invalid_data("invalid LZ4 frame magic number"));
+            }
+        }
+        Ok(output)
+    }
+}
+
+impl Default for LZ4 {
+    fn default() -> Self {
+        LZ4::new()
+    }
+}
+
+/// Write a token and the literal length extension, the match length being at least `MIN_MATCH`
+/// or `0` for the last sequence.
+fn write_lengths(output: &mut Vec<u8>, literal_length: usize, match_length: usize) {
+    let match_code = match_length.saturating_sub(MIN_MATCH);
+    output.push((min(literal_length, 15) << 4 | min(match_code, 15)) as u8);
+    if literal_length >= 15 {
+        write_length_extension(output, literal_length - 15);
+    }
+}
+
+fn write_length_extension(output: &mut Vec<u8>, mut length: usize) {
+    while length >= 255 {
+        output.push(255);
+        length -= 255;
+    }
+    output.push(length as u8);
+}
+
+fn write_sequence(output: &mut Vec<u8>, literals: &[u8], distance: usize, length: usize) {
+    write_lengths(output, literals.len(), length);
+    output.extend_from_slice(literals);
+    output.extend_from_slice(&(distance as u16).to_le_bytes());
+    if length - MIN_MATCH >= 15 {
+        write_length_extension(output, length - MIN_MATCH - 15);
+    }
+}
+
+fn read_length_extension(data: &mut &[u8]) -> Result<usize> {
+    let mut length: usize = 0;
+    loop {
+        let byte = take(data, 1)?[0];
+        length += byte as usize;
+        if byte != 255 {
+            return Ok(length);
+        }
+    }
+}
+
+/// Decompress a single block, without any framing.
+///
+/// # Arguments
+/// * data - The compressed block
+/// * max_size - Largest decompressed size accepted
+pub fn decompress_block(data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+    let mut output: Vec<u8> = Vec::new();
+    decompress_block_into(data, &mut output, 0, max_size)?;
+    Ok(output)
+}
+
+/// Decompress a block at the end of `output`, matches reaching back to `history_start`.
+fn decompress_block_into(
+    data: &[u8],
+    output: &mut Vec<u8>,
+    history_start: usize,
+    max_size: usize,
+) -> Result<()> {
+    let limit = output.len() + max_size;
+    let mut rest = data;
+    loop {
+        let token = take(&mut rest, 1)?[0];
+        let mut literal_length = (token >> 4) as usize;
+        if literal_length == 15 {
+            literal_length += read_length_extension(&mut rest)?;
+        }
+        if output.len() + literal_length > limit {
+            return Err(invalid_data("LZ4 block is larger than announced"));
+        }
+        output.extend_from_slice(take(&mut rest, literal_length)?);
+        if rest.is_empty() {
+            return Ok(());
+        }
+
+        let offset = take(&mut rest, 2)?;
+        let distance = read_u16(offset) as usize;
+        let mut length = (token & 15) as usize + MIN_MATCH;
+        if length == 15 + MIN_MATCH {
+            length += read_length_extension(&mut rest)?;
+        }
+        if distance == 0 || distance > output.len() - history_start {
+            return Err(invalid_data("LZ4 match refers outside of the decoded data"));
+        }
+        if output.len() + length > limit {
+            return Err(invalid_data("LZ4 block is larger than announced"));
+        }
+        let start = output.len() - distance;
+        if distance >= length {
+            output.extend_from_within(start..start + length);
+        } else {
+            for index in start..start + length {
+                output.push(output[index]);
+            }
+        }
+    }
+}
+
+/// Read a frame following its magic number, appending its content to `output`.
+fn read_frame(data: &mut &[u8], output: &mut Vec<u8>) -> Result<()> {
+    let descriptor = *data;
+    let header = take(data, 2)?;
+    let (flags, block_descriptor) = (header[0], header[1]);
+    if flags & 0xc0 != FLAG_VERSION || flags & 0x02 != 0 || block_descriptor & 0x8f != 0 {
+        return Err(invalid_data("unsupported LZ4 frame descriptor"));
+    }
+    let block_size = match BlockSize::from_u8(block_descriptor >> 4) {
+        Some(block_size) => block_size.bytes(),
+        None => return Err(invalid_data("invalid LZ4 block size")),
+    };
+    let content_size = match flags & FLAG_CONTENT_SIZE {
+        0 => None,
+        _ => {
+            let bytes = take(data, 8)?;
+            let mut value = [0u8; 8];
+            value.copy_from_slice(bytes);
+            Some(u64::from_le_bytes(value))
+        }
+    };
+    if flags & FLAG_DICTIONARY_ID != 0 {
+        return Err(invalid_data("LZ4 frame needs a dictionary"));
+    }
+    let descriptor_length = descriptor.len() - data.len();
+    let header_checksum = take(data, 1)?[0];
+    if header_checksum != (xxhash32(&descriptor[..descriptor_length], 0) >> 8) as u8 {
+        return Err(invalid_data("LZ4 frame descriptor checksum mismatch"));
+    }
+
+    let frame_start = output.len();
+    loop {
+        let size = read_u32(take(data, 4)?);
+        if size == 0 {
+            break;
+        }
+        let stored_size = (size & !UNCOMPRESSED_FLAG) as usize;
+        if stored_size > block_size {
+            return Err(invalid_data(
+                "LZ4 block is larger than the frame block size",
+            ));
+        }
+        let block = take(data, stored_size)?;
+        if flags & FLAG_BLOCK_CHECKSUM != 0 && read_u32(take(data, 4)?) != xxhash32(block, 0) {
+            return Err(invalid_data("LZ4 block checksum mismatch"));
+        }
+        if size & UNCOMPRESSED_FLAG != 0 {
+            output.extend_from_slice(block);
+        } else {
+            let history_start = match flags & FLAG_INDEPENDENT_BLOCKS {
+                0 => frame_start,
+                _ => output.len(),
+            };
+            decompress_block_into(block, output, history_start, block_size)?;
+        }
+    }
+
+    let content = &output[frame_start..];
+    if flags & FLAG_CONTENT_CHECKSUM != 0 && read_u32(take(data, 4)?) != xxhash32(content, 0) {
+        return Err(invalid_data("LZ4 content checksum mismatch"));
+    }
+    match content_size {
+        Some(size) if size != content.len() as u64 => {
+            Err(invalid_data("LZ4 frame content size mismatch"))
+        }
+        _ => Ok(()),
+    }
+}
@@ -1,6 +1,14 @@
 use std::cmp::{min, Ordering};
+use std::io::Result;
 use std::slice::Iter;
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::bits::{invalid_data, unexpected_end};
+use crate::options::CompressionOptions;
+use crate::pipeline::{Stage, StageKind};
+
 #[derive(Debug, Default, Eq)]
 pub struct LZ77Match {
     index: usize,
@@ -11,6 +19,10 @@ impl LZ77Match {
     pub fn len(&self) -> usize {
         self.match_data.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.match_data.is_empty()
+    }
 }
 
 impl From<(Vec<u8>, usize)> for LZ77Match {
@@ -38,10 +50,17 @@ impl Ord for LZ77Match {
     }
 }
 
+/// Char following the match of a node, serialized as `{"NextMatch":97}`, `"EndOfData"` or `"LzssMatch"`.
+///
+/// `EndOfData` means that no char follows the match because the data ends,
+/// and `LzssMatch` that the node is a match of the LZSS mode, never followed
+/// by a char.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum LZ77NodeMatch {
     NextMatch(u8),
     EndOfData,
+    LzssMatch,
 }
 
 impl PartialEq for LZ77NodeMatch {
@@ -50,18 +69,39 @@ impl PartialEq for LZ77NodeMatch {
             LZ77NodeMatch::NextMatch(value) => match other {
                 LZ77NodeMatch::NextMatch(other_value) => value == other_value,
                 LZ77NodeMatch::EndOfData => true,
+                LZ77NodeMatch::LzssMatch => false,
             },
             Self::EndOfData => match other {
-                LZ77NodeMatch::NextMatch(_) => false,
+                LZ77NodeMatch::NextMatch(_) | LZ77NodeMatch::LzssMatch => false,
                 LZ77NodeMatch::EndOfData => true,
             },
+            Self::LzssMatch => matches!(other, LZ77NodeMatch::LzssMatch),
         }
     }
 }
 
 impl Eq for LZ77NodeMatch {}
 
+/// Token of the LZ77 stream, serialized as `{"offset":3,"length":2,"next_match":...}`.
+///
+/// # Examples
+/// ```
+/// # #[cfg(feature = "serde")]
+/// # {
+/// # use reducto::lz77::{LZ77, LZ77Node};
+/// let mut lz77 = LZ77::new(4, 6);
+/// lz77.encode("aacaacabcabaaac");
+/// let json = serde_json::to_string(&lz77.iter().collect::<Vec<_>>()).unwrap();
+/// assert!(json.starts_with(r#"[{"offset":0,"length":0,"next_match":{"NextMatch":97}}"#));
+///
+/// let nodes: Vec<LZ77Node> = serde_json::from_str(&json).unwrap();
+/// let mut other = LZ77::new(4, 6);
+/// other.load_nodes(nodes).unwrap();
+/// assert_eq!(other.decode(), "aacaacabcabaaac");
+/// # }
+/// ```
 #[derive(Debug, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct LZ77Node {
     offset: usize,
     length: usize,
@@ -77,11 +117,13 @@ impl LZ77Node {
         }
     }
 
+    /// Serialize the node as `offset, length[, next_char]`.
+    ///
+    /// LZSS matches and the last node of a stream end without a next char.
     pub fn to_vec_u8(&self) -> Vec<u8> {
         let mut res: Vec<u8> = Vec::new();
         res.push(self.offset as u8);
         res.push(self.length as u8);
-        res.push(self.offset as u8);
         if let LZ77NodeMatch::NextMatch(data) = self.next_match {
             res.push(data);
         }
@@ -101,39 +143,397 @@ impl PartialEq for LZ77Node {
 pub struct LZ77 {
     window_size: usize,
     dictionary_size: usize,
+    /// Shortest match of the LZSS mode, `None` for `(offset, length, next_char)` triples.
+    min_match: Option<usize>,
+    /// Preset history that the first nodes can refer to.
+    preset: Vec<u8>,
     position: usize,
     nodes: Vec<LZ77Node>,
 }
 
-fn find_subvec_in_vec(source: &[u8], target: &[u8]) -> Vec<(usize, usize)> {
-    let mut res: Vec<(usize, usize)> = Vec::new();
-    if target.len() == 0 || target.len() > source.len() {
-        // return 0
-        return res;
-    }
-
-    for (index, byte) in source.iter().enumerate().rev() {
-        if *byte == target[0] {
-            let res_index = index;
-            let mut res_size: usize = 0;
-            for i in 0..min(source.len(), target.len()) {
-                if index + i < source.len() {
-                    if target[i] == source[index + i] {
-                        res_size += 1;
-                    } else {
+/// Find the longest match of the bytes at `position` in the `dictionary_size` bytes before it.
+///
+/// The match is at most `window_size` bytes long and lies entirely before
+/// `position`; between matches of the same length the closest one wins.
+pub(crate) fn find_longest_match(
+    data: &[u8],
+    position: usize,
+    dictionary_size: usize,
+    window_size: usize,
+) -> LZ77Match {
+    let dictionary_index_start = position.saturating_sub(dictionary_size);
+    let data_window_index_end = min(position + window_size, data.len());
+    let mut longest: (usize, usize) = (0, 0);
+
+    for index in (dictionary_index_start..position).rev() {
+        let max_length = min(data_window_index_end - position, position - index);
+        let length = data[index..index + max_length]
+            .iter()
+            .zip(&data[position..])
+            .take_while(|(available, window)| available == window)
+            .count();
+        if length > longest.1 {
+            longest = (index, length);
+            if length == data_window_index_end - position {
+                break;
+            }
+        }
+    }
+
+    let (index, length) = longest;
+    LZ77Match::from((Vec::from(&data[index..index + length]), index))
+}
+
+/// Bytes hashed by default by the `MatchFinder`, which only finds matches at least that long.
+pub(crate) const HASH_LENGTH: usize = 4;
+
+const EMPTY_POSITION: u32 = u32::MAX;
+/// Base 2 logarithm of the size of the table of the positions by their first three bytes.
+const HASH3_LOG: u32 = 16;
+
+/// Lookahead and history sizes of every level.
+const LEVELS: [(usize, usize); 10] = [
+    (1, 1),
+    (8, 16),
+    (16, 32),
+    (16, 64),
+    (32, 64),
+    (32, 128),
+    (64, 128),
+    (64, 255),
+    (128, 255),
+    (255, 255),
+];
+
+/// Lookahead and history sizes set by `options`, or by its level.
+pub(crate) fn sizes(options: &CompressionOptions) -> (usize, usize) {
+    let (lookahead_size, history_size) = LEVELS[options.level as usize];
+    (
+        options.max_match_length.unwrap_or(lookahead_size),
+        options.history_size.unwrap_or(history_size),
+    )
+}
+
+/// Hash chains over the positions of some data, to find long matches quickly.
+///
+/// Positions are hashed by their first `hash_length` bytes, `HASH_LENGTH` by
+/// default, and those sharing a hash are linked from the newest to the oldest.
+/// Searching a match only compares the `max_chain` newest positions of the chain.
+///
+/// As the `hc4` and `bt4` match finders of xz, `find_all` collects a match of
+/// every length instead of the longest one. Matches of two and three bytes are
+/// then found from tables of the last positions starting with them, and the
+/// positions sharing a hash can form binary search trees instead of chains.
+#[derive(Debug)]
+pub(crate) struct MatchFinder {
+    heads: Vec<u32>,
+    /// Previous position in the chain of every position of the window, or its
+    /// smaller and larger children in a binary tree.
+    previous: Vec<u32>,
+    /// Number of positions in the window.
+    cyclic_size: usize,
+    hash_log: u32,
+    hash_length: usize,
+    dictionary_size: usize,
+    max_chain: usize,
+    /// Last positions by their first two bytes, empty unless short matches are searched.
+    hash2: Vec<u32>,
+    /// Last positions by the hash of their first three bytes, empty unless short matches are searched.
+    hash3: Vec<u32>,
+    binary_tree: bool,
+}
+
+impl MatchFinder {
+    /// Create a `MatchFinder` for `data_length` bytes of data.
+    ///
+    /// # Arguments
+    /// * data_length - Length of the data that will be searched
+    /// * dictionary_size - Largest distance of a match
+    /// * max_chain - Number of positions compared per search
+    pub(crate) fn new(data_length: usize, dictionary_size: usize, max_chain: usize) -> Self {
+        assert!(data_length < EMPTY_POSITION as usize);
+        let cyclic_size = min(data_length, dictionary_size.saturating_add(1))
+            .max(1)
+            .next_power_of_two();
+        let hash_log = cyclic_size.trailing_zeros().clamp(8, 24);
+        MatchFinder {
+            heads: vec![EMPTY_POSITION; 1 << hash_log],
+            previous: vec![EMPTY_POSITION; cyclic_size],
+            cyclic_size,
+            hash_log,
+            hash_length: HASH_LENGTH,
+            dictionary_size,
+            max_chain,
+            hash2: Vec::new(),
+            hash3: Vec::new(),
+            binary_tree: false,
+        }
+    }
+
+    /// Set the number of bytes hashed, from 2 to 4, which is the length of the shortest matches.
+    pub(crate) fn hash_length(mut self, hash_length: usize) -> Self {
+        assert!((2..=HASH_LENGTH).contains(&hash_length));
+        self.hash_length = hash_length;
+        self
+    }
+
+    /// Also collect the matches of two and three bytes in `find_all`.
+    pub(crate) fn short_matches(mut self) -> Self {
+        self.hash2 = vec![EMPTY_POSITION; 1 << 16];
+        self.hash3 = vec![EMPTY_POSITION; 1 << HASH3_LOG];
+        self
+    }
+
+    /// Sort the positions sharing a hash in binary search trees by the bytes
+    /// that follow, instead of chaining them.
+    ///
+    /// A tree is rebuilt around every new position while searching its
+    /// matches, so every position must be passed to `find_all` or `skip`, in
+    /// increasing order, and `insert` and `find` are not available.
+    pub(crate) fn binary_tree(mut self) -> Self {
+        self.cyclic_size = min(self.cyclic_size, self.dictionary_size.saturating_add(1));
+        self.previous = vec![EMPTY_POSITION; 2 * self.cyclic_size];
+        self.binary_tree = true;
+        self
+    }
+
+    fn hash(&self, data: &[u8], position: usize) -> usize {
+        let bytes = match self.hash_length {
+            2 => [data[position], data[position + 1], 0, 0],
+            3 => [data[position], data[position + 1], data[position + 2], 0],
+            _ => [
+                data[position],
+                data[position + 1],
+                data[position + 2],
+                data[position + 3],
+            ],
+        };
+        (u32::from_le_bytes(bytes).wrapping_mul(2_654_435_761) >> (32 - self.hash_log)) as usize
+    }
+
+    /// Index of `position` in the window.
+    fn slot(&self, position: usize) -> usize {
+        if self.binary_tree {
+            position % self.cyclic_size
+        } else {
+            position & (self.cyclic_size - 1)
+        }
+    }
+
+    /// Distance from `position` back to `candidate`, if a match can refer to it.
+    fn distance(&self, position: usize, candidate: u32) -> Option<usize> {
+        let index = candidate as usize;
+        if candidate == EMPTY_POSITION || index >= position {
+            return None;
+        }
+        Some(position - index).filter(|distance| *distance <= self.dictionary_size)
+    }
+
+    /// Add `position` to its chain, positions being inserted in increasing order.
+    pub(crate) fn insert(&mut self, data: &[u8], position: usize) {
+        debug_assert!(!self.binary_tree);
+        if position + self.hash_length > data.len() {
+            return;
+        }
+        let hash = self.hash(data, position);
+        let slot = self.slot(position);
+        self.previous[slot] = self.heads[hash];
+        self.heads[hash] = position as u32;
+    }
+
+    /// Find the longest match of the bytes at `position` among the inserted positions before it.
+    ///
+    /// Returns the distance and length of a match of `hash_length` to
+    /// `max_length` bytes, preferring the closest one between equal lengths.
+    /// The match may overlap `position`.
+    pub(crate) fn find(
+        &self,
+        data: &[u8],
+        position: usize,
+        max_length: usize,
+    ) -> Option<(usize, usize)> {
+        debug_assert!(!self.binary_tree);
+        let max_length = min(max_length, data.len().saturating_sub(position));
+        if max_length < self.hash_length {
+            return None;
+        }
+        let mut best: Option<(usize, usize)> = None;
+        let mut best_length = self.hash_length - 1;
+        let mut candidate = self.heads[self.hash(data, position)];
+        for _ in 0..self.max_chain {
+            let distance = match self.distance(position, candidate) {
+                Some(distance) => distance,
+                None => break,
+            };
+            let index = candidate as usize;
+            // a longer match must at least agree on its last byte
+            if data[index + best_length] == data[position + best_length] {
+                let length = data[index..]
+                    .iter()
+                    .zip(&data[position..position + max_length])
+                    .take_while(|(previous, current)| previous == current)
+                    .count();
+                if length > best_length {
+                    best = Some((distance, length));
+                    best_length = length;
+                    if length == max_length {
+                        break;
+                    }
+                }
+            }
+            let next = self.previous[self.slot(index)];
+            if next != EMPTY_POSITION && next >= candidate {
+                break;
+            }
+            candidate = next;
+        }
+        best
+    }
+
+    /// Add `position`, the one after the last added position, without collecting its matches.
+    pub(crate) fn skip(&mut self, data: &[u8], position: usize, max_length: usize) {
+        self.visit(data, position, max_length, None);
+    }
+
+    /// Add `position`, the one after the last added position, and collect its
+    /// matches among the positions before it.
+    ///
+    /// The matches are collected as `(distance, length)`, each one longer than
+    /// the previous one, up to `max_length` bytes.
+    pub(crate) fn find_all(
+        &mut self,
+        data: &[u8],
+        position: usize,
+        max_length: usize,
+        matches: &mut Vec<(usize, usize)>,
+    ) {
+        matches.clear();
+        self.visit(data, position, max_length, Some(matches));
+    }
+
+    fn visit(
+        &mut self,
+        data: &[u8],
+        position: usize,
+        max_length: usize,
+        mut matches: Option<&mut Vec<(usize, usize)>>,
+    ) {
+        let limit = min(max_length, data.len() - position);
+        let current = &data[position..position + limit];
+        let common = |index: usize| {
+            data[index..]
+                .iter()
+                .zip(current)
+                .take_while(|(previous, current)| previous == current)
+                .count()
+        };
+        let collect = matches.is_some();
+        let mut best: usize = 1;
+        let mut record = |distance: usize, length: usize| {
+            if length > best {
+                best = length;
+                if let Some(matches) = matches.as_mut() {
+                    matches.push((distance, length));
+                }
+            }
+        };
+
+        let mut candidate2 = EMPTY_POSITION;
+        if !self.hash2.is_empty() && limit >= 2 {
+            let hash2 = usize::from(current[0]) | usize::from(current[1]) << 8;
+            candidate2 = std::mem::replace(&mut self.hash2[hash2], position as u32);
+            if let Some(distance) = self.distance(position, candidate2) {
+                record(distance, common(position - distance));
+            }
+        }
+        if !self.hash3.is_empty() && limit >= 3 {
+            let bytes = u32::from_le_bytes([current[0], current[1], current[2], 0]);
+            let hash3 = (bytes.wrapping_mul(2_654_435_761) >> (32 - HASH3_LOG)) as usize;
+            let candidate3 = std::mem::replace(&mut self.hash3[hash3], position as u32);
+            if let Some(distance) = self
+                .distance(position, candidate3)
+                .filter(|_| candidate3 != candidate2)
+            {
+                record(distance, common(position - distance));
+            }
+        }
+        if limit < self.hash_length {
+            return;
+        }
+
+        if !self.binary_tree {
+            if collect {
+                let mut candidate = self.heads[self.hash(data, position)];
+                for _ in 0..self.max_chain {
+                    let distance = match self.distance(position, candidate) {
+                        Some(distance) => distance,
+                        None => break,
+                    };
+                    let length = common(position - distance);
+                    record(distance, length);
+                    if length == limit {
                         break;
                     }
-                } else {
-                    break;
+                    let next = self.previous[self.slot(candidate as usize)];
+                    if next != EMPTY_POSITION && next >= candidate {
+                        break;
+                    }
+                    candidate = next;
                 }
             }
-            res.push((res_index, res_size));
+            self.insert(data, position);
+            return;
+        }
+
+        // the new position becomes the root, the others being split in smaller and larger ones
+        let hash = self.hash(data, position);
+        let mut candidate = std::mem::replace(&mut self.heads[hash], position as u32);
+        let mut smaller = 2 * self.slot(position);
+        let mut larger = smaller + 1;
+        let mut smaller_length: usize = 0;
+        let mut larger_length: usize = 0;
+        for _ in 0..self.max_chain {
+            let distance = match self.distance(position, candidate) {
+                Some(distance) => distance,
+                None => break,
+            };
+            let index = candidate as usize;
+            let pair = 2 * self.slot(index);
+            let mut length = min(smaller_length, larger_length);
+            length += data[index + length..]
+                .iter()
+                .zip(&current[length..])
+                .take_while(|(previous, current)| previous == current)
+                .count();
+            record(distance, length);
+            if length == limit {
+                self.previous[smaller] = self.previous[pair];
+                self.previous[larger] = self.previous[pair + 1];
+                return;
+            }
+            if data[index + length] < current[length] {
+                self.previous[smaller] = candidate;
+                smaller = pair + 1;
+                candidate = self.previous[smaller];
+                smaller_length = length;
+            } else {
+                self.previous[larger] = candidate;
+                larger = pair;
+                candidate = self.previous[larger];
+                larger_length = length;
+            }
         }
+        self.previous[smaller] = EMPTY_POSITION;
+        self.previous[larger] = EMPTY_POSITION;
     }
-    res
 }
 
 impl LZ77 {
+    /// Create a new `LZ77`.
+    ///
+    /// # Arguments
+    /// * window_size - Lookahead, the bytes ahead of the position that a match can cover
+    /// * dictionary_size - History, the bytes before the position that a match can refer to
     pub fn new(window_size: usize, dictionary_size: usize) -> LZ77 {
         assert_ne!(window_size, 0);
         assert_ne!(dictionary_size, 0);
@@ -142,74 +542,169 @@ impl LZ77 {
         LZ77 {
             window_size,
             dictionary_size,
+            min_match: None,
+            preset: Vec::new(),
             position: 0,
             nodes: Vec::new(),
         }
     }
 
-    fn find_longest_match(&self, data: &[u8]) -> LZ77Match {
-        let mut res: LZ77Match = Default::default();
-        if self.position > 0 {
-            let dictionary_index_start =
-                self.position.checked_sub(self.dictionary_size).unwrap_or(0);
-            let data_available_index_end = min(self.position, data.len());
-            let data_available = &data[dictionary_index_start..data_available_index_end];
-            let data_window_index_end = min(self.position + self.window_size, data.len());
-            let data_window = &data[self.position..data_window_index_end];
-
-            for i in 0..data_window.len() {
-                let substring = &data_window[..=i];
-                let matches = find_subvec_in_vec(data_available, substring);
-
-                for (index, size) in matches {
-                    let index_start = dictionary_index_start + index;
-
-                    let index_end = match index + size < data_available.len() {
-                        true => index + size,
-                        false => data_available.len(),
-                    };
+    /// Create a new `LZ77` with the history and lookahead sizes of `options`.
+    ///
+    /// Unset sizes follow the level, from 8 and 16 bytes at level 1 to 255 at
+    /// level 9. The nodes store offsets and lengths in a byte, so larger sizes
+    /// are rejected like by `new`. The other knobs are ignored.
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::lz77::LZ77;
+    /// # use reducto::options::CompressionOptions;
+    /// let options = CompressionOptions::default().history_size(6).lookahead_size(4);
+    /// let mut lz77 = LZ77::with_options(options);
+    /// lz77.encode("aacaacabcabaaac");
+    /// assert_eq!(lz77.decode(), "aacaacabcabaaac");
+    ///
+    /// let lorem = std::fs::read_to_string("resources/small_lorem.txt").unwrap();
+    /// let mut lz77 = LZ77::with_options(CompressionOptions::best());
+    /// lz77.encode(&lorem);
+    /// assert_eq!(lz77.decode(), lorem);
+    /// ```
+    pub fn with_options(options: CompressionOptions) -> LZ77 {
+        let (lookahead_size, history_size) = sizes(&options);
+        LZ77::new(lookahead_size, history_size)
+    }
 
-                    let match_data: Vec<u8> = Vec::from(&data_available[index..index_end]);
-                    let new_match = LZ77Match::from((match_data, index_start));
+    /// Switch to the LZSS mode, where a node is either a literal or a match of at least `min_match` bytes.
+    ///
+    /// A literal is a node `(0, 0, next_char)` and a match a node
+    /// `(offset, length, LzssMatch)`, without the next char. Matches come from
+    /// the hash chains of the match finder and may overlap the bytes they
+    /// produce. `to_vec_u8` groups the nodes by eight behind a flag byte whose
+    /// bit `i` tells whether node `i` is a match (`1`) written as its offset
+    /// and length, or a literal (`0`) written as its byte.
+    ///
+    /// # Arguments
+    /// * min_match - Shortest match, from 2 to the window size
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::lz77::LZ77;
+    /// let mut lz77 = LZ77::new(18, 255).lzss(3);
+    /// lz77.encode("abcabcabcd");
+    /// let nodes: Vec<Vec<u8>> = lz77.iter().map(|n| n.to_vec_u8()).collect();
+    /// assert_eq!(nodes, vec![vec![0, 0, b'a'], vec![0, 0, b'b'], vec![0, 0, b'c'], vec![3, 6], vec![0, 0, b'd']]);
+    /// # #[cfg(feature = "serde")]
+    /// assert_eq!(
+    ///     serde_json::to_string(lz77.iter().nth(3).unwrap()).unwrap(),
+    ///     r#"{"offset":3,"length":6,"next_match":"LzssMatch"}"#
+    /// );
+    ///
+    /// let serialized = lz77.to_vec_u8();
+    /// assert_eq!(serialized, vec![0b1000, b'a', b'b', b'c', 3, 6, b'd']);
+    /// let mut other = LZ77::new(18, 255).lzss(3);
+    /// other.load(&serialized).unwrap();
+    /// assert_eq!(other.decode(), "abcabcabcd");
+    /// ```
+    pub fn lzss(mut self, min_match: usize) -> LZ77 {
+        assert!(min_match >= 2 && min_match <= self.window_size);
+        self.min_match = Some(min_match);
+        self
+    }
 
-                    if new_match > res {
-                        res = new_match;
-                    }
-                }
-            }
-        }
-        res
+    /// Prime the history with `preset`, so matches can refer to it from the first byte.
+    ///
+    /// Only its last `dictionary_size` bytes are used, and the same preset is
+    /// needed to decode the nodes.
+    ///
+    /// # Arguments
+    /// * preset - Data resembling the one to encode
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::lz77::LZ77;
+    /// let mut plain = LZ77::new(16, 32);
+    /// plain.encode("user=42&page=home");
+    ///
+    /// let mut primed = LZ77::new(16, 32).dictionary(b"user=&page=");
+    /// primed.encode("user=42&page=home");
+    /// assert!(primed.iter().count() < plain.iter().count());
+    /// assert_eq!(primed.decode(), "user=42&page=home");
+    ///
+    /// let serialized: Vec<u8> = primed.iter().flat_map(|n| n.to_vec_u8()).collect();
+    /// let mut other = LZ77::new(16, 32).dictionary(b"user=&page=");
+    /// other.load(&serialized).unwrap();
+    /// assert_eq!(other.decode(), "user=42&page=home");
+    /// assert!(LZ77::new(16, 32).load(&serialized).is_err());
+    /// ```
+    pub fn dictionary(mut self, preset: &[u8]) -> LZ77 {
+        let start = preset.len().saturating_sub(self.dictionary_size);
+        self.preset = preset[start..].to_vec();
+        self
+    }
+
+    fn find_longest_match(&self, data: &[u8]) -> LZ77Match {
+        find_longest_match(data, self.position, self.dictionary_size, self.window_size)
     }
 
     pub fn encode<S>(&mut self, data: S)
     where
         S: Sized + ToString,
     {
-        let data: Vec<u8> = Vec::from(data.to_string().as_bytes());
+        self.encode_bytes(data.to_string().as_bytes());
+    }
+
+    /// Encode raw bytes, appending the nodes to the current ones.
+    ///
+    /// # Arguments
+    /// * data - The bytes to encode
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::lz77::LZ77;
+    /// let mut lz77 = LZ77::new(4, 6);
+    /// lz77.encode_bytes(&[0, 0, 255, 0, 0, 255]);
+    /// assert_eq!(lz77.decode_bytes(), vec![0, 0, 255, 0, 0, 255]);
+    /// ```
+    pub fn encode_bytes(&mut self, data: &[u8]) {
+        if !self.preset.is_empty() {
+            let primed = [&self.preset[..], data].concat();
+            self.position += self.preset.len();
+            self.encode_from_position(&primed);
+            self.position -= self.preset.len();
+            return;
+        }
+        self.encode_from_position(data);
+    }
+
+    /// Encode `data` from the current position, the bytes before it being the history.
+    fn encode_from_position(&mut self, data: &[u8]) {
+        if let Some(min_match) = self.min_match {
+            self.encode_lzss(data, min_match);
+            return;
+        }
         let data_length = data.len();
 
         while self.position < data_length {
             // 1. get the longest match
-            let longest_match = self.find_longest_match(&data);
+            let longest_match = self.find_longest_match(data);
 
             // get the window after the match
-            let next_match_value: u8;
             let longest_match_size = longest_match.len();
             let offset = match longest_match_size {
                 0 => 0,
-                _ => self.position.checked_sub(longest_match.index).unwrap_or(0),
+                _ => self.position.saturating_sub(longest_match.index),
             };
             let next_match_position_start = self.position + longest_match_size;
-            if next_match_position_start < data_length {
-                next_match_value = match data.iter().nth(next_match_position_start) {
+            let next_match_value: u8 = if next_match_position_start < data_length {
+                match data.get(next_match_position_start) {
                     Some(character) => *character,
                     None => panic!("Error: Index {} out of bound", next_match_position_start),
-                };
+                }
             } else {
                 let node = LZ77Node::new(offset, longest_match_size, LZ77NodeMatch::EndOfData);
                 self.nodes.push(node);
                 break;
-            }
+            };
 
             let node = LZ77Node::new(
                 offset,
@@ -227,8 +722,38 @@ impl LZ77 {
         }
     }
 
+    /// Encode `data` from the current position as LZSS literals and matches.
+    fn encode_lzss(&mut self, data: &[u8], min_match: usize) {
+        let mut finder = MatchFinder::new(data.len(), self.dictionary_size, self.dictionary_size)
+            .hash_length(min(min_match, HASH_LENGTH));
+        for position in 0..self.position {
+            finder.insert(data, position);
+        }
+        while self.position < data.len() {
+            let node = match finder.find(data, self.position, self.window_size) {
+                Some((offset, length)) if length >= min_match => {
+                    LZ77Node::new(offset, length, LZ77NodeMatch::LzssMatch)
+                }
+                _ => LZ77Node::new(0, 0, LZ77NodeMatch::NextMatch(data[self.position])),
+            };
+            for position in self.position..self.position + node.length.max(1) {
+                finder.insert(data, position);
+            }
+            self.position += node.length.max(1);
+            self.nodes.push(node);
+        }
+    }
+
     pub fn decode(&self) -> String {
-        let mut decoded_string: Vec<u8> = Vec::new();
+        match String::from_utf8(self.decode_bytes()) {
+            Ok(string) => string,
+            Err(reason) => panic!("{}", reason),
+        }
+    }
+
+    /// Decode the nodes back to raw bytes.
+    pub fn decode_bytes(&self) -> Vec<u8> {
+        let mut decoded_string: Vec<u8> = self.preset.clone();
 
         for lz77_node in &self.nodes {
             let length = lz77_node.length;
@@ -237,39 +762,216 @@ impl LZ77 {
             if length == 0 {
                 match lz77_match {
                     LZ77NodeMatch::NextMatch(character) => decoded_string.push(*character),
-                    LZ77NodeMatch::EndOfData => break,
+                    LZ77NodeMatch::EndOfData | LZ77NodeMatch::LzssMatch => break,
                 }
             } else {
-                // Read the stored sequence
-                let offset = lz77_node.offset;
+                // Read the stored sequence, byte per byte as it may overlap the copied bytes
+                let index_start = decoded_string.len().saturating_sub(lz77_node.offset);
+                for index in index_start..index_start + length {
+                    decoded_string.push(decoded_string[index]);
+                }
 
-                let index_start = decoded_string // start = string.len() - offset
-                    .len()
-                    .checked_sub(offset)
-                    .unwrap_or(0);
+                // push the next symbol
+                if let LZ77NodeMatch::NextMatch(character) = lz77_match {
+                    decoded_string.push(*character);
+                }
+            }
+        }
 
-                let index_end = index_start // end = start + (len || (string.len - 1))
-                    .checked_add(length)
-                    .unwrap_or(decoded_string.len().checked_sub(1).unwrap_or(0));
+        decoded_string.split_off(self.preset.len())
+    }
 
-                let substring: Vec<u8> = Vec::from(&decoded_string[index_start..index_end]);
-                decoded_string.extend(substring.iter());
+    /// Serialize the nodes, as concatenated triples or, in LZSS mode, behind flag bytes.
+    pub fn to_vec_u8(&self) -> Vec<u8> {
+        if self.min_match.is_none() {
+            return self.nodes.iter().flat_map(|n| n.to_vec_u8()).collect();
+        }
+        let mut output: Vec<u8> = Vec::with_capacity(self.nodes.len() * 2);
+        for group in self.nodes.chunks(8) {
+            let flags_index = output.len();
+            output.push(0);
+            for (bit, node) in group.iter().enumerate() {
+                match node.next_match {
+                    LZ77NodeMatch::NextMatch(character) => output.push(character),
+                    LZ77NodeMatch::EndOfData | LZ77NodeMatch::LzssMatch => {
+                        output[flags_index] |= 1 << bit;
+                        output.extend(&[node.offset as u8, node.length as u8]);
+                    }
+                }
+            }
+        }
+        output
+    }
 
-                // push the next symbol
-                match lz77_match {
-                    LZ77NodeMatch::NextMatch(character) => decoded_string.push(*character),
-                    LZ77NodeMatch::EndOfData => break,
+    /// Replace the nodes with the ones serialized in `data` by `to_vec_u8`.
+    ///
+    /// # Arguments
+    /// * data - The serialized nodes
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::lz77::LZ77;
+    /// let mut lz77 = LZ77::new(4, 6);
+    /// lz77.encode("aacaacabcabaaac");
+    /// let serialized: Vec<u8> = lz77.iter().flat_map(|n| n.to_vec_u8()).collect();
+    ///
+    /// let mut other = LZ77::new(4, 6);
+    /// other.load(&serialized).unwrap();
+    /// assert_eq!(other.decode(), "aacaacabcabaaac");
+    /// ```
+    pub fn load(&mut self, data: &[u8]) -> Result<()> {
+        if self.min_match.is_some() {
+            return self.load_lzss(data);
+        }
+        let mut nodes: Vec<LZ77Node> = Vec::new();
+        for chunk in data.chunks(3) {
+            let (offset, length) = match chunk {
+                [offset, length, ..] => (*offset as usize, *length as usize),
+                _ => return Err(invalid_data("truncated LZ77 node")),
+            };
+            let next_match = match chunk.get(2) {
+                Some(character) => LZ77NodeMatch::NextMatch(*character),
+                None => LZ77NodeMatch::EndOfData,
+            };
+            nodes.push(LZ77Node::new(offset, length, next_match));
+        }
+        self.load_nodes(nodes)
+    }
+
+    fn load_lzss(&mut self, data: &[u8]) -> Result<()> {
+        let mut nodes: Vec<LZ77Node> = Vec::with_capacity(data.len());
+        let mut bytes = data.iter();
+        while let Some(flags) = bytes.next() {
+            for bit in 0..8 {
+                let byte = match bytes.next() {
+                    Some(byte) => *byte,
+                    // the unused bits of the last flag byte are literals
+                    None if bit > 0 && flags >> bit == 0 => break,
+                    None => return Err(unexpected_end()),
+                };
+                if flags & (1 << bit) == 0 {
+                    nodes.push(LZ77Node::new(0, 0, LZ77NodeMatch::NextMatch(byte)));
+                    continue;
+                }
+                match bytes.next() {
+                    Some(length) => nodes.push(LZ77Node::new(
+                        byte as usize,
+                        *length as usize,
+                        LZ77NodeMatch::LzssMatch,
+                    )),
+                    None => return Err(unexpected_end()),
                 }
             }
         }
+        self.load_nodes(nodes)
+    }
 
-        match String::from_utf8(decoded_string) {
-            Ok(string) => string,
-            Err(reason) => panic!("{}", reason),
+    /// Replace the nodes with `nodes`, checking that they can be decoded.
+    ///
+    /// # Arguments
+    /// * nodes - The nodes, like the ones of another `LZ77` with the same preset dictionary
+    pub fn load_nodes<I: IntoIterator<Item = LZ77Node>>(&mut self, nodes: I) -> Result<()> {
+        let nodes: Vec<LZ77Node> = nodes.into_iter().collect();
+        let mut decoded_length: usize = self.preset.len();
+        for (index, node) in nodes.iter().enumerate() {
+            // matches start in the already decoded data, and may overlap the bytes they produce
+            if node.length > 0 && (node.offset == 0 || node.offset > decoded_length) {
+                return Err(invalid_data("LZ77 node refers outside of the decoded data"));
+            }
+            decoded_length += node.length;
+            match (&node.next_match, self.min_match) {
+                (LZ77NodeMatch::NextMatch(_), Some(_)) if node.length > 0 => {
+                    return Err(invalid_data("LZSS match followed by a char"))
+                }
+                (LZ77NodeMatch::LzssMatch, Some(min_match))
+                    if node.length < min_match || node.length > self.window_size =>
+                {
+                    return Err(invalid_data("invalid LZSS match length"))
+                }
+                (LZ77NodeMatch::LzssMatch, None) | (LZ77NodeMatch::EndOfData, Some(_)) => {
+                    return Err(invalid_data("LZ77 node of the other mode"))
+                }
+                (LZ77NodeMatch::NextMatch(_), _) => decoded_length += 1,
+                (LZ77NodeMatch::LzssMatch, Some(_)) => {}
+                (LZ77NodeMatch::EndOfData, None) if index + 1 < nodes.len() => {
+                    return Err(invalid_data("LZ77 data ends before the last node"))
+                }
+                (LZ77NodeMatch::EndOfData, None) => {}
+            }
         }
+        self.nodes = nodes;
+        self.position = decoded_length - self.preset.len();
+        Ok(())
     }
 
     pub fn iter(&self) -> Iter<'_, LZ77Node> {
         self.nodes.iter()
     }
 }
+
+impl LZ77 {
+    /// Empty `LZ77` with the same sizes and mode.
+    fn empty(&self) -> LZ77 {
+        let lz77 = LZ77::new(self.window_size, self.dictionary_size);
+        match self.min_match {
+            Some(min_match) => lz77.lzss(min_match),
+            None => lz77,
+        }
+    }
+}
+
+/// Stage emitting the serialized nodes, parameterized by the window and
+/// dictionary sizes, followed by the minimum match in LZSS mode.
+///
+/// # Examples
+/// ```
+/// # use reducto::huffman::Huffman;
+/// # use reducto::lz77::LZ77;
+/// # use reducto::pipeline::Pipeline;
+/// let data = b"abababababababababab, cdcdcdcdcdcdcdcdcdcd";
+/// let triples = Pipeline::new().stage(LZ77::new(32, 255)).compress(data);
+/// let lzss = Pipeline::new().stage(LZ77::new(32, 255).lzss(2)).compress(data);
+/// assert!(lzss.len() < triples.len());
+/// assert_eq!(Pipeline::decompress(&lzss).unwrap(), data);
+/// ```
+impl Stage for LZ77 {
+    fn kind(&self) -> StageKind {
+        StageKind::LZ77
+    }
+
+    fn parameters(&self) -> Vec<u8> {
+        let mut parameters = vec![self.window_size as u8, self.dictionary_size as u8];
+        parameters.extend(self.min_match.map(|min_match| min_match as u8));
+        parameters
+    }
+
+    fn from_parameters(parameters: &[u8]) -> Result<Self> {
+        match *parameters {
+            [window_size, dictionary_size, ..] if window_size == 0 || dictionary_size == 0 => {
+                Err(invalid_data("invalid LZ77 parameters"))
+            }
+            [window_size, dictionary_size] => {
+                Ok(LZ77::new(window_size as usize, dictionary_size as usize))
+            }
+            [window_size, dictionary_size, min_match]
+                if min_match >= 2 && min_match <= window_size =>
+            {
+                Ok(LZ77::new(window_size as usize, dictionary_size as usize)
+                    .lzss(min_match as usize))
+            }
+            _ => Err(invalid_data("invalid LZ77 parameters")),
+        }
+    }
+
+    fn forward(&self, data: &[u8]) -> Vec<u8> {
+        let mut lz77 = self.empty();
+        lz77.encode_bytes(data);
+        lz77.to_vec_u8()
+    }
+
+    fn inverse(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut lz77 = self.empty();
+        lz77.load(data)?;
+        Ok(lz77.decode_bytes())
+    }
+}
@@ -0,0 +1,287 @@
+//! LZ78 dictionary coder
+//!
+//! Each node is the index of the longest phrase of the dictionary matching
+//! the input, followed by the byte extending it into a new phrase. Indexes are
+//! written with a width growing from 9 to 16 bits with the dictionary, and
+//! both sides reset their dictionary once it is full.
+use std::collections::HashMap;
+use std::io::Result;
+
+use crate::bits::{invalid_data, read_varint, write_varint, BitRead, MsbBitReader, MsbBitWriter};
+use crate::pipeline::{Stage, StageKind};
+
+/// Narrowest code written by the dictionary coders.
+pub const MIN_WIDTH: u8 = 9;
+/// Widest code written by the dictionary coders.
+pub const MAX_WIDTH: u8 = 16;
+
+/// Dictionary of phrases, stored as a trie whose edges are `(parent, byte)` pairs.
+#[derive(Debug, Default)]
+pub(crate) struct Trie {
+    edges: HashMap<(u32, u8), u32>,
+}
+
+impl Trie {
+    pub(crate) fn new() -> Self {
+        Default::default()
+    }
+
+    /// Node reached from `node` through `byte`.
+    pub(crate) fn child(&self, node: u32, byte: u8) -> Option<u32> {
+        self.edges.get(&(node, byte)).copied()
+    }
+
+    pub(crate) fn insert(&mut self, node: u32, byte: u8, child: u32) {
+        self.edges.insert((node, byte), child);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.edges.clear();
+    }
+}
+
+/// Phrases known by a decoder, each one being a parent phrase and a last byte.
+#[derive(Debug)]
+pub(crate) struct Phrases {
+    parents: Vec<u32>,
+    bytes: Vec<u8>,
+    lengths: Vec<u32>,
+}
+
+impl Phrases {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Phrases {
+            parents: Vec::with_capacity(capacity),
+            bytes: Vec::with_capacity(capacity),
+            lengths: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.parents.len()
+    }
+
+    pub(crate) fn truncate(&mut self, length: usize) {
+        self.parents.truncate(length);
+        self.bytes.truncate(length);
+        self.lengths.truncate(length);
+    }
+
+    /// Add a phrase; a `length` of 1 makes it a single byte, whatever its parent.
+    pub(crate) fn push(&mut self, parent: u32, byte: u8, length: u32) {
+        self.parents.push(parent);
+        self.bytes.push(byte);
+        self.lengths.push(length);
+    }
+
+    pub(crate) fn length(&self, phrase: u32) -> u32 {
+        self.lengths[phrase as usize]
+    }
+
+    /// Append the bytes of `phrase` to `output` and return its first byte, if any.
+    pub(crate) fn write(&self, phrase: u32, output: &mut Vec<u8>) -> Option<u8> {
+        let length = self.lengths[phrase as usize] as usize;
+        let start = output.len();
+        output.resize(start + length, 0);
+        let mut phrase = phrase as usize;
+        for byte in output[start..].iter_mut().rev() {
+            *byte = self.bytes[phrase];
+            phrase = self.parents[phrase] as usize;
+        }
+        output.get(start).copied()
+    }
+}
+
+/// Width of the codes needed to write every value below `count`.
+fn width_for(count: u32) -> u8 {
+    let bits = 32 - count.saturating_sub(1).leading_zeros();
+    (bits as u8).max(MIN_WIDTH)
+}
+
+/// Output node of the `LZ78` coder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LZ78Node {
+    index: u32,
+    next_byte: Option<u8>,
+}
+
+impl LZ78Node {
+    /// Index of the matched phrase, `0` being the empty phrase.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Byte following the phrase, missing when the data ends with it.
+    pub fn next_byte(&self) -> Option<u8> {
+        self.next_byte
+    }
+}
+
+/// LZ78 coder.
+///
+/// # Examples
+/// ```
+/// # use reducto::lz78::LZ78;
+/// # use reducto::pipeline::Stage;
+/// let lz78 = LZ78::new(16);
+/// let nodes = lz78.encode(b"abababa");
+/// assert_eq!(nodes.len(), 4);
+/// assert_eq!(lz78.decode(&nodes).unwrap(), b"abababa");
+///
+/// let compressed = lz78.forward(b"abababa");
+/// assert_eq!(lz78.inverse(&compressed).unwrap(), b"abababa");
+/// ```
+#[derive(Debug)]
+pub struct LZ78 {
+    max_width: u8,
+}
+
+impl LZ78 {
+    /// Create a new `LZ78` whose dictionary holds `1 << max_width` phrases.
+    ///
+    /// # Arguments
+    /// * max_width - Widest index, from 9 to 16 bits
+    pub fn new(max_width: u8) -> LZ78 {
+        assert!((MIN_WIDTH..=MAX_WIDTH).contains(&max_width));
+        LZ78 { max_width }
+    }
+
+    fn capacity(&self) -> u32 {
+        1 << self.max_width
+    }
+
+    /// Split `data` in nodes.
+    pub fn encode(&self, data: &[u8]) -> Vec<LZ78Node> {
+        let mut nodes: Vec<LZ78Node> = Vec::new();
+        let mut trie = Trie::new();
+        let mut next_index: u32 = 1;
+        let mut node: u32 = 0;
+        for byte in data {
+            if let Some(child) = trie.child(node, *byte) {
+                node = child;
+                continue;
+            }
+            nodes.push(LZ78Node {
+                index: node,
+                next_byte: Some(*byte),
+            });
+            trie.insert(node, *byte, next_index);
+            next_index += 1;
+            if next_index == self.capacity() {
+                trie.clear();
+                next_index = 1;
+            }
+            node = 0;
+        }
+        if node != 0 {
+            nodes.push(LZ78Node {
+                index: node,
+                next_byte: None,
+            });
+        }
+        nodes
+    }
+
+    /// Rebuild the data from its nodes.
+    pub fn decode(&self, nodes: &[LZ78Node]) -> Result<Vec<u8>> {
+        let mut output: Vec<u8> = Vec::new();
+        let mut phrases = self.empty_phrases();
+        for node in nodes {
+            self.decode_node(&mut phrases, node.index, node.next_byte, &mut output)?;
+        }
+        Ok(output)
+    }
+
+    fn empty_phrases(&self) -> Phrases {
+        let mut phrases = Phrases::with_capacity(self.capacity() as usize);
+        phrases.push(0, 0, 0);
+        phrases
+    }
+
+    fn decode_node(
+        &self,
+        phrases: &mut Phrases,
+        index: u32,
+        next_byte: Option<u8>,
+        output: &mut Vec<u8>,
+    ) -> Result<()> {
+        if index as usize >= phrases.len() {
+            return Err(invalid_data("LZ78 node refers to an unknown phrase"));
+        }
+        phrases.write(index, output);
+        if let Some(byte) = next_byte {
+            output.push(byte);
+            phrases.push(index, byte, phrases.length(index) + 1);
+            if phrases.len() == self.capacity() as usize {
+                phrases.truncate(1);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for LZ78 {
+    fn default() -> Self {
+        LZ78::new(MAX_WIDTH)
+    }
+}
+
+/// Stage writing the decoded length (varint) then every index and byte, most significant bit first.
+impl Stage for LZ78 {
+    fn kind(&self) -> StageKind {
+        StageKind::LZ78
+    }
+
+    fn parameters(&self) -> Vec<u8> {
+        vec![self.max_width]
+    }
+
+    fn from_parameters(parameters: &[u8]) -> Result<Self> {
+        match parameters {
+            [max_width] if (MIN_WIDTH..=MAX_WIDTH).contains(max_width) => Ok(LZ78::new(*max_width)),
+            _ => Err(invalid_data("invalid LZ78 parameters")),
+        }
+    }
+
+    fn forward(&self, data: &[u8]) -> Vec<u8> {
+        let mut output: Vec<u8> = Vec::new();
+        write_varint(&mut output, data.len() as u64);
+        let mut writer = MsbBitWriter::new();
+        let mut phrase_count: u32 = 1;
+        for node in self.encode(data) {
+            writer.write_bits(node.index, width_for(phrase_count));
+            if let Some(byte) = node.next_byte {
+                writer.write_bits(u32::from(byte), 8);
+                phrase_count += 1;
+                if phrase_count == self.capacity() {
+                    phrase_count = 1;
+                }
+            }
+        }
+        output.extend(writer.finish());
+        output
+    }
+
+    fn inverse(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut data = data;
+        let length = read_varint(&mut data)? as usize;
+        let mut reader = MsbBitReader::new(data);
+        let mut output: Vec<u8> = Vec::new();
+        let mut phrases = self.empty_phrases();
+        while output.len() < length {
+            let index = reader.read_bits(width_for(phrases.len() as u32))?;
+            if index as usize >= phrases.len() {
+                return Err(invalid_data("LZ78 node refers to an unknown phrase"));
+            }
+            let next_byte = match output.len() + (phrases.length(index) as usize) < length {
+                true => Some(reader.read_bits(8)? as u8),
+                false => None,
+            };
+            self.decode_node(&mut phrases, index, next_byte, &mut output)?;
+        }
+        if output.len() != length {
+            return Err(invalid_data("LZ78 data longer than announced"));
+        }
+        Ok(output)
+    }
+}
@@ -0,0 +1,1807 @@
+//! LZMA compressor and decompressor
+//!
+//! LZMA codes every decision of an LZ77 parser with a binary range coder whose
+//! probabilities depend on a twelve states machine, remembering whether the
+//! last symbols were literals, matches or repeated matches. The four last
+//! distances can be reused by repeated matches.
+//!
+//! The fast presets parse the data greedily over hash chains. The others
+//! search binary trees for a match of every length and pick the decisions
+//! costing the fewest bits with the current probabilities, several positions
+//! ahead, as xz does.
+//!
+//! Two containers are supported: the legacy `.lzma` header followed by a
+//! single LZMA stream, and `.xz` streams whose blocks hold LZMA2 chunks,
+//! followed by an index of the blocks and checked by CRC-64. Streams checked
+//! by CRC-32 or SHA-256 can be decompressed too.
+use std::cmp::min;
+use std::collections::VecDeque;
+use std::io::Result;
+
+use crate::bits::{invalid_data, read_le, read_varint, take, unexpected_end, write_varint};
+use crate::checksum::{crc32, crc64, sha256};
+use crate::lz77::MatchFinder;
+use crate::options::CompressionOptions;
+
+const PROBABILITY_INIT: u16 = 1 << 10;
+const PROBABILITY_BITS: u32 = 11;
+const MOVE_BITS: u32 = 5;
+const RANGE_TOP: u32 = 1 << 24;
+
+const STATES: usize = 12;
+/// States below this one follow a literal.
+const LITERAL_STATES: usize = 7;
+const MAX_POSITION_STATES: usize = 16;
+const MIN_MATCH: usize = 2;
+const MAX_MATCH: usize = 273;
+const LENGTH_STATES: usize = 4;
+const START_POSITION_MODEL: u32 = 4;
+const END_POSITION_MODEL: u32 = 14;
+const FULL_DISTANCES: usize = 128;
+const ALIGN_BITS: u8 = 4;
+/// Distance written as the end of stream marker.
+const END_MARKER: u32 = u32::MAX;
+
+/// Largest uncompressed and compressed sizes of an LZMA2 chunk.
+const LZMA2_MAX_UNCOMPRESSED: usize = 1 << 21;
+const LZMA2_MAX_COMPRESSED: usize = 1 << 16;
+const LZMA2_FILTER_ID: u64 = 0x21;
+
+const XZ_HEADER_MAGIC: &[u8; 6] = b"\xfd7zXZ\x00";
+const XZ_FOOTER_MAGIC: &[u8; 2] = b"YZ";
+const CHECK_NONE: u8 = 0;
+const CHECK_CRC32: u8 = 1;
+const CHECK_CRC64: u8 = 4;
+const CHECK_SHA256: u8 = 0x0a;
+
+const DICTIONARY_SIZES: [u32; 10] = [
+    1 << 18,
+    1 << 20,
+    1 << 21,
+    1 << 22,
+    1 << 22,
+    1 << 23,
+    1 << 23,
+    1 << 24,
+    1 << 25,
+    1 << 26,
+];
+const NICE_LENGTHS: [usize; 10] = [128, 128, 273, 273, 16, 32, 64, 64, 64, 64];
+const DEPTHS: [usize; 10] = [4, 8, 24, 48, 24, 32, 48, 48, 48, 48];
+/// Presets from this one find the decisions by their prices rather than greedily.
+const OPTIMAL_PRESET: usize = 4;
+
+fn read_be(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0, |value, byte| value << 8 | *byte as usize)
+}
+
+/// Literal context bits, literal position bits and position bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Properties {
+    lc: u8,
+    lp: u8,
+    pb: u8,
+}
+
+impl Properties {
+    fn from_byte(byte: u8) -> Result<Properties> {
+        if byte >= 9 * 5 * 5 {
+            return Err(invalid_data("invalid LZMA properties"));
+        }
+        Ok(Properties {
+            lc: byte % 9,
+            lp: byte / 9 % 5,
+            pb: byte / 45,
+        })
+    }
+
+    fn to_byte(self) -> u8 {
+        (self.pb * 5 + self.lp) * 9 + self.lc
+    }
+}
+
+impl Default for Properties {
+    fn default() -> Self {
+        Properties {
+            lc: 3,
+            lp: 0,
+            pb: 2,
+        }
+    }
+}
+
+/// Encoder half of the binary range coder.
+#[derive(Debug)]
+struct RangeEncoder {
+    low: u64,
+    range: u32,
+    cache: u8,
+    cache_size: u64,
+    output: Vec<u8>,
+}
+
+impl RangeEncoder {
+    fn new() -> RangeEncoder {
+        RangeEncoder {
+            low: 0,
+            range: u32::MAX,
+            cache: 0,
+            cache_size: 1,
+            output: Vec::new(),
+        }
+    }
+
+    /// Number of bytes written once flushed.
+    fn pending_size(&self) -> usize {
+        self.output.len() + self.cache_size as usize + 4
+    }
+
+    fn shift_low(&mut self) {
+        if (self.low as u32) < 0xff00_0000 || self.low >> 32 != 0 {
+            let carry = (self.low >> 32) as u8;
+            let mut byte = self.cache;
+            loop {
+                self.output.push(byte.wrapping_add(carry));
+                byte = 0xff;
+                self.cache_size -= 1;
+                if self.cache_size == 0 {
+                    break;
+                }
+            }
+            self.cache = (self.low >> 24) as u8;
+        }
+        self.cache_size += 1;
+        self.low = (self.low & 0x00ff_ffff) << 8;
+    }
+
+    fn encode_bit(&mut self, probability: &mut u16, bit: u32) {
+        let bound = (self.range >> PROBABILITY_BITS) * u32::from(*probability);
+        if bit == 0 {
+            self.range = bound;
+            *probability += ((1 << PROBABILITY_BITS) - *probability) >> MOVE_BITS;
+        } else {
+            self.low += u64::from(bound);
+            self.range -= bound;
+            *probability -= *probability >> MOVE_BITS;
+        }
+        while self.range < RANGE_TOP {
+            self.range <<= 8;
+            self.shift_low();
+        }
+    }
+
+    /// Write the `count` low bits of `value` with a probability of one half.
+    fn encode_direct(&mut self, value: u32, count: u8) {
+        for shift in (0..count).rev() {
+            self.range >>= 1;
+            if (value >> shift) & 1 != 0 {
+                self.low += u64::from(self.range);
+            }
+            while self.range < RANGE_TOP {
+                self.range <<= 8;
+                self.shift_low();
+            }
+        }
+    }
+
+    /// Write `count` bits of `value`, most significant first, through a tree of probabilities.
+    fn encode_tree(&mut self, probabilities: &mut [u16], count: u8, value: u32) {
+        let mut node: usize = 1;
+        for shift in (0..count).rev() {
+            let bit = (value >> shift) & 1;
+            self.encode_bit(&mut probabilities[node], bit);
+            node = node << 1 | bit as usize;
+        }
+    }
+
+    /// Write `count` bits of `value`, least significant first, through a tree of probabilities.
+    fn encode_reverse_tree(&mut self, probabilities: &mut [u16], count: u8, value: u32) {
+        let mut node: usize = 1;
+        for shift in 0..count {
+            let bit = (value >> shift) & 1;
+            self.encode_bit(&mut probabilities[node], bit);
+            node = node << 1 | bit as usize;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        for _ in 0..5 {
+            self.shift_low();
+        }
+        self.output
+    }
+}
+
+/// Decoder half of the binary range coder.
+///
+/// Reading past the end yields zeros and marks the decoder as overrun.
+#[derive(Debug)]
+struct RangeDecoder<'a> {
+    data: &'a [u8],
+    position: usize,
+    range: u32,
+    code: u32,
+    overrun: bool,
+}
+
+impl<'a> RangeDecoder<'a> {
+    fn new(data: &'a [u8]) -> Result<RangeDecoder<'a>> {
+        if data.len() < 5 {
+            return Err(unexpected_end());
+        }
+        if data[0] != 0 {
+            return Err(invalid_data("invalid LZMA range coder initialization"));
+        }
+        Ok(RangeDecoder {
+            data,
+            position: 5,
+            range: u32::MAX,
+            code: read_be(&data[1..5]) as u32,
+            overrun: false,
+        })
+    }
+
+    fn next_byte(&mut self) -> u32 {
+        match self.data.get(self.position) {
+            Some(byte) => {
+                self.position += 1;
+                u32::from(*byte)
+            }
+            None => {
+                self.overrun = true;
+                0
+            }
+        }
+    }
+
+    fn normalize(&mut self) {
+        if self.range < RANGE_TOP {
+            self.range <<= 8;
+            self.code = self.code << 8 | self.next_byte();
+        }
+    }
+
+    /// Whether the stream has been read entirely and ends cleanly.
+    fn is_finished(&self) -> bool {
+        self.code == 0 && !self.overrun && self.position == self.data.len()
+    }
+
+    fn decode_bit(&mut self, probability: &mut u16) -> u32 {
+        let bound = (self.range >> PROBABILITY_BITS) * u32::from(*probability);
+        let bit = if self.code < bound {
+            self.range = bound;
+            *probability += ((1 << PROBABILITY_BITS) - *probability) >> MOVE_BITS;
+            0
+        } else {
+            self.code -= bound;
+            self.range -= bound;
+            *probability -= *probability >> MOVE_BITS;
+            1
+        };
+        self.normalize();
+        bit
+    }
+
+    fn decode_direct(&mut self, count: u8) -> u32 {
+        let mut value: u32 = 0;
+        for _ in 0..count {
+            self.range >>= 1;
+            let bit = (self.code >= self.range) as u32;
+            if bit == 1 {
+                self.code -= self.range;
+            }
+            value = value << 1 | bit;
+            self.normalize();
+        }
+        value
+    }
+
+    fn decode_tree(&mut self, probabilities: &mut [u16], count: u8) -> u32 {
+        let mut node: usize = 1;
+        for _ in 0..count {
+            node = node << 1 | self.decode_bit(&mut probabilities[node]) as usize;
+        }
+        (node - (1 << count)) as u32
+    }
+
+    fn decode_reverse_tree(&mut self, probabilities: &mut [u16], count: u8) -> u32 {
+        let mut node: usize = 1;
+        let mut value: u32 = 0;
+        for shift in 0..count {
+            let bit = self.decode_bit(&mut probabilities[node]);
+            node = node << 1 | bit as usize;
+            value |= bit << shift;
+        }
+        value
+    }
+}
+
+/// Probabilities of a match length.
+#[derive(Debug, Clone)]
+struct LengthModel {
+    choice: u16,
+    choice2: u16,
+    low: [[u16; 8]; MAX_POSITION_STATES],
+    mid: [[u16; 8]; MAX_POSITION_STATES],
+    high: [u16; 256],
+}
+
+impl LengthModel {
+    fn new() -> LengthModel {
+        LengthModel {
+            choice: PROBABILITY_INIT,
+            choice2: PROBABILITY_INIT,
+            low: [[PROBABILITY_INIT; 8]; MAX_POSITION_STATES],
+            mid: [[PROBABILITY_INIT; 8]; MAX_POSITION_STATES],
+            high: [PROBABILITY_INIT; 256],
+        }
+    }
+
+    fn encode(&mut self, encoder: &mut RangeEncoder, length: usize, position_state: usize) {
+        let length = (length - MIN_MATCH) as u32;
+        if length < 8 {
+            encoder.encode_bit(&mut self.choice, 0);
+            encoder.encode_tree(&mut self.low[position_state], 3, length);
+        } else if length < 16 {
+            encoder.encode_bit(&mut self.choice, 1);
+            encoder.encode_bit(&mut self.choice2, 0);
+            encoder.encode_tree(&mut self.mid[position_state], 3, length - 8);
+        } else {
+            encoder.encode_bit(&mut self.choice, 1);
+            encoder.encode_bit(&mut self.choice2, 1);
+            encoder.encode_tree(&mut self.high, 8, length - 16);
+        }
+    }
+
+    fn decode(&mut self, decoder: &mut RangeDecoder, position_state: usize) -> usize {
+        let length = if decoder.decode_bit(&mut self.choice) == 0 {
+            decoder.decode_tree(&mut self.low[position_state], 3)
+        } else if decoder.decode_bit(&mut self.choice2) == 0 {
+            8 + decoder.decode_tree(&mut self.mid[position_state], 3)
+        } else {
+            16 + decoder.decode_tree(&mut self.high, 8)
+        };
+        length as usize + MIN_MATCH
+    }
+}
+
+/// Every probability of the LZMA model, with the state and the repeated distances.
+#[derive(Debug, Clone)]
+struct Model {
+    properties: Properties,
+    literals: Vec<u16>,
+    is_match: [[u16; MAX_POSITION_STATES]; STATES],
+    is_rep: [u16; STATES],
+    is_rep0: [u16; STATES],
+    is_rep1: [u16; STATES],
+    is_rep2: [u16; STATES],
+    is_rep0_long: [[u16; MAX_POSITION_STATES]; STATES],
+    position_slots: [[u16; 64]; LENGTH_STATES],
+    position_special: [u16; 1 + FULL_DISTANCES - END_POSITION_MODEL as usize],
+    align: [u16; 1 << ALIGN_BITS],
+    lengths: LengthModel,
+    rep_lengths: LengthModel,
+    state: usize,
+    /// Last four distances, minus one.
+    reps: [u32; 4],
+}
+
+impl Model {
+    fn new(properties: Properties) -> Model {
+        Model {
+            properties,
+            literals: vec![PROBABILITY_INIT; 0x300 << (properties.lc + properties.lp)],
+            is_match: [[PROBABILITY_INIT; MAX_POSITION_STATES]; STATES],
+            is_rep: [PROBABILITY_INIT; STATES],
+            is_rep0: [PROBABILITY_INIT; STATES],
+            is_rep1: [PROBABILITY_INIT; STATES],
+            is_rep2: [PROBABILITY_INIT; STATES],
+            is_rep0_long: [[PROBABILITY_INIT; MAX_POSITION_STATES]; STATES],
+            position_slots: [[PROBABILITY_INIT; 64]; LENGTH_STATES],
+            position_special: [PROBABILITY_INIT; 1 + FULL_DISTANCES - END_POSITION_MODEL as usize],
+            align: [PROBABILITY_INIT; 1 << ALIGN_BITS],
+            lengths: LengthModel::new(),
+            rep_lengths: LengthModel::new(),
+            state: 0,
+            reps: [0; 4],
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Model::new(self.properties);
+    }
+
+    fn position_state(&self, position: usize) -> usize {
+        position & ((1 << self.properties.pb) - 1)
+    }
+
+    /// Offset of the probabilities of the literal at `position`, following `previous`.
+    fn literal_offset(&self, position: usize, previous: u8) -> usize {
+        let Properties { lc, lp, .. } = self.properties;
+        let literal_state = ((position & ((1 << lp) - 1)) << lc) + (previous as usize >> (8 - lc));
+        0x300 * literal_state
+    }
+
+    fn update_literal(&mut self) {
+        self.state = state_after_literal(self.state);
+    }
+
+    fn update_match(&mut self) {
+        self.state = state_after_match(self.state);
+    }
+
+    fn update_rep(&mut self) {
+        self.state = state_after_rep(self.state);
+    }
+
+    fn update_short_rep(&mut self) {
+        self.state = state_after_short_rep(self.state);
+    }
+
+    /// Move the repeated distance `index` in front of the others.
+    fn promote_rep(&mut self, index: usize) {
+        let distance = self.reps[index];
+        self.reps.copy_within(0..index, 1);
+        self.reps[0] = distance;
+    }
+}
+
+fn state_after_literal(state: usize) -> usize {
+    match state {
+        0..=3 => 0,
+        4..=9 => state - 3,
+        _ => state - 6,
+    }
+}
+
+fn state_after_match(state: usize) -> usize {
+    if state < LITERAL_STATES {
+        7
+    } else {
+        10
+    }
+}
+
+fn state_after_rep(state: usize) -> usize {
+    if state < LITERAL_STATES {
+        8
+    } else {
+        11
+    }
+}
+
+fn state_after_short_rep(state: usize) -> usize {
+    if state < LITERAL_STATES {
+        9
+    } else {
+        11
+    }
+}
+
+fn position_slot(distance: u32) -> u32 {
+    if distance < START_POSITION_MODEL {
+        return distance;
+    }
+    let bits = 31 - distance.leading_zeros();
+    bits << 1 | (distance >> (bits - 1)) & 1
+}
+
+/// Price of a bit, in sixteenths of a bit, by probability of a zero divided by 16.
+const BIT_PRICES: [u32; (1 << PROBABILITY_BITS) >> 4] = bit_prices();
+/// Number of fractional bits of a price.
+const PRICE_SHIFT: u32 = 4;
+const INFINITE_PRICE: u32 = 1 << 30;
+
+/// `-log2(probability)` of every probability, computed as the 7-zip encoder does with integers.
+const fn bit_prices() -> [u32; (1 << PROBABILITY_BITS) >> 4] {
+    let mut prices = [0u32; (1 << PROBABILITY_BITS) >> 4];
+    let mut index = 0;
+    while index < prices.len() {
+        let mut value = (index as u32) << 4 | 8;
+        let mut bit_count: u32 = 0;
+        let mut cycle = 0;
+        while cycle < PRICE_SHIFT {
+            value *= value;
+            bit_count <<= 1;
+            while value >= 1 << 16 {
+                value >>= 1;
+                bit_count += 1;
+            }
+            cycle += 1;
+        }
+        prices[index] = (PROBABILITY_BITS << PRICE_SHIFT) - 15 - bit_count;
+        index += 1;
+    }
+    prices
+}
+
+fn bit_price(probability: u16, bit: u32) -> u32 {
+    let probability = match bit {
+        0 => u32::from(probability),
+        _ => (1 << PROBABILITY_BITS) - u32::from(probability),
+    };
+    BIT_PRICES[(probability >> 4) as usize]
+}
+
+fn tree_price(probabilities: &[u16], count: u8, value: u32) -> u32 {
+    let mut price: u32 = 0;
+    let mut node: usize = 1;
+    for shift in (0..count).rev() {
+        let bit = (value >> shift) & 1;
+        price += bit_price(probabilities[node], bit);
+        node = node << 1 | bit as usize;
+    }
+    price
+}
+
+fn reverse_tree_price(probabilities: &[u16], count: u8, value: u32) -> u32 {
+    let mut price: u32 = 0;
+    let mut node: usize = 1;
+    for shift in 0..count {
+        let bit = (value >> shift) & 1;
+        price += bit_price(probabilities[node], bit);
+        node = node << 1 | bit as usize;
+    }
+    price
+}
+
+/// Number of match lengths, from `MIN_MATCH` to `MAX_MATCH`.
+const LENGTH_SYMBOLS: usize = MAX_MATCH - MIN_MATCH + 1;
+/// Number of symbols coded between two updates of the prices.
+const PRICES_UPDATE: usize = 64;
+
+/// Prices of the lengths and distances, computed from the probabilities from time to time.
+#[derive(Debug, Clone)]
+struct Prices {
+    lengths: Vec<[u32; LENGTH_SYMBOLS]>,
+    rep_lengths: Vec<[u32; LENGTH_SYMBOLS]>,
+    slots: [[u32; 64]; LENGTH_STATES],
+    distances: [[u32; FULL_DISTANCES]; LENGTH_STATES],
+    align: [u32; 1 << ALIGN_BITS],
+    /// Matches coded since the last update.
+    pending: usize,
+}
+
+impl LengthModel {
+    fn prices(&self, position_states: usize) -> Vec<[u32; LENGTH_SYMBOLS]> {
+        let mut prices = vec![[0u32; LENGTH_SYMBOLS]; position_states];
+        for (position_state, prices) in prices.iter_mut().enumerate() {
+            for (length, price) in prices.iter_mut().enumerate() {
+                let length = length as u32;
+                *price = if length < 8 {
+                    bit_price(self.choice, 0) + tree_price(&self.low[position_state], 3, length)
+                } else if length < 16 {
+                    bit_price(self.choice, 1)
+                        + bit_price(self.choice2, 0)
+                        + tree_price(&self.mid[position_state], 3, length - 8)
+                } else {
+                    bit_price(self.choice, 1)
+                        + bit_price(self.choice2, 1)
+                        + tree_price(&self.high, 8, length - 16)
+                };
+            }
+        }
+        prices
+    }
+}
+
+impl Prices {
+    fn new(model: &Model) -> Prices {
+        let position_states = 1 << model.properties.pb;
+        let mut prices = Prices {
+            lengths: model.lengths.prices(position_states),
+            rep_lengths: model.rep_lengths.prices(position_states),
+            slots: [[0; 64]; LENGTH_STATES],
+            distances: [[0; FULL_DISTANCES]; LENGTH_STATES],
+            align: [0; 1 << ALIGN_BITS],
+            pending: 0,
+        };
+        for (length_state, slots) in prices.slots.iter_mut().enumerate() {
+            for (slot, price) in slots.iter_mut().enumerate() {
+                *price = tree_price(&model.position_slots[length_state], 6, slot as u32);
+                if slot as u32 >= END_POSITION_MODEL {
+                    *price += ((slot as u32 >> 1) - 1 - u32::from(ALIGN_BITS)) << PRICE_SHIFT;
+                }
+            }
+            for (distance, price) in prices.distances[length_state].iter_mut().enumerate() {
+                let distance = distance as u32;
+                let slot = position_slot(distance);
+                *price = slots[slot as usize];
+                if slot >= START_POSITION_MODEL {
+                    let footer_bits = ((slot >> 1) - 1) as u8;
+                    let base = (2 | (slot & 1)) << footer_bits;
+                    let probabilities = &model.position_special[(base - slot) as usize..];
+                    *price += reverse_tree_price(probabilities, footer_bits, distance - base);
+                }
+            }
+        }
+        for (value, price) in prices.align.iter_mut().enumerate() {
+            *price = reverse_tree_price(&model.align, ALIGN_BITS, value as u32);
+        }
+        prices
+    }
+
+    /// Price of the distance of a match of `length` bytes.
+    fn distance(&self, distance: usize, length: usize) -> u32 {
+        let length_state = min(length - MIN_MATCH, LENGTH_STATES - 1);
+        let distance = (distance - 1) as u32;
+        match self.distances[length_state].get(distance as usize) {
+            Some(price) => *price,
+            None => {
+                self.slots[length_state][position_slot(distance) as usize]
+                    + self.align[(distance & 15) as usize]
+            }
+        }
+    }
+}
+
+/// Decision of the encoder at one position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Decision {
+    Literal,
+    /// Distance from 1 and length.
+    Match(usize, usize),
+    /// Index of the repeated distance and length.
+    Rep(usize, usize),
+    /// One byte at the last distance.
+    ShortRep,
+}
+
+/// Largest number of bytes covered by the decisions of one optimal parse.
+const OPTIMUM_SIZE: usize = 1 << 12;
+
+/// Way to reach a position in the optimal parse.
+#[derive(Debug, Clone, Copy)]
+struct Arrival {
+    price: u32,
+    /// Position the decision starts from.
+    previous: usize,
+    decision: Decision,
+    /// Distance of the decision, which repeated distances refer to by index.
+    distance: usize,
+    state: usize,
+    reps: [u32; 4],
+}
+
+impl Default for Arrival {
+    fn default() -> Self {
+        Arrival {
+            price: INFINITE_PRICE,
+            previous: 0,
+            decision: Decision::Literal,
+            distance: 0,
+            state: 0,
+            reps: [0; 4],
+        }
+    }
+}
+
+/// Parser choosing the decisions, greedily or by their prices.
+#[derive(Debug)]
+struct Encoder<'a> {
+    data: &'a [u8],
+    model: Model,
+    finder: MatchFinder,
+    dictionary_size: usize,
+    nice_length: usize,
+    optimal: bool,
+    /// Next position to add to `finder`.
+    finder_position: usize,
+    /// Matches found at a position, as `(distance, length)`.
+    matches: Vec<(usize, usize)>,
+    /// Position of `matches`.
+    matches_position: Option<usize>,
+    prices: Prices,
+    arrivals: Vec<Arrival>,
+    /// Decisions of the last optimal parse not coded yet, with their distance.
+    decisions: VecDeque<(Decision, usize)>,
+}
+
+/// Whether `small` is so much smaller than `big` that it is worth a shorter match.
+fn change_pair(small: usize, big: usize) -> bool {
+    small < big >> 7
+}
+
+impl<'a> Encoder<'a> {
+    fn new(data: &'a [u8], lzma: &Lzma) -> Encoder<'a> {
+        let model = Model::new(Properties::default());
+        let dictionary_size = lzma.dictionary_size as usize;
+        // as xz, the optimal parse uses binary trees and the greedy one hash chains
+        let mut finder = MatchFinder::new(data.len(), dictionary_size, lzma.depth).short_matches();
+        if lzma.optimal {
+            finder = finder.binary_tree();
+        }
+        Encoder {
+            data,
+            prices: Prices::new(&model),
+            model,
+            finder,
+            finder_position: 0,
+            dictionary_size,
+            nice_length: lzma.nice_length,
+            optimal: lzma.optimal,
+            matches: Vec::new(),
+            matches_position: None,
+            arrivals: vec![Arrival::default(); OPTIMUM_SIZE + MAX_MATCH + 1],
+            decisions: VecDeque::new(),
+        }
+    }
+
+    /// Find the matches at `position`, the longest one being extended beyond the nice length.
+    fn find_matches(&mut self, position: usize) {
+        if self.matches_position == Some(position) {
+            return;
+        }
+        while self.finder_position < position {
+            self.finder
+                .skip(self.data, self.finder_position, self.nice_length);
+            self.finder_position += 1;
+        }
+        self.finder
+            .find_all(self.data, position, self.nice_length, &mut self.matches);
+        self.finder_position = position + 1;
+        self.matches_position = Some(position);
+        let limit = min(self.data.len() - position, MAX_MATCH);
+        if let Some((distance, length)) = self.matches.last_mut() {
+            while *length < limit
+                && self.data[position + *length] == self.data[position + *length - *distance]
+            {
+                *length += 1;
+            }
+        }
+    }
+
+    /// Longest match at `position`, as its distance and length.
+    fn find_match(&mut self, position: usize) -> Option<(usize, usize)> {
+        self.find_matches(position);
+        match self.matches.last() {
+            // a short and far match costs more than its literals
+            Some((distance, 2)) if *distance > 1 << 7 => None,
+            last => last.copied(),
+        }
+    }
+
+    /// Length of the match at `position` with the distance `distance`.
+    fn match_length(&self, position: usize, distance: usize) -> usize {
+        if distance > position || distance > self.dictionary_size {
+            return 0;
+        }
+        let limit = min(self.data.len() - position, MAX_MATCH);
+        self.data[position..position + limit]
+            .iter()
+            .zip(&self.data[position - distance..])
+            .take_while(|(current, previous)| current == previous)
+            .count()
+    }
+
+    /// Length of the match at `position` with the repeated distance `index`.
+    fn rep_length(&self, position: usize, index: usize) -> usize {
+        self.match_length(position, self.model.reps[index] as usize + 1)
+    }
+
+    /// Longest repeated match at `position`, as its index and length.
+    fn best_rep(&self, position: usize) -> (usize, usize) {
+        (0..4)
+            .map(|index| (index, self.rep_length(position, index)))
+            .fold((0, 0), |best, rep| if rep.1 > best.1 { rep } else { best })
+    }
+
+    /// Choose the decision at `position`.
+    ///
+    /// Decisions found by an optimal parse refer to the repeated distances
+    /// they were found with, so those changed since, by a reset of the model,
+    /// are coded as plain matches.
+    fn decide(&mut self, position: usize) -> Decision {
+        if !self.optimal {
+            return self.decide_greedy(position);
+        }
+        if self.decisions.is_empty() {
+            self.optimum(position);
+        }
+        match self.decisions.pop_front() {
+            Some((Decision::Rep(index, length), distance))
+                if self.model.reps[index] as usize + 1 != distance =>
+            {
+                Decision::Match(distance, length)
+            }
+            Some((Decision::ShortRep, distance)) if self.model.reps[0] as usize + 1 != distance => {
+                Decision::Literal
+            }
+            Some((decision, _)) => decision,
+            None => Decision::Literal,
+        }
+    }
+
+    /// Choose the decision at `position` greedily, delaying a match for a better one at the next position.
+    fn decide_greedy(&mut self, position: usize) -> Decision {
+        if self.data.len() - position < MIN_MATCH {
+            return Decision::Literal;
+        }
+        let (rep_index, rep_length) = self.best_rep(position);
+        if rep_length >= self.nice_length {
+            return Decision::Rep(rep_index, rep_length);
+        }
+        let (distance, length) = match self.find_match(position) {
+            Some(found) => found,
+            None if rep_length >= MIN_MATCH => return Decision::Rep(rep_index, rep_length),
+            None => return Decision::Literal,
+        };
+        if length >= self.nice_length {
+            return Decision::Match(distance, length);
+        }
+        if rep_length >= MIN_MATCH
+            && (rep_length + 1 >= length
+                || (rep_length + 2 >= length && distance > 1 << 9)
+                || (rep_length + 3 >= length && distance > 1 << 15))
+        {
+            return Decision::Rep(rep_index, rep_length);
+        }
+
+        if position + 1 < self.data.len() {
+            if let Some((next_distance, next_length)) = self.find_match(position + 1) {
+                if (next_length >= length && next_distance < distance)
+                    || (next_length == length + 1 && !change_pair(distance, next_distance))
+                    || next_length > length + 1
+                    || (next_length + 1 >= length
+                        && length >= 3
+                        && change_pair(next_distance, distance))
+                {
+                    return Decision::Literal;
+                }
+            }
+            let limit = (length - 1).max(MIN_MATCH);
+            if (0..4).any(|index| self.rep_length(position + 1, index) >= limit) {
+                return Decision::Literal;
+            }
+        }
+        Decision::Match(distance, length)
+    }
+
+    /// Price of coding the repeated distance `index` in `state`.
+    fn rep_price(&self, index: usize, state: usize, position_state: usize) -> u32 {
+        let model = &self.model;
+        match index {
+            0 => {
+                bit_price(model.is_rep0[state], 0)
+                    + bit_price(model.is_rep0_long[state][position_state], 1)
+            }
+            _ => {
+                bit_price(model.is_rep0[state], 1)
+                    + match index {
+                        1 => bit_price(model.is_rep1[state], 0),
+                        _ => {
+                            bit_price(model.is_rep1[state], 1)
+                                + bit_price(model.is_rep2[state], index as u32 - 2)
+                        }
+                    }
+            }
+        }
+    }
+
+    /// Price of the literal at `position` in `state`, following a match at `distance`.
+    fn literal_price(&self, position: usize, state: usize, distance: usize) -> u32 {
+        let model = &self.model;
+        let previous = match position {
+            0 => 0,
+            _ => self.data[position - 1],
+        };
+        let offset = model.literal_offset(position, previous);
+        let probabilities = &model.literals[offset..offset + 0x300];
+        let byte = u32::from(self.data[position]);
+        if state < LITERAL_STATES {
+            return tree_price(probabilities, 8, byte);
+        }
+        let match_byte = u32::from(self.data[position - distance]);
+        let mut price: u32 = 0;
+        let mut matched = true;
+        let mut node: usize = 1;
+        for shift in (0..8).rev() {
+            let bit = (byte >> shift) & 1;
+            let probability = if matched {
+                let match_bit = (match_byte >> shift) & 1;
+                matched = match_bit == bit;
+                probabilities[((1 + match_bit as usize) << 8) + node]
+            } else {
+                probabilities[node]
+            };
+            price += bit_price(probability, bit);
+            node = node << 1 | bit as usize;
+        }
+        price
+    }
+
+    /// Offer to reach `target` from `previous` with `decision`.
+    fn arrive(
+        &mut self,
+        target: usize,
+        price: u32,
+        previous: usize,
+        decision: Decision,
+        distance: usize,
+    ) {
+        let arrival = &mut self.arrivals[target];
+        if price < arrival.price {
+            *arrival = Arrival {
+                price,
+                previous,
+                decision,
+                distance,
+                ..*arrival
+            };
+        }
+    }
+
+    /// Find the cheapest decisions from `position` and queue them.
+    ///
+    /// Every position reachable by the matches found so far is reached by its
+    /// cheapest way, the parse ending at the farthest of them or at a match
+    /// longer than the nice length, which is taken right away.
+    fn optimum(&mut self, position: usize) {
+        if self.prices.pending >= PRICES_UPDATE {
+            self.prices = Prices::new(&self.model);
+        }
+        self.find_matches(position);
+        if self.data.len() - position < MIN_MATCH {
+            self.decisions.push_back((Decision::Literal, 0));
+            return;
+        }
+        let (rep_index, rep_length) = self.best_rep(position);
+        if rep_length >= self.nice_length {
+            let distance = self.model.reps[rep_index] as usize + 1;
+            self.decisions
+                .push_back((Decision::Rep(rep_index, rep_length), distance));
+            return;
+        }
+        if let Some((distance, length)) = self.matches.last().copied() {
+            if length >= self.nice_length {
+                self.decisions
+                    .push_back((Decision::Match(distance, length), distance));
+                return;
+            }
+        }
+
+        self.arrivals[0] = Arrival {
+            price: 0,
+            state: self.model.state,
+            reps: self.model.reps,
+            ..Arrival::default()
+        };
+        let mut end: usize = 0;
+        let mut current: usize = 0;
+        loop {
+            if current > 0 {
+                let Arrival {
+                    previous,
+                    decision,
+                    distance,
+                    ..
+                } = self.arrivals[current];
+                let mut state = self.arrivals[previous].state;
+                let mut reps = self.arrivals[previous].reps;
+                match decision {
+                    Decision::Literal => state = state_after_literal(state),
+                    Decision::Match(..) => {
+                        state = state_after_match(state);
+                        reps = [distance as u32 - 1, reps[0], reps[1], reps[2]];
+                    }
+                    Decision::Rep(index, _) => {
+                        state = state_after_rep(state);
+                        reps.copy_within(0..index, 1);
+                        reps[0] = distance as u32 - 1;
+                    }
+                    Decision::ShortRep => state = state_after_short_rep(state),
+                }
+                self.arrivals[current].state = state;
+                self.arrivals[current].reps = reps;
+                if current == end || current >= OPTIMUM_SIZE {
+                    break;
+                }
+                self.find_matches(position + current);
+                if self
+                    .matches
+                    .last()
+                    .is_some_and(|(_, length)| *length >= self.nice_length)
+                {
+                    break;
+                }
+            }
+            end = self.relax(position, current, end);
+            current += 1;
+        }
+
+        let first = self.decisions.len();
+        while current > 0 {
+            let arrival = &self.arrivals[current];
+            self.decisions
+                .push_back((arrival.decision, arrival.distance));
+            current = arrival.previous;
+        }
+        self.decisions.make_contiguous()[first..].reverse();
+    }
+
+    /// Offer every decision at `position + current` to the positions it reaches,
+    /// returning the farthest position reached so far.
+    fn relax(&mut self, position: usize, current: usize, mut end: usize) -> usize {
+        let Arrival {
+            price, state, reps, ..
+        } = self.arrivals[current];
+        let at = position + current;
+        let position_state = self.model.position_state(at);
+        let available = min(self.data.len() - at, MAX_MATCH);
+        let rep_lengths = reps.map(|rep| self.match_length(at, rep as usize + 1));
+        let longest = self.matches.last().map_or(0, |(_, length)| *length);
+        let farthest = current
+            + longest
+                .max(rep_lengths.iter().copied().max().unwrap_or(0))
+                .max(1);
+        while end < farthest {
+            end += 1;
+            self.arrivals[end].price = INFINITE_PRICE;
+        }
+
+        let match_price = price + bit_price(self.model.is_match[state][position_state], 1);
+        let literal_price = price
+            + bit_price(self.model.is_match[state][position_state], 0)
+            + self.literal_price(at, state, reps[0] as usize + 1);
+        self.arrive(current + 1, literal_price, current, Decision::Literal, 0);
+        let rep_match_price = match_price + bit_price(self.model.is_rep[state], 1);
+        if rep_lengths[0] >= 1 {
+            let short_rep_price = rep_match_price
+                + bit_price(self.model.is_rep0[state], 0)
+                + bit_price(self.model.is_rep0_long[state][position_state], 0);
+            let distance = reps[0] as usize + 1;
+            self.arrive(
+                current + 1,
+                short_rep_price,
+                current,
+                Decision::ShortRep,
+                distance,
+            );
+        }
+        if available < MIN_MATCH {
+            return end;
+        }
+
+        let mut start_length = MIN_MATCH;
+        for (index, rep_length) in rep_lengths.iter().enumerate() {
+            if *rep_length < MIN_MATCH {
+                continue;
+            }
+            let distance = reps[index] as usize + 1;
+            let rep_price = rep_match_price + self.rep_price(index, state, position_state);
+            for length in MIN_MATCH..=*rep_length {
+                let price = rep_price + self.prices.rep_lengths[position_state][length - MIN_MATCH];
+                self.arrive(
+                    current + length,
+                    price,
+                    current,
+                    Decision::Rep(index, length),
+                    distance,
+                );
+            }
+            if index == 0 {
+                start_length = rep_length + 1;
+            }
+        }
+
+        let normal_price = match_price + bit_price(self.model.is_rep[state], 0);
+        let mut length = start_length;
+        for match_index in 0..self.matches.len() {
+            let (distance, match_length) = self.matches[match_index];
+            while length <= match_length {
+                let price = normal_price
+                    + self.prices.lengths[position_state][length - MIN_MATCH]
+                    + self.prices.distance(distance, length);
+                self.arrive(
+                    current + length,
+                    price,
+                    current,
+                    Decision::Match(distance, length),
+                    distance,
+                );
+                length += 1;
+            }
+        }
+        end
+    }
+
+    /// Encode the decision at `position` and return the number of bytes it covers.
+    fn encode(&mut self, encoder: &mut RangeEncoder, position: usize, decision: Decision) -> usize {
+        let model = &mut self.model;
+        let position_state = model.position_state(position);
+        let state = model.state;
+        match decision {
+            Decision::Literal => {
+                encoder.encode_bit(&mut model.is_match[state][position_state], 0);
+                let previous = match position {
+                    0 => 0,
+                    _ => self.data[position - 1],
+                };
+                let offset = model.literal_offset(position, previous);
+                let probabilities = &mut model.literals[offset..offset + 0x300];
+                let byte = u32::from(self.data[position]);
+                if state < LITERAL_STATES {
+                    encoder.encode_tree(probabilities, 8, byte);
+                } else {
+                    let match_byte = u32::from(self.data[position - model.reps[0] as usize - 1]);
+                    let mut matched = true;
+                    let mut node: usize = 1;
+                    for shift in (0..8).rev() {
+                        let bit = (byte >> shift) & 1;
+                        let probability = if matched {
+                            let match_bit = (match_byte >> shift) & 1;
+                            matched = match_bit == bit;
+                            &mut probabilities[((1 + match_bit as usize) << 8) + node]
+                        } else {
+                            &mut probabilities[node]
+                        };
+                        encoder.encode_bit(probability, bit);
+                        node = node << 1 | bit as usize;
+                    }
+                }
+                model.update_literal();
+                1
+            }
+            Decision::Match(distance, length) => {
+                self.prices.pending += 1;
+                encoder.encode_bit(&mut model.is_match[state][position_state], 1);
+                encoder.encode_bit(&mut model.is_rep[state], 0);
+                model.lengths.encode(encoder, length, position_state);
+                let distance = (distance - 1) as u32;
+                let slot = position_slot(distance);
+                let length_state = min(length - MIN_MATCH, LENGTH_STATES - 1);
+                encoder.encode_tree(&mut model.position_slots[length_state], 6, slot);
+                if slot >= START_POSITION_MODEL {
+                    let footer_bits = ((slot >> 1) - 1) as u8;
+                    let base = (2 | (slot & 1)) << footer_bits;
+                    let reduced = distance - base;
+                    if slot < END_POSITION_MODEL {
+                        let probabilities = &mut model.position_special[(base - slot) as usize..];
+                        encoder.encode_reverse_tree(probabilities, footer_bits, reduced);
+                    } else {
+                        encoder.encode_direct(reduced >> ALIGN_BITS, footer_bits - ALIGN_BITS);
+                        encoder.encode_reverse_tree(&mut model.align, ALIGN_BITS, reduced & 15);
+                    }
+                }
+                model.reps = [distance, model.reps[0], model.reps[1], model.reps[2]];
+                model.update_match();
+                length
+            }
+            Decision::ShortRep => {
+                encoder.encode_bit(&mut model.is_match[state][position_state], 1);
+                encoder.encode_bit(&mut model.is_rep[state], 1);
+                encoder.encode_bit(&mut model.is_rep0[state], 0);
+                encoder.encode_bit(&mut model.is_rep0_long[state][position_state], 0);
+                model.update_short_rep();
+                1
+            }
+            Decision::Rep(index, length) => {
+                self.prices.pending += 1;
+                encoder.encode_bit(&mut model.is_match[state][position_state], 1);
+                encoder.encode_bit(&mut model.is_rep[state], 1);
+                if index == 0 {
+                    encoder.encode_bit(&mut model.is_rep0[state], 0);
+                    encoder.encode_bit(&mut model.is_rep0_long[state][position_state], 1);
+                } else {
+                    encoder.encode_bit(&mut model.is_rep0[state], 1);
+                    if index == 1 {
+                        encoder.encode_bit(&mut model.is_rep1[state], 0);
+                    } else {
+                        encoder.encode_bit(&mut model.is_rep1[state], 1);
+                        encoder.encode_bit(&mut model.is_rep2[state], index as u32 - 2);
+                    }
+                    model.promote_rep(index);
+                }
+                model.rep_lengths.encode(encoder, length, position_state);
+                model.update_rep();
+                length
+            }
+        }
+    }
+}
+
+/// Decode symbols at the end of `output` until it reaches `target` bytes, or until the end
+/// marker when there is no target.
+///
+/// # Arguments
+/// * decoder - Range decoder of the stream
+/// * model - Probabilities and state, kept between calls
+/// * output - Decoded data, the dictionary starting at `dictionary_start`
+/// * dictionary_start - First byte matches can refer to
+/// * target - Length of `output` to reach
+fn decode_symbols(
+    decoder: &mut RangeDecoder,
+    model: &mut Model,
+    output: &mut Vec<u8>,
+    dictionary_start: usize,
+    target: Option<usize>,
+) -> Result<()> {
+    loop {
+        if decoder.overrun {
+            return Err(unexpected_end());
+        }
+        if Some(output.len()) == target {
+            return Ok(());
+        }
+        let position = output.len() - dictionary_start;
+        let position_state = model.position_state(position);
+        let state = model.state;
+
+        if decoder.decode_bit(&mut model.is_match[state][position_state]) == 0 {
+            let previous = match position {
+                0 => 0,
+                _ => output[output.len() - 1],
+            };
+            let offset = model.literal_offset(position, previous);
+            let probabilities = &mut model.literals[offset..offset + 0x300];
+            let mut node: usize = 1;
+            if state >= LITERAL_STATES {
+                let distance = model.reps[0] as usize + 1;
+                if distance > position {
+                    return Err(invalid_data(
+                        "LZMA match refers outside of the decoded data",
+                    ));
+                }
+                let mut match_byte = output[output.len() - distance] as usize;
+                while node < 0x100 {
+                    let match_bit = (match_byte >> 7) & 1;
+                    match_byte <<= 1;
+                    let index = ((1 + match_bit) << 8) + node;
+                    let bit = decoder.decode_bit(&mut probabilities[index]) as usize;
+                    node = node << 1 | bit;
+                    if match_bit != bit {
+                        break;
+                    }
+                }
+            }
+            while node < 0x100 {
+                node = node << 1 | decoder.decode_bit(&mut probabilities[node]) as usize;
+            }
+            output.push(node as u8);
+            model.update_literal();
+            continue;
+        }
+
+        let length = if decoder.decode_bit(&mut model.is_rep[state]) == 0 {
+            let length = model.lengths.decode(decoder, position_state);
+            let length_state = min(length - MIN_MATCH, LENGTH_STATES - 1);
+            let slot = decoder.decode_tree(&mut model.position_slots[length_state], 6);
+            let distance = if slot < START_POSITION_MODEL {
+                slot
+            } else {
+                let footer_bits = ((slot >> 1) - 1) as u8;
+                let base = (2 | (slot & 1)) << footer_bits;
+                if slot < END_POSITION_MODEL {
+                    let probabilities = &mut model.position_special[(base - slot) as usize..];
+                    base + decoder.decode_reverse_tree(probabilities, footer_bits)
+                } else {
+                    let direct = decoder.decode_direct(footer_bits - ALIGN_BITS);
+                    let align = decoder.decode_reverse_tree(&mut model.align, ALIGN_BITS);
+                    base.wrapping_add(direct << ALIGN_BITS).wrapping_add(align)
+                }
+            };
+            if distance == END_MARKER {
+                return match target {
+                    None if !decoder.overrun => Ok(()),
+                    None => Err(unexpected_end()),
+                    Some(_) => Err(invalid_data("unexpected LZMA end marker")),
+                };
+            }
+            model.reps = [distance, model.reps[0], model.reps[1], model.reps[2]];
+            model.update_match();
+            length
+        } else if decoder.decode_bit(&mut model.is_rep0[state]) == 0 {
+            if decoder.decode_bit(&mut model.is_rep0_long[state][position_state]) == 0 {
+                model.update_short_rep();
+                1
+            } else {
+                model.update_rep();
+                model.rep_lengths.decode(decoder, position_state)
+            }
+        } else {
+            let index = if decoder.decode_bit(&mut model.is_rep1[state]) == 0 {
+                1
+            } else {
+                2 + decoder.decode_bit(&mut model.is_rep2[state]) as usize
+            };
+            model.promote_rep(index);
+            model.update_rep();
+            model.rep_lengths.decode(decoder, position_state)
+        };
+
+        let distance = model.reps[0] as usize + 1;
+        if distance > position {
+            return Err(invalid_data(
+                "LZMA match refers outside of the decoded data",
+            ));
+        }
+        if let Some(target) = target {
+            if output.len() + length > target {
+                return Err(invalid_data("LZMA data is longer than announced"));
+            }
+        }
+        let start = output.len() - distance;
+        if distance >= length {
+            output.extend_from_within(start..start + length);
+        } else {
+            for index in start..start + length {
+                output.push(output[index]);
+            }
+        }
+    }
+}
+
+/// Smallest LZMA2 dictionary size byte covering `dictionary_size`.
+fn lzma2_dictionary_byte(dictionary_size: u32) -> u8 {
+    (0..40)
+        .find(|byte| lzma2_dictionary_size(*byte) >= dictionary_size)
+        .unwrap_or(40)
+}
+
+fn lzma2_dictionary_size(byte: u8) -> u32 {
+    match byte {
+        40 => u32::MAX,
+        _ => (2 | u32::from(byte & 1)) << (byte / 2 + 11),
+    }
+}
+
+/// Decode LZMA2 chunks up to the end marker, advancing `data` past it.
+fn decode_lzma2(data: &mut &[u8], output: &mut Vec<u8>) -> Result<()> {
+    let mut dictionary_start: Option<usize> = None;
+    let mut model: Option<Model> = None;
+    loop {
+        let control = take(data, 1)?[0];
+        if control == 0 {
+            return Ok(());
+        }
+        if control == 1 || control >= 0xe0 {
+            dictionary_start = Some(output.len());
+            if control == 1 {
+                // the next LZMA chunk must set new properties
+                model = None;
+            }
+        }
+        let dictionary_start = match dictionary_start {
+            Some(start) => start,
+            None => {
+                return Err(invalid_data(
+                    "LZMA2 data does not start with a dictionary reset",
+                ))
+            }
+        };
+
+        if control < 0x80 {
+            if control > 2 {
+                return Err(invalid_data("invalid LZMA2 control byte"));
+            }
+            let size = read_be(take(data, 2)?) + 1;
+            output.extend_from_slice(take(data, size)?);
+            continue;
+        }
+
+        let uncompressed_size = ((control & 0x1f) as usize) << 16 | read_be(take(data, 2)?);
+        let compressed_size = read_be(take(data, 2)?) + 1;
+        let reset = (control >> 5) & 3;
+        if reset >= 2 {
+            let properties = Properties::from_byte(take(data, 1)?[0])?;
+            if properties.lc + properties.lp > 4 {
+                return Err(invalid_data("invalid LZMA2 properties"));
+            }
+            model = Some(Model::new(properties));
+        }
+        let model = match model.as_mut() {
+            Some(model) => model,
+            None => return Err(invalid_data("LZMA2 chunk without properties")),
+        };
+        if reset == 1 {
+            model.reset();
+        }
+        let mut decoder = RangeDecoder::new(take(data, compressed_size)?)?;
+        let target = output.len() + uncompressed_size + 1;
+        decode_symbols(&mut decoder, model, output, dictionary_start, Some(target))?;
+        if !decoder.is_finished() {
+            return Err(invalid_data("LZMA2 chunk size mismatch"));
+        }
+    }
+}
+
+/// LZMA codec.
+///
+/// # Examples
+/// ```
+/// # use reducto::checksum::sha256;
+/// # use reducto::lzma::Lzma;
+/// let lzma = Lzma::new(6);
+/// let data = b"hello hello hello hello hello hello";
+///
+/// let compressed = lzma.compress(data);
+/// assert_eq!(lzma.decompress(&compressed).unwrap(), &data[..]);
+///
+/// let compressed = lzma.compress_xz(data);
+/// assert_eq!(&compressed[..6], b"\xfd7zXZ\x00");
+/// assert_eq!(lzma.decompress_xz(&compressed).unwrap(), &data[..]);
+///
+/// let expected = std::fs::read("resources/lorem.txt").unwrap();
+/// let vector = std::fs::read("resources/lzma/lorem.txt.xz").unwrap();
+/// assert_eq!(lzma.decompress_xz(&vector).unwrap(), expected);
+/// let vector = std::fs::read("resources/lzma/lorem.txt.lzma").unwrap();
+/// assert_eq!(lzma.decompress(&vector).unwrap(), expected);
+/// // xz -6 makes 21292 bytes
+/// assert!(lzma.compress_xz(&expected).len() < 21_292 * 101 / 100);
+///
+/// // xz --check=sha256
+/// let mut vector = std::fs::read("resources/lzma/lorem.sha256.xz").unwrap();
+/// assert_eq!(lzma.decompress_xz(&vector).unwrap(), expected);
+/// let hash = sha256(&expected);
+/// let check = vector.windows(32).position(|check| check == hash).unwrap();
+/// vector[check] ^= 1;
+/// assert!(lzma.decompress_xz(&vector).is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Lzma {
+    dictionary_size: u32,
+    nice_length: usize,
+    depth: usize,
+    optimal: bool,
+}
+
+impl Lzma {
+    /// Create a new `Lzma` following an xz preset.
+    ///
+    /// # Arguments
+    /// * preset - From 0 (fastest, 256 KiB dictionary) to 9 (best, 64 MiB dictionary), the
+    ///   parse being greedy up to 3 and optimal from 4
+    pub fn new(preset: u8) -> Lzma {
+        assert!(preset <= 9);
+        let preset = preset as usize;
+        Lzma {
+            dictionary_size: DICTIONARY_SIZES[preset],
+            nice_length: NICE_LENGTHS[preset],
+            depth: DEPTHS[preset],
+            optimal: preset >= OPTIMAL_PRESET,
+        }
+    }
+
+    /// Create a new `Lzma` tuned by `options`.
+    ///
+    /// The level is the xz preset, whose dictionary size, nice length and
+    /// search depth are replaced by the history size, nice length and chain
+    /// depth when they are set. The dictionary is between 4 KiB and 4 GiB and
+    /// the nice length between 2 and 273 bytes, the options out of these bounds
+    /// being clamped. The other knobs are ignored.
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::lzma::Lzma;
+    /// # use reducto::options::CompressionOptions;
+    /// let lorem = std::fs::read("resources/lorem.txt").unwrap();
+    /// let preset = Lzma::new(6).compress(&lorem);
+    /// assert_eq!(Lzma::with_options(CompressionOptions::new(6)).compress(&lorem), preset);
+    ///
+    /// let options = CompressionOptions::new(6).history_size(1024).nice_length(8).chain_depth(1);
+    /// let lzma = Lzma::with_options(options);
+    /// let compressed = lzma.compress(&lorem);
+    /// assert!(compressed.len() > preset.len());
+    /// assert_eq!(lzma.decompress(&compressed).unwrap(), lorem);
+    /// ```
+    pub fn with_options(options: CompressionOptions) -> Lzma {
+        let mut lzma = Lzma::new(options.level);
+        if let Some(history_size) = options.history_size {
+            let dictionary_size = history_size.clamp(1 << 12, u32::MAX as usize);
+            lzma = lzma.dictionary_size(dictionary_size as u32);
+        }
+        if let Some(nice_length) = options.nice_length {
+            lzma.nice_length = nice_length.clamp(MIN_MATCH, MAX_MATCH);
+        }
+        if let Some(chain_depth) = options.chain_depth {
+            lzma.depth = chain_depth.max(1);
+        }
+        lzma
+    }
+
+    /// Set the largest distance of a match.
+    ///
+    /// # Arguments
+    /// * dictionary_size - At least 4 KiB
+    pub fn dictionary_size(mut self, dictionary_size: u32) -> Lzma {
+        assert!(dictionary_size >= 1 << 12);
+        self.dictionary_size = dictionary_size;
+        self
+    }
+
+    /// Compress `data` into a `.lzma` file, whose header holds the uncompressed size.
+    pub fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut output: Vec<u8> = Vec::with_capacity(data.len() / 3 + 32);
+        output.push(Properties::default().to_byte());
+        output.extend_from_slice(&self.dictionary_size.to_le_bytes());
+        output.extend_from_slice(&(data.len() as u64).to_le_bytes());
+
+        let mut encoder = RangeEncoder::new();
+        let mut parser = Encoder::new(data, self);
+        let mut position: usize = 0;
+        while position < data.len() {
+            let decision = parser.decide(position);
+            position += parser.encode(&mut encoder, position, decision);
+        }
+        output.extend(encoder.finish());
+        output
+    }
+
+    /// Decompress a `.lzma` file.
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut rest = data;
+        let properties = Properties::from_byte(take(&mut rest, 1)?[0])?;
+        take(&mut rest, 4)?;
+        let target = match read_le(take(&mut rest, 8)?) {
+            u64::MAX => None,
+            size => Some(size as usize),
+        };
+        let mut output: Vec<u8> = Vec::new();
+        let mut model = Model::new(properties);
+        let mut decoder = RangeDecoder::new(rest)?;
+        decode_symbols(&mut decoder, &mut model, &mut output, 0, target)?;
+        Ok(output)
+    }
+
+    /// Compress `data` into LZMA2 chunks, ended by the end marker.
+    fn compress_lzma2(&self, data: &[u8], output: &mut Vec<u8>) {
+        let mut parser = Encoder::new(data, self);
+        let properties = parser.model.properties.to_byte();
+        let mut dictionary_reset = true;
+        let mut properties_reset = true;
+        let mut state_reset = false;
+        let mut position: usize = 0;
+
+        while position < data.len() {
+            let chunk_start = position;
+            let mut encoder = RangeEncoder::new();
+            while position < data.len()
+                && position - chunk_start + MAX_MATCH <= LZMA2_MAX_UNCOMPRESSED
+                && encoder.pending_size() + 64 <= LZMA2_MAX_COMPRESSED
+            {
+                let decision = parser.decide(position);
+                position += parser.encode(&mut encoder, position, decision);
+            }
+            let compressed = encoder.finish();
+            let uncompressed_size = position - chunk_start;
+
+            if compressed.len() < uncompressed_size {
+                let reset = match (dictionary_reset, properties_reset, state_reset) {
+                    (true, _, _) => 3,
+                    (_, true, _) => 2,
+                    (_, _, true) => 1,
+                    _ => 0,
+                };
+                output.push(0x80 | reset << 5 | ((uncompressed_size - 1) >> 16) as u8);
+                output.extend_from_slice(&((uncompressed_size - 1) as u16).to_be_bytes());
+                output.extend_from_slice(&((compressed.len() - 1) as u16).to_be_bytes());
+                if reset >= 2 {
+                    output.push(properties);
+                }
+                output.extend(compressed);
+                dictionary_reset = false;
+                properties_reset = false;
+                state_reset = false;
+            } else {
+                // the decoder does not see the symbols of stored chunks
+                for stored in data[chunk_start..position].chunks(LZMA2_MAX_COMPRESSED) {
+                    output.push(if dictionary_reset { 1 } else { 2 });
+                    output.extend_from_slice(&((stored.len() - 1) as u16).to_be_bytes());
+                    output.extend_from_slice(stored);
+                    dictionary_reset = false;
+                }
+                parser.model.reset();
+                state_reset = true;
+            }
+        }
+        output.push(0);
+    }
+
+    /// Compress `data` into an `.xz` stream of one block, checked by CRC-64.
+    pub fn compress_xz(&self, data: &[u8]) -> Vec<u8> {
+        let mut output: Vec<u8> = Vec::with_capacity(data.len() / 3 + 64);
+        let stream_flags = [0, CHECK_CRC64];
+        output.extend_from_slice(XZ_HEADER_MAGIC);
+        output.extend_from_slice(&stream_flags);
+        output.extend_from_slice(&crc32(&stream_flags).to_le_bytes());
+
+        let mut index: Vec<u8> = vec![0];
+        if data.is_empty() {
+            write_varint(&mut index, 0);
+        } else {
+            let block_start = output.len();
+            let mut header: Vec<u8> = vec![0, 0];
+            write_varint(&mut header, LZMA2_FILTER_ID);
+            write_varint(&mut header, 1);
+            header.push(lzma2_dictionary_byte(self.dictionary_size));
+            header.resize((header.len() + 4).div_ceil(4) * 4 - 4, 0);
+            header[0] = ((header.len() + 4) / 4 - 1) as u8;
+            output.extend_from_slice(&header);
+            output.extend_from_slice(&crc32(&header).to_le_bytes());
+
+            self.compress_lzma2(data, &mut output);
+            let unpadded_size = output.len() - block_start + 8;
+            while !output.len().is_multiple_of(4) {
+                output.push(0);
+            }
+            output.extend_from_slice(&crc64(data).to_le_bytes());
+
+            write_varint(&mut index, 1);
+            write_varint(&mut index, unpadded_size as u64);
+            write_varint(&mut index, data.len() as u64);
+        }
+        while !index.len().is_multiple_of(4) {
+            index.push(0);
+        }
+        let index_crc = crc32(&index);
+        index.extend_from_slice(&index_crc.to_le_bytes());
+        output.extend_from_slice(&index);
+
+        let mut footer: Vec<u8> = Vec::with_capacity(6);
+        footer.extend_from_slice(&((index.len() / 4 - 1) as u32).to_le_bytes());
+        footer.extend_from_slice(&stream_flags);
+        output.extend_from_slice(&crc32(&footer).to_le_bytes());
+        output.extend_from_slice(&footer);
+        output.extend_from_slice(XZ_FOOTER_MAGIC);
+        output
+    }
+
+    /// Decompress every concatenated `.xz` stream of `data`.
+    pub fn decompress_xz(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut output: Vec<u8> = Vec::new();
+        let mut rest = data;
+        loop {
+            read_xz_stream(&mut rest, &mut output)?;
+            // stream padding is a multiple of four zero bytes
+            let padding = rest.iter().take_while(|byte| **byte == 0).count();
+            if padding % 4 != 0 {
+                return Err(invalid_data("invalid xz stream padding"));
+            }
+            rest = &rest[padding..];
+            if rest.is_empty() {
+                return Ok(output);
+            }
+        }
+    }
+}
+
+impl Default for Lzma {
+    fn default() -> Self {
+        Lzma::new(6)
+    }
+}
+
+fn check_size(check: u8) -> Result<usize> {
+    match check {
+        CHECK_NONE => Ok(0),
+        CHECK_CRC32 => Ok(4),
+        CHECK_CRC64 => Ok(8),
+        CHECK_SHA256 => Ok(32),
+        _ => Err(invalid_data("unsupported xz check")),
+    }
+}
+
+/// Read a stream, appending its content to `output`.
+fn read_xz_stream(data: &mut &[u8], output: &mut Vec<u8>) -> Result<()> {
+    if take(data, 6)? != XZ_HEADER_MAGIC {
+        return Err(invalid_data("invalid xz magic number"));
+    }
+    let stream_flags = take(data, 2)?;
+    if read_le(take(data, 4)?) as u32 != crc32(stream_flags) {
+        return Err(invalid_data("xz stream header checksum mismatch"));
+    }
+    if stream_flags[0] != 0 || stream_flags[1] > 0x0f {
+        return Err(invalid_data("unsupported xz stream flags"));
+    }
+    let check = stream_flags[1];
+    let check_size = check_size(check)?;
+
+    let mut records: Vec<(u64, u64)> = Vec::new();
+    while data.first() != Some(&0) {
+        let block_output = output.len();
+        let unpadded_size = read_xz_block(data, output)? + check_size;
+        let content = &output[block_output..];
+        let expected = match check {
+            CHECK_CRC32 => crc32(content).to_le_bytes().to_vec(),
+            CHECK_CRC64 => crc64(content).to_le_bytes().to_vec(),
+            CHECK_SHA256 => sha256(content).to_vec(),
+            _ => Vec::new(),
+        };
+        if take(data, check_size)? != &expected[..] {
+            return Err(invalid_data("xz block check mismatch"));
+        }
+        records.push((unpadded_size as u64, content.len() as u64));
+    }
+
+    let index_start = *data;
+    take(data, 1)?;
+    let count = read_varint(data)?;
+    if count != records.len() as u64 {
+        return Err(invalid_data("xz index does not match the blocks"));
+    }
+    for (unpadded_size, uncompressed_size) in &records {
+        let record_unpadded = read_varint(data)?;
+        let record_uncompressed = read_varint(data)?;
+        if record_unpadded != *unpadded_size || record_uncompressed != *uncompressed_size {
+            return Err(invalid_data("xz index does not match the blocks"));
+        }
+    }
+    while !(index_start.len() - data.len()).is_multiple_of(4) {
+        if take(data, 1)?[0] != 0 {
+            return Err(invalid_data("invalid xz index padding"));
+        }
+    }
+    let index_size = index_start.len() - data.len();
+    if read_le(take(data, 4)?) as u32 != crc32(&index_start[..index_size]) {
+        return Err(invalid_data("xz index checksum mismatch"));
+    }
+
+    let footer_crc = read_le(take(data, 4)?) as u32;
+    let footer = take(data, 6)?;
+    if footer_crc != crc32(footer) {
+        return Err(invalid_data("xz stream footer checksum mismatch"));
+    }
+    if (read_le(&footer[..4]) as usize + 1) * 4 != index_size + 4
+        || &footer[4..] != stream_flags
+        || take(data, 2)? != XZ_FOOTER_MAGIC
+    {
+        return Err(invalid_data("invalid xz stream footer"));
+    }
+    Ok(())
+}
+
+/// Read a block up to its check, appending its content to `output`.
+///
+/// Return the size of the block header and compressed data, without padding.
+fn read_xz_block(data: &mut &[u8], output: &mut Vec<u8>) -> Result<usize> {
+    let header_size = (*data.first().ok_or_else(unexpected_end)? as usize + 1) * 4;
+    let header = take(data, header_size)?;
+    let (mut fields, header_crc) = header.split_at(header_size - 4);
+    if read_le(header_crc) as u32 != crc32(fields) {
+        return Err(invalid_data("xz block header checksum mismatch"));
+    }
+    let flags = fields[1];
+    fields = &fields[2..];
+    if flags & 0x3c != 0 {
+        return Err(invalid_data("unsupported xz block flags"));
+    }
+    let compressed_size = match flags & 0x40 {
+        0 => None,
+        _ => Some(read_varint(&mut fields)?),
+    };
+    let uncompressed_size = match flags & 0x80 {
+        0 => None,
+        _ => Some(read_varint(&mut fields)?),
+    };
+    if flags & 3 != 0 || read_varint(&mut fields)? != LZMA2_FILTER_ID {
+        return Err(invalid_data(
+            "unsupported xz filter, only LZMA2 is supported",
+        ));
+    }
+    if read_varint(&mut fields)? != 1 {
+        return Err(invalid_data("invalid LZMA2 filter properties"));
+    }
+    if take(&mut fields, 1)?[0] > 40 || fields.iter().any(|byte| *byte != 0) {
+        return Err(invalid_data("invalid xz block header"));
+    }
+
+    let compressed_start = *data;
+    let output_start = output.len();
+    decode_lzma2(data, output)?;
+    let block_compressed_size = (compressed_start.len() - data.len()) as u64;
+    if compressed_size.is_some_and(|size| size != block_compressed_size)
+        || uncompressed_size.is_some_and(|size| size != (output.len() - output_start) as u64)
+    {
+        return Err(invalid_data("xz block size mismatch"));
+    }
+    let mut padding: u64 = 0;
+    while !(header_size as u64 + block_compressed_size + padding).is_multiple_of(4) {
+        if take(data, 1)?[0] != 0 {
+            return Err(invalid_data("invalid xz block padding"));
+        }
+        padding += 1;
+    }
+    Ok(header_size + block_compressed_size as usize)
+}
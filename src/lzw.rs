@@ -0,0 +1,404 @@
+//! LZW dictionary coder
+//!
+//! The dictionary starts with every literal, followed by a CLEAR and an
+//! end-of-information code. Codes grow from `literal_width + 1` bits up to the
+//! configured maximum; once the dictionary is full the encoder writes a CLEAR
+//! code and both sides start over. The GIF and TIFF variants differ in bit
+//! order and in when the code width grows.
+use std::io::{Error, ErrorKind, Result};
+
+use crate::bits::{
+    invalid_data, unexpected_end, BitRead, BitWrite, LsbBitReader, LsbBitWriter, MsbBitReader,
+    MsbBitWriter,
+};
+use crate::lz78::{Phrases, Trie, MAX_WIDTH};
+use crate::pipeline::{Stage, StageKind};
+
+/// Order in which the codes are packed in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOrder {
+    /// Most significant bit first, as TIFF does.
+    Msb,
+    /// Least significant bit first, as GIF does.
+    Lsb,
+}
+
+/// Dictionary size and code width as seen by the decoder, which adds each
+/// entry one code later than the encoder.
+#[derive(Debug)]
+struct DecoderView {
+    next_code: u32,
+    width: u8,
+    first_since_clear: bool,
+}
+
+/// LZW coder.
+///
+/// # Examples
+/// ```
+/// # use reducto::lzw::Lzw;
+/// let lzw = Lzw::new();
+/// let compressed = lzw.compress(b"TOBEORNOTTOBEORTOBEORNOT").unwrap();
+/// assert_eq!(lzw.decompress(&compressed).unwrap(), b"TOBEORNOTTOBEORTOBEORNOT");
+///
+/// // GIF codes of 2 bits pixels
+/// let gif = Lzw::gif(2);
+/// let compressed = gif.compress(&[0, 1, 2, 3, 3, 3, 3]).unwrap();
+/// assert_eq!(gif.decompress(&compressed).unwrap(), &[0, 1, 2, 3, 3, 3, 3]);
+/// assert!(gif.compress(&[4]).is_err());
+/// assert!(gif.decompress(&compressed[..1]).is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Lzw {
+    literal_width: u8,
+    max_width: u8,
+    bit_order: BitOrder,
+    early_change: bool,
+}
+
+impl Lzw {
+    /// Create a new `Lzw` over bytes, with codes of 9 to 16 bits packed most significant bit first.
+    pub fn new() -> Lzw {
+        Lzw {
+            literal_width: 8,
+            max_width: MAX_WIDTH,
+            bit_order: BitOrder::Msb,
+            early_change: false,
+        }
+    }
+
+    /// LZW flavour of GIF image data: codes of up to 12 bits, least significant bit first.
+    ///
+    /// # Arguments
+    /// * min_code_size - Bits per pixel index, from 2 to 8
+    pub fn gif(min_code_size: u8) -> Lzw {
+        assert!((2..=8).contains(&min_code_size));
+        Lzw {
+            literal_width: min_code_size,
+            max_width: 12,
+            bit_order: BitOrder::Lsb,
+            early_change: false,
+        }
+    }
+
+    /// LZW flavour of TIFF strips: codes of up to 12 bits, most significant bit first,
+    /// the width growing one code early.
+    pub fn tiff() -> Lzw {
+        Lzw {
+            literal_width: 8,
+            max_width: 12,
+            bit_order: BitOrder::Msb,
+            early_change: true,
+        }
+    }
+
+    /// Set the widest code.
+    ///
+    /// # Arguments
+    /// * max_width - Widest code, at most 16 bits
+    pub fn max_width(mut self, max_width: u8) -> Lzw {
+        assert!(max_width > self.literal_width && max_width <= MAX_WIDTH);
+        self.max_width = max_width;
+        self
+    }
+
+    fn clear_code(&self) -> u32 {
+        1 << self.literal_width
+    }
+
+    fn end_code(&self) -> u32 {
+        self.clear_code() + 1
+    }
+
+    fn first_code(&self) -> u32 {
+        self.clear_code() + 2
+    }
+
+    fn capacity(&self) -> u32 {
+        1 << self.max_width
+    }
+
+    /// Width of the next code, once the decoder holds `next_code` codes.
+    fn next_width(&self, next_code: u32, width: u8) -> u8 {
+        let limit = (1u32 << width) - self.early_change as u32;
+        match next_code >= limit && width < self.max_width {
+            true => width + 1,
+            false => width,
+        }
+    }
+
+    /// Compress `data`, starting with a CLEAR code and ending with an end-of-information code.
+    ///
+    /// Fails when a byte does not fit in the literal width, like a pixel index
+    /// above 3 for a GIF of 2 bits per pixel.
+    pub fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        if let Some(byte) = data
+            .iter()
+            .find(|byte| u32::from(**byte) >= self.clear_code())
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} does not fit in {} bits", byte, self.literal_width),
+            ));
+        }
+        Ok(match self.bit_order {
+            BitOrder::Msb => {
+                let mut writer = MsbBitWriter::new();
+                self.write_codes(data, &mut writer);
+                writer.finish()
+            }
+            BitOrder::Lsb => {
+                let mut writer = LsbBitWriter::new();
+                self.write_codes(data, &mut writer);
+                writer.finish()
+            }
+        })
+    }
+
+    fn write_codes<W: BitWrite>(&self, data: &[u8], writer: &mut W) {
+        // TIFF decoders expect a CLEAR code before the table reaches 4094 entries
+        let limit = self.capacity() - 2 * self.early_change as u32;
+        let mut trie = Trie::new();
+        let mut encoder_next: u32 = self.first_code();
+        let mut state = self.cleared_state();
+        writer.write_bits(self.clear_code(), state.width);
+
+        let mut prefix: Option<u32> = None;
+        for byte in data {
+            let code = match prefix {
+                Some(code) => code,
+                None => {
+                    prefix = Some(u32::from(*byte));
+                    continue;
+                }
+            };
+            if let Some(child) = trie.child(code, *byte) {
+                prefix = Some(child);
+                continue;
+            }
+
+            self.emit(writer, code, &mut state);
+            if encoder_next < limit {
+                trie.insert(code, *byte, encoder_next);
+                encoder_next += 1;
+            } else {
+                writer.write_bits(self.clear_code(), state.width);
+                trie.clear();
+                encoder_next = self.first_code();
+                state = self.cleared_state();
+            }
+            prefix = Some(u32::from(*byte));
+        }
+        if let Some(code) = prefix {
+            self.emit(writer, code, &mut state);
+        }
+        writer.write_bits(self.end_code(), state.width);
+    }
+
+    fn cleared_state(&self) -> DecoderView {
+        DecoderView {
+            next_code: self.first_code(),
+            width: self.literal_width + 1,
+            first_since_clear: true,
+        }
+    }
+
+    /// Write `code` and update the view of the decoder after it reads it.
+    fn emit<W: BitWrite>(&self, writer: &mut W, code: u32, state: &mut DecoderView) {
+        writer.write_bits(code, state.width);
+        if !state.first_since_clear {
+            if state.next_code < self.capacity() {
+                state.next_code += 1;
+            }
+            state.width = self.next_width(state.next_code, state.width);
+        }
+        state.first_since_clear = false;
+    }
+
+    /// Decompress `data`, stopping at the end-of-information code.
+    ///
+    /// Some encoders omit that code, so the end of `data` is accepted instead
+    /// when only the padding of the last code is left.
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self.bit_order {
+            BitOrder::Msb => self.read_codes(&mut MsbBitReader::new(data), data.len()),
+            BitOrder::Lsb => self.read_codes(&mut LsbBitReader::new(data), data.len()),
+        }
+    }
+
+    fn reset_phrases(&self, phrases: &mut Phrases) {
+        phrases.truncate(0);
+        for literal in 0..self.clear_code() {
+            phrases.push(0, literal as u8, 1);
+        }
+        // CLEAR and end-of-information codes have no phrase
+        phrases.push(0, 0, 0);
+        phrases.push(0, 0, 0);
+    }
+
+    fn read_codes<R: BitRead>(&self, reader: &mut R, length: usize) -> Result<Vec<u8>> {
+        let mut output: Vec<u8> = Vec::with_capacity(length * 2);
+        let mut phrases = Phrases::with_capacity(self.capacity() as usize);
+        self.reset_phrases(&mut phrases);
+        let mut width = self.literal_width + 1;
+        let mut previous: Option<u32> = None;
+        let mut remaining = length * 8;
+        loop {
+            if remaining < width as usize {
+                // some encoders omit the end-of-information code, leaving only zero padding
+                return match remaining < 8 && reader.read_bits(remaining as u8)? == 0 {
+                    true => Ok(output),
+                    false => Err(unexpected_end()),
+                };
+            }
+            let code = reader.read_bits(width)?;
+            remaining -= width as usize;
+            if code == self.clear_code() {
+                self.reset_phrases(&mut phrases);
+                width = self.literal_width + 1;
+                previous = None;
+                continue;
+            }
+            if code == self.end_code() {
+                return Ok(output);
+            }
+
+            let previous_code = match previous {
+                Some(previous_code) => previous_code,
+                None => {
+                    if code >= self.clear_code() {
+                        return Err(invalid_data("LZW stream starts with an unknown code"));
+                    }
+                    output.push(code as u8);
+                    previous = Some(code);
+                    continue;
+                }
+            };
+
+            let next_code = phrases.len() as u32;
+            let first_byte = if code < next_code {
+                phrases.write(code, &mut output)
+            } else if code == next_code && next_code < self.capacity() {
+                // the code being defined: the previous phrase followed by its own first byte
+                let first_byte = phrases.write(previous_code, &mut output);
+                output.extend(first_byte);
+                first_byte
+            } else {
+                None
+            };
+            let first_byte = match first_byte {
+                Some(first_byte) => first_byte,
+                None => return Err(invalid_data("LZW code refers to an unknown phrase")),
+            };
+
+            if next_code < self.capacity() {
+                phrases.push(previous_code, first_byte, phrases.length(previous_code) + 1);
+            }
+            width = self.next_width(phrases.len() as u32, width);
+            previous = Some(code);
+        }
+    }
+}
+
+impl Default for Lzw {
+    fn default() -> Self {
+        Lzw::new()
+    }
+}
+
+/// Decode the image data of a GIF frame: the LZW minimum code size then the data sub-blocks.
+///
+/// # Arguments
+/// * data - Image data following the image descriptor and local color table
+///
+/// # Examples
+/// ```
+/// # use reducto::lzw::decode_gif_image_data;
+/// // 10×10 image of the "What's in a GIF" tutorial
+/// let data = [
+///     0x02, 0x16, 0x8c, 0x2d, 0x99, 0x87, 0x2a, 0x1c, 0xdc, 0x33, 0xa0, 0x02, 0x75, 0xec, 0x95,
+///     0xfa, 0xa8, 0xde, 0x60, 0x8c, 0x04, 0x91, 0x4c, 0x01, 0x00,
+/// ];
+/// let pixels = decode_gif_image_data(&data).unwrap();
+/// assert_eq!(pixels.len(), 100);
+/// assert_eq!(&pixels[..10], &[1, 1, 1, 1, 1, 2, 2, 2, 2, 2]);
+/// assert_eq!(&pixels[30..40], &[1, 1, 1, 0, 0, 0, 0, 2, 2, 2]);
+/// ```
+pub fn decode_gif_image_data(data: &[u8]) -> Result<Vec<u8>> {
+    let (min_code_size, mut rest) = match data.split_first() {
+        Some((min_code_size, rest)) => (*min_code_size, rest),
+        None => return Err(unexpected_end()),
+    };
+    if !(2..=8).contains(&min_code_size) {
+        return Err(invalid_data("invalid GIF LZW minimum code size"));
+    }
+    let mut codes: Vec<u8> = Vec::with_capacity(rest.len());
+    loop {
+        let (size, tail) = match rest.split_first() {
+            Some((size, tail)) => (*size as usize, tail),
+            None => return Err(unexpected_end()),
+        };
+        if size == 0 {
+            break;
+        }
+        if tail.len() < size {
+            return Err(unexpected_end());
+        }
+        codes.extend_from_slice(&tail[..size]);
+        rest = &tail[size..];
+    }
+    Lzw::gif(min_code_size).decompress(&codes)
+}
+
+/// Stage storing the literal width, maximum width, bit order and early change flag as parameters.
+///
+/// `forward` cannot fail, so it panics on bytes that do not fit in a literal
+/// width below 8.
+impl Stage for Lzw {
+    fn kind(&self) -> StageKind {
+        StageKind::Lzw
+    }
+
+    fn parameters(&self) -> Vec<u8> {
+        vec![
+            self.literal_width,
+            self.max_width,
+            (self.bit_order == BitOrder::Lsb) as u8,
+            self.early_change as u8,
+        ]
+    }
+
+    fn from_parameters(parameters: &[u8]) -> Result<Self> {
+        match parameters {
+            [literal_width, max_width, bit_order, early_change]
+                if (1..=8).contains(literal_width)
+                    && max_width > literal_width
+                    && *max_width <= MAX_WIDTH
+                    && *bit_order < 2
+                    && *early_change < 2 =>
+            {
+                Ok(Lzw {
+                    literal_width: *literal_width,
+                    max_width: *max_width,
+                    bit_order: match bit_order {
+                        0 => BitOrder::Msb,
+                        _ => BitOrder::Lsb,
+                    },
+                    early_change: *early_change == 1,
+                })
+            }
+            _ => Err(invalid_data("invalid LZW parameters")),
+        }
+    }
+
+    fn forward(&self, data: &[u8]) -> Vec<u8> {
+        match self.compress(data) {
+            Ok(compressed) => compressed,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    fn inverse(&self, data: &[u8]) -> Result<Vec<u8>> {
+        self.decompress(data)
+    }
+}
@@ -0,0 +1,98 @@
+//! Move-to-front transform
+//!
+//! Every byte is replaced by its rank in a list of recently seen bytes, then
+//! moved to the front of that list. Runs of the same byte, as produced by the
+//! Burrows–Wheeler transform, become runs of zeros.
+use std::io::Result;
+
+use crate::bits::invalid_data;
+use crate::pipeline::{Stage, StageKind};
+
+fn identity() -> [u8; 256] {
+    let mut order = [0u8; 256];
+    for (index, value) in order.iter_mut().enumerate() {
+        *value = index as u8;
+    }
+    order
+}
+
+/// Replace every byte of `data` by its rank in the move-to-front list.
+///
+/// The list starts in the natural byte order.
+///
+/// # Examples
+/// ```
+/// # use reducto::mtf;
+/// assert_eq!(mtf::encode(b"bbbaab"), vec![98, 0, 0, 98, 0, 1]);
+/// ```
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let mut order = identity();
+    data.iter()
+        .map(|byte| {
+            let rank = match order.iter().position(|value| value == byte) {
+                Some(rank) => rank,
+                None => unreachable!(),
+            };
+            order.copy_within(0..rank, 1);
+            order[0] = *byte;
+            rank as u8
+        })
+        .collect()
+}
+
+/// Undo `encode`.
+///
+/// # Examples
+/// ```
+/// # use reducto::mtf;
+/// assert_eq!(mtf::decode(&[98, 0, 0, 98, 0, 1]), b"bbbaab");
+/// ```
+pub fn decode(ranks: &[u8]) -> Vec<u8> {
+    let mut order = identity();
+    ranks
+        .iter()
+        .map(|rank| {
+            let rank = *rank as usize;
+            let byte = order[rank];
+            order.copy_within(0..rank, 1);
+            order[0] = byte;
+            byte
+        })
+        .collect()
+}
+
+/// Move-to-front `Stage`.
+///
+/// # Examples
+/// ```
+/// # use reducto::bwt::Bwt;
+/// # use reducto::huffman::Huffman;
+/// # use reducto::mtf::Mtf;
+/// # use reducto::pipeline::Pipeline;
+/// let pipeline = Pipeline::new().stage(Bwt::default()).stage(Mtf).stage(Huffman::new());
+/// let compressed = pipeline.compress(b"to be or not to be");
+/// assert_eq!(Pipeline::decompress(&compressed).unwrap(), b"to be or not to be");
+/// ```
+#[derive(Debug, Default)]
+pub struct Mtf;
+
+impl Stage for Mtf {
+    fn kind(&self) -> StageKind {
+        StageKind::Mtf
+    }
+
+    fn from_parameters(parameters: &[u8]) -> Result<Self> {
+        match parameters.is_empty() {
+            true => Ok(Mtf),
+            false => Err(invalid_data("unexpected MTF parameters")),
+        }
+    }
+
+    fn forward(&self, data: &[u8]) -> Vec<u8> {
+        encode(data)
+    }
+
+    fn inverse(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(decode(data))
+    }
+}
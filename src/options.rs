@@ -0,0 +1,173 @@
+//! Tuning parameters of the LZ77 based compressors
+//!
+//! A `CompressionOptions` starts from one of the levels 0 to 9, which every
+//! codec maps onto its own presets, then its knobs can be set one by one to
+//! override them. The knobs follow the usual naming: the history is the
+//! already seen data that matches refer to, the lookahead the data ahead of
+//! the position that a match can cover, so its longest length. The nice
+//! length is the length of a match good enough to stop searching a longer one.
+//!
+//! Every codec takes the knobs that make sense for its format and documents
+//! how it bounds them, the others being ignored.
+//!
+//! The strategies are the ones of zlib, for data that suits poorly the default
+//! search like small values with noise, images or runs of bytes.
+
+/// How the matches are searched and the blocks coded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    /// Search the longest matches.
+    #[default]
+    Default,
+    /// Drop the matches shorter than 6 bytes, coding more literals.
+    Filtered,
+    /// Code every byte as a literal.
+    HuffmanOnly,
+    /// Only find runs of the previous byte, matches at distance 1.
+    Rle,
+    /// Always use the fixed Huffman codes instead of describing them.
+    Fixed,
+}
+
+/// Compression level and tuning parameters.
+///
+/// # Examples
+/// ```
+/// # use reducto::deflate::RawDeflate;
+/// # use reducto::options::{CompressionOptions, Strategy};
+/// assert_eq!(CompressionOptions::best(), CompressionOptions::new(9));
+/// assert_eq!(CompressionOptions::default(), CompressionOptions::new(6));
+///
+/// let lorem = std::fs::read("resources/lorem.txt").unwrap();
+/// let fast = RawDeflate::with_options(CompressionOptions::fast()).compress(&lorem);
+/// let deep = CompressionOptions::fast().chain_depth(4096).lazy_matching(true);
+/// let deep = RawDeflate::with_options(deep).compress(&lorem);
+/// assert!(deep.len() < fast.len());
+///
+/// let rle = CompressionOptions::default().strategy(Strategy::Rle);
+/// let deflate = RawDeflate::with_options(rle);
+/// let compressed = deflate.compress(&lorem);
+/// assert!(compressed.len() > deep.len());
+/// assert_eq!(deflate.decompress(&compressed).unwrap(), lorem);
+///
+/// // the lookahead is the longest match under its LZ77 name
+/// let options = CompressionOptions::default().lookahead_size(16);
+/// assert_eq!(options, CompressionOptions::default().max_match_length(16));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressionOptions {
+    pub(crate) level: u8,
+    pub(crate) history_size: Option<usize>,
+    pub(crate) nice_length: Option<usize>,
+    pub(crate) min_match_length: Option<usize>,
+    pub(crate) max_match_length: Option<usize>,
+    pub(crate) chain_depth: Option<usize>,
+    pub(crate) lazy_matching: Option<bool>,
+    pub(crate) strategy: Strategy,
+    pub(crate) block_size: Option<usize>,
+}
+
+impl CompressionOptions {
+    /// Create the options of a level, every knob following the presets of the codec.
+    ///
+    /// # Arguments
+    /// * level - From 0 (stored) and 1 (fastest) to 9 (best)
+    pub fn new(level: u8) -> CompressionOptions {
+        assert!(level <= 9);
+        CompressionOptions {
+            level,
+            history_size: None,
+            nice_length: None,
+            min_match_length: None,
+            max_match_length: None,
+            chain_depth: None,
+            lazy_matching: None,
+            strategy: Strategy::Default,
+            block_size: None,
+        }
+    }
+
+    /// Create the options of the fastest level, 1.
+    pub fn fast() -> CompressionOptions {
+        CompressionOptions::new(1)
+    }
+
+    /// Create the options of the best level, 9.
+    pub fn best() -> CompressionOptions {
+        CompressionOptions::new(9)
+    }
+
+    /// Set the largest distance of a match, at most 32 KiB for DEFLATE.
+    pub fn history_size(mut self, history_size: usize) -> CompressionOptions {
+        assert!(history_size > 0);
+        self.history_size = Some(history_size);
+        self
+    }
+
+    /// Set the lookahead, the bytes ahead of the position that a match can cover.
+    ///
+    /// It is the LZ77 name of `max_match_length`, which it sets.
+    pub fn lookahead_size(self, lookahead_size: usize) -> CompressionOptions {
+        self.max_match_length(lookahead_size)
+    }
+
+    /// Set the length of a match ending the search, shorter being faster.
+    ///
+    /// Codecs raise it to their shortest match and cap it at their longest one.
+    pub fn nice_length(mut self, nice_length: usize) -> CompressionOptions {
+        assert!(nice_length > 0);
+        self.nice_length = Some(nice_length);
+        self
+    }
+
+    /// Set the length of the shortest match, at least 3 bytes for DEFLATE.
+    pub fn min_match_length(mut self, min_match_length: usize) -> CompressionOptions {
+        assert!(min_match_length > 0);
+        assert!(self
+            .max_match_length
+            .is_none_or(|max| min_match_length <= max));
+        self.min_match_length = Some(min_match_length);
+        self
+    }
+
+    /// Set the length of the longest match, at most 258 bytes for DEFLATE.
+    pub fn max_match_length(mut self, max_match_length: usize) -> CompressionOptions {
+        assert!(max_match_length > 0);
+        assert!(self
+            .min_match_length
+            .is_none_or(|min| min <= max_match_length));
+        self.max_match_length = Some(max_match_length);
+        self
+    }
+
+    /// Set the number of previous positions compared to find a match.
+    pub fn chain_depth(mut self, chain_depth: usize) -> CompressionOptions {
+        self.chain_depth = Some(chain_depth);
+        self
+    }
+
+    /// Set whether a match is dropped for a literal when the next position has a longer one.
+    pub fn lazy_matching(mut self, lazy_matching: bool) -> CompressionOptions {
+        self.lazy_matching = Some(lazy_matching);
+        self
+    }
+
+    /// Set the strategy, level 0 storing the data whatever it is.
+    pub fn strategy(mut self, strategy: Strategy) -> CompressionOptions {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Set the size of the blocks, compressed in parallel by DEFLATE where it is 128 KiB by default.
+    pub fn block_size(mut self, block_size: usize) -> CompressionOptions {
+        assert!(block_size > 0);
+        self.block_size = Some(block_size);
+        self
+    }
+}
+
+impl Default for CompressionOptions {
+    fn default() -> Self {
+        CompressionOptions::new(6)
+    }
+}
@@ -0,0 +1,194 @@
+//! Composable chains of transforms and entropy coders
+//!
+//! A `Pipeline` applies its stages in order when compressing and writes the
+//! chain description in front of the output, so `Pipeline::decompress` can
+//! rebuild the same stages and undo them without any configuration.
+//!
+//! Header layout:
+//! ```text
+//! "RDCT" | stage count (u8) | for each stage: tag (u8), parameters length (u8), parameters
+//! ```
+use std::fmt::Debug;
+use std::io::Result;
+
+use crate::bits::{invalid_data, unexpected_end};
+use crate::bwt::Bwt;
+use crate::huffman::Huffman;
+use crate::lz77::LZ77;
+use crate::lz78::LZ78;
+use crate::lzw::Lzw;
+use crate::mtf::Mtf;
+use crate::rle::Rle;
+
+const MAGIC: &[u8; 4] = b"RDCT";
+
+/// Identifier of every stage known by `Pipeline::decompress`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum StageKind {
+    LZ77 = 1,
+    Huffman = 2,
+    Bwt = 3,
+    Mtf = 4,
+    Rle = 5,
+    LZ78 = 6,
+    Lzw = 7,
+}
+
+impl StageKind {
+    fn from_u8(tag: u8) -> Option<StageKind> {
+        match tag {
+            1 => Some(StageKind::LZ77),
+            2 => Some(StageKind::Huffman),
+            3 => Some(StageKind::Bwt),
+            4 => Some(StageKind::Mtf),
+            5 => Some(StageKind::Rle),
+            6 => Some(StageKind::LZ78),
+            7 => Some(StageKind::Lzw),
+            _ => None,
+        }
+    }
+
+    fn build(self, parameters: &[u8]) -> Result<Box<dyn Stage>> {
+        Ok(match self {
+            StageKind::LZ77 => Box::new(LZ77::from_parameters(parameters)?),
+            StageKind::Huffman => Box::new(Huffman::from_parameters(parameters)?),
+            StageKind::Bwt => Box::new(Bwt::from_parameters(parameters)?),
+            StageKind::Mtf => Box::new(Mtf::from_parameters(parameters)?),
+            StageKind::Rle => Box::new(Rle::from_parameters(parameters)?),
+            StageKind::LZ78 => Box::new(LZ78::from_parameters(parameters)?),
+            StageKind::Lzw => Box::new(Lzw::from_parameters(parameters)?),
+        })
+    }
+}
+
+/// A reversible step of a `Pipeline`.
+pub trait Stage: Debug {
+    /// Identifier written in the pipeline header.
+    fn kind(&self) -> StageKind;
+
+    /// Parameters written in the pipeline header, at most 255 bytes.
+    fn parameters(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Rebuild the stage from the parameters stored in the header.
+    fn from_parameters(parameters: &[u8]) -> Result<Self>
+    where
+        Self: Sized;
+
+    /// Apply the stage to `data`.
+    fn forward(&self, data: &[u8]) -> Vec<u8>;
+
+    /// Undo `forward`.
+    fn inverse(&self, data: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// Ordered chain of `Stage`s.
+#[derive(Debug, Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn Stage>>,
+}
+
+impl Pipeline {
+    /// Create a new empty `Pipeline`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use reducto::huffman::Huffman;
+    /// # use reducto::lz77::LZ77;
+    /// # use reducto::pipeline::Pipeline;
+    /// let pipeline = Pipeline::new().stage(LZ77::new(16, 32)).stage(Huffman::new());
+    /// let compressed = pipeline.compress(b"aacaacabcabaaac");
+    /// assert_eq!(Pipeline::decompress(&compressed).unwrap(), b"aacaacabcabaaac");
+    /// ```
+    pub fn new() -> Self {
+        Pipeline { stages: Vec::new() }
+    }
+
+    /// Append a stage at the end of the chain.
+    ///
+    /// # Arguments
+    /// * stage - The stage applied after the current ones
+    pub fn stage<S>(mut self, stage: S) -> Self
+    where
+        S: Stage + 'static,
+    {
+        assert!(self.stages.len() < u8::MAX as usize);
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// Run every stage in order and prefix the result with the chain description.
+    pub fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let mut output: Vec<u8> = Vec::from(&MAGIC[..]);
+        output.push(self.stages.len() as u8);
+        for stage in &self.stages {
+            let parameters = stage.parameters();
+            assert!(parameters.len() <= u8::MAX as usize);
+            output.push(stage.kind() as u8);
+            output.push(parameters.len() as u8);
+            output.extend(parameters);
+        }
+
+        let mut buffer: Vec<u8> = Vec::from(data);
+        for stage in &self.stages {
+            buffer = stage.forward(&buffer);
+        }
+        output.extend(buffer);
+        output
+    }
+
+    /// Read the chain description of `data` and undo every stage.
+    ///
+    /// # Arguments
+    /// * data - Output of `Pipeline::compress`
+    pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+        let (pipeline, payload) = Pipeline::from_header(data)?;
+        let mut buffer: Vec<u8> = Vec::from(payload);
+        for stage in pipeline.stages.iter().rev() {
+            buffer = stage.inverse(&buffer)?;
+        }
+        Ok(buffer)
+    }
+
+    /// Rebuild the `Pipeline` described in front of `data` and return it with the remaining bytes.
+    pub fn from_header(data: &[u8]) -> Result<(Pipeline, &[u8])> {
+        if data.len() < MAGIC.len() + 1 {
+            return Err(unexpected_end());
+        }
+        if &data[..MAGIC.len()] != MAGIC {
+            return Err(invalid_data("not a reducto pipeline"));
+        }
+        let stage_count = data[MAGIC.len()];
+        let mut rest = &data[MAGIC.len() + 1..];
+        let mut pipeline = Pipeline::new();
+        for _ in 0..stage_count {
+            if rest.len() < 2 {
+                return Err(unexpected_end());
+            }
+            let kind = match StageKind::from_u8(rest[0]) {
+                Some(kind) => kind,
+                None => return Err(invalid_data("unknown pipeline stage")),
+            };
+            let parameters_length = rest[1] as usize;
+            rest = &rest[2..];
+            if rest.len() < parameters_length {
+                return Err(unexpected_end());
+            }
+            pipeline
+                .stages
+                .push(kind.build(&rest[..parameters_length])?);
+            rest = &rest[parameters_length..];
+        }
+        Ok((pipeline, rest))
+    }
+}
@@ -0,0 +1,203 @@
+//! Zero-run length encoding
+//!
+//! Runs of zeros, as produced by the move-to-front transform, are written in
+//! bijective base 2 with the two digits `RunA` (1) and `RunB` (2), least
+//! significant digit first, as bzip2 does. Other bytes are kept as literals.
+use std::cmp::min;
+use std::io::Result;
+
+use crate::bits::{invalid_data, read_varint, unexpected_end, write_varint};
+use crate::pipeline::{Stage, StageKind};
+
+/// Output symbol of the zero-run length encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Symbol {
+    RunA,
+    RunB,
+    Literal(u8),
+}
+
+impl Symbol {
+    /// Value of the symbol in bzip2's alphabet: `RunA` is 0, `RunB` is 1 and a literal `v` is `v + 1`.
+    pub fn to_u16(self) -> u16 {
+        match self {
+            Symbol::RunA => 0,
+            Symbol::RunB => 1,
+            Symbol::Literal(value) => u16::from(value) + 1,
+        }
+    }
+
+    /// Inverse of `to_u16`.
+    pub fn from_u16(value: u16) -> Option<Symbol> {
+        match value {
+            0 => Some(Symbol::RunA),
+            1 => Some(Symbol::RunB),
+            2..=256 => Some(Symbol::Literal((value - 1) as u8)),
+            _ => None,
+        }
+    }
+}
+
+fn push_run(symbols: &mut Vec<Symbol>, mut run: usize) {
+    while run > 0 {
+        if run & 1 == 1 {
+            symbols.push(Symbol::RunA);
+            run = (run - 1) / 2;
+        } else {
+            symbols.push(Symbol::RunB);
+            run = (run - 2) / 2;
+        }
+    }
+}
+
+/// Encode the runs of zeros of `data`.
+///
+/// # Examples
+/// ```
+/// # use reducto::counter::Counter;
+/// # use reducto::rle::{self, Symbol};
+/// # use std::iter::FromIterator;
+/// let symbols = rle::encode(&[0, 0, 0, 7, 0, 0]);
+/// assert_eq!(symbols, vec![Symbol::RunA, Symbol::RunA, Symbol::Literal(7), Symbol::RunB]);
+///
+/// let counter: Counter<Symbol> = Counter::from_iter(&symbols);
+/// assert_eq!(counter[Symbol::RunA], 2);
+/// ```
+pub fn encode(data: &[u8]) -> Vec<Symbol> {
+    let mut symbols: Vec<Symbol> = Vec::with_capacity(data.len());
+    let mut run: usize = 0;
+    for byte in data {
+        if *byte == 0 {
+            run += 1;
+        } else {
+            push_run(&mut symbols, run);
+            run = 0;
+            symbols.push(Symbol::Literal(*byte));
+        }
+    }
+    push_run(&mut symbols, run);
+    symbols
+}
+
+/// Undo `encode`, failing when the data is longer than `max_length`.
+///
+/// A few symbols can describe a huge run, so the length is checked before the
+/// zeros are written.
+///
+/// # Arguments
+/// * symbols - The encoded symbols
+/// * max_length - Largest length of the decoded data, like the size of a block
+///
+/// # Examples
+/// ```
+/// # use reducto::rle::{self, Symbol};
+/// let symbols = [Symbol::RunA, Symbol::RunA, Symbol::Literal(7), Symbol::RunB];
+/// assert_eq!(rle::decode(&symbols, 6).unwrap(), vec![0, 0, 0, 7, 0, 0]);
+/// assert!(rle::decode(&symbols, 5).is_err());
+/// assert!(rle::decode(&[Symbol::RunB; 40], 1 << 20).is_err());
+/// ```
+pub fn decode(symbols: &[Symbol], max_length: usize) -> Result<Vec<u8>> {
+    let mut data: Vec<u8> = Vec::with_capacity(min(symbols.len(), max_length));
+    let mut run: usize = 0;
+    let mut weight: usize = 1;
+    for symbol in symbols {
+        let digit = match symbol {
+            Symbol::RunA => 1,
+            Symbol::RunB => 2,
+            Symbol::Literal(value) => {
+                if data.len() + run >= max_length {
+                    return Err(invalid_data("RLE data is too long"));
+                }
+                data.resize(data.len() + run, 0);
+                run = 0;
+                weight = 1;
+                data.push(*value);
+                continue;
+            }
+        };
+        run = match weight
+            .checked_mul(digit)
+            .and_then(|value| run.checked_add(value))
+        {
+            Some(run) if data.len() + run <= max_length => run,
+            _ => return Err(invalid_data("zero run is too long")),
+        };
+        weight = weight.saturating_mul(2);
+    }
+    data.resize(data.len() + run, 0);
+    Ok(data)
+}
+
+/// Zero-run length encoding `Stage`.
+///
+/// The length of the data is written as a varint, then the symbols as their
+/// `to_u16` value, values 255 and 256 being escaped as `255, 0` and `255, 1`.
+///
+/// # Examples
+/// ```
+/// # use reducto::bwt::Bwt;
+/// # use reducto::huffman::Huffman;
+/// # use reducto::mtf::Mtf;
+/// # use reducto::pipeline::Pipeline;
+/// # use reducto::rle::Rle;
+/// let pipeline = Pipeline::new()
+///     .stage(Bwt::default())
+///     .stage(Mtf)
+///     .stage(Rle)
+///     .stage(Huffman::new());
+/// let compressed = pipeline.compress(b"to be or not to be");
+/// assert_eq!(Pipeline::decompress(&compressed).unwrap(), b"to be or not to be");
+/// ```
+#[derive(Debug, Default)]
+pub struct Rle;
+
+impl Stage for Rle {
+    fn kind(&self) -> StageKind {
+        StageKind::Rle
+    }
+
+    fn from_parameters(parameters: &[u8]) -> Result<Self> {
+        match parameters.is_empty() {
+            true => Ok(Rle),
+            false => Err(invalid_data("unexpected RLE parameters")),
+        }
+    }
+
+    fn forward(&self, data: &[u8]) -> Vec<u8> {
+        let mut output: Vec<u8> = Vec::with_capacity(data.len());
+        write_varint(&mut output, data.len() as u64);
+        for symbol in encode(data) {
+            match symbol.to_u16() {
+                value if value < 255 => output.push(value as u8),
+                value => output.extend(&[255, (value - 255) as u8]),
+            }
+        }
+        output
+    }
+
+    fn inverse(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut data = data;
+        let length = read_varint(&mut data)? as usize;
+        let mut symbols: Vec<Symbol> = Vec::with_capacity(data.len());
+        let mut bytes = data.iter();
+        while let Some(byte) = bytes.next() {
+            let value = match *byte {
+                255 => match bytes.next() {
+                    Some(escaped) if *escaped < 2 => 255 + u16::from(*escaped),
+                    Some(_) => return Err(invalid_data("invalid RLE escape")),
+                    None => return Err(unexpected_end()),
+                },
+                byte => u16::from(byte),
+            };
+            match Symbol::from_u16(value) {
+                Some(symbol) => symbols.push(symbol),
+                None => return Err(invalid_data("invalid RLE symbol")),
+            }
+        }
+        let decoded = decode(&symbols, length)?;
+        if decoded.len() != length {
+            return Err(invalid_data("RLE length mismatch"));
+        }
+        Ok(decoded)
+    }
+}
@@ -0,0 +1,277 @@
+//! Snappy raw and framing formats
+//!
+//! A raw stream is the decompressed length as a varint followed by elements,
+//! the two low bits of each tag byte telling a literal from a copy with a one,
+//! two or four bytes offset.
+//!
+//! The framing format splits the data in chunks of at most 64 kB, each one
+//! compressed or stored and preceded by the masked CRC-32C of its content.
+use std::cmp::min;
+use std::io::Result;
+
+use crate::bits::{invalid_data, read_le, read_varint, take, unexpected_end, write_varint};
+use crate::checksum::crc32c;
+use crate::lz77::{MatchFinder, HASH_LENGTH};
+
+/// Matches never reach further back than the start of a 64 kB fragment.
+const FRAGMENT_SIZE: usize = 1 << 16;
+const MAX_CHAIN: usize = 4;
+/// Searches skip more positions after every 32 bytes without a match.
+const SKIP_STRENGTH: usize = 5;
+
+const TAG_LITERAL: u8 = 0;
+const TAG_COPY_1: u8 = 1;
+const TAG_COPY_2: u8 = 2;
+const TAG_COPY_4: u8 = 3;
+
+const CHUNK_STREAM_IDENTIFIER: u8 = 0xff;
+const CHUNK_COMPRESSED: u8 = 0x00;
+const CHUNK_UNCOMPRESSED: u8 = 0x01;
+const CHUNK_PADDING: u8 = 0xfe;
+const STREAM_IDENTIFIER: &[u8; 6] = b"sNaPpY";
+/// Largest content of a chunk.
+const MAX_CHUNK_SIZE: usize = 1 << 16;
+
+/// CRC-32C of `data`, rotated and offset as the framing format stores it.
+fn masked_crc(data: &[u8]) -> u32 {
+    crc32c(data).rotate_right(15).wrapping_add(0xa282_ead8)
+}
+
+/// Compress `data` into a raw Snappy stream.
+///
+/// # Examples
+/// ```
+/// # use reducto::snappy;
+/// let compressed = snappy::compress(b"hello hello hello hello");
+/// assert_eq!(compressed[0], 23);
+/// assert_eq!(snappy::decompress(&compressed).unwrap(), b"hello hello hello hello");
+///
+/// // compressed by the snap crate, a port of the reference implementation
+/// let expected = std::fs::read("resources/alice29.txt").unwrap();
+/// let vector = std::fs::read("resources/snappy/alice29.txt.rawsnappy").unwrap();
+/// assert_eq!(snappy::decompress(&vector).unwrap(), expected);
+/// assert!(snappy::compress(&expected).len() <= vector.len());
+///
+/// // a literal then a copy with a four bytes offset, which the reference never writes
+/// let vector = [8, 0x0c, b'a', b'b', b'c', b'd', 0x0f, 4, 0, 0, 0];
+/// assert_eq!(snappy::decompress(&vector).unwrap(), b"abcdabcd");
+/// ```
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    assert!(data.len() <= u32::MAX as usize);
+    let mut output: Vec<u8> = Vec::with_capacity(data.len() / 2 + 16);
+    write_varint(&mut output, data.len() as u64);
+    for fragment in data.chunks(FRAGMENT_SIZE) {
+        compress_fragment(fragment, &mut output);
+    }
+    output
+}
+
+fn compress_fragment(data: &[u8], output: &mut Vec<u8>) {
+    let mut finder = MatchFinder::new(data.len(), FRAGMENT_SIZE - 1, MAX_CHAIN);
+    let mut anchor: usize = 0;
+    let mut position: usize = 0;
+    while position + HASH_LENGTH <= data.len() {
+        let found = finder.find(data, position, data.len() - position);
+        finder.insert(data, position);
+        let (offset, length) = match found {
+            Some(found) => found,
+            None => {
+                position += 1 + ((position - anchor) >> SKIP_STRENGTH);
+                continue;
+            }
+        };
+        write_literal(output, &data[anchor..position]);
+        write_copy(output, offset, length);
+        for inserted in position + 1..position + length {
+            finder.insert(data, inserted);
+        }
+        position += length;
+        anchor = position;
+    }
+    write_literal(output, &data[anchor..]);
+}
+
+fn write_literal(output: &mut Vec<u8>, literal: &[u8]) {
+    if literal.is_empty() {
+        return;
+    }
+    let length = literal.len() - 1;
+    if length < 60 {
+        output.push((length as u8) << 2 | TAG_LITERAL);
+    } else {
+        let bytes = (usize::BITS - length.leading_zeros()).div_ceil(8) as usize;
+        output.push((59 + bytes as u8) << 2 | TAG_LITERAL);
+        output.extend_from_slice(&(length as u32).to_le_bytes()[..bytes]);
+    }
+    output.extend_from_slice(literal);
+}
+
+/// Write a copy as elements of at most 64 bytes, none of them shorter than 4 bytes.
+fn write_copy(output: &mut Vec<u8>, offset: usize, mut length: usize) {
+    while length >= 68 {
+        write_short_copy(output, offset, 64);
+        length -= 64;
+    }
+    if length > 64 {
+        write_short_copy(output, offset, 60);
+        length -= 60;
+    }
+    write_short_copy(output, offset, length);
+}
+
+fn write_short_copy(output: &mut Vec<u8>, offset: usize, length: usize) {
+    if length < 12 && offset < 2048 {
+        output.push(((offset >> 8) << 5 | (length - 4) << 2) as u8 | TAG_COPY_1);
+        output.push(offset as u8);
+    } else {
+        output.push(((length - 1) << 2) as u8 | TAG_COPY_2);
+        output.extend_from_slice(&(offset as u16).to_le_bytes());
+    }
+}
+
+/// Decompress a raw Snappy stream.
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut rest = data;
+    let length = read_varint(&mut rest)?;
+    if length > u64::from(u32::MAX) {
+        return Err(invalid_data("Snappy length is too large"));
+    }
+    let length = length as usize;
+    // a tag byte yields at most 64 bytes, do not trust the length any further
+    let mut output: Vec<u8> = Vec::with_capacity(min(length, rest.len() * 64));
+
+    while !rest.is_empty() {
+        let tag = take(&mut rest, 1)?[0];
+        let (offset, copy_length) = match tag & 3 {
+            TAG_LITERAL => {
+                let mut literal_length = (tag >> 2) as usize;
+                if literal_length >= 60 {
+                    literal_length = read_le(take(&mut rest, literal_length - 59)?) as usize;
+                }
+                let literal = take(&mut rest, literal_length + 1)?;
+                if output.len() + literal.len() > length {
+                    return Err(invalid_data("Snappy data is longer than announced"));
+                }
+                output.extend_from_slice(literal);
+                continue;
+            }
+            TAG_COPY_1 => {
+                let offset = ((tag >> 5) as usize) << 8 | read_le(take(&mut rest, 1)?) as usize;
+                (offset, ((tag >> 2) & 7) as usize + 4)
+            }
+            TAG_COPY_2 => (
+                read_le(take(&mut rest, 2)?) as usize,
+                (tag >> 2) as usize + 1,
+            ),
+            TAG_COPY_4 => (
+                read_le(take(&mut rest, 4)?) as usize,
+                (tag >> 2) as usize + 1,
+            ),
+            _ => unreachable!(),
+        };
+        if offset == 0 || offset > output.len() {
+            return Err(invalid_data(
+                "Snappy copy refers outside of the decoded data",
+            ));
+        }
+        if output.len() + copy_length > length {
+            return Err(invalid_data("Snappy data is longer than announced"));
+        }
+        let start = output.len() - offset;
+        if offset >= copy_length {
+            output.extend_from_within(start..start + copy_length);
+        } else {
+            for index in start..start + copy_length {
+                output.push(output[index]);
+            }
+        }
+    }
+
+    if output.len() != length {
+        return Err(invalid_data("Snappy data is shorter than announced"));
+    }
+    Ok(output)
+}
+
+fn write_chunk(output: &mut Vec<u8>, chunk_type: u8, body: &[&[u8]]) {
+    let length: usize = body.iter().map(|part| part.len()).sum();
+    output.push(chunk_type);
+    output.extend_from_slice(&(length as u32).to_le_bytes()[..3]);
+    for part in body {
+        output.extend_from_slice(part);
+    }
+}
+
+/// Compress `data` into a Snappy framed stream.
+///
+/// Chunks are stored uncompressed when compression saves less than an eighth of their size.
+///
+/// # Examples
+/// ```
+/// # use reducto::snappy;
+/// let compressed = snappy::compress_framed(b"hello hello hello hello");
+/// assert_eq!(&compressed[..10], b"\xff\x06\x00\x00sNaPpY");
+/// assert_eq!(snappy::decompress_framed(&compressed).unwrap(), b"hello hello hello hello");
+///
+/// // compressed by the snap crate, a port of the reference implementation
+/// for name in &["alice29.txt", "lorem.txt"] {
+///     let expected = std::fs::read(format!("resources/{}", name)).unwrap();
+///     let vector = std::fs::read(format!("resources/snappy/{}.sz", name)).unwrap();
+///     assert_eq!(snappy::decompress_framed(&vector).unwrap(), expected);
+/// }
+/// ```
+pub fn compress_framed(data: &[u8]) -> Vec<u8> {
+    let mut output: Vec<u8> = Vec::with_capacity(data.len() / 2 + 16);
+    write_chunk(&mut output, CHUNK_STREAM_IDENTIFIER, &[STREAM_IDENTIFIER]);
+    for chunk in data.chunks(MAX_CHUNK_SIZE) {
+        let crc = masked_crc(chunk).to_le_bytes();
+        let compressed = compress(chunk);
+        if compressed.len() < chunk.len() - chunk.len() / 8 {
+            write_chunk(&mut output, CHUNK_COMPRESSED, &[&crc, &compressed]);
+        } else {
+            write_chunk(&mut output, CHUNK_UNCOMPRESSED, &[&crc, chunk]);
+        }
+    }
+    output
+}
+
+/// Decompress a Snappy framed stream, possibly made of several concatenated streams.
+pub fn decompress_framed(data: &[u8]) -> Result<Vec<u8>> {
+    let mut output: Vec<u8> = Vec::new();
+    let mut rest = data;
+    let mut identified = false;
+    while !rest.is_empty() {
+        let chunk_type = take(&mut rest, 1)?[0];
+        let length = read_le(take(&mut rest, 3)?) as usize;
+        let body = take(&mut rest, length)?;
+        if chunk_type == CHUNK_STREAM_IDENTIFIER {
+            if body != STREAM_IDENTIFIER {
+                return Err(invalid_data("invalid Snappy stream identifier"));
+            }
+            identified = true;
+            continue;
+        }
+        if !identified {
+            return Err(invalid_data(
+                "Snappy stream does not start with its identifier",
+            ));
+        }
+
+        let content = match chunk_type {
+            CHUNK_COMPRESSED | CHUNK_UNCOMPRESSED if length < 4 => return Err(unexpected_end()),
+            CHUNK_COMPRESSED => decompress(&body[4..])?,
+            CHUNK_UNCOMPRESSED => body[4..].to_vec(),
+            // padding and reserved skippable chunks
+            CHUNK_PADDING | 0x80..=0xfd => continue,
+            _ => return Err(invalid_data("reserved unskippable Snappy chunk")),
+        };
+        if content.len() > MAX_CHUNK_SIZE {
+            return Err(invalid_data("Snappy chunk is larger than 64 kB"));
+        }
+        if masked_crc(&content).to_le_bytes() != body[..4] {
+            return Err(invalid_data("Snappy chunk checksum mismatch"));
+        }
+        output.extend_from_slice(&content);
+    }
+    Ok(output)
+}
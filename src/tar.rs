@@ -0,0 +1,744 @@
+//! tar archives
+//!
+//! An archive is a list of 512 bytes headers, each one followed by the data
+//! padded to a whole block, and ends with two zero blocks. The ustar header
+//! holds octal fields and a path split in a prefix and a name, values that
+//! don't fit going in a pax extended header placed before the entry.
+//!
+//! The data of an entry is always skipped by the size of its header, so
+//! entries that aren't understood, such as GNU sparse files, are listed with
+//! their stored data rather than derailing the archive.
+//!
+//! An archive is compressed as a whole, see `Compression`.
+use std::convert::TryFrom;
+use std::io::Result;
+
+use crate::bits::{invalid_data, take, unexpected_end};
+use crate::bzip2::Bzip2;
+use crate::gzip::Gzip;
+use crate::lzma::Lzma;
+
+const BLOCK_SIZE: usize = 512;
+/// Archives are padded to a multiple of 20 blocks, as GNU tar does.
+const RECORD_SIZE: usize = 20 * BLOCK_SIZE;
+
+const NAME: (usize, usize) = (0, 100);
+const MODE: (usize, usize) = (100, 8);
+const UID: (usize, usize) = (108, 8);
+const GID: (usize, usize) = (116, 8);
+const SIZE: (usize, usize) = (124, 12);
+const MTIME: (usize, usize) = (136, 12);
+const CHECKSUM: (usize, usize) = (148, 8);
+const TYPE_FLAG: usize = 156;
+const LINK_NAME: (usize, usize) = (157, 100);
+const MAGIC: (usize, usize) = (257, 8);
+const USER_NAME: (usize, usize) = (265, 32);
+const GROUP_NAME: (usize, usize) = (297, 32);
+const DEV_MAJOR: (usize, usize) = (329, 8);
+const DEV_MINOR: (usize, usize) = (337, 8);
+const PREFIX: (usize, usize) = (345, 155);
+
+/// Magic and version of POSIX archives, old GNU ones having `"ustar  \0"` and no prefix.
+const USTAR_MAGIC: &[u8; 8] = b"ustar\x0000";
+
+const TYPE_FILE: u8 = b'0';
+const TYPE_OLD_FILE: u8 = 0;
+const TYPE_HARD_LINK: u8 = b'1';
+const TYPE_SYMLINK: u8 = b'2';
+const TYPE_DIRECTORY: u8 = b'5';
+const TYPE_PAX: u8 = b'x';
+const TYPE_PAX_GLOBAL: u8 = b'g';
+const TYPE_GNU_LONG_NAME: u8 = b'L';
+const TYPE_GNU_LONG_LINK: u8 = b'K';
+
+fn padding(size: usize) -> usize {
+    size.next_multiple_of(BLOCK_SIZE) - size
+}
+
+fn field(header: &[u8], (start, size): (usize, usize)) -> &[u8] {
+    &header[start..start + size]
+}
+
+/// Zero terminated string of a header field.
+fn read_string(header: &[u8], position: (usize, usize)) -> String {
+    let bytes = field(header, position);
+    let end = bytes
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// Octal number of a header field, or big endian base-256 when the high bit of the first byte is set.
+fn read_number(header: &[u8], position: (usize, usize)) -> Result<u64> {
+    let bytes = field(header, position);
+    if bytes[0] & 0x80 != 0 {
+        // the next bit is the sign
+        let mut value = u64::from(bytes[0] & 0x3f);
+        for byte in &bytes[1..] {
+            if bytes[0] & 0x40 != 0 || value >> 56 != 0 {
+                return Err(invalid_data("tar number out of range"));
+            }
+            value = value << 8 | u64::from(*byte);
+        }
+        return Ok(value);
+    }
+    let digits = bytes
+        .iter()
+        .skip_while(|byte| **byte == b' ')
+        .take_while(|byte| **byte != 0 && **byte != b' ');
+    let mut value: u64 = 0;
+    for digit in digits {
+        if !(b'0'..=b'7').contains(digit) || value >> 61 != 0 {
+            return Err(invalid_data("invalid tar octal number"));
+        }
+        value = value << 3 | u64::from(digit - b'0');
+    }
+    Ok(value)
+}
+
+/// Sum of the header bytes, the checksum field counting as spaces.
+fn checksum(header: &[u8]) -> u64 {
+    header
+        .iter()
+        .enumerate()
+        .map(|(index, byte)| match index {
+            index if (CHECKSUM.0..CHECKSUM.0 + CHECKSUM.1).contains(&index) => u64::from(b' '),
+            _ => u64::from(*byte),
+        })
+        .sum()
+}
+
+/// Kind of an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryType {
+    File,
+    HardLink,
+    Symlink,
+    Directory,
+    /// Any other type flag, such as devices, FIFOs or GNU sparse files.
+    Other(u8),
+}
+
+impl EntryType {
+    fn from_u8(flag: u8) -> EntryType {
+        match flag {
+            TYPE_FILE | TYPE_OLD_FILE => EntryType::File,
+            TYPE_HARD_LINK => EntryType::HardLink,
+            TYPE_SYMLINK => EntryType::Symlink,
+            TYPE_DIRECTORY => EntryType::Directory,
+            flag => EntryType::Other(flag),
+        }
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            EntryType::File => TYPE_FILE,
+            EntryType::HardLink => TYPE_HARD_LINK,
+            EntryType::Symlink => TYPE_SYMLINK,
+            EntryType::Directory => TYPE_DIRECTORY,
+            EntryType::Other(flag) => flag,
+        }
+    }
+}
+
+/// Ownership, permissions and modification time of an entry.
+///
+/// # Examples
+/// ```
+/// # use reducto::tar::Metadata;
+/// let metadata = Metadata::new(0o755).uid(1000).gid(1000).mtime(1_600_000_000);
+/// assert_eq!(metadata, Metadata::new(0o755).gid(1000).uid(1000).mtime(1_600_000_000));
+/// assert_eq!(Metadata::default(), Metadata::new(0o644));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    mode: u32,
+    uid: u64,
+    gid: u64,
+    mtime: u64,
+    user_name: String,
+    group_name: String,
+}
+
+impl Metadata {
+    /// Create a new `Metadata` owned by root at the Unix epoch.
+    ///
+    /// # Arguments
+    /// * mode - Permission bits, such as `0o644`
+    pub fn new(mode: u32) -> Metadata {
+        Metadata {
+            mode,
+            uid: 0,
+            gid: 0,
+            mtime: 0,
+            user_name: String::new(),
+            group_name: String::new(),
+        }
+    }
+
+    pub fn uid(mut self, uid: u64) -> Metadata {
+        self.uid = uid;
+        self
+    }
+
+    pub fn gid(mut self, gid: u64) -> Metadata {
+        self.gid = gid;
+        self
+    }
+
+    /// Set the modification time, in seconds since the Unix epoch.
+    pub fn mtime(mut self, mtime: u64) -> Metadata {
+        self.mtime = mtime;
+        self
+    }
+
+    pub fn user_name(mut self, user_name: &str) -> Metadata {
+        self.user_name = user_name.to_string();
+        self
+    }
+
+    pub fn group_name(mut self, group_name: &str) -> Metadata {
+        self.group_name = group_name.to_string();
+        self
+    }
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Metadata::new(0o644)
+    }
+}
+
+/// Entry of an archive, with the values of its pax headers applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TarEntry {
+    path: String,
+    entry_type: EntryType,
+    link_name: String,
+    metadata: Metadata,
+    size: u64,
+    offset: usize,
+}
+
+impl TarEntry {
+    /// Path of the entry.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn entry_type(&self) -> EntryType {
+        self.entry_type
+    }
+
+    /// Target of a link.
+    pub fn link_name(&self) -> Option<&str> {
+        match self.entry_type {
+            EntryType::HardLink | EntryType::Symlink => Some(&self.link_name),
+            _ => None,
+        }
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn mode(&self) -> u32 {
+        self.metadata.mode
+    }
+
+    pub fn uid(&self) -> u64 {
+        self.metadata.uid
+    }
+
+    pub fn gid(&self) -> u64 {
+        self.metadata.gid
+    }
+
+    /// Modification time, in seconds since the Unix epoch.
+    pub fn mtime(&self) -> u64 {
+        self.metadata.mtime
+    }
+
+    pub fn user_name(&self) -> &str {
+        &self.metadata.user_name
+    }
+
+    pub fn group_name(&self) -> &str {
+        &self.metadata.group_name
+    }
+
+    /// Size of the data stored in the archive.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// Values of pax extended headers.
+#[derive(Debug, Clone, Default)]
+struct PaxValues {
+    path: Option<String>,
+    link_name: Option<String>,
+    size: Option<u64>,
+    uid: Option<u64>,
+    gid: Option<u64>,
+    mtime: Option<u64>,
+    user_name: Option<String>,
+    group_name: Option<String>,
+}
+
+impl PaxValues {
+    /// Parse records written as `"<length> <key>=<value>\n"`.
+    fn read(&mut self, mut data: &[u8]) -> Result<()> {
+        let invalid = || invalid_data("invalid pax record");
+        while !data.is_empty() {
+            let space = data
+                .iter()
+                .position(|byte| *byte == b' ')
+                .ok_or_else(invalid)?;
+            let length: usize = std::str::from_utf8(&data[..space])
+                .ok()
+                .and_then(|length| length.parse().ok())
+                .ok_or_else(invalid)?;
+            if length <= space + 1 || length > data.len() || data[length - 1] != b'\n' {
+                return Err(invalid());
+            }
+            let record = &data[space + 1..length - 1];
+            data = &data[length..];
+            let equal = record
+                .iter()
+                .position(|byte| *byte == b'=')
+                .ok_or_else(invalid)?;
+            let value = String::from_utf8_lossy(&record[equal + 1..]).into_owned();
+            // times may have a fractional part
+            let number = || -> Result<u64> {
+                value
+                    .split('.')
+                    .next()
+                    .and_then(|integer| integer.parse().ok())
+                    .ok_or_else(invalid)
+            };
+            match &record[..equal] {
+                b"path" => self.path = Some(value),
+                b"linkpath" => self.link_name = Some(value),
+                b"size" => self.size = Some(number()?),
+                b"uid" => self.uid = Some(number()?),
+                b"gid" => self.gid = Some(number()?),
+                b"mtime" => self.mtime = Some(number()?),
+                b"uname" => self.user_name = Some(value),
+                b"gname" => self.group_name = Some(value),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Values of `self` overridden by those of `other`.
+    fn merge(&self, other: &PaxValues) -> PaxValues {
+        PaxValues {
+            path: other.path.clone().or_else(|| self.path.clone()),
+            link_name: other.link_name.clone().or_else(|| self.link_name.clone()),
+            size: other.size.or(self.size),
+            uid: other.uid.or(self.uid),
+            gid: other.gid.or(self.gid),
+            mtime: other.mtime.or(self.mtime),
+            user_name: other.user_name.clone().or_else(|| self.user_name.clone()),
+            group_name: other.group_name.clone().or_else(|| self.group_name.clone()),
+        }
+    }
+}
+
+/// tar archive reader.
+///
+/// Reading an archive walks its headers only, the data being borrowed from
+/// the archive on demand.
+///
+/// # Examples
+/// ```
+/// # use reducto::tar::{EntryType, TarArchive};
+/// // written by GNU tar in the pax format
+/// let tar = std::fs::read("resources/tar/lorem.tar").unwrap();
+/// let archive = TarArchive::new(&tar).unwrap();
+/// let paths: Vec<&str> = archive.entries().iter().map(|entry| entry.path()).collect();
+/// assert_eq!(paths.len(), 4);
+///
+/// let entry = archive.by_path("lorem/small_lorem.txt").unwrap();
+/// assert_eq!(entry.entry_type(), EntryType::File);
+/// assert_eq!(entry.mode(), 0o644);
+/// let small_lorem = std::fs::read("resources/small_lorem.txt").unwrap();
+/// assert_eq!(archive.data(entry), &small_lorem[..]);
+///
+/// let link = archive.by_path("lorem/link").unwrap();
+/// assert_eq!(link.link_name(), Some("small_lorem.txt"));
+/// assert!(archive.entries().iter().any(|entry| entry.path().len() > 100));
+/// ```
+#[derive(Debug, Clone)]
+pub struct TarArchive<'a> {
+    data: &'a [u8],
+    entries: Vec<TarEntry>,
+}
+
+impl<'a> TarArchive<'a> {
+    /// Read the headers of the archive held in `data`.
+    pub fn new(data: &'a [u8]) -> Result<TarArchive<'a>> {
+        let mut entries: Vec<TarEntry> = Vec::new();
+        let mut global = PaxValues::default();
+        let mut local = PaxValues::default();
+        let mut remaining = data;
+        // an archive may end without its zero blocks
+        while !remaining.is_empty() {
+            let header = take(&mut remaining, BLOCK_SIZE)?;
+            if header.iter().all(|byte| *byte == 0) {
+                break;
+            }
+            if read_number(header, CHECKSUM)? != checksum(header) {
+                return Err(invalid_data("tar header checksum mismatch"));
+            }
+            let flag = header[TYPE_FLAG];
+            let size = match flag {
+                TYPE_PAX | TYPE_PAX_GLOBAL | TYPE_GNU_LONG_NAME | TYPE_GNU_LONG_LINK => {
+                    read_number(header, SIZE)?
+                }
+                _ => match local.size.or(global.size) {
+                    Some(size) => size,
+                    None => read_number(header, SIZE)?,
+                },
+            };
+            let size = usize::try_from(size)
+                .ok()
+                .filter(|size| *size <= remaining.len())
+                .ok_or_else(unexpected_end)?;
+            let offset = data.len() - remaining.len();
+            let content = take(&mut remaining, size)?;
+            let padding = padding(size).min(remaining.len());
+            take(&mut remaining, padding)?;
+
+            match flag {
+                TYPE_PAX => local.read(content)?,
+                TYPE_PAX_GLOBAL => global.read(content)?,
+                TYPE_GNU_LONG_NAME => local.path = Some(read_string(content, (0, size))),
+                TYPE_GNU_LONG_LINK => local.link_name = Some(read_string(content, (0, size))),
+                _ => {
+                    let values = global.merge(&local);
+                    local = PaxValues::default();
+                    entries.push(read_entry(header, values, size as u64, offset)?);
+                }
+            }
+        }
+        Ok(TarArchive { data, entries })
+    }
+
+    /// Entries in the order of the archive.
+    pub fn entries(&self) -> &[TarEntry] {
+        &self.entries
+    }
+
+    /// Last entry at `path`, the one extracted by tar when several share it.
+    pub fn by_path(&self, path: &str) -> Option<&TarEntry> {
+        self.entries.iter().rev().find(|entry| entry.path == path)
+    }
+
+    /// Data stored for `entry`.
+    pub fn data(&self, entry: &TarEntry) -> &'a [u8] {
+        &self.data[entry.offset..entry.offset + entry.size as usize]
+    }
+}
+
+fn read_entry(header: &[u8], values: PaxValues, size: u64, offset: usize) -> Result<TarEntry> {
+    let path = match values.path {
+        Some(path) => path,
+        None => {
+            let name = read_string(header, NAME);
+            let prefix = match field(header, MAGIC) == USTAR_MAGIC {
+                true => read_string(header, PREFIX),
+                false => String::new(),
+            };
+            match prefix.is_empty() {
+                true => name,
+                false => format!("{}/{}", prefix, name),
+            }
+        }
+    };
+    let metadata = Metadata {
+        mode: read_number(header, MODE)? as u32 & 0o7777,
+        uid: values.uid.map_or_else(|| read_number(header, UID), Ok)?,
+        gid: values.gid.map_or_else(|| read_number(header, GID), Ok)?,
+        mtime: values
+            .mtime
+            .map_or_else(|| read_number(header, MTIME), Ok)?,
+        user_name: values
+            .user_name
+            .unwrap_or_else(|| read_string(header, USER_NAME)),
+        group_name: values
+            .group_name
+            .unwrap_or_else(|| read_string(header, GROUP_NAME)),
+    };
+    let mut entry_type = EntryType::from_u8(header[TYPE_FLAG]);
+    // directories of old archives only end with a slash
+    if entry_type == EntryType::File && path.ends_with('/') {
+        entry_type = EntryType::Directory;
+    }
+    Ok(TarEntry {
+        path,
+        entry_type,
+        link_name: values
+            .link_name
+            .unwrap_or_else(|| read_string(header, LINK_NAME)),
+        metadata,
+        size,
+        offset,
+    })
+}
+
+/// tar archive writer.
+///
+/// Entries are written as ustar headers, preceded by a pax extended header
+/// when a path, link or number doesn't fit in them.
+///
+/// # Examples
+/// ```
+/// # use reducto::tar::{Compression, EntryType, Metadata, TarArchive, TarWriter};
+/// let mut writer = TarWriter::new();
+/// writer.add_directory("app", &Metadata::new(0o755).mtime(1_600_000_000));
+/// writer.add_file("app/run.sh", b"#!/bin/sh\necho hello\n", &Metadata::new(0o755));
+/// writer.add_symlink("app/start", "run.sh", &Metadata::new(0o777));
+/// let long_path = format!("app/{}", "very/".repeat(40) + "deep.txt");
+/// writer.add_file(&long_path, b"deep", &Metadata::default().uid(1 << 30));
+/// let tar = writer.finish();
+///
+/// // a .tar.gz, end to end
+/// let tar_gz = Compression::Gzip.compress(&tar);
+/// assert_eq!(Compression::from_path("bundle.tgz"), Compression::Gzip);
+/// let tar = Compression::Gzip.decompress(&tar_gz).unwrap();
+///
+/// let archive = TarArchive::new(&tar).unwrap();
+/// assert_eq!(archive.entries().len(), 4);
+/// let directory = archive.by_path("app/").unwrap();
+/// assert_eq!(directory.entry_type(), EntryType::Directory);
+/// assert_eq!(directory.mtime(), 1_600_000_000);
+/// assert_eq!(archive.by_path("app/start").unwrap().link_name(), Some("run.sh"));
+/// let deep = archive.by_path(&long_path).unwrap();
+/// assert_eq!((archive.data(deep), deep.uid()), (&b"deep"[..], 1 << 30));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TarWriter {
+    output: Vec<u8>,
+}
+
+impl TarWriter {
+    /// Create a new empty `TarWriter`.
+    pub fn new() -> TarWriter {
+        TarWriter { output: Vec::new() }
+    }
+
+    /// Add a file holding `data`.
+    pub fn add_file(&mut self, path: &str, data: &[u8], metadata: &Metadata) {
+        self.add_entry(path, EntryType::File, "", data, metadata);
+    }
+
+    /// Add a directory, appending `/` to `path` if needed.
+    pub fn add_directory(&mut self, path: &str, metadata: &Metadata) {
+        let mut path = path.to_string();
+        if !path.ends_with('/') {
+            path.push('/');
+        }
+        self.add_entry(&path, EntryType::Directory, "", &[], metadata);
+    }
+
+    /// Add a symbolic link to `target`.
+    pub fn add_symlink(&mut self, path: &str, target: &str, metadata: &Metadata) {
+        self.add_entry(path, EntryType::Symlink, target, &[], metadata);
+    }
+
+    /// Add a hard link to the entry at `target`.
+    pub fn add_hard_link(&mut self, path: &str, target: &str, metadata: &Metadata) {
+        self.add_entry(path, EntryType::HardLink, target, &[], metadata);
+    }
+
+    fn add_entry(
+        &mut self,
+        path: &str,
+        entry_type: EntryType,
+        link_name: &str,
+        data: &[u8],
+        metadata: &Metadata,
+    ) {
+        let mut header = [0u8; BLOCK_SIZE];
+        let mut records: Vec<u8> = Vec::new();
+
+        match split_path(path) {
+            Some((prefix, name)) => {
+                write_bytes(&mut header, PREFIX, prefix.as_bytes());
+                write_bytes(&mut header, NAME, name.as_bytes());
+            }
+            None => {
+                write_record(&mut records, "path", path);
+                write_bytes(&mut header, NAME, truncate(path, NAME.1).as_bytes());
+            }
+        }
+        if link_name.len() > LINK_NAME.1 {
+            write_record(&mut records, "linkpath", link_name);
+        }
+        write_bytes(
+            &mut header,
+            LINK_NAME,
+            truncate(link_name, LINK_NAME.1).as_bytes(),
+        );
+        for (key, value, position) in &[
+            ("uid", metadata.uid, UID),
+            ("gid", metadata.gid, GID),
+            ("size", data.len() as u64, SIZE),
+            ("mtime", metadata.mtime, MTIME),
+        ] {
+            if !write_number(&mut header, *position, *value) {
+                write_record(&mut records, key, &value.to_string());
+            }
+        }
+        write_number(&mut header, MODE, u64::from(metadata.mode & 0o7777));
+        for (key, value, position) in &[
+            ("uname", &metadata.user_name, USER_NAME),
+            ("gname", &metadata.group_name, GROUP_NAME),
+        ] {
+            if value.len() >= position.1 {
+                write_record(&mut records, key, value);
+            }
+            write_bytes(
+                &mut header,
+                *position,
+                truncate(value, position.1 - 1).as_bytes(),
+            );
+        }
+        write_number(&mut header, DEV_MAJOR, 0);
+        write_number(&mut header, DEV_MINOR, 0);
+        header[TYPE_FLAG] = entry_type.to_u8();
+
+        if !records.is_empty() {
+            let name = format!(
+                "PaxHeaders/{}",
+                path.rsplit('/').find(|s| !s.is_empty()).unwrap_or("")
+            );
+            let mut pax = [0u8; BLOCK_SIZE];
+            write_bytes(&mut pax, NAME, truncate(&name, NAME.1).as_bytes());
+            write_number(&mut pax, MODE, 0o644);
+            write_number(&mut pax, UID, 0);
+            write_number(&mut pax, GID, 0);
+            write_number(&mut pax, SIZE, records.len() as u64);
+            write_number(&mut pax, MTIME, metadata.mtime.min(0o777_7777_7777));
+            write_number(&mut pax, DEV_MAJOR, 0);
+            write_number(&mut pax, DEV_MINOR, 0);
+            pax[TYPE_FLAG] = TYPE_PAX;
+            self.write_block(pax, &records);
+        }
+        self.write_block(header, data);
+    }
+
+    /// Write a header, filling its magic and checksum, followed by its padded data.
+    fn write_block(&mut self, mut header: [u8; BLOCK_SIZE], data: &[u8]) {
+        write_bytes(&mut header, MAGIC, USTAR_MAGIC);
+        let sum = checksum(&header);
+        // six digits, a NUL and a space
+        write_bytes(&mut header, CHECKSUM, format!("{:06o}\0 ", sum).as_bytes());
+        self.output.extend_from_slice(&header);
+        self.output.extend_from_slice(data);
+        self.output
+            .resize(self.output.len() + padding(data.len()), 0);
+    }
+
+    /// Write the end of archive blocks and return the archive.
+    pub fn finish(mut self) -> Vec<u8> {
+        let size = (self.output.len() + 2 * BLOCK_SIZE).next_multiple_of(RECORD_SIZE);
+        self.output.resize(size, 0);
+        self.output
+    }
+}
+
+/// Longest prefix of `text` of at most `size` bytes ending on a character boundary.
+fn truncate(text: &str, size: usize) -> &str {
+    let mut end = text.len().min(size);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// Split `path` into the prefix and name fields of a ustar header, when it fits in them.
+fn split_path(path: &str) -> Option<(&str, &str)> {
+    if path.len() <= NAME.1 {
+        return Some(("", path));
+    }
+    // a directory keeps its trailing slash in the name
+    let search = &path[..path.len() - 1];
+    search
+        .match_indices('/')
+        .map(|(index, _)| (&path[..index], &path[index + 1..]))
+        .find(|(prefix, name)| prefix.len() <= PREFIX.1 && name.len() <= NAME.1)
+}
+
+fn write_bytes(header: &mut [u8], (start, size): (usize, usize), bytes: &[u8]) {
+    let count = bytes.len().min(size);
+    header[start..start + count].copy_from_slice(&bytes[..count]);
+}
+
+/// Write `value` in octal followed by a NUL, returning false when it doesn't fit.
+fn write_number(header: &mut [u8], position: (usize, usize), value: u64) -> bool {
+    let digits = format!("{:0width$o}", value, width = position.1 - 1);
+    if digits.len() >= position.1 {
+        return false;
+    }
+    write_bytes(header, position, digits.as_bytes());
+    true
+}
+
+/// Append the pax record `"<length> <key>=<value>\n"`, its length counting its own digits.
+fn write_record(records: &mut Vec<u8>, key: &str, value: &str) {
+    let size = key.len() + value.len() + 3;
+    let mut length = size + 1;
+    while size + length.to_string().len() != length {
+        length += 1;
+    }
+    records.extend_from_slice(format!("{} {}={}\n", length, key, value).as_bytes());
+}
+
+/// Compression of a whole archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Bzip2,
+    Xz,
+}
+
+impl Compression {
+    /// Guess the compression from the extension of `path`, such as `.tar.gz` or `.tgz`.
+    pub fn from_path(path: &str) -> Compression {
+        let path = path.to_ascii_lowercase();
+        let extensions: [(&[&str], Compression); 3] = [
+            (&[".gz", ".tgz", ".taz"], Compression::Gzip),
+            (&[".bz2", ".tbz", ".tbz2", ".tb2"], Compression::Bzip2),
+            (&[".xz", ".txz"], Compression::Xz),
+        ];
+        extensions
+            .iter()
+            .find(|(suffixes, _)| suffixes.iter().any(|suffix| path.ends_with(suffix)))
+            .map_or(Compression::None, |(_, compression)| *compression)
+    }
+
+    /// Compress `data` with the default level of the codec.
+    pub fn compress(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::None => data.to_vec(),
+            Compression::Gzip => Gzip::default().compress(data),
+            Compression::Bzip2 => Bzip2::default().compress(data),
+            Compression::Xz => Lzma::default().compress_xz(data),
+        }
+    }
+
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Gzip => Gzip::default().decompress(data),
+            Compression::Bzip2 => Bzip2::default().decompress(data),
+            Compression::Xz => Lzma::default().decompress_xz(data),
+        }
+    }
+}
@@ -0,0 +1,658 @@
+//! ZIP archives
+//!
+//! An archive is a list of entries, each one a local header followed by the
+//! data and an optional data descriptor, then the central directory that
+//! repeats the headers with the offset of every entry, and the end of central
+//! directory record. Sizes and offsets over 4 GiB go in the ZIP64 extra field,
+//! and the ZIP64 end records then point at the central directory.
+//!
+//! Entries are stored or compressed with `deflate::RawDeflate`, and checked with
+//! a CRC-32 of their content.
+use std::cmp::min;
+use std::convert::TryFrom;
+use std::io::{Read, Result};
+
+use crate::bits::{invalid_data, read_u16, read_u32, read_u64, take, unexpected_end};
+use crate::checksum::{crc32, Checksum, Crc32};
+use crate::deflate::{Inflater, RawDeflate};
+use crate::options::CompressionOptions;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x0807_4b50;
+const END_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_END_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+
+const LOCAL_HEADER_SIZE: usize = 30;
+const CENTRAL_HEADER_SIZE: usize = 46;
+const END_SIZE: usize = 22;
+const ZIP64_END_SIZE: usize = 56;
+const ZIP64_LOCATOR_SIZE: usize = 20;
+const MAX_COMMENT_SIZE: usize = 0xffff;
+
+const ZIP64_EXTRA_ID: u16 = 0x0001;
+const FLAG_ENCRYPTED: u16 = 0x0001;
+const FLAG_DATA_DESCRIPTOR: u16 = 0x0008;
+const FLAG_UTF8: u16 = 0x0800;
+
+const VERSION_DEFAULT: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+const HOST_UNIX: u16 = 3;
+const MODE_FILE: u32 = 0o100_644;
+const MODE_DIRECTORY: u32 = 0o040_755;
+const DOS_DIRECTORY: u32 = 0x10;
+/// 1980-01-01 00:00, the earliest MS-DOS date, written on every entry.
+const DOS_DATE: u16 = (1 << 5) | 1;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+/// Compression method of an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Stored,
+    Deflated,
+    /// Any other method, which entries can be listed but not read with.
+    Unsupported(u16),
+}
+
+impl Method {
+    fn from_u16(id: u16) -> Method {
+        match id {
+            METHOD_STORED => Method::Stored,
+            METHOD_DEFLATED => Method::Deflated,
+            id => Method::Unsupported(id),
+        }
+    }
+
+    fn to_u16(self) -> u16 {
+        match self {
+            Method::Stored => METHOD_STORED,
+            Method::Deflated => METHOD_DEFLATED,
+            Method::Unsupported(id) => id,
+        }
+    }
+}
+
+/// Entry of the central directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZipEntry {
+    name: String,
+    method: Method,
+    flags: u16,
+    crc32: u32,
+    compressed_size: u64,
+    size: u64,
+    header_offset: u64,
+    mode: Option<u32>,
+}
+
+impl ZipEntry {
+    /// Path of the entry, directories ending with `/`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    /// CRC-32 of the content.
+    pub fn crc32(&self) -> u32 {
+        self.crc32
+    }
+
+    /// Size of the data in the archive.
+    pub fn compressed_size(&self) -> u64 {
+        self.compressed_size
+    }
+
+    /// Size of the content.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+
+    /// Unix permissions and file type, when the entry was written on Unix.
+    pub fn mode(&self) -> Option<u32> {
+        self.mode
+    }
+
+    /// Read a central directory header.
+    fn read(data: &mut &[u8]) -> Result<ZipEntry> {
+        if read_u32(take(data, 4)?) != CENTRAL_HEADER_SIGNATURE {
+            return Err(invalid_data("invalid ZIP central directory header"));
+        }
+        let version_made_by = read_u16(take(data, 2)?);
+        let _version_needed = read_u16(take(data, 2)?);
+        let flags = read_u16(take(data, 2)?);
+        let method = Method::from_u16(read_u16(take(data, 2)?));
+        let _time = read_u16(take(data, 2)?);
+        let _date = read_u16(take(data, 2)?);
+        let crc32 = read_u32(take(data, 4)?);
+        let compressed_size = read_u32(take(data, 4)?);
+        let size = read_u32(take(data, 4)?);
+        let name_size = read_u16(take(data, 2)?) as usize;
+        let extra_size = read_u16(take(data, 2)?) as usize;
+        let comment_size = read_u16(take(data, 2)?) as usize;
+        let _disk = read_u16(take(data, 2)?);
+        let _internal_attributes = read_u16(take(data, 2)?);
+        let external_attributes = read_u32(take(data, 4)?);
+        let header_offset = read_u32(take(data, 4)?);
+        let name = String::from_utf8_lossy(take(data, name_size)?).into_owned();
+        let mut extra = take(data, extra_size)?;
+        take(data, comment_size)?;
+
+        let mut entry = ZipEntry {
+            name,
+            method,
+            flags,
+            crc32,
+            compressed_size: u64::from(compressed_size),
+            size: u64::from(size),
+            header_offset: u64::from(header_offset),
+            mode: match version_made_by >> 8 {
+                HOST_UNIX => Some(external_attributes >> 16),
+                _ => None,
+            },
+        };
+        while !extra.is_empty() {
+            let id = read_u16(take(&mut extra, 2)?);
+            let field_size = read_u16(take(&mut extra, 2)?) as usize;
+            let mut field = take(&mut extra, field_size)?;
+            if id != ZIP64_EXTRA_ID {
+                continue;
+            }
+            // only the values saturated in the header are present, in this order
+            if size == u32::MAX {
+                entry.size = read_u64(take(&mut field, 8)?);
+            }
+            if compressed_size == u32::MAX {
+                entry.compressed_size = read_u64(take(&mut field, 8)?);
+            }
+            if header_offset == u32::MAX {
+                entry.header_offset = read_u64(take(&mut field, 8)?);
+            }
+        }
+        Ok(entry)
+    }
+}
+
+/// ZIP archive reader.
+///
+/// Only the central directory is read on creation, the entries being
+/// decompressed on demand.
+///
+/// # Examples
+/// ```
+/// # use reducto::zip::{ZipArchive, ZipWriter};
+/// # use std::io::Read;
+/// let mut writer = ZipWriter::new();
+/// writer.add_directory("docs");
+/// writer.add_file("docs/hello.txt", b"hello hello hello hello hello hello");
+/// writer.add_file("empty", b"");
+/// let zip = writer.finish();
+///
+/// let archive = ZipArchive::new(&zip).unwrap();
+/// let names: Vec<&str> = archive.entries().iter().map(|entry| entry.name()).collect();
+/// assert_eq!(names, ["docs/", "docs/hello.txt", "empty"]);
+///
+/// let entry = archive.by_name("docs/hello.txt").unwrap();
+/// let mut hello = [0; 5];
+/// archive.reader(entry).unwrap().read_exact(&mut hello).unwrap();
+/// assert_eq!(&hello, b"hello");
+///
+/// // streamed by Info-ZIP, with data descriptors
+/// let zip = std::fs::read("resources/zip/lorem.zip").unwrap();
+/// let archive = ZipArchive::new(&zip).unwrap();
+/// let entry = archive.by_name("lorem.txt").unwrap();
+/// let lorem = std::fs::read("resources/lorem.txt").unwrap();
+/// assert_eq!(archive.extract(entry).unwrap(), lorem);
+///
+/// // ZIP64 records forced by Info-ZIP
+/// let zip = std::fs::read("resources/zip/small_lorem64.zip").unwrap();
+/// let archive = ZipArchive::new(&zip).unwrap();
+/// let entry = archive.by_name("small_lorem.txt").unwrap();
+/// let small_lorem = std::fs::read("resources/small_lorem.txt").unwrap();
+/// assert_eq!(archive.extract(entry).unwrap(), small_lorem);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ZipArchive<'a> {
+    data: &'a [u8],
+    entries: Vec<ZipEntry>,
+}
+
+impl<'a> ZipArchive<'a> {
+    /// Read the central directory of the archive held in `data`.
+    pub fn new(data: &'a [u8]) -> Result<ZipArchive<'a>> {
+        let end = find_end(data)?;
+        let mut record = &data[end + 4..];
+        let disk = read_u16(take(&mut record, 2)?);
+        let directory_disk = read_u16(take(&mut record, 2)?);
+        let _disk_entries = read_u16(take(&mut record, 2)?);
+        let mut entry_count = u64::from(read_u16(take(&mut record, 2)?));
+        let mut directory_size = u64::from(read_u32(take(&mut record, 4)?));
+        let mut directory_offset = u64::from(read_u32(take(&mut record, 4)?));
+        if disk != directory_disk {
+            return Err(invalid_data("multi-disk ZIP archives are not supported"));
+        }
+        let mut directory_end = end;
+        if end >= ZIP64_LOCATOR_SIZE {
+            let mut locator = &data[end - ZIP64_LOCATOR_SIZE..end];
+            if read_u32(take(&mut locator, 4)?) == ZIP64_LOCATOR_SIGNATURE {
+                let _disk = read_u32(take(&mut locator, 4)?);
+                let offset = read_u64(take(&mut locator, 8)?);
+                let mut record = usize::try_from(offset)
+                    .ok()
+                    .and_then(|offset| data.get(offset..end))
+                    .ok_or_else(|| invalid_data("invalid ZIP64 end of central directory offset"))?;
+                if read_u32(take(&mut record, 4)?) != ZIP64_END_SIGNATURE {
+                    return Err(invalid_data("invalid ZIP64 end of central directory"));
+                }
+                let _record_size = read_u64(take(&mut record, 8)?);
+                let _version_made_by = read_u16(take(&mut record, 2)?);
+                let _version_needed = read_u16(take(&mut record, 2)?);
+                let _disk = read_u32(take(&mut record, 4)?);
+                let _directory_disk = read_u32(take(&mut record, 4)?);
+                let _disk_entries = read_u64(take(&mut record, 8)?);
+                entry_count = read_u64(take(&mut record, 8)?);
+                directory_size = read_u64(take(&mut record, 8)?);
+                directory_offset = read_u64(take(&mut record, 8)?);
+                directory_end = offset as usize;
+            }
+        }
+        if directory_offset
+            .checked_add(directory_size)
+            .is_none_or(|directory| directory > directory_end as u64)
+        {
+            return Err(invalid_data("invalid ZIP central directory position"));
+        }
+        let mut directory = &data[directory_offset as usize..directory_end];
+        let mut entries: Vec<ZipEntry> = Vec::with_capacity(min(
+            entry_count,
+            directory_size / CENTRAL_HEADER_SIZE as u64,
+        ) as usize);
+        for _ in 0..entry_count {
+            entries.push(ZipEntry::read(&mut directory)?);
+        }
+        Ok(ZipArchive { data, entries })
+    }
+
+    /// Entries in the order of the central directory.
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    /// First entry called `name`.
+    pub fn by_name(&self, name: &str) -> Option<&ZipEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Stream the content of `entry`, checking its size and CRC-32 at the end.
+    pub fn reader(&self, entry: &ZipEntry) -> Result<ZipEntryReader<'a>> {
+        if entry.flags & FLAG_ENCRYPTED != 0 {
+            return Err(invalid_data("encrypted ZIP entries are not supported"));
+        }
+        let mut header = usize::try_from(entry.header_offset)
+            .ok()
+            .and_then(|offset| self.data.get(offset..))
+            .ok_or_else(unexpected_end)?;
+        if read_u32(take(&mut header, 4)?) != LOCAL_HEADER_SIGNATURE {
+            return Err(invalid_data("invalid ZIP local header"));
+        }
+        let mut sizes = header
+            .get(LOCAL_HEADER_SIZE - 8..)
+            .ok_or_else(unexpected_end)?;
+        let name_size = read_u16(take(&mut sizes, 2)?) as usize;
+        let extra_size = read_u16(take(&mut sizes, 2)?) as usize;
+        let start = LOCAL_HEADER_SIZE - 4 + name_size + extra_size;
+        let data = usize::try_from(entry.compressed_size)
+            .ok()
+            .and_then(|size| header.get(start..start.checked_add(size)?))
+            .ok_or_else(unexpected_end)?;
+        let source = match entry.method {
+            Method::Stored => Source::Stored(data),
+            Method::Deflated => Source::Deflated(Inflater::new(data)),
+            Method::Unsupported(_) => {
+                return Err(invalid_data("unsupported ZIP compression method"))
+            }
+        };
+        Ok(ZipEntryReader {
+            source,
+            crc32: Crc32::new(),
+            size: 0,
+            expected_crc32: entry.crc32,
+            expected_size: entry.size,
+        })
+    }
+
+    /// Decompress the whole content of `entry`.
+    pub fn extract(&self, entry: &ZipEntry) -> Result<Vec<u8>> {
+        let mut output: Vec<u8> = Vec::with_capacity(min(entry.size, 1 << 24) as usize);
+        self.reader(entry)?.read_to_end(&mut output)?;
+        Ok(output)
+    }
+}
+
+/// Position of the end of central directory record, searched backward over the archive comment.
+fn find_end(data: &[u8]) -> Result<usize> {
+    let last = data
+        .len()
+        .checked_sub(END_SIZE)
+        .ok_or_else(unexpected_end)?;
+    (last.saturating_sub(MAX_COMMENT_SIZE)..=last)
+        .rev()
+        .find(|position| data[*position..*position + 4] == END_SIGNATURE.to_le_bytes())
+        .ok_or_else(|| invalid_data("ZIP end of central directory not found"))
+}
+
+#[derive(Debug)]
+enum Source<'a> {
+    Stored(&'a [u8]),
+    Deflated(Inflater<'a>),
+}
+
+/// Streaming reader of one entry, created by `ZipArchive::reader`.
+#[derive(Debug)]
+pub struct ZipEntryReader<'a> {
+    source: Source<'a>,
+    crc32: Crc32,
+    size: u64,
+    expected_crc32: u32,
+    expected_size: u64,
+}
+
+impl<'a> Read for ZipEntryReader<'a> {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        let count = match &mut self.source {
+            Source::Stored(data) => data.read(buffer)?,
+            Source::Deflated(inflater) => inflater.read(buffer)?,
+        };
+        self.crc32.write(&buffer[..count]);
+        self.size += count as u64;
+        if self.size > self.expected_size {
+            return Err(invalid_data("ZIP entry larger than its size"));
+        }
+        if count == 0 && !buffer.is_empty() {
+            if self.size != self.expected_size {
+                return Err(invalid_data("ZIP entry smaller than its size"));
+            }
+            if self.crc32.finish() != self.expected_crc32 {
+                return Err(invalid_data("ZIP entry CRC-32 mismatch"));
+            }
+        }
+        Ok(count)
+    }
+}
+
+/// ZIP archive writer.
+///
+/// Entries get ZIP64 extra fields only when their sizes or offset need them,
+/// unless forced with `zip64`.
+///
+/// # Examples
+/// ```
+/// # use reducto::zip::{Method, ZipArchive, ZipWriter};
+/// let lorem = std::fs::read("resources/lorem.txt").unwrap();
+/// let mut writer = ZipWriter::new().level(9).data_descriptors(true).zip64(true);
+/// writer.add_file("lorem.txt", &lorem);
+/// let zip = writer.finish();
+/// assert!(zip.len() < lorem.len() / 3);
+///
+/// let archive = ZipArchive::new(&zip).unwrap();
+/// let entry = &archive.entries()[0];
+/// assert_eq!(entry.method(), Method::Deflated);
+/// assert_eq!(entry.size(), lorem.len() as u64);
+/// assert_eq!(archive.extract(entry).unwrap(), lorem);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ZipWriter {
+    deflate: RawDeflate,
+    method: Method,
+    data_descriptors: bool,
+    zip64: bool,
+    output: Vec<u8>,
+    entries: Vec<ZipEntry>,
+}
+
+impl ZipWriter {
+    /// Create a new `ZipWriter` deflating the files at level 6.
+    pub fn new() -> ZipWriter {
+        ZipWriter {
+            deflate: RawDeflate::default(),
+            method: Method::Deflated,
+            data_descriptors: false,
+            zip64: false,
+            output: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Set the method of the files added next, files that don't shrink being stored anyway.
+    pub fn method(mut self, method: Method) -> ZipWriter {
+        assert!(matches!(method, Method::Stored | Method::Deflated));
+        self.method = method;
+        self
+    }
+
+    /// Set the DEFLATE level, from 0 to 9.
+    pub fn level(mut self, level: u8) -> ZipWriter {
+        self.deflate = RawDeflate::new(level);
+        self
+    }
+
+    /// Set the DEFLATE level and tuning parameters.
+    pub fn options(mut self, options: CompressionOptions) -> ZipWriter {
+        self.deflate = RawDeflate::with_options(options);
+        self
+    }
+
+    /// Write the CRC-32 and sizes in a data descriptor after the data, as streaming writers do.
+    pub fn data_descriptors(mut self, data_descriptors: bool) -> ZipWriter {
+        self.data_descriptors = data_descriptors;
+        self
+    }
+
+    /// Write ZIP64 fields and end records even when no value exceeds the 32 bits ones.
+    pub fn zip64(mut self, zip64: bool) -> ZipWriter {
+        self.zip64 = zip64;
+        self
+    }
+
+    /// Add a file holding `data`.
+    pub fn add_file(&mut self, name: &str, data: &[u8]) {
+        let compressed = match self.method {
+            Method::Deflated if !data.is_empty() => Some(self.deflate.compress(data)),
+            _ => None,
+        };
+        match compressed {
+            Some(compressed) if compressed.len() < data.len() => self.add_entry(
+                name.to_string(),
+                data,
+                Method::Deflated,
+                &compressed,
+                MODE_FILE,
+            ),
+            _ => self.add_entry(name.to_string(), data, Method::Stored, data, MODE_FILE),
+        }
+    }
+
+    /// Add a directory, appending `/` to `name` if needed.
+    pub fn add_directory(&mut self, name: &str) {
+        let mut name = name.to_string();
+        if !name.ends_with('/') {
+            name.push('/');
+        }
+        self.add_entry(name, &[], Method::Stored, &[], MODE_DIRECTORY);
+    }
+
+    fn add_entry(&mut self, name: String, data: &[u8], method: Method, stored: &[u8], mode: u32) {
+        let mut flags = 0;
+        if !name.is_ascii() {
+            flags |= FLAG_UTF8;
+        }
+        if self.data_descriptors {
+            flags |= FLAG_DATA_DESCRIPTOR;
+        }
+        let entry = ZipEntry {
+            name,
+            method,
+            flags,
+            crc32: crc32(data),
+            compressed_size: stored.len() as u64,
+            size: data.len() as u64,
+            header_offset: self.output.len() as u64,
+            mode: Some(mode),
+        };
+        let zip64 = self.zip64 || entry.size >= u64::from(u32::MAX);
+
+        let output = &mut self.output;
+        output.extend_from_slice(&LOCAL_HEADER_SIGNATURE.to_le_bytes());
+        let version = if zip64 {
+            VERSION_ZIP64
+        } else {
+            VERSION_DEFAULT
+        };
+        output.extend_from_slice(&version.to_le_bytes());
+        output.extend_from_slice(&flags.to_le_bytes());
+        output.extend_from_slice(&method.to_u16().to_le_bytes());
+        output.extend_from_slice(&0u16.to_le_bytes());
+        output.extend_from_slice(&DOS_DATE.to_le_bytes());
+        let (crc32, compressed_size, size) = match self.data_descriptors {
+            true => (0, 0, 0),
+            false => (entry.crc32, entry.compressed_size, entry.size),
+        };
+        output.extend_from_slice(&crc32.to_le_bytes());
+        for value in &[compressed_size, size] {
+            let value = if zip64 { u32::MAX } else { *value as u32 };
+            output.extend_from_slice(&value.to_le_bytes());
+        }
+        output.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+        output.extend_from_slice(&(if zip64 { 20u16 } else { 0 }).to_le_bytes());
+        output.extend_from_slice(entry.name.as_bytes());
+        if zip64 {
+            output.extend_from_slice(&ZIP64_EXTRA_ID.to_le_bytes());
+            output.extend_from_slice(&16u16.to_le_bytes());
+            output.extend_from_slice(&size.to_le_bytes());
+            output.extend_from_slice(&compressed_size.to_le_bytes());
+        }
+        output.extend_from_slice(stored);
+        if self.data_descriptors {
+            output.extend_from_slice(&DATA_DESCRIPTOR_SIGNATURE.to_le_bytes());
+            output.extend_from_slice(&entry.crc32.to_le_bytes());
+            if zip64 {
+                output.extend_from_slice(&entry.compressed_size.to_le_bytes());
+                output.extend_from_slice(&entry.size.to_le_bytes());
+            } else {
+                output.extend_from_slice(&(entry.compressed_size as u32).to_le_bytes());
+                output.extend_from_slice(&(entry.size as u32).to_le_bytes());
+            }
+        }
+        self.entries.push(entry);
+    }
+
+    /// Write the central directory and return the archive.
+    pub fn finish(mut self) -> Vec<u8> {
+        let directory_offset = self.output.len() as u64;
+        for entry in &self.entries {
+            write_central_header(&mut self.output, entry, self.zip64);
+        }
+        let directory_size = self.output.len() as u64 - directory_offset;
+        let entry_count = self.entries.len() as u64;
+        let output = &mut self.output;
+
+        if self.zip64
+            || entry_count >= 0xffff
+            || directory_offset >= u64::from(u32::MAX)
+            || directory_size >= u64::from(u32::MAX)
+        {
+            let record_offset = output.len() as u64;
+            output.extend_from_slice(&ZIP64_END_SIGNATURE.to_le_bytes());
+            output.extend_from_slice(&(ZIP64_END_SIZE as u64 - 12).to_le_bytes());
+            output.extend_from_slice(&(HOST_UNIX << 8 | VERSION_ZIP64).to_le_bytes());
+            output.extend_from_slice(&VERSION_ZIP64.to_le_bytes());
+            output.extend_from_slice(&[0; 8]);
+            for value in &[entry_count, entry_count, directory_size, directory_offset] {
+                output.extend_from_slice(&value.to_le_bytes());
+            }
+            output.extend_from_slice(&ZIP64_LOCATOR_SIGNATURE.to_le_bytes());
+            output.extend_from_slice(&0u32.to_le_bytes());
+            output.extend_from_slice(&record_offset.to_le_bytes());
+            output.extend_from_slice(&1u32.to_le_bytes());
+        }
+
+        output.extend_from_slice(&END_SIGNATURE.to_le_bytes());
+        output.extend_from_slice(&[0; 4]);
+        let entry_count = min(entry_count, 0xffff) as u16;
+        output.extend_from_slice(&entry_count.to_le_bytes());
+        output.extend_from_slice(&entry_count.to_le_bytes());
+        for value in &[directory_size, directory_offset] {
+            output.extend_from_slice(&(min(*value, u64::from(u32::MAX)) as u32).to_le_bytes());
+        }
+        output.extend_from_slice(&0u16.to_le_bytes());
+        self.output
+    }
+}
+
+impl Default for ZipWriter {
+    fn default() -> Self {
+        ZipWriter::new()
+    }
+}
+
+fn write_central_header(output: &mut Vec<u8>, entry: &ZipEntry, force_zip64: bool) {
+    let saturated = |value: u64| force_zip64 || value >= u64::from(u32::MAX);
+    let mut zip64: Vec<u8> = Vec::new();
+    for value in &[entry.size, entry.compressed_size, entry.header_offset] {
+        if saturated(*value) {
+            zip64.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+    let version = if zip64.is_empty() {
+        VERSION_DEFAULT
+    } else {
+        VERSION_ZIP64
+    };
+    let mode = entry.mode.unwrap_or(MODE_FILE);
+    let mut attributes = mode << 16;
+    if mode == MODE_DIRECTORY {
+        attributes |= DOS_DIRECTORY;
+    }
+
+    output.extend_from_slice(&CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+    output.extend_from_slice(&(HOST_UNIX << 8 | version).to_le_bytes());
+    output.extend_from_slice(&version.to_le_bytes());
+    output.extend_from_slice(&entry.flags.to_le_bytes());
+    output.extend_from_slice(&entry.method.to_u16().to_le_bytes());
+    output.extend_from_slice(&0u16.to_le_bytes());
+    output.extend_from_slice(&DOS_DATE.to_le_bytes());
+    output.extend_from_slice(&entry.crc32.to_le_bytes());
+    for value in &[entry.compressed_size, entry.size] {
+        let value = if saturated(*value) {
+            u32::MAX
+        } else {
+            *value as u32
+        };
+        output.extend_from_slice(&value.to_le_bytes());
+    }
+    output.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+    let extra_size = if zip64.is_empty() { 0 } else { 4 + zip64.len() };
+    output.extend_from_slice(&(extra_size as u16).to_le_bytes());
+    output.extend_from_slice(&[0; 6]);
+    output.extend_from_slice(&attributes.to_le_bytes());
+    let offset = match saturated(entry.header_offset) {
+        true => u32::MAX,
+        false => entry.header_offset as u32,
+    };
+    output.extend_from_slice(&offset.to_le_bytes());
+    output.extend_from_slice(entry.name.as_bytes());
+    if !zip64.is_empty() {
+        output.extend_from_slice(&ZIP64_EXTRA_ID.to_le_bytes());
+        output.extend_from_slice(&(zip64.len() as u16).to_le_bytes());
+        output.extend_from_slice(&zip64);
+    }
+}
@@ -0,0 +1,184 @@
+//! zlib compressor and decompressor
+//!
+//! A zlib stream, as described by RFC 1950, is a two bytes header telling the
+//! window size and level, raw DEFLATE blocks, then the big endian Adler-32 of
+//! the content.
+//!
+//! When compressed with a preset dictionary, the header is followed by the
+//! Adler-32 of the dictionary, so the decoder can check it has the right one.
+use std::io::{Read, Result};
+
+use crate::bits::{invalid_data, take};
+use crate::checksum::adler32;
+use crate::deflate::{Inflater, RawDeflate};
+use crate::options::CompressionOptions;
+
+const METHOD_DEFLATE: u8 = 8;
+/// Base 2 logarithm of the window size, minus 8.
+const MAX_WINDOW_INFO: u8 = 7;
+const FLAG_DICTIONARY: u8 = 0x20;
+
+/// Whether `data` starts with a valid zlib header and DEFLATE block header.
+///
+/// # Arguments
+/// * data - Start of the stream
+/// * dictionary - Whether the stream may need a preset dictionary
+pub(crate) fn is_header(data: &[u8], dictionary: bool) -> bool {
+    let (cmf, flg) = match data {
+        [cmf, flg, ..] => (*cmf, *flg),
+        _ => return false,
+    };
+    let block = match flg & FLAG_DICTIONARY {
+        0 => 2,
+        _ if dictionary => 6,
+        _ => return false,
+    };
+    cmf & 0x0f == METHOD_DEFLATE
+        && cmf >> 4 <= MAX_WINDOW_INFO
+        && (u16::from(cmf) << 8 | u16::from(flg)).is_multiple_of(31)
+        // the first block is stored, fixed or dynamic, never of the reserved type
+        && data.get(block).is_some_and(|header| header >> 1 & 3 != 3)
+}
+
+/// zlib codec.
+///
+/// # Examples
+/// ```
+/// # use reducto::zlib::Zlib;
+/// let zlib = Zlib::new(6);
+/// let data = b"hello hello hello hello hello hello";
+/// let compressed = zlib.compress(data);
+/// assert_eq!(&compressed[..2], &[0x78, 0x9c]);
+/// assert_eq!(zlib.decompress(&compressed).unwrap(), &data[..]);
+///
+/// // "hello hello hello hello" compressed by zlib
+/// let compressed = [
+///     0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x27, 0x01, 0x68, 0x03, 0x08,
+///     0xb1,
+/// ];
+/// assert_eq!(zlib.decompress(&compressed).unwrap(), b"hello hello hello hello");
+///
+/// let primed = Zlib::new(6).dictionary(b"hello world");
+/// let compressed = primed.compress(b"hello hello");
+/// assert_eq!(primed.decompress(&compressed).unwrap(), b"hello hello");
+/// assert!(zlib.decompress(&compressed).is_err());
+/// assert!(zlib.clone().dictionary(b"goodbye").decompress(&compressed).is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct Zlib {
+    options: CompressionOptions,
+    threads: usize,
+    dictionary: Option<Vec<u8>>,
+}
+
+impl Zlib {
+    /// Create a new `Zlib`.
+    ///
+    /// # Arguments
+    /// * level - DEFLATE level, from 0 (stored) and 1 (fastest) to 9 (best)
+    pub fn new(level: u8) -> Zlib {
+        Zlib::with_options(CompressionOptions::new(level))
+    }
+
+    /// Create a new `Zlib` deflating with `options`, see `RawDeflate::with_options`.
+    pub fn with_options(options: CompressionOptions) -> Zlib {
+        Zlib {
+            options,
+            threads: 1,
+            dictionary: None,
+        }
+    }
+
+    /// Set the preset dictionary, identified in the stream by its Adler-32.
+    pub fn dictionary(mut self, dictionary: &[u8]) -> Zlib {
+        self.dictionary = Some(dictionary.to_vec());
+        self
+    }
+
+    fn deflate(&self) -> RawDeflate {
+        let deflate = RawDeflate::with_options(self.options.clone()).threads(self.threads);
+        match &self.dictionary {
+            Some(dictionary) => deflate.dictionary(dictionary),
+            None => deflate,
+        }
+    }
+
+    /// Set the number of threads compressing the blocks of the input, see `RawDeflate::threads`.
+    pub fn threads(mut self, threads: usize) -> Zlib {
+        assert!(threads > 0);
+        self.threads = threads;
+        self
+    }
+
+    /// Compress `data` into a zlib stream with a 32 KiB window.
+    pub fn compress(&self, data: &[u8]) -> Vec<u8> {
+        let cmf = MAX_WINDOW_INFO << 4 | METHOD_DEFLATE;
+        let level_info: u8 = match self.options.level {
+            0 | 1 => 0,
+            2..=5 => 1,
+            6 => 2,
+            _ => 3,
+        };
+        let mut flg = level_info << 6;
+        if self.dictionary.is_some() {
+            flg |= FLAG_DICTIONARY;
+        }
+        let check = (31 - (u16::from(cmf) << 8 | u16::from(flg)) % 31) % 31;
+
+        let mut output: Vec<u8> = Vec::with_capacity(data.len() / 2 + 8);
+        output.push(cmf);
+        output.push(flg | check as u8);
+        if let Some(dictionary) = &self.dictionary {
+            output.extend_from_slice(&adler32(dictionary).to_be_bytes());
+        }
+        output.extend_from_slice(&self.deflate().compress(data));
+        output.extend_from_slice(&adler32(data).to_be_bytes());
+        output
+    }
+
+    /// Decompress every concatenated stream of `data`, each one checked against its Adler-32.
+    pub fn decompress(&self, mut data: &[u8]) -> Result<Vec<u8>> {
+        let mut output: Vec<u8> = Vec::new();
+        loop {
+            self.read_stream(&mut data, &mut output)?;
+            if data.is_empty() {
+                return Ok(output);
+            }
+        }
+    }
+
+    /// Decompress the stream at the start of `data` and skip it.
+    fn read_stream(&self, data: &mut &[u8], output: &mut Vec<u8>) -> Result<()> {
+        if !is_header(data, true) {
+            return Err(invalid_data("invalid zlib header"));
+        }
+        let header = take(data, 2)?;
+        let mut dictionary: &[u8] = &[];
+        if header[1] & FLAG_DICTIONARY != 0 {
+            let id = take(data, 4)?;
+            dictionary = match &self.dictionary {
+                Some(dictionary) if adler32(dictionary).to_be_bytes() == id => dictionary,
+                Some(_) => return Err(invalid_data("wrong zlib preset dictionary")),
+                None => return Err(invalid_data("zlib stream needs a preset dictionary")),
+            };
+        }
+
+        let start = output.len();
+        let mut inflater = Inflater::with_dictionary(data, dictionary);
+        inflater.read_to_end(output)?;
+        take(data, inflater.compressed_len())?;
+        let checksum = take(data, 4)?;
+        if u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]])
+            != adler32(&output[start..])
+        {
+            return Err(invalid_data("zlib Adler-32 mismatch"));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Zlib {
+    fn default() -> Self {
+        Zlib::new(6)
+    }
+}
@@ -0,0 +1,759 @@
+//! Zstandard decoder
+//!
+//! Decodes `.zst` frames as described by RFC 8878. A frame is a header
+//! followed by raw, RLE or compressed blocks and an optional xxHash64 of the
+//! content. A compressed block holds a literals section, possibly Huffman
+//! coded, then sequences of literal length, match length and offset coded
+//! with three interleaved FSE states.
+//!
+//! Frames needing a dictionary are not supported.
+use std::io::Result;
+
+use crate::bits::{invalid_data, read_le, take, BackwardBitReader, BitRead, LsbBitReader};
+use crate::checksum::xxhash64;
+use crate::huffman::canonical_codes;
+
+const FRAME_MAGIC: u32 = 0xfd2f_b528;
+/// Skippable frames use the magic numbers `0x184D2A50` to `0x184D2A5F`.
+const SKIPPABLE_MAGIC: u32 = 0x184d_2a50;
+const MAX_BLOCK_SIZE: usize = 128 * 1024;
+
+const BLOCK_RAW: u8 = 0;
+const BLOCK_RLE: u8 = 1;
+const BLOCK_COMPRESSED: u8 = 2;
+
+const LITERALS_RAW: u8 = 0;
+const LITERALS_RLE: u8 = 1;
+const LITERALS_COMPRESSED: u8 = 2;
+
+const MODE_PREDEFINED: u8 = 0;
+const MODE_RLE: u8 = 1;
+const MODE_COMPRESSED: u8 = 2;
+
+const MAX_HUFFMAN_BITS: u8 = 11;
+const MAX_HUFFMAN_WEIGHTS_LOG: u8 = 6;
+
+const LITERAL_LENGTH_MAX_SYMBOL: u8 = 35;
+const LITERAL_LENGTH_MAX_LOG: u8 = 9;
+const LITERAL_LENGTH_DEFAULT_LOG: u8 = 6;
+const LITERAL_LENGTH_DEFAULT: [i16; 36] = [
+    4, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 3, 2, 1, 1, 1, 1, 1,
+    -1, -1, -1, -1,
+];
+const LITERAL_LENGTH_BASELINES: [u32; 36] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 18, 20, 22, 24, 28, 32, 40, 48, 64,
+    128, 256, 512, 1024, 2048, 4096, 8192, 16384, 32768, 65536,
+];
+const LITERAL_LENGTH_BITS: [u8; 36] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 3, 3, 4, 6, 7, 8, 9, 10, 11,
+    12, 13, 14, 15, 16,
+];
+
+const MATCH_LENGTH_MAX_SYMBOL: u8 = 52;
+const MATCH_LENGTH_MAX_LOG: u8 = 9;
+const MATCH_LENGTH_DEFAULT_LOG: u8 = 6;
+const MATCH_LENGTH_DEFAULT: [i16; 53] = [
+    1, 4, 3, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1, -1, -1,
+];
+const MATCH_LENGTH_BASELINES: [u32; 53] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27,
+    28, 29, 30, 31, 32, 33, 34, 35, 37, 39, 41, 43, 47, 51, 59, 67, 83, 99, 131, 259, 515, 1027,
+    2051, 4099, 8195, 16387, 32771, 65539,
+];
+const MATCH_LENGTH_BITS: [u8; 53] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    1, 1, 1, 1, 2, 2, 3, 3, 4, 4, 5, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16,
+];
+
+const OFFSET_MAX_SYMBOL: u8 = 31;
+const OFFSET_MAX_LOG: u8 = 8;
+const OFFSET_DEFAULT_LOG: u8 = 5;
+const OFFSET_DEFAULT: [i16; 29] = [
+    1, 1, 1, 1, 1, 1, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1,
+];
+
+/// Decompress every concatenated frame of `data`, ignoring the skippable ones.
+///
+/// # Examples
+/// ```
+/// # use reducto::zstd;
+/// // `echo -n "hello hello hello hello" | zstd -c`
+/// let data = [
+///     0x28, 0xb5, 0x2f, 0xfd, 0x04, 0x58, 0x65, 0x00, 0x00, 0x30, 0x68, 0x65, 0x6c, 0x6c, 0x6f,
+///     0x20, 0x01, 0x00, 0x99, 0x4b, 0x11, 0x17, 0x5e, 0xae, 0x0d,
+/// ];
+/// assert_eq!(zstd::decompress(&data).unwrap(), b"hello hello hello hello");
+///
+/// // `zstd -19`: four blocks, their literals in 1 or 4 Huffman streams, reusing
+/// // the previous table in the treeless ones, and offsets repeated with and
+/// // without literals
+/// let lorem = std::fs::read("resources/lorem.txt").unwrap();
+/// let compressed = std::fs::read("resources/zstd/lorem.txt.zst").unwrap();
+/// assert_eq!(zstd::decompress(&compressed).unwrap(), lorem);
+///
+/// // `zstd -3 --target-compressed-block-size=1340`: 43 blocks, most of them
+/// // repeating the previous Huffman and FSE tables
+/// let alice = std::fs::read("resources/alice29.txt").unwrap();
+/// let mut compressed = std::fs::read("resources/zstd/alice29.txt.zst").unwrap();
+/// assert_eq!(zstd::decompress(&compressed).unwrap(), alice);
+/// let last = compressed.len() - 1;
+/// compressed[last] ^= 1;
+/// assert!(zstd::decompress(&compressed).is_err());
+///
+/// // `cat small_lorem.txt | zstd -3`, a skippable frame, `zstd -1 --no-check` of
+/// // 200000 zeros, an RLE block then raw literals with predefined tables, and
+/// // 15000 numbered lines `| zstd -1`, whose literal lengths are RLE coded
+/// let mut expected = std::fs::read("resources/small_lorem.txt").unwrap();
+/// expected.resize(expected.len() + 200_000, 0);
+/// for line in 0..15000 {
+///     let status = if line % 7 == 0 { "fail" } else { "ok" };
+///     expected.extend(format!("{:05},{}\n", line, status).bytes());
+/// }
+/// let compressed = std::fs::read("resources/zstd/frames.zst").unwrap();
+/// assert_eq!(zstd::decompress(&compressed).unwrap(), expected);
+/// ```
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut output: Vec<u8> = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let magic = read_le(take(&mut rest, 4)?) as u32;
+        if magic & 0xffff_fff0 == SKIPPABLE_MAGIC {
+            let size = read_le(take(&mut rest, 4)?) as usize;
+            take(&mut rest, size)?;
+        } else if magic == FRAME_MAGIC {
+            read_frame(&mut rest, &mut output)?;
+        } else {
+            return Err(invalid_data("invalid Zstandard frame magic number"));
+        }
+    }
+    Ok(output)
+}
+
+#[derive(Debug)]
+struct FrameHeader {
+    window_size: u64,
+    content_size: Option<u64>,
+    checksum: bool,
+}
+
+fn read_frame_header(data: &mut &[u8]) -> Result<FrameHeader> {
+    let descriptor = take(data, 1)?[0];
+    let single_segment = descriptor & 0x20 != 0;
+    if descriptor & 0x08 != 0 {
+        return Err(invalid_data("reserved bit set in Zstandard frame header"));
+    }
+
+    let mut window_size: u64 = 0;
+    if !single_segment {
+        let window_descriptor = take(data, 1)?[0];
+        let base = 1u64 << (10 + (window_descriptor >> 3));
+        window_size = base + (base / 8) * u64::from(window_descriptor & 7);
+    }
+
+    let dictionary_id_size = [0, 1, 2, 4][(descriptor & 3) as usize];
+    if read_le(take(data, dictionary_id_size)?) != 0 {
+        return Err(invalid_data("Zstandard frame needs a dictionary"));
+    }
+
+    let content_size = match (descriptor >> 6, single_segment) {
+        (0, false) => None,
+        (0, true) => Some(read_le(take(data, 1)?)),
+        (1, _) => Some(read_le(take(data, 2)?) + 256),
+        (2, _) => Some(read_le(take(data, 4)?)),
+        _ => Some(read_le(take(data, 8)?)),
+    };
+    if single_segment {
+        window_size = content_size.unwrap_or(0);
+    }
+
+    Ok(FrameHeader {
+        window_size,
+        content_size,
+        checksum: descriptor & 0x04 != 0,
+    })
+}
+
+/// Read a frame following its magic number, appending its content to `output`.
+fn read_frame(data: &mut &[u8], output: &mut Vec<u8>) -> Result<()> {
+    let header = read_frame_header(data)?;
+    let max_block_size = header.window_size.min(MAX_BLOCK_SIZE as u64) as usize;
+    let frame_start = output.len();
+    let mut state = FrameState::new(frame_start);
+
+    loop {
+        let block_header = read_le(take(data, 3)?) as usize;
+        let last_block = block_header & 1 != 0;
+        let block_size = block_header >> 3;
+        if block_size > max_block_size {
+            return Err(invalid_data("Zstandard block is larger than allowed"));
+        }
+        match (block_header >> 1 & 3) as u8 {
+            BLOCK_RAW => output.extend_from_slice(take(data, block_size)?),
+            BLOCK_RLE => {
+                let byte = take(data, 1)?[0];
+                output.resize(output.len() + block_size, byte);
+            }
+            BLOCK_COMPRESSED => state.read_block(take(data, block_size)?, output)?,
+            _ => return Err(invalid_data("reserved Zstandard block type")),
+        }
+        if last_block {
+            break;
+        }
+    }
+
+    let content = &output[frame_start..];
+    if let Some(size) = header.content_size {
+        if size != content.len() as u64 {
+            return Err(invalid_data("Zstandard frame content size mismatch"));
+        }
+    }
+    if header.checksum && read_le(take(data, 4)?) != xxhash64(content, 0) & 0xffff_ffff {
+        return Err(invalid_data("Zstandard content checksum mismatch"));
+    }
+    Ok(())
+}
+
+/// Cell of an FSE decoding table.
+#[derive(Debug, Clone, Copy, Default)]
+struct FseEntry {
+    symbol: u8,
+    bits: u8,
+    baseline: u16,
+}
+
+/// FSE decoding table, one cell per state.
+#[derive(Debug, Clone)]
+struct FseTable {
+    accuracy_log: u8,
+    entries: Vec<FseEntry>,
+}
+
+impl FseTable {
+    /// Table always decoding `symbol` without reading any bit.
+    fn rle(symbol: u8) -> FseTable {
+        FseTable {
+            accuracy_log: 0,
+            entries: vec![FseEntry {
+                symbol,
+                bits: 0,
+                baseline: 0,
+            }],
+        }
+    }
+
+    /// Spread the symbols over `1 << accuracy_log` states, `-1` standing for "less than one".
+    fn from_distribution(probabilities: &[i16], accuracy_log: u8) -> Result<FseTable> {
+        let size = 1usize << accuracy_log;
+        let mask = size - 1;
+        let mut symbols: Vec<u8> = vec![0; size];
+        let mut next_states: Vec<u32> = vec![0; probabilities.len()];
+
+        let mut high = size;
+        for (symbol, probability) in probabilities.iter().enumerate() {
+            if *probability == -1 {
+                if high == 0 {
+                    return Err(invalid_data("invalid FSE distribution"));
+                }
+                high -= 1;
+                symbols[high] = symbol as u8;
+                next_states[symbol] = 1;
+            }
+        }
+
+        let step = (size >> 1) + (size >> 3) + 3;
+        let mut position: usize = 0;
+        for (symbol, probability) in probabilities.iter().enumerate() {
+            if *probability <= 0 {
+                continue;
+            }
+            next_states[symbol] = *probability as u32;
+            for _ in 0..*probability {
+                symbols[position] = symbol as u8;
+                loop {
+                    position = (position + step) & mask;
+                    if position < high {
+                        break;
+                    }
+                }
+            }
+        }
+        if position != 0 {
+            return Err(invalid_data("invalid FSE distribution"));
+        }
+
+        let entries = symbols
+            .iter()
+            .map(|symbol| {
+                let next_state = next_states[*symbol as usize];
+                next_states[*symbol as usize] += 1;
+                let bits = accuracy_log as u32 - (31 - next_state.leading_zeros());
+                FseEntry {
+                    symbol: *symbol,
+                    bits: bits as u8,
+                    baseline: ((next_state << bits) as usize - size) as u16,
+                }
+            })
+            .collect();
+        Ok(FseTable {
+            accuracy_log,
+            entries,
+        })
+    }
+
+    /// Read a table description, advancing `data` past it.
+    fn read(data: &mut &[u8], max_accuracy_log: u8, max_symbol: u8) -> Result<FseTable> {
+        let mut reader = LsbBitReader::new(data);
+        let accuracy_log = reader.read_bits(4)? as u8 + 5;
+        if accuracy_log > max_accuracy_log {
+            return Err(invalid_data("FSE accuracy log is too large"));
+        }
+
+        let mut probabilities: Vec<i16> = Vec::new();
+        let mut remaining: i32 = (1 << accuracy_log) + 1;
+        let mut threshold: i32 = 1 << accuracy_log;
+        let mut bits: u8 = accuracy_log + 1;
+        while remaining > 1 {
+            if probabilities.len() > max_symbol as usize {
+                return Err(invalid_data("too many symbols in FSE distribution"));
+            }
+            let max = 2 * threshold - 1 - remaining;
+            let mut value = reader.read_bits(bits - 1)? as i32;
+            if value >= max {
+                value += (reader.read_bit()? as i32) << (bits - 1);
+                if value >= threshold {
+                    value -= max;
+                }
+            }
+            let probability = value - 1;
+            remaining -= probability.abs();
+            probabilities.push(probability as i16);
+            if probability == 0 {
+                loop {
+                    let repeat = reader.read_bits(2)?;
+                    probabilities.extend((0..repeat).map(|_| 0));
+                    if repeat != 3 {
+                        break;
+                    }
+                }
+            }
+            while remaining < threshold {
+                bits -= 1;
+                threshold >>= 1;
+            }
+        }
+        if remaining != 1 || probabilities.len() > max_symbol as usize + 1 {
+            return Err(invalid_data("invalid FSE distribution"));
+        }
+
+        let consumed = reader.bit_position().div_ceil(8);
+        *data = &data[consumed..];
+        FseTable::from_distribution(&probabilities, accuracy_log)
+    }
+}
+
+/// Current state of an FSE decoder.
+#[derive(Debug)]
+struct FseState<'t> {
+    table: &'t FseTable,
+    state: usize,
+}
+
+impl<'t> FseState<'t> {
+    fn new(table: &'t FseTable, reader: &mut BackwardBitReader) -> Result<Self> {
+        let state = reader.read_bits(table.accuracy_log)? as usize;
+        Ok(FseState { table, state })
+    }
+
+    fn symbol(&self) -> u8 {
+        self.table.entries[self.state].symbol
+    }
+
+    fn update(&mut self, reader: &mut BackwardBitReader) -> Result<()> {
+        let entry = self.table.entries[self.state];
+        self.state = entry.baseline as usize + reader.read_bits(entry.bits)? as usize;
+        Ok(())
+    }
+}
+
+/// Huffman decoding table indexed by the next `max_bits` bits.
+#[derive(Debug, Clone)]
+struct HuffmanDecoder {
+    max_bits: u8,
+    /// Symbol and code length of every possible `max_bits` prefix.
+    entries: Vec<(u8, u8)>,
+}
+
+impl HuffmanDecoder {
+    /// Read a tree description, advancing `data` past it.
+    fn read(data: &mut &[u8]) -> Result<HuffmanDecoder> {
+        let header = take(data, 1)?[0] as usize;
+        let mut weights: Vec<u8> = Vec::with_capacity(256);
+        if header >= 128 {
+            let count = header - 127;
+            for byte in take(data, count.div_ceil(2))? {
+                weights.push(byte >> 4);
+                weights.push(byte & 15);
+            }
+            weights.truncate(count);
+        } else {
+            let mut compressed = take(data, header)?;
+            let table = FseTable::read(&mut compressed, MAX_HUFFMAN_WEIGHTS_LOG, MAX_HUFFMAN_BITS)?;
+            let mut reader = BackwardBitReader::new(compressed)?;
+            let mut states = [
+                FseState::new(&table, &mut reader)?,
+                FseState::new(&table, &mut reader)?,
+            ];
+            // the states take turns until the stream is exhausted
+            for turn in 0.. {
+                if weights.len() > 255 {
+                    return Err(invalid_data("too many Huffman weights"));
+                }
+                let state = &mut states[turn % 2];
+                weights.push(state.symbol());
+                state.update(&mut reader)?;
+                if reader.is_overflowed() {
+                    weights.push(states[(turn + 1) % 2].symbol());
+                    break;
+                }
+            }
+        }
+        HuffmanDecoder::from_weights(&weights)
+    }
+
+    /// Build the table from the weights of every symbol but the last, which completes the code.
+    fn from_weights(weights: &[u8]) -> Result<HuffmanDecoder> {
+        if weights.len() > 255 {
+            return Err(invalid_data("too many Huffman weights"));
+        }
+        let mut total: u32 = 0;
+        for weight in weights {
+            if *weight > MAX_HUFFMAN_BITS {
+                return Err(invalid_data("Huffman weight is too large"));
+            }
+            if *weight > 0 {
+                total += 1 << (weight - 1);
+            }
+        }
+        if total == 0 {
+            return Err(invalid_data("Huffman weights are all zero"));
+        }
+        let max_bits = 32 - total.leading_zeros() as u8;
+        let left = (1u32 << max_bits) - total;
+        if max_bits > MAX_HUFFMAN_BITS || !left.is_power_of_two() {
+            return Err(invalid_data("invalid Huffman weights"));
+        }
+
+        let mut lengths: Vec<u8> = weights
+            .iter()
+            .map(|weight| match weight {
+                0 => 0,
+                weight => max_bits + 1 - weight,
+            })
+            .collect();
+        lengths.push(max_bits - left.trailing_zeros() as u8);
+
+        // Zstandard gives the smallest codes to the longest lengths and, for a
+        // same length, to the smallest symbols: the complement of the canonical
+        // codes of the symbols taken in reverse order
+        let reversed: Vec<u8> = lengths.iter().rev().copied().collect();
+        let codes = canonical_codes(&reversed);
+        let mut entries: Vec<(u8, u8)> = vec![(0, 0); 1 << max_bits];
+        for (index, length) in reversed.iter().enumerate() {
+            if *length == 0 {
+                continue;
+            }
+            let symbol = (reversed.len() - 1 - index) as u8;
+            let code = !codes[index] & ((1 << length) - 1);
+            let shift = max_bits - length;
+            let start = (code << shift) as usize;
+            for entry in &mut entries[start..start + (1 << shift)] {
+                *entry = (symbol, *length);
+            }
+        }
+        Ok(HuffmanDecoder { max_bits, entries })
+    }
+
+    /// Decode `count` symbols of a whole stream.
+    fn decode_stream(&self, stream: &[u8], count: usize, output: &mut Vec<u8>) -> Result<()> {
+        let mut reader = BackwardBitReader::new(stream)?;
+        for _ in 0..count {
+            let (symbol, length) = self.entries[reader.peek_bits(self.max_bits) as usize];
+            reader.consume(length);
+            output.push(symbol);
+        }
+        if !reader.is_finished() {
+            return Err(invalid_data("Huffman stream size mismatch"));
+        }
+        Ok(())
+    }
+}
+
+/// Tables and offsets kept from one block to the next.
+#[derive(Debug)]
+struct FrameState {
+    frame_start: usize,
+    huffman: Option<HuffmanDecoder>,
+    literal_lengths: Option<FseTable>,
+    offsets: Option<FseTable>,
+    match_lengths: Option<FseTable>,
+    repeat_offsets: [usize; 3],
+}
+
+impl FrameState {
+    fn new(frame_start: usize) -> FrameState {
+        FrameState {
+            frame_start,
+            huffman: None,
+            literal_lengths: None,
+            offsets: None,
+            match_lengths: None,
+            repeat_offsets: [1, 4, 8],
+        }
+    }
+
+    fn read_block(&mut self, block: &[u8], output: &mut Vec<u8>) -> Result<()> {
+        let mut rest = block;
+        let literals = self.read_literals(&mut rest)?;
+        let block_start = output.len();
+        let literals_end = self.execute_sequences(rest, &literals, output)?;
+        output.extend_from_slice(&literals[literals_end..]);
+        if output.len() - block_start > MAX_BLOCK_SIZE {
+            return Err(invalid_data("Zstandard block is larger than allowed"));
+        }
+        Ok(())
+    }
+
+    fn read_literals(&mut self, data: &mut &[u8]) -> Result<Vec<u8>> {
+        let first = take(data, 1)?[0];
+        let literals_type = first & 3;
+        let size_format = (first >> 2) & 3;
+
+        if literals_type == LITERALS_RAW || literals_type == LITERALS_RLE {
+            let size = match size_format {
+                0 | 2 => (first >> 3) as usize,
+                1 => (first >> 4) as usize | (take(data, 1)?[0] as usize) << 4,
+                _ => (first >> 4) as usize | (read_le(take(data, 2)?) as usize) << 4,
+            };
+            return match literals_type {
+                LITERALS_RAW => Ok(take(data, size)?.to_vec()),
+                _ => Ok(vec![take(data, 1)?[0]; size]),
+            };
+        }
+
+        let (streams, bits, header_size) = match size_format {
+            0 => (1, 10, 3),
+            1 => (4, 10, 3),
+            2 => (4, 14, 4),
+            _ => (4, 18, 5),
+        };
+        let header = read_le(take(data, header_size - 1)?) << 8 | u64::from(first);
+        let mask = (1 << bits) - 1;
+        let regenerated_size = (header >> 4 & mask) as usize;
+        let compressed_size = (header >> (4 + bits) & mask) as usize;
+        if regenerated_size > MAX_BLOCK_SIZE {
+            return Err(invalid_data("Zstandard literals are larger than a block"));
+        }
+
+        let mut compressed = take(data, compressed_size)?;
+        if literals_type == LITERALS_COMPRESSED {
+            self.huffman = Some(HuffmanDecoder::read(&mut compressed)?);
+        }
+        let huffman = match &self.huffman {
+            Some(huffman) => huffman,
+            None => {
+                return Err(invalid_data(
+                    "Zstandard literals reuse a missing Huffman table",
+                ))
+            }
+        };
+
+        let mut literals: Vec<u8> = Vec::with_capacity(regenerated_size);
+        if streams == 1 {
+            huffman.decode_stream(compressed, regenerated_size, &mut literals)?;
+            return Ok(literals);
+        }
+        let jump_table = take(&mut compressed, 6)?;
+        let segment_size = regenerated_size.div_ceil(4);
+        if segment_size * 3 > regenerated_size {
+            return Err(invalid_data("invalid Zstandard literals size"));
+        }
+        for stream in 0..4 {
+            let (stream_size, count) = match stream {
+                3 => (compressed.len(), regenerated_size - 3 * segment_size),
+                _ => (
+                    read_le(&jump_table[stream * 2..][..2]) as usize,
+                    segment_size,
+                ),
+            };
+            huffman.decode_stream(take(&mut compressed, stream_size)?, count, &mut literals)?;
+        }
+        Ok(literals)
+    }
+
+    /// Pick the table of one kind of symbol, as told by its compression mode.
+    fn read_table(
+        data: &mut &[u8],
+        mode: u8,
+        previous: &mut Option<FseTable>,
+        default: (&[i16], u8),
+        max_accuracy_log: u8,
+        max_symbol: u8,
+    ) -> Result<()> {
+        let table = match mode {
+            MODE_PREDEFINED => FseTable::from_distribution(default.0, default.1)?,
+            MODE_RLE => {
+                let symbol = take(data, 1)?[0];
+                if symbol > max_symbol {
+                    return Err(invalid_data("invalid Zstandard RLE symbol"));
+                }
+                FseTable::rle(symbol)
+            }
+            MODE_COMPRESSED => FseTable::read(data, max_accuracy_log, max_symbol)?,
+            _ => match previous.take() {
+                Some(table) => table,
+                None => return Err(invalid_data("Zstandard sequences reuse a missing table")),
+            },
+        };
+        *previous = Some(table);
+        Ok(())
+    }
+
+    /// Decode and execute the sequences, returning the number of literals used.
+    fn execute_sequences(
+        &mut self,
+        data: &[u8],
+        literals: &[u8],
+        output: &mut Vec<u8>,
+    ) -> Result<usize> {
+        let block_start = output.len();
+        let mut rest = data;
+        let first = take(&mut rest, 1)?[0] as usize;
+        let count = match first {
+            0 => return Ok(0),
+            1..=127 => first,
+            128..=254 => (first - 128) << 8 | take(&mut rest, 1)?[0] as usize,
+            _ => read_le(take(&mut rest, 2)?) as usize + 0x7f00,
+        };
+
+        let modes = take(&mut rest, 1)?[0];
+        if modes & 3 != 0 {
+            return Err(invalid_data(
+                "reserved bits set in Zstandard sequences header",
+            ));
+        }
+        FrameState::read_table(
+            &mut rest,
+            modes >> 6,
+            &mut self.literal_lengths,
+            (&LITERAL_LENGTH_DEFAULT, LITERAL_LENGTH_DEFAULT_LOG),
+            LITERAL_LENGTH_MAX_LOG,
+            LITERAL_LENGTH_MAX_SYMBOL,
+        )?;
+        FrameState::read_table(
+            &mut rest,
+            modes >> 4 & 3,
+            &mut self.offsets,
+            (&OFFSET_DEFAULT, OFFSET_DEFAULT_LOG),
+            OFFSET_MAX_LOG,
+            OFFSET_MAX_SYMBOL,
+        )?;
+        FrameState::read_table(
+            &mut rest,
+            modes >> 2 & 3,
+            &mut self.match_lengths,
+            (&MATCH_LENGTH_DEFAULT, MATCH_LENGTH_DEFAULT_LOG),
+            MATCH_LENGTH_MAX_LOG,
+            MATCH_LENGTH_MAX_SYMBOL,
+        )?;
+
+        let (literal_lengths, offsets, match_lengths) =
+            match (&self.literal_lengths, &self.offsets, &self.match_lengths) {
+                (Some(literal_lengths), Some(offsets), Some(match_lengths)) => {
+                    (literal_lengths, offsets, match_lengths)
+                }
+                _ => unreachable!(),
+            };
+        let mut reader = BackwardBitReader::new(rest)?;
+        let mut literal_length_state = FseState::new(literal_lengths, &mut reader)?;
+        let mut offset_state = FseState::new(offsets, &mut reader)?;
+        let mut match_length_state = FseState::new(match_lengths, &mut reader)?;
+
+        let mut literals_position: usize = 0;
+        for index in 0..count {
+            let offset_code = offset_state.symbol();
+            let match_length_code = match_length_state.symbol() as usize;
+            let literal_length_code = literal_length_state.symbol() as usize;
+            let offset_value = (1usize << offset_code) + reader.read_bits(offset_code)? as usize;
+            let match_length = MATCH_LENGTH_BASELINES[match_length_code] as usize
+                + reader.read_bits(MATCH_LENGTH_BITS[match_length_code])? as usize;
+            let literal_length = LITERAL_LENGTH_BASELINES[literal_length_code] as usize
+                + reader.read_bits(LITERAL_LENGTH_BITS[literal_length_code])? as usize;
+            if index + 1 < count {
+                literal_length_state.update(&mut reader)?;
+                match_length_state.update(&mut reader)?;
+                offset_state.update(&mut reader)?;
+            }
+
+            let offset = resolve_offset(&mut self.repeat_offsets, offset_value, literal_length)?;
+            let literals_end = literals_position + literal_length;
+            if literals_end > literals.len() {
+                return Err(invalid_data("Zstandard sequence uses missing literals"));
+            }
+            output.extend_from_slice(&literals[literals_position..literals_end]);
+            literals_position = literals_end;
+
+            if offset > output.len() - self.frame_start {
+                return Err(invalid_data(
+                    "Zstandard match refers outside of the decoded data",
+                ));
+            }
+            if output.len() - block_start + match_length > MAX_BLOCK_SIZE {
+                return Err(invalid_data("Zstandard block is larger than allowed"));
+            }
+            let start = output.len() - offset;
+            if offset >= match_length {
+                output.extend_from_within(start..start + match_length);
+            } else {
+                for index in start..start + match_length {
+                    output.push(output[index]);
+                }
+            }
+        }
+        if !reader.is_finished() {
+            return Err(invalid_data("Zstandard sequences size mismatch"));
+        }
+        Ok(literals_position)
+    }
+}
+
+/// Resolve an offset value, updating the repeated offsets.
+fn resolve_offset(
+    repeat: &mut [usize; 3],
+    offset_value: usize,
+    literal_length: usize,
+) -> Result<usize> {
+    if offset_value > 3 {
+        let offset = offset_value - 3;
+        *repeat = [offset, repeat[0], repeat[1]];
+        return Ok(offset);
+    }
+    // without literals, the repeated offsets are shifted by one
+    let index = match literal_length {
+        0 => offset_value,
+        _ => offset_value - 1,
+    };
+    let offset = match index {
+        0 => return Ok(repeat[0]),
+        3 => repeat[0].wrapping_sub(1),
+        index => repeat[index],
+    };
+    if offset == 0 {
+        return Err(invalid_data("Zstandard repeated offset is zero"));
+    }
+    *repeat = match index {
+        1 => [offset, repeat[0], repeat[2]],
+        _ => [offset, repeat[0], repeat[1]],
+    };
+    Ok(offset)
+}